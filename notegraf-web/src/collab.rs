@@ -0,0 +1,425 @@
+//! Collaborative editing sessions of notes.
+//!
+//! Everyone editing a note together joins the same session, which holds the latest note body
+//! and relays [`TextOperation`]s between the participants, as the server of
+//! [ot.js](https://github.com/Operational-Transformation/ot.js) does.
+//! An operation is transformed against the operations applied since the version it was made on,
+//! and then sent to every participant, including the one who made it as an acknowledgement.
+//!
+//! The note body is committed as a revision periodically, see [`CollabSettings`], and when the
+//! last participant leaves, merging edits made outside of the session with
+//! [`update_note_merging`].
+//! If they conflict, the note body of the session is kept as a draft, and the session starts over
+//! from the current revision.
+use crate::configuration::CollabSettings;
+use crate::NoteType;
+use actix_web::web::Data;
+use futures::stream::{self, BoxStream, StreamExt};
+use notegraf::errors::NoteStoreError;
+use notegraf::merge::{update_note_merging, MergeOutcome};
+use notegraf::notemetadata::NoteMetadataEditable;
+use notegraf::notestore::BoxedNoteStore;
+use notegraf::ot::{OperationError, TextOperation};
+use notegraf::{Note, NoteID, NoteLocator, Revision};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{broadcast, Mutex};
+
+/// The change message of the revisions committed by sessions.
+const COMMIT_MESSAGE: &str = "Collaborative editing session";
+/// How many events a participant can fall behind before it's disconnected.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+/// How many times to continue the draft of a conflicting session with the operations applied
+/// while it was created, before holding up operations to finish it.
+const MAX_DRAFT_ATTEMPTS: usize = 3;
+
+#[derive(Error, Debug)]
+pub enum CollabError {
+    #[error("no collaborative session for note `{0}`")]
+    NoSession(NoteID),
+    #[error("version {0} is ahead of the session")]
+    FutureVersion(u64),
+    #[error("version {0} is from before the session started over")]
+    StaleVersion(u64),
+    #[error(transparent)]
+    Operation(#[from] OperationError),
+    #[error(transparent)]
+    Store(#[from] NoteStoreError),
+}
+
+/// What participants of a session are told.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// The note body to start from, sent when joining and whenever the session starts over.
+    Snapshot {
+        version: u64,
+        revision: Revision,
+        text: String,
+    },
+    /// An operation that turned version `version - 1` into `version`.
+    ///
+    /// Operations from the server itself, e.g., merged edits made outside of the session, don't
+    /// have a client.
+    Operation {
+        version: u64,
+        client: Option<String>,
+        operation: TextOperation,
+    },
+}
+
+struct Session {
+    id: NoteID,
+    /// The revision that the note body was loaded from or last committed as.
+    revision: Revision,
+    text: String,
+    /// The version that the session (last) started from.
+    start_version: u64,
+    /// The operations applied since the session (last) started.
+    history: Vec<TextOperation>,
+    committed_version: u64,
+    /// Held while the note body is committed, so that commits of the session take turns without
+    /// holding up operations.
+    commit_lock: Arc<Mutex<()>>,
+    participants: usize,
+    /// Whether the session is over, so that a new one has to be started.
+    closed: bool,
+    sender: broadcast::Sender<SessionEvent>,
+}
+
+impl Session {
+    fn new(note: Box<dyn Note<NoteType>>) -> Self {
+        Session {
+            id: note.get_id(),
+            revision: note.get_revision(),
+            text: note.get_note_inner().into(),
+            start_version: 0,
+            history: vec![],
+            committed_version: 0,
+            commit_lock: Default::default(),
+            participants: 0,
+            closed: false,
+            sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    fn version(&self) -> u64 {
+        self.start_version + self.history.len() as u64
+    }
+
+    fn snapshot(&self) -> SessionEvent {
+        SessionEvent::Snapshot {
+            version: self.version(),
+            revision: self.revision.clone(),
+            text: self.text.clone(),
+        }
+    }
+
+    /// Apply an operation made on a version of the note body, and return the new version.
+    fn apply(
+        &mut self,
+        client: Option<String>,
+        version: u64,
+        mut operation: TextOperation,
+    ) -> Result<u64, CollabError> {
+        if version > self.version() {
+            return Err(CollabError::FutureVersion(version));
+        }
+        if version < self.start_version {
+            return Err(CollabError::StaleVersion(version));
+        }
+        for concurrent in &self.history[(version - self.start_version) as usize..] {
+            operation = TextOperation::transform(&operation, concurrent)?.0;
+        }
+        self.text = operation.apply(&self.text)?;
+        self.history.push(operation.clone());
+        let version = self.version();
+        // There may be no participant listening
+        let _ = self.sender.send(SessionEvent::Operation {
+            version,
+            client,
+            operation,
+        });
+        Ok(version)
+    }
+
+    /// Start over from a revision, dropping the operations made on earlier versions.
+    fn start_over(&mut self, revision: Revision, text: String) {
+        self.start_version = self.version() + 1;
+        self.history.clear();
+        self.committed_version = self.start_version;
+        self.revision = revision;
+        self.text = text;
+        let _ = self.sender.send(self.snapshot());
+    }
+}
+
+/// A participant of a session, who leaves the session when dropped.
+pub struct Participant {
+    sessions: Arc<CollabSessions>,
+    id: NoteID,
+    snapshot: Option<SessionEvent>,
+    events: broadcast::Receiver<SessionEvent>,
+}
+
+impl Participant {
+    /// Stream the snapshot to start from, followed by the events of the session.
+    ///
+    /// The stream ends if the participant falls behind, and it has to join again.
+    pub fn into_stream(self) -> BoxStream<'static, SessionEvent> {
+        stream::unfold(self, |mut participant| async move {
+            let event = match participant.snapshot.take() {
+                Some(snapshot) => snapshot,
+                None => participant.events.recv().await.ok()?,
+            };
+            Some((event, participant))
+        })
+        .boxed()
+    }
+}
+
+impl Drop for Participant {
+    fn drop(&mut self) {
+        let sessions = self.sessions.clone();
+        let id = self.id.clone();
+        drop(tokio::spawn(async move { sessions.leave(&id).await }));
+    }
+}
+
+/// The collaborative editing sessions of all notes.
+pub struct CollabSessions {
+    store: Data<BoxedNoteStore<NoteType>>,
+    commit_interval: Duration,
+    sessions: std::sync::Mutex<HashMap<NoteID, Arc<Mutex<Session>>>>,
+}
+
+impl CollabSessions {
+    pub fn new(store: Data<BoxedNoteStore<NoteType>>, settings: &CollabSettings) -> Self {
+        CollabSessions {
+            store,
+            commit_interval: Duration::from_secs(settings.commit_interval_secs),
+            sessions: Default::default(),
+        }
+    }
+
+    fn get(&self, id: &NoteID) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    /// Get the session of a note, starting one from the current revision if there is none.
+    async fn get_or_start(
+        self: &Arc<Self>,
+        id: &NoteID,
+    ) -> Result<Arc<Mutex<Session>>, CollabError> {
+        if let Some(session) = self.get(id) {
+            return Ok(session);
+        }
+        let note = self
+            .store
+            .get_note(&NoteLocator::Current(id.clone()))
+            .await?;
+        let started = Arc::new(Mutex::new(Session::new(note)));
+        // Someone else may have started one in the meantime
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .entry(id.clone())
+            .or_insert_with(|| started.clone())
+            .clone();
+        if Arc::ptr_eq(&session, &started) && !self.commit_interval.is_zero() {
+            self.spawn_commits(Arc::downgrade(&session));
+        }
+        Ok(session)
+    }
+
+    /// Join the session of a note.
+    pub async fn join(self: Arc<Self>, id: NoteID) -> Result<Participant, CollabError> {
+        loop {
+            let session = self.get_or_start(&id).await?;
+            let mut s = session.lock().await;
+            // The last participant left while we were joining
+            if s.closed {
+                continue;
+            }
+            s.participants += 1;
+            return Ok(Participant {
+                sessions: self.clone(),
+                id,
+                snapshot: Some(s.snapshot()),
+                events: s.sender.subscribe(),
+            });
+        }
+    }
+
+    async fn leave(&self, id: &NoteID) {
+        let Some(session) = self.get(id) else {
+            return;
+        };
+        {
+            let mut s = session.lock().await;
+            s.participants -= 1;
+            if s.participants > 0 {
+                return;
+            }
+        }
+        self.commit(&session).await;
+        let mut s = session.lock().await;
+        // Someone may have joined during the commit
+        if s.participants == 0 && !s.closed {
+            s.closed = true;
+            self.sessions.lock().unwrap().remove(id);
+        }
+    }
+
+    /// Apply an operation made by a client on a version of the note body, and return the new
+    /// version.
+    pub async fn apply(
+        &self,
+        id: &NoteID,
+        client: String,
+        version: u64,
+        operation: TextOperation,
+    ) -> Result<u64, CollabError> {
+        let session = self
+            .get(id)
+            .ok_or_else(|| CollabError::NoSession(id.clone()))?;
+        let mut s = session.lock().await;
+        if s.closed {
+            return Err(CollabError::NoSession(id.clone()));
+        }
+        s.apply(Some(client), version, operation)
+    }
+
+    fn spawn_commits(self: &Arc<Self>, session: Weak<Mutex<Session>>) {
+        let sessions = self.clone();
+        drop(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(sessions.commit_interval);
+            // The first tick is right away
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(session) = session.upgrade() else {
+                    return;
+                };
+                if session.lock().await.closed {
+                    return;
+                }
+                sessions.commit(&session).await;
+            }
+        }));
+    }
+
+    /// Commit the note body of a session if it changed since the last commit.
+    ///
+    /// The session is not locked while the note body is merged with the note or kept as a draft,
+    /// so that operations can still be applied.
+    async fn commit(&self, session: &Mutex<Session>) {
+        let commit_lock = session.lock().await.commit_lock.clone();
+        let _committing = commit_lock.lock().await;
+        let (id, revision, text, version) = {
+            let s = session.lock().await;
+            if s.version() == s.committed_version {
+                return;
+            }
+            (
+                s.id.clone(),
+                s.revision.clone(),
+                s.text.clone(),
+                s.version(),
+            )
+        };
+        if let Err(e) = self.try_commit(session, &id, revision, text, version).await {
+            error!(error = %e, note_id = %id, "Failed to commit a collaborative session");
+        }
+    }
+
+    /// Commit `text`, the note body of a session at `version`, made on `revision`.
+    async fn try_commit(
+        &self,
+        session: &Mutex<Session>,
+        id: &NoteID,
+        revision: Revision,
+        text: String,
+        version: u64,
+    ) -> Result<(), CollabError> {
+        let store = self.store.as_ref().as_ref();
+        let loc = NoteLocator::Specific(id.clone(), revision);
+        let note_inner = NoteType::from(text.clone());
+        let metadata = NoteMetadataEditable {
+            message: Some(COMMIT_MESSAGE.to_owned()),
+            ..NoteMetadataEditable::unchanged()
+        };
+        if store.get_current_revision(&loc).await?.is_none() {
+            // Don't bring back a note deleted during the session, but keep what was written
+            store
+                .create_draft(&loc, None, Some(note_inner), metadata)
+                .await?;
+            session.lock().await.committed_version = version;
+            return Ok(());
+        }
+        let outcome = update_note_merging(
+            store,
+            &loc,
+            None,
+            Some(note_inner.clone()),
+            metadata.clone(),
+        )
+        .await?;
+        match outcome {
+            MergeOutcome::Updated(new_loc) => {
+                let note = store.get_note(&new_loc).await?;
+                let (new_revision, merged): (_, String) =
+                    (note.get_revision(), note.get_note_inner().into());
+                drop(note);
+                let mut s = session.lock().await;
+                s.revision = new_revision;
+                s.committed_version = version;
+                if merged != text {
+                    // Edits made outside of the session were merged in, on top of what was
+                    // committed, and are transformed against the operations applied since
+                    let operation = TextOperation::from_diff(&text, &merged);
+                    let applied = s.apply(None, version, operation)?;
+                    if applied == version + 1 {
+                        s.committed_version = applied;
+                    }
+                }
+            }
+            MergeOutcome::Conflict(_) => {
+                let note = store.get_note(&loc.current()).await?;
+                let (current_revision, current_text) =
+                    (note.get_revision(), note.get_note_inner().into());
+                drop(note);
+                // Keep the operations applied since as well, continuing the draft with those
+                // applied while it is created
+                let mut draft_loc = loc;
+                for attempt in 1.. {
+                    let mut s = session.lock().await;
+                    let (text, drafted_version) = (s.text.clone(), s.version());
+                    let note_inner = Some(NoteType::from(text));
+                    if attempt == MAX_DRAFT_ATTEMPTS {
+                        // Operations keep coming, so hold them up this once
+                        store
+                            .create_draft(&draft_loc, None, note_inner, metadata)
+                            .await?;
+                    } else {
+                        drop(s);
+                        draft_loc = store
+                            .create_draft(&draft_loc, None, note_inner, metadata.clone())
+                            .await?;
+                        s = session.lock().await;
+                        if s.version() != drafted_version {
+                            continue;
+                        }
+                    }
+                    s.start_over(current_revision, current_text);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
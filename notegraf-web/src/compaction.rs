@@ -0,0 +1,37 @@
+//! Periodic compaction of old revisions according to the configured retention policy, and pruning
+//! of the change feed.
+use crate::configuration::RetentionSettings;
+use crate::NoteType;
+use actix_web::web::Data;
+use chrono::Utc;
+use notegraf::notestore::{compact, BoxedNoteStore};
+use std::time::Duration;
+
+/// Run [`compact`] on `store` every `interval_hours` in the background, starting right away, and
+/// prune the changes older than `changes_days`.
+pub fn spawn_compaction(store: Data<BoxedNoteStore<NoteType>>, settings: &RetentionSettings) {
+    let policy = settings.policy();
+    let changes_days = settings.changes_days;
+    let period = Duration::from_secs(settings.interval_hours.max(1) * 3600);
+    drop(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match compact(store.as_ref().as_ref(), &policy, Utc::now()).await {
+                Ok(report) => info!(
+                    notes = report.notes,
+                    revisions_purged = report.revisions_purged,
+                    "Compacted revision history"
+                ),
+                Err(e) => error!(error = %e, "Failed to compact revision history"),
+            }
+            if let Some(days) = changes_days {
+                let before = Utc::now() - chrono::Duration::days(days.into());
+                match store.prune_changes(&before).await {
+                    Ok(pruned) => info!(changes_pruned = pruned, "Pruned change feed"),
+                    Err(e) => error!(error = %e, "Failed to prune change feed"),
+                }
+            }
+        }
+    }));
+}
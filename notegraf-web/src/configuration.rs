@@ -1,11 +1,13 @@
-use notegraf::notestore::BoxedNoteStore;
+use notegraf::errors::NoteStoreError;
+use notegraf::notestore::retention::RetentionTier;
+use notegraf::notestore::{BoxedNoteStore, RetentionPolicy};
 use notegraf::{InMemoryStore, PostgreSQLStoreBuilder};
 use sqlx::postgres::PgConnectOptions;
 use sqlx::{ConnectOptions, Connection, Executor, PgConnection};
 use tracing::log::LevelFilter;
 use uuid::Uuid;
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum NoteStoreType {
     InMemory,
     PostgreSQL,
@@ -21,6 +23,10 @@ pub struct Settings {
     populatetestdata: bool,
     pub otlpendpoint: Option<String>,
     pub loglevel: Option<String>,
+    pub migration: Option<MigrationSettings>,
+    pub retention: Option<RetentionSettings>,
+    #[serde(default)]
+    pub collab: CollabSettings,
 }
 
 impl Settings {
@@ -28,36 +34,131 @@ impl Settings {
         &self,
         random_db: bool,
         log_statement_filter: LevelFilter,
-    ) -> BoxedNoteStore<crate::NoteType> {
-        let store: BoxedNoteStore<crate::NoteType> = match self.notestoretype {
-            NoteStoreType::InMemory => Box::new(InMemoryStore::new()),
-            NoteStoreType::PostgreSQL => {
-                let database_settings = CONFIGURATION.database
-                    .as_ref()
-                    .expect("When notestoretype is set to PostgreSQL, you must configure the keys under database");
-                let db_options = if random_db {
-                    let db_name = Uuid::new_v4().to_string();
-                    let db_options = database_settings.options_without_db();
-                    let mut connection = PgConnection::connect_with(&db_options)
-                        .await
-                        .expect("Failed to connect to Postgres");
-                    connection
-                        .execute(&*format!(r#"CREATE DATABASE "{db_name}";"#))
-                        .await
-                        .expect("Failed to create database.");
-                    db_options.database(&db_name)
-                } else {
-                    database_settings.options()
-                }
-                .log_statements(log_statement_filter);
-                Box::new(PostgreSQLStoreBuilder::new(db_options).build().await)
+    ) -> Result<BoxedNoteStore<crate::NoteType>, NoteStoreError> {
+        build_note_store(
+            &self.notestoretype,
+            self.database.as_ref(),
+            self.populatetestdata,
+            random_db,
+            log_statement_filter,
+        )
+        .await
+    }
+}
+
+/// Settings of the periodic compaction of old revisions.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RetentionSettings {
+    /// Hours between two runs of compaction.
+    pub interval_hours: u64,
+    pub tiers: Vec<RetentionTier>,
+    /// Days to keep changes in the change feed, forever if not set.
+    #[serde(default)]
+    pub changes_days: Option<u32>,
+}
+
+impl RetentionSettings {
+    pub fn policy(&self) -> RetentionPolicy {
+        RetentionPolicy::new(self.tiers.clone())
+    }
+}
+
+/// Settings of collaborative editing sessions.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CollabSettings {
+    /// Seconds between two commits of the note body of a session.
+    ///
+    /// With 0, the note body is only committed when the last participant leaves.
+    pub commit_interval_secs: u64,
+}
+
+impl Default for CollabSettings {
+    fn default() -> Self {
+        CollabSettings {
+            commit_interval_secs: 30,
+        }
+    }
+}
+
+/// Settings of the `notegraf-migrate` command.
+#[derive(serde::Deserialize, Debug)]
+pub struct MigrationSettings {
+    /// The note store to copy from.
+    pub source: Option<NoteStoreSettings>,
+    /// The archive to copy from instead, e.g., a backup or an export of a running server.
+    pub archive: Option<String>,
+    pub destination: NoteStoreSettings,
+}
+
+/// Settings of a note store other than the one used by the server.
+#[derive(serde::Deserialize, Debug)]
+pub struct NoteStoreSettings {
+    pub notestoretype: NoteStoreType,
+    pub database: Option<DatabaseSettings>,
+    #[serde(default)]
+    pub populatetestdata: bool,
+}
+
+impl NoteStoreSettings {
+    pub async fn get_note_store(
+        &self,
+        random_db: bool,
+        log_statement_filter: LevelFilter,
+    ) -> Result<BoxedNoteStore<crate::NoteType>, NoteStoreError> {
+        build_note_store(
+            &self.notestoretype,
+            self.database.as_ref(),
+            self.populatetestdata,
+            random_db,
+            log_statement_filter,
+        )
+        .await
+    }
+}
+
+async fn build_note_store(
+    notestoretype: &NoteStoreType,
+    database: Option<&DatabaseSettings>,
+    populatetestdata: bool,
+    random_db: bool,
+    log_statement_filter: LevelFilter,
+) -> Result<BoxedNoteStore<crate::NoteType>, NoteStoreError> {
+    let store: BoxedNoteStore<crate::NoteType> = match notestoretype {
+        NoteStoreType::InMemory => Box::new(InMemoryStore::new()),
+        NoteStoreType::PostgreSQL => {
+            let database_settings = database
+                .expect("When notestoretype is set to PostgreSQL, you must configure the keys under database");
+            let db_options = if random_db {
+                let db_name = Uuid::new_v4().to_string();
+                let db_options = database_settings.options_without_db();
+                let mut connection = PgConnection::connect_with(&db_options)
+                    .await
+                    .expect("Failed to connect to Postgres");
+                connection
+                    .execute(&*format!(r#"CREATE DATABASE "{db_name}";"#))
+                    .await
+                    .expect("Failed to create database.");
+                db_options.database(&db_name)
+            } else {
+                database_settings.options()
+            }
+            .log_statements(log_statement_filter);
+            let mut builder = PostgreSQLStoreBuilder::new(db_options);
+            if let Some(keyframe_interval) = database_settings.keyframeinterval {
+                builder = builder.keyframe_interval(keyframe_interval);
+            }
+            let store = builder.build().await;
+            if database_settings.compactdeltas {
+                let converted = store.compact_deltas().await?;
+                info!("Stored {} existing revisions as deltas", converted);
             }
-        };
-        if cfg!(feature = "notetype_markdown") && self.populatetestdata {
-            notegraf::notestore::util::populate_test_data(&store).await;
+            Box::new(store)
         }
-        store
+    };
+    if cfg!(feature = "notetype_markdown") && populatetestdata {
+        notegraf::notestore::util::populate_test_data(&store).await;
     }
+    Ok(store)
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -67,6 +168,11 @@ pub struct DatabaseSettings {
     pub name: String,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Keep the full text of one in this many revisions of a note, and store the others as deltas.
+    pub keyframeinterval: Option<u32>,
+    /// Store the revisions saved before deltas were enabled as deltas too, when starting.
+    #[serde(default)]
+    pub compactdeltas: bool,
 }
 
 impl DatabaseSettings {
@@ -0,0 +1,55 @@
+//! The change feed of the note store, shared by the event streams of all clients.
+use crate::NoteType;
+use actix_web::web::Data;
+use futures::stream::{self, BoxStream, StreamExt};
+use notegraf::errors::NoteStoreError;
+use notegraf::notestore::{BoxedNoteStore, Change};
+use tokio::sync::{broadcast, Mutex};
+
+/// How many changes a client can fall behind before its stream ends.
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+/// One subscription to the note store, fanned out to every event stream.
+///
+/// The subscription starts with the first stream, and ends once no stream is left when a change
+/// arrives.
+/// If the note store ends the subscription, the streams end and the browsers reconnect.
+pub struct ChangeFeed {
+    store: Data<BoxedNoteStore<NoteType>>,
+    sender: Mutex<Option<broadcast::WeakSender<Change>>>,
+}
+
+impl ChangeFeed {
+    pub fn new(store: Data<BoxedNoteStore<NoteType>>) -> Self {
+        ChangeFeed {
+            store,
+            sender: Mutex::new(None),
+        }
+    }
+
+    /// Stream the changes from now on.
+    pub async fn subscribe(&self) -> Result<BoxStream<'static, Change>, NoteStoreError> {
+        let mut sender = self.sender.lock().await;
+        let receiver = match sender.as_ref().and_then(|s| s.upgrade()) {
+            Some(s) => s.subscribe(),
+            None => {
+                let mut subscription = self.store.subscribe().await?;
+                let (s, receiver) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+                *sender = Some(s.downgrade());
+                drop(tokio::spawn(async move {
+                    while let Some(change) = subscription.next().await {
+                        if s.send(change).is_err() {
+                            break;
+                        }
+                    }
+                }));
+                receiver
+            }
+        };
+        Ok(stream::unfold(receiver, |mut receiver| async move {
+            let change = receiver.recv().await.ok()?;
+            Some((change, receiver))
+        })
+        .boxed())
+    }
+}
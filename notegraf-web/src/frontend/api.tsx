@@ -1,4 +1,5 @@
-import {Note} from "./types";
+import {Ancestor, DeletedNote, Note, RevisionLabel, SessionDraft} from "./types";
+import {TextOperation} from "./utils/ot";
 
 type NoteLocator = {
     Specific: string[]
@@ -12,6 +13,22 @@ export async function getNote(noteID: string): Promise<Note> {
     return response.json();
 }
 
+export async function getSequence(noteID: string): Promise<Note[]> {
+    const response = await fetch(`/api/v1/note/${noteID}/sequence`);
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function getAncestors(noteID: string): Promise<Ancestor[]> {
+    const response = await fetch(`/api/v1/note/${noteID}/ancestors`);
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
 export async function getNoteSpecific(noteID: string, revision: string): Promise<Note> {
     const response = await fetch(`/api/v1/note/${noteID}/revision/${revision}`);
     if (!response.ok) {
@@ -28,6 +45,32 @@ export async function getNoteRevisions(noteID: string): Promise<Note[]> {
     return response.json();
 }
 
+export async function listLabels(noteID: string): Promise<RevisionLabel[]> {
+    const response = await fetch(`/api/v1/note/${noteID}/label`);
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function addLabel(noteID: string, revision: string, label: string) {
+    const response = await fetch(`/api/v1/note/${noteID}/revision/${revision}/label/${encodeURIComponent(label)}`, {
+        method: 'POST'
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+}
+
+export async function removeLabel(noteID: string, label: string) {
+    const response = await fetch(`/api/v1/note/${noteID}/label/${encodeURIComponent(label)}`, {
+        method: "DELETE"
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+}
+
 export async function searchNotes(query: string): Promise<Note[]> {
     const response = await fetch("/api/v1/note?" + new URLSearchParams({query: query}));
     if (!response.ok) {
@@ -36,9 +79,12 @@ export async function searchNotes(query: string): Promise<Note[]> {
     return response.json();
 }
 
-export async function deleteNote(noteID: string) {
+export async function deleteNote(noteID: string, revision: string) {
     const response = await fetch(`/api/v1/note/${noteID}`, {
-        method: "DELETE"
+        method: "DELETE",
+        headers: {
+            'If-Match': `"${revision}"`,
+        },
     });
     if (!response.ok) {
         throw new Error(response.statusText + " " + await response.text());
@@ -59,10 +105,95 @@ export async function postNote(endpoint: string, data: any): Promise<NoteLocator
     return response.json();
 }
 
+export async function listDeleted(): Promise<DeletedNote[]> {
+    const response = await fetch(`/api/v1/trash`);
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function restoreDeleted(noteID: string): Promise<NoteLocator> {
+    const response = await fetch(`/api/v1/trash/${noteID}/restore`, {
+        method: 'POST'
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function purgeNote(noteID: string) {
+    const response = await fetch(`/api/v1/trash/${noteID}`, {
+        method: "DELETE"
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+}
+
 export async function getTags(): Promise<string[]> {
     const response = await fetch(`/api/v1/tags`);
     if (!response.ok) {
         throw new Error(response.statusText + " " + await response.text());
     }
     return response.json();
-}
\ No newline at end of file
+}
+
+// Send an operation made on a version of the note body to the collaborative editing session of a note
+export async function postCollabOperation(noteID: string, client: string, version: number, operation: TextOperation): Promise<number> {
+    const response = await fetch(`/api/v1/note/${noteID}/collab`, {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({client, version, operation}),
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function putSessionDraft(session: string, content: any, noteID?: string, revision?: string): Promise<SessionDraft> {
+    const response = await fetch(`/api/v1/draft/${encodeURIComponent(session)}`, {
+        method: 'PUT',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({note_id: noteID, revision, content}),
+    });
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+// Get the draft of an editor session, or null if there is none
+export async function getSessionDraft(session: string): Promise<SessionDraft | null> {
+    const response = await fetch(`/api/v1/draft/${encodeURIComponent(session)}`);
+    if (response.status === 404) {
+        return null;
+    }
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
+
+export async function deleteSessionDraft(session: string) {
+    const response = await fetch(`/api/v1/draft/${encodeURIComponent(session)}`, {
+        method: "DELETE"
+    });
+    if (!response.ok && response.status !== 404) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+}
+
+export async function listSessionDrafts(prefix: string): Promise<SessionDraft[]> {
+    const response = await fetch("/api/v1/draft?" + new URLSearchParams({prefix: prefix}));
+    if (!response.ok) {
+        throw new Error(response.statusText + " " + await response.text());
+    }
+    return response.json();
+}
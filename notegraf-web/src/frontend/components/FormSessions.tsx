@@ -1,29 +1,45 @@
 import * as React from "react";
 import {useEffect, useState} from "react";
-import {getKeysByPrefix, getStorageValue, renderTitle, showAgo} from "../utils";
+import {renderTitle, showAgo} from "../utils";
 import {Link, useNavigate} from "react-router-dom";
+import {deleteSessionDraft, listSessionDrafts, putSessionDraft} from "../api";
 import * as types from "../types";
 import {TrashIcon} from "@heroicons/react/24/outline";
 
 type FormSessionsProps = {
-    keyPrefix: string,
+    sessionPrefix: string,
+    noteID?: string,
     title: string
 }
 
+// Sessions used to be kept in the browser only, move them to the server
+async function importLocalSessions(sessionPrefix: string, noteID?: string) {
+    const keys = Object.keys(localStorage).filter(key => key.startsWith(`autosave.${sessionPrefix}.`));
+    for (const key of keys) {
+        const content = JSON.parse(localStorage.getItem(key) as string);
+        if (content) {
+            await putSessionDraft(key.slice("autosave.".length), content, noteID);
+        }
+        localStorage.removeItem(key);
+    }
+}
+
 export function FormSessions(props: FormSessionsProps) {
     const navigate = useNavigate();
-    const [keys, setKeys] = useState<string[]>([]);
-    const [notes, setNotes] = useState<types.Note[]>([]);
+    const [drafts, setDrafts] = useState<types.SessionDraft[]>([]);
+    const [error, setError] = useState<any>(null);
 
-    function loadSessions() {
-        let existingKeys = getKeysByPrefix(props.keyPrefix);
-        existingKeys.sort();
-        let notes = existingKeys.map(key => getStorageValue(key, null));
-        if (existingKeys.length == 0) {
-            navigate(`./${Date.now()}`, {replace: true});
-        } else {
-            setKeys(existingKeys);
-            setNotes(notes);
+    async function loadSessions() {
+        try {
+            await importLocalSessions(props.sessionPrefix, props.noteID);
+            const drafts = await listSessionDrafts(`${props.sessionPrefix}.`);
+            if (drafts.length == 0) {
+                navigate(`./${Date.now()}`, {replace: true});
+            } else {
+                setDrafts(drafts);
+            }
+        } catch (e) {
+            setError(e);
         }
     }
 
@@ -31,22 +47,29 @@ export function FormSessions(props: FormSessionsProps) {
         document.title = props.title;
         loadSessions();
     }, []);
+
+    if (error) {
+        return (<div>{error.toString()}</div>);
+    }
+
     return (<div>
         Choose a session:
         <div>
-            {keys.map((key, idx) => {
-                let parts = key.split('.');
+            {drafts.map((draft) => {
+                let parts = draft.session.split('.');
                 let ts = parts[parts.length - 1];
-                let date = new Date(parseInt(ts));
-                let note = notes[idx];
-                let title = note ? renderTitle(note.title) : (<span></span>);
-                return (<div key={key} className={"my-0.5"}>
-                    <Link to={`./${ts}`} className={"underline"}>{title} ({showAgo(date)})</Link>
+                let title = draft.content ? renderTitle(draft.content.title) : (<span></span>);
+                return (<div key={draft.session} className={"my-0.5"}>
+                    <Link to={`./${ts}`} className={"underline"}>{title} ({showAgo(new Date(draft.saved_at))})</Link>
                     <button
-                        onClick={() => {
+                        onClick={async () => {
                             if (window.confirm("Are you sure you want to delete this session?")) {
-                                localStorage.removeItem(key);
-                                loadSessions();
+                                try {
+                                    await deleteSessionDraft(draft.session);
+                                    await loadSessions();
+                                } catch (e) {
+                                    setError(e);
+                                }
                             }
                         }}
                         className={"ng-button ng-button-danger inline ml-2"}
@@ -58,4 +81,4 @@ export function FormSessions(props: FormSessionsProps) {
             <div className={"my-0.5"}><Link to={`./${Date.now()}`} className={"underline"}>New session</Link></div>
         </div>
     </div>);
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 import * as React from "react";
 import {useEffect, useState} from "react";
 import {renderTitle} from "../utils";
-import {getNote} from "../api";
+import {getAncestors, getNote} from "../api";
 import {Tags} from "./Tags";
 
 type LazyLinksProps = {
@@ -22,8 +22,17 @@ export function LazyLinks(props: LazyLinksProps) {
         const baseNote = await getNote(noteID);
         let rootNote = baseNote;
         let transitive = false;
-        while (rootNote.title == "" && rootNote.prev != null) {
-            rootNote = await getNote(rootNote.prev);
+        if (rootNote.title == "" && rootNote.prev != null) {
+            // Use the title of the closest titled note before it in the same sequence
+            for (const ancestor of await getAncestors(noteID)) {
+                if (ancestor.link != "Sequence") {
+                    break;
+                }
+                rootNote = ancestor.note;
+                if (rootNote.title != "") {
+                    break;
+                }
+            }
         }
 
         if (rootNote != baseNote) {
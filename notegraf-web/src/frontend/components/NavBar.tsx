@@ -66,7 +66,7 @@ export function NavBar() {
 
     return (
         <nav className={"flex p-1 w-full bg-gray-500 items-center gap-1"}>
-            <MiscDropdown links={[["/note", "Recent"], ["/tags", "Tags"]]}/>
+            <MiscDropdown links={[["/note", "Recent"], ["/tags", "Tags"], ["/trash", "Trash"]]}/>
             <div className={"ml-auto flex gap-1 items-center min-w-0"}>
                 <div className={"min-w-0"}>
                     <SearchBox/>
@@ -18,7 +18,8 @@ import {
     PencilSquareIcon,
     RectangleStackIcon,
     ShareIcon,
-    TrashIcon
+    TrashIcon,
+    UsersIcon
 } from "@heroicons/react/24/outline";
 import * as katex from "katex";
 import * as hljs from 'highlight.js';
@@ -197,7 +198,7 @@ function NoteControls(props: NoteControlProps) {
     const onDelete = async () => {
         try {
             if (window.confirm("Are you sure you want to delete this note?")) {
-                await deleteNote(props.note.id);
+                await deleteNote(props.note.id, props.note.revision);
                 props.onDelete();
             }
         } catch (e) {
@@ -259,6 +260,11 @@ function NoteControls(props: NoteControlProps) {
                     <PencilSquareIcon className={"h-6 w-6"}/>
                 </button>
             </Link>
+            <Link to={`/note/${props.note.id}/collab`}>
+                <button className={"ng-button ng-button-primary"} title={"Edit together"}>
+                    <UsersIcon className={"h-6 w-6"}/>
+                </button>
+            </Link>
             <button onClick={onDelete} className={"ng-button ng-button-danger ml-auto"} title={"Delete"}>
                 <TrashIcon className={"h-6 w-6"}/>
             </button>
@@ -1,6 +1,6 @@
 import {useForm} from "react-hook-form";
 import {useNavigate, useParams} from "react-router-dom";
-import {incrementCounter, useLocalStorage} from "../utils";
+import {DraftTarget, useSessionDraft} from "../utils";
 import {postNote} from "../api";
 import * as React from "react";
 import {useEffect, useState} from "react";
@@ -11,13 +11,16 @@ type NoteFormContent = {
     title: string,
     note_inner: string,
     metadata_tags: string,
-    metadata_custom_metadata: string
+    metadata_custom_metadata: string,
+    metadata_author?: string,
+    metadata_message?: string
 }
 
 type NoteFormProps = {
     defaultValue: NoteFormContent,
     endpoint: string,
-    autoSaveKeyPrefix: string,
+    draftSessionPrefix: string,
+    draftTarget: DraftTarget,
     submitText: string,
     title: string
 }
@@ -38,16 +41,15 @@ export function NoteFormSession(props: NoteFormProps) {
     const [error, setError] = useState<any>(null);
     const [preview, setPreview] = useState(false);
     const {sessionTs} = useParams();
-    const autoSaveKey = `${props.autoSaveKeyPrefix}.${sessionTs}`;
-    useLocalStorage(autoSaveKey, watch, setValue, props.defaultValue, 5000);
+    const session = `${props.draftSessionPrefix}.${sessionTs}`;
+    const discardDraft = useSessionDraft(session, props.draftTarget, watch, setValue, props.defaultValue, 5000);
 
     const onSubmit = async (data: any) => {
         try {
             let dataCloned = structuredClone(data); // Avoid mutating the original data
             dataCloned.note_inner = dataCloned.note_inner.replaceAll(`${window.origin}/note/`, "notegraf:/note/");
             let res = await postNote(props.endpoint, dataCloned);
-            localStorage.removeItem(autoSaveKey);
-            incrementCounter();
+            await discardDraft();
             navigate(`/note/${res["Specific"][0]}`);
         } catch (e: any) {
             setError(e);
@@ -92,6 +94,15 @@ export function NoteFormSession(props: NoteFormProps) {
                        spellCheck={true}
                        type={"text"} {...register("metadata_custom_metadata", {validate: isValidJSON})} />
             </div>
+            <div className={"flex gap-2 m-1 items-center"}>
+                <label htmlFor={"author"}>Author</label>
+                <input className={"form-input bg-transparent w-1/4"} id={"author"} placeholder={"optional"}
+                       type={"text"} {...register("metadata_author")} />
+                <label htmlFor={"message"}>Message</label>
+                <input className={"form-input bg-transparent w-full"} id={"message"}
+                       placeholder={"what changed and why"}
+                       spellCheck={true} type={"text"} {...register("metadata_message")} />
+            </div>
             <div className={"flex-1 flex justify-center min-h-0 overflow-y-auto"}>
                 <textarea required={true} autoFocus={true} id={"note_inner"}
                           className={"form-textarea bg-transparent flex-1 font-mono"}
@@ -4,6 +4,7 @@ import {useEffect, useState} from "react";
 import {renderTitle, showAgo} from "../utils";
 import {Note} from "./Note";
 import {Tags} from "./Tags";
+import {BookmarkIcon, XMarkIcon} from "@heroicons/react/24/outline";
 
 type NotesTwoPaneProps = {
     setError: any,
@@ -12,19 +13,47 @@ type NotesTwoPaneProps = {
     showAgoKey: any,
     showPrevNext: boolean,
     permaLink: boolean,
-    showingRevision: boolean
+    showingRevision: boolean,
+    labels?: types.RevisionLabel[],
+    onAddLabel?: (revision: string, label: string) => void,
+    onRemoveLabel?: (label: string) => void
+}
+
+type RevisionLabelsProps = {
+    labels: types.RevisionLabel[],
+    onRemove?: (label: string) => void
+}
+
+function RevisionLabels(props: RevisionLabelsProps) {
+    return (<div className={"flex gap-1 flex-wrap"}>{props.labels.map(l =>
+        <div key={l.label} className={"flex items-center rounded border border-sky-500"}>
+            <BookmarkIcon className={"h-[1em] w-[1em] inline"}/>
+            <p className={"select-none"}>{l.label}</p>
+            {props.onRemove &&
+                <button title={"Remove label"} onClick={(e) => {
+                    e.stopPropagation();
+                    props.onRemove?.(l.label);
+                }}>
+                    <XMarkIcon className={"h-[1em] w-[1em]"}/>
+                </button>}
+        </div>
+    )}</div>);
 }
 
 export function NotesTwoPane(props: NotesTwoPaneProps) {
     const [revisionSelected, setRevisionSelected] = useState<any>(null);
 
     useEffect(() => {
-            setRevisionSelected(props.notes[0].revision);
+            // Keep the selection when the notes are refreshed
+            setRevisionSelected((selected: any) =>
+                props.notes.some((note: types.Note) => note.revision === selected) ? selected : props.notes[0].revision);
         }, [props.notes]
     );
 
     const noteSelected = props.notes.find((note: types.Note) => note.revision === revisionSelected);
     const noteToShow = noteSelected ? noteSelected : props.notes[0];
+    const labelsOf = (revision: string) => (props.labels || []).filter(l => l.revision === revision);
+    const [newLabel, setNewLabel] = useState("");
 
     return (<div className="min-h-0 p-2 flex">
         <div className={"basis-1/3 sm:basis-1/4 md:basis-1/5 lg:basis-1/6 min-w-0 divide-y divide-neutral-500 overflow-y-auto"}>
@@ -35,11 +64,29 @@ export function NotesTwoPane(props: NotesTwoPaneProps) {
                 <p className={"truncate"}>{renderTitle(note.title)}</p>
                 <p className={"truncate"}>
                     {showAgo(props.showAgoKey(note))}
+                    {props.showingRevision && note.metadata.author && ` by ${note.metadata.author}`}
                 </p>
+                {props.showingRevision && note.metadata.message &&
+                    <p className={"truncate italic"} title={note.metadata.message}>{note.metadata.message}</p>}
                 <Tags tags={note.metadata.tags} disableLink={true}/>
+                <RevisionLabels labels={labelsOf(note.revision)} onRemove={props.onRemoveLabel}/>
             </div>))}
         </div>
         <div className={"ml-1 overflow-hidden basis-2/3 sm:basis-3/4 md:basis-4/5 lg:basis-5/6 overflow-y-auto"}>
+            {props.onAddLabel &&
+                <form className={"flex gap-2 m-1 items-center"} onSubmit={(e) => {
+                    e.preventDefault();
+                    if (newLabel.trim()) {
+                        props.onAddLabel?.(noteToShow.revision, newLabel.trim());
+                        setNewLabel("");
+                    }
+                }}>
+                    <label htmlFor={"label"}>Label</label>
+                    <input className={"form-input bg-transparent w-full"} id={"label"}
+                           placeholder={"e.g., submitted-draft"} type={"text"} value={newLabel}
+                           onChange={(e) => setNewLabel(e.target.value)}/>
+                    <input type="submit" className={"ng-button ng-button-primary"} value={"Add"}/>
+                </form>}
             <Note note={noteToShow}
                   showPrevNext={props.showPrevNext}
                   showingRevision={props.showingRevision}
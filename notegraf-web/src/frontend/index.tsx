@@ -31,6 +31,7 @@ root.render(
                                 <Route index element={<pages.NoteAppendSessions/>}/>
                                 <Route path={":sessionTs"} element={<pages.NoteAppend/>}/>
                             </Route>
+                            <Route path={"collab"} element={<pages.NoteCollab/>}/>
                             <Route path="revision">
                                 <Route index element={<pages.NoteRevisions/>}/>
                                 <Route path=":revision" element={<pages.NoteRevision/>}/>
@@ -38,6 +39,7 @@ root.render(
                         </Route>
                     </Route>
                     <Route path={"tags"} element={<pages.TagList/>}/>
+                    <Route path={"trash"} element={<pages.Trash/>}/>
                 </Route>
                 <Route
                     path="*"
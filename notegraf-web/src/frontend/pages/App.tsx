@@ -1,12 +1,15 @@
 import {Outlet} from "react-router-dom";
 import * as React from "react";
 import {NavBar} from "../components/NavBar";
+import {NoteChangesProvider} from "../utils";
 
 export function App() {
     return (
-        <div className={"h-screen bg-white dark:bg-slate-800 dark:text-white flex flex-col"}>
-            <NavBar/>
-            <Outlet/>
-        </div>
+        <NoteChangesProvider>
+            <div className={"h-screen bg-white dark:bg-slate-800 dark:text-white flex flex-col"}>
+                <NavBar/>
+                <Outlet/>
+            </div>
+        </NoteChangesProvider>
     );
-}
\ No newline at end of file
+}
@@ -0,0 +1,116 @@
+import * as React from "react";
+import {useEffect, useRef, useState} from "react";
+import {Link, useParams} from "react-router-dom";
+import {postCollabOperation} from "../api";
+import * as types from "../types";
+import {CollabClient, TextOperation} from "../utils";
+
+// Move a cursor position over an operation applied by someone else
+function transformIndex(index: number, operation: TextOperation): number {
+    let oldPos = 0, newPos = index;
+    for (const c of operation.components) {
+        if (oldPos >= index) {
+            break;
+        }
+        if (typeof c === "string") {
+            newPos += Array.from(c).length;
+        } else if (c > 0) {
+            oldPos += c;
+        } else {
+            newPos -= Math.min(-c, index - oldPos);
+            oldPos -= c;
+        }
+    }
+    return newPos;
+}
+
+// Cursor positions of a textarea are in UTF-16 code units, operations are in code points
+function toCodePoints(text: string, index: number): number {
+    return Array.from(text.slice(0, index)).length;
+}
+
+function toCodeUnits(text: string, index: number): number {
+    return Array.from(text).slice(0, index).join("").length;
+}
+
+export function NoteCollab() {
+    let {noteID} = useParams();
+    const [text, setText] = useState("");
+    const [revision, setRevision] = useState<string | null>(null);
+    const [error, setError] = useState<any>(null);
+    const client = useRef<CollabClient | null>(null);
+    const textRef = useRef("");
+    const textarea = useRef<HTMLTextAreaElement>(null);
+
+    function updateText(newText: string) {
+        textRef.current = newText;
+        setText(newText);
+    }
+
+    useEffect(() => {
+        const clientID = Math.random().toString(36).slice(2);
+        document.title = `Editing together - Notegraf`;
+
+        function send(version: number, operation: TextOperation) {
+            postCollabOperation(noteID as string, clientID, version, operation).catch(setError);
+        }
+
+        function applyRemote(operation: TextOperation) {
+            const oldText = textRef.current;
+            const newText = operation.apply(oldText);
+            const element = textarea.current;
+            const selection = element && [element.selectionStart, element.selectionEnd]
+                .map(i => toCodeUnits(newText, transformIndex(toCodePoints(oldText, i), operation)));
+            updateText(newText);
+            if (element && selection) {
+                // Keep the cursor where it was after React renders the new text
+                requestAnimationFrame(() => element.setSelectionRange(selection[0], selection[1]));
+            }
+        }
+
+        // Joining starts a session if there is none, leaving commits the note body
+        const events = new EventSource(`/api/v1/note/${noteID}/collab`);
+        events.onmessage = (message: MessageEvent) => {
+            const event: types.SessionEvent = JSON.parse(message.data);
+            if (event.type === "snapshot") {
+                // Local edits not acknowledged yet are dropped when the session starts over
+                client.current = new CollabClient(event.version, clientID, send, applyRemote);
+                setRevision(event.revision);
+                updateText(event.text);
+            } else if (client.current) {
+                client.current.receive(event.version, event.client, TextOperation.fromJSON(event.operation));
+            }
+        };
+        return () => {
+            events.close();
+            client.current = null;
+        };
+    }, [noteID]);
+
+    function handleChange(event: React.ChangeEvent<HTMLTextAreaElement>) {
+        if (!client.current) {
+            return;
+        }
+        const newText = event.currentTarget.value;
+        const operation = TextOperation.fromDiff(textRef.current, newText);
+        updateText(newText);
+        client.current.applyLocal(operation);
+    }
+
+    if (error) {
+        return (<div>{error.toString()}</div>);
+    }
+    if (revision === null) {
+        return (<div>Loading...</div>);
+    }
+
+    return (<div className="p-2 flex-1 flex flex-col min-h-0">
+        <div className={"flex flex-wrap gap-1 my-1 items-center"}>
+            <Link to={`/note/${noteID}`} className={"underline"}>Back to note</Link>
+            <span className={"text-gray-500"}>Changes are saved as revisions while editing together</span>
+        </div>
+        <textarea ref={textarea} autoFocus={true} spellCheck={true}
+                  className={"form-textarea bg-transparent flex-1 font-mono"}
+                  value={text} onChange={handleChange}></textarea>
+    </div>);
+}
@@ -1,22 +1,28 @@
 import {useParams} from "react-router-dom";
 import * as React from "react";
 import {useEffect, useState} from "react";
-import {getNote} from "../api";
+import {getNote, getSessionDraft} from "../api";
 import {NoteFormSession} from "../components/NoteFormSession";
 import {tileInTitle} from "../utils";
 import {FormSessions} from "../components/FormSessions";
 
 export function NoteEdit() {
-    let {noteID} = useParams();
+    let {noteID, sessionTs} = useParams();
     const [note, setNote] = useState<any>(null);
+    const [base, setBase] = useState<string | null>(null);
     const [error, setError] = useState<any>(null);
     const [isLoaded, setIsLoaded] = useState(false);
 
     useEffect(() => {
         async function fetchNote() {
             try {
-                const note = await getNote(noteID as string);
+                const [note, draft] = await Promise.all([
+                    getNote(noteID as string),
+                    getSessionDraft(`note.${noteID}.edit.${sessionTs}`)
+                ]);
                 setNote(note);
+                // A session picked up again is merged with what changed since it started
+                setBase(draft?.base ?? note.revision);
                 setIsLoaded(true);
             } catch (e) {
                 setError(e);
@@ -25,7 +31,7 @@ export function NoteEdit() {
         }
 
         fetchNote();
-    }, [noteID]);
+    }, [noteID, sessionTs]);
 
 
     if (!isLoaded) {
@@ -42,8 +48,9 @@ export function NoteEdit() {
             metadata_tags: note.metadata.tags.join(", "),
             metadata_custom_metadata: JSON.stringify(note.metadata.custom_metadata)
         }}
-        endpoint={`note/${note.id}/revision`}
-        autoSaveKeyPrefix={`autosave.note.${note.id}.edit`}
+        endpoint={`note/${note.id}/revision/${base}?merge=true`}
+        draftSessionPrefix={`note.${note.id}.edit`}
+        draftTarget={{noteID: note.id, revision: base as string}}
         submitText={"Update"}
         title={`Update note ${tileInTitle(note.title)} - Notegraf`}
     />);
@@ -52,6 +59,6 @@ export function NoteEdit() {
 export function NoteEditSessions() {
     let {noteID} = useParams();
 
-    return (<FormSessions keyPrefix={`autosave.note.${noteID}.edit`}
+    return (<FormSessions sessionPrefix={`note.${noteID}.edit`} noteID={noteID}
                           title={`Choose a session: update note ${noteID} - Notegraf`}/>);
 }
\ No newline at end of file
@@ -12,14 +12,15 @@ export function NoteNew() {
             metadata_custom_metadata: "{}"
         }}
         endpoint={"note"}
-        autoSaveKeyPrefix={"autosave.note.new"}
+        draftSessionPrefix={"note.new"}
+        draftTarget={{}}
         submitText={"Create"}
         title={`New note - Notegraf`}
     />);
 }
 
 export function NoteNewSessions() {
-    return (<FormSessions keyPrefix={`autosave.note.new`}
+    return (<FormSessions sessionPrefix={`note.new`}
                           title={`Choose a session: new note - Notegraf`}/>);
 }
 
@@ -34,7 +35,8 @@ export function NoteAppend() {
             metadata_custom_metadata: "{}"
         }}
         endpoint={`note/${noteID}/next`}
-        autoSaveKeyPrefix={`autosave.note.${noteID}.append`}
+        draftSessionPrefix={`note.${noteID}.append`}
+        draftTarget={{noteID}}
         submitText={"Append"}
         title={`Append note ${noteID} - Notegraf`}
     />);
@@ -42,7 +44,7 @@ export function NoteAppend() {
 
 export function NoteAppendSessions() {
     let {noteID} = useParams();
-    return (<FormSessions keyPrefix={`autosave.note.${noteID}.append`}
+    return (<FormSessions sessionPrefix={`note.${noteID}.append`} noteID={noteID}
                           title={`Choose a session: append note ${noteID} - Notegraf`}/>);
 }
 
@@ -57,7 +59,8 @@ export function NoteBranch() {
             metadata_custom_metadata: "{}"
         }}
         endpoint={`note/${noteID}/branch`}
-        autoSaveKeyPrefix={`autosave.note.${noteID}.branch`}
+        draftSessionPrefix={`note.${noteID}.branch`}
+        draftTarget={{noteID}}
         submitText={"Branch"}
         title={`Add branch ${noteID} - Notegraf`}
     />);
@@ -65,6 +68,6 @@ export function NoteBranch() {
 
 export function NoteBranchSessions() {
     let {noteID} = useParams();
-    return (<FormSessions keyPrefix={`autosave.note.${noteID}.branch`}
+    return (<FormSessions sessionPrefix={`note.${noteID}.branch`} noteID={noteID}
                           title={`Choose a session: add branch ${noteID} - Notegraf`}/>);
 }
\ No newline at end of file
@@ -1,33 +1,46 @@
 import * as React from "react";
 import {useEffect, useState} from "react";
 import {useParams} from "react-router-dom";
-import {getNoteRevisions} from "../api";
-import {tileInTitle} from "../utils";
+import {addLabel, getNoteRevisions, listLabels, removeLabel} from "../api";
+import {changedNoteID, tileInTitle, useNoteChanges} from "../utils";
 import * as types from "../types";
 import {NotesTwoPane} from "../components/NotesTwoPane";
 
 export function NoteRevisions() {
     let {noteID} = useParams();
     const [notes, setNotes] = useState<any>(null);
+    const [labels, setLabels] = useState<types.RevisionLabel[]>([]);
     const [error, setError] = useState<any>(null);
     const [isLoaded, setIsLoaded] = useState(false);
 
-    useEffect(() => {
-        async function fetchNoteRevisions() {
-            try {
-                const notes = await getNoteRevisions(noteID as string);
-                setNotes(notes);
-                setIsLoaded(true);
-                document.title = `${tileInTitle(notes[notes.length - 1].title)} (revisions) - Notegraf`;
-            } catch (e) {
-                setError(e);
-                setIsLoaded(true);
-            }
+    async function fetchNoteRevisions() {
+        try {
+            const notes = await getNoteRevisions(noteID as string);
+            setNotes(notes);
+            setLabels(await listLabels(noteID as string));
+            setIsLoaded(true);
+            document.title = `${tileInTitle(notes[notes.length - 1].title)} (revisions) - Notegraf`;
+        } catch (e) {
+            setError(e);
+            setIsLoaded(true);
         }
+    }
 
+    useEffect(() => {
         fetchNoteRevisions();
     }, [noteID]);
 
+    useNoteChanges(fetchNoteRevisions, (change) => changedNoteID(change) === noteID);
+
+    const updateLabels = async (update: () => Promise<void>) => {
+        try {
+            await update();
+            setLabels(await listLabels(noteID as string));
+        } catch (e) {
+            setError(e);
+        }
+    };
+
     if (!isLoaded) {
         return (<div>Loading...</div>);
     }
@@ -44,5 +57,10 @@ export function NoteRevisions() {
         showPrevNext={false}
         showingRevision={true}
         permaLink={true}
+        labels={labels}
+        onAddLabel={(revision: string, label: string) =>
+            updateLabels(() => addLabel(noteID as string, revision, label))}
+        onRemoveLabel={(label: string) =>
+            updateLabels(() => removeLabel(noteID as string, label))}
     />);
 }
\ No newline at end of file
@@ -1,26 +1,16 @@
 import * as React from "react";
 import {useEffect, useState} from "react";
 import {useParams, useSearchParams} from "react-router-dom";
-import {getNote} from "../api";
+import {getNote, getSequence} from "../api";
 import {Note} from "../components/Note";
 import * as types from "../types";
-import {tileInTitle} from "../utils";
+import {tileInTitle, useNoteChanges} from "../utils";
 
 async function fetchNoteSequence(anchorNoteID: string, recursiveLoad: boolean): Promise<types.Note[]> {
-    let notes: types.Note[] = [];
-    let anchorNote = await getNote(anchorNoteID);
-    notes.push(anchorNote);
     if (recursiveLoad) {
-        while (notes[0].prev != null) {
-            let note = await getNote(notes[0].prev);
-            notes = [note, ...notes];
-        }
-        while (notes[notes.length - 1].next != null) {
-            let note = await getNote(notes[notes.length - 1].next as string);
-            notes.push(note);
-        }
+        return getSequence(anchorNoteID);
     }
-    return notes;
+    return [await getNote(anchorNoteID)];
 }
 
 export function NoteSequence() {
@@ -48,6 +38,9 @@ export function NoteSequence() {
         fetchNoteSequenceInner();
     }, [noteID, recursiveLoad]);
 
+    // Branches, backlinks and the rest of the sequence may change with any note
+    useNoteChanges(fetchNoteSequenceInner);
+
     function handleCheckbox(event: React.FormEvent<HTMLInputElement>) {
         const checked = event.currentTarget.checked;
         setSearchParams({
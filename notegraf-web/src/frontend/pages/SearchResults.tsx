@@ -4,6 +4,7 @@ import {useSearchParams} from "react-router-dom";
 import {searchNotes} from "../api";
 import * as types from "../types";
 import {NotesTwoPane} from "../components/NotesTwoPane";
+import {useNoteChanges} from "../utils";
 
 export function SearchResults() {
     let [searchParams, _setSearchParams] = useSearchParams();
@@ -39,6 +40,8 @@ export function SearchResults() {
         fetchSearch();
     }, [searchParams]);
 
+    useNoteChanges(fetchSearch);
+
     if (!isLoaded) {
         return (<div>Loading...</div>);
     }
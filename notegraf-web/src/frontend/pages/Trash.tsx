@@ -0,0 +1,82 @@
+import * as React from "react";
+import {useEffect, useState} from "react";
+import {useNavigate} from "react-router-dom";
+import {listDeleted, purgeNote, restoreDeleted} from "../api";
+import * as types from "../types";
+import {renderTitle, showAgo} from "../utils";
+
+export function Trash() {
+    const [deleted, setDeleted] = useState<types.DeletedNote[]>([]);
+    const [error, setError] = useState<any>(null);
+    const [isLoaded, setIsLoaded] = useState(false);
+    const navigate = useNavigate();
+
+    async function fetchDeleted() {
+        try {
+            const deleted = await listDeleted();
+            setError(null);
+            setDeleted(deleted);
+            setIsLoaded(true);
+            document.title = `Trash - Notegraf`;
+        } catch (e) {
+            setError(e);
+            setIsLoaded(true);
+        }
+    }
+
+    async function restore(noteID: string) {
+        try {
+            await restoreDeleted(noteID);
+            navigate(`/note/${noteID}`);
+        } catch (e) {
+            setError(e);
+        }
+    }
+
+    async function purge(noteID: string) {
+        if (!window.confirm("Are you sure you want to permanently remove this note and all its revisions?")) {
+            return;
+        }
+        try {
+            await purgeNote(noteID);
+            await fetchDeleted();
+        } catch (e) {
+            setError(e);
+        }
+    }
+
+    useEffect(() => {
+        fetchDeleted();
+    }, []);
+
+    if (!isLoaded) {
+        return (<div>Loading...</div>);
+    }
+    if (error) {
+        return (<div>{error.toString()}</div>);
+    }
+
+    return (<div className={"p-2 min-h-0 overflow-y-auto"}>
+        <h1 className={"text-4xl mb-2"}>Trash</h1>
+        {deleted.length == 0 && (<p>No deleted notes.</p>)}
+        <ul>
+            {deleted.map(d => (
+                <li key={d.note.id} className={"flex gap-2 items-center mb-1"}>
+                    <button className={"ng-button ng-button-primary"} onClick={() => restore(d.note.id)}>
+                        Restore
+                    </button>
+                    <button className={"ng-button ng-button-danger"} onClick={() => purge(d.note.id)}>
+                        Purge
+                    </button>
+                    <a className={"min-w-0 truncate underline"}
+                       href={`/note/${d.note.id}/revision/${d.note.revision}`}>
+                        {renderTitle(d.note.title)}
+                    </a>
+                    <p className={"text-gray-500"} title={d.deleted_at}>
+                        Deleted {showAgo(new Date(d.deleted_at))}
+                    </p>
+                </li>
+            ))}
+        </ul>
+    </div>);
+}
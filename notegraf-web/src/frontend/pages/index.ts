@@ -1,4 +1,5 @@
 export * from "./App";
+export * from "./NoteCollab";
 export * from "./NoteEditSessions";
 export * from "./NoteNewSessions";
 export * from "./NoteRevision";
@@ -6,4 +7,5 @@ export * from "./NoteRevisions";
 export * from "./NoteSequence";
 export * from "./NoteTop";
 export * from "./SearchResults";
-export * from "./TagList";
\ No newline at end of file
+export * from "./TagList";
+export * from "./Trash";
\ No newline at end of file
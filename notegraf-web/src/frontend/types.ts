@@ -3,7 +3,9 @@ export type NoteMetadata = {
     created_at: string,
     modified_at: string,
     tags: string[],
-    custom_metadata: any
+    custom_metadata: any,
+    author: string | null,
+    message: string | null
 }
 
 export type Note = {
@@ -17,5 +19,49 @@ export type Note = {
     next: string | null,
     references: string[],
     referents: string[],
-    metadata: NoteMetadata
+    metadata: NoteMetadata,
+    based_on: string | null,
+    is_draft: boolean
+}
+
+export type Ancestor = {
+    link: "Sequence" | "Branch",
+    note: Note
+}
+
+export type RevisionLabel = {
+    label: string,
+    revision: string
+}
+
+export type DeletedNote = {
+    deleted_at: string,
+    note: Note
+}
+
+export type Change = {
+    cursor: number,
+    kind: "create" | "update" | "delete" | "append" | "branch",
+    loc: { Specific: string[] } | { Current: string },
+    changed_at: string
+}
+
+export type SessionEvent = {
+    type: "snapshot",
+    version: number,
+    revision: string,
+    text: string
+} | {
+    type: "operation",
+    version: number,
+    client: string | null,
+    operation: (number | string)[]
+}
+
+export type SessionDraft = {
+    session: string,
+    id: string | null,
+    base: string | null,
+    content: any,
+    saved_at: string
 }
@@ -1,54 +1,72 @@
 import {debounce} from "lodash";
-import * as React from "react";
-import {useEffect, useState} from "react";
-
-let autoSaveCounter = 0;
-
-export function getStorageValue(key: string, defaultValue: any): any {
-    const saved = localStorage.getItem(key);
-    if (saved) {
-        const initial = JSON.parse(saved);
-        return initial || defaultValue;
-    } else {
-        return defaultValue
-    }
-}
+import {useEffect, useMemo, useRef, useState} from "react";
+import {deleteSessionDraft, getSessionDraft, putSessionDraft} from "../api";
 
-export function incrementCounter() {
-    autoSaveCounter++;
+// Where a draft will be published, e.g., the note being updated and the revision it is based on
+export type DraftTarget = {
+    noteID?: string,
+    revision?: string
 }
 
-export function useLocalStorage(key: string, watch: any, setValue: any, defaultValue: any, debounceMS: number): any {
-    const [value, _] = useState(() => {
-        return getStorageValue(key, defaultValue);
-    });
+// Keep the values of a form in the draft of an editor session on the server, so that the session
+// can be picked up again on another device
+// Returns a function to discard the draft once it is published
+export function useSessionDraft(session: string, target: DraftTarget, watch: any, setValue: any, defaultValue: any, debounceMS: number): () => Promise<void> {
+    const [loaded, setLoaded] = useState(false);
+    const lastSaved = useRef<string | null>(null);
 
-    const watchedValues = watch();
-    const currentCounter = autoSaveCounter;
-
-    useEffect(debounce(() => {
-        if (autoSaveCounter > currentCounter) {
-            return;
-        }
-        localStorage.setItem(key, JSON.stringify(watchedValues));
-    }, debounceMS), [key, watchedValues]);
+    const save = useMemo(() => debounce((values: any) => {
+        putSessionDraft(session, values, target.noteID, target.revision).catch(console.error);
+    }, debounceMS), [session, target.noteID, target.revision, debounceMS]);
 
     useEffect(() => {
-            Object.keys(value).forEach((key) => {
-                setValue(key, value[key], {
+        let cancelled = false;
+        getSessionDraft(session).catch(e => {
+            console.error(e);
+            return null;
+        }).then(draft => {
+            if (cancelled) {
+                return;
+            }
+            const values = draft ? draft.content : defaultValue;
+            Object.keys(values).forEach((key) => {
+                setValue(key, values[key], {
                     shouldValidate: true,
                     shouldDirty: true,
                     shouldTouch: true
                 })
-            })
-        },
-        []
-    );
-    return null;
-}
+            });
+            lastSaved.current = null;
+            setLoaded(true);
+        });
+        return () => {
+            cancelled = true;
+        };
+    }, [session]);
 
-export function getKeysByPrefix(prefix: string): string[] {
-    let keys = Object.keys(localStorage).filter(x => x.startsWith(prefix));
-    keys.sort();
-    return keys;
-}
\ No newline at end of file
+    const watchedValues = JSON.stringify(watch());
+    useEffect(() => {
+        if (!loaded) {
+            return;
+        }
+        // Opening a session doesn't save it, only editing does
+        if (lastSaved.current === null) {
+            lastSaved.current = watchedValues;
+        } else if (watchedValues !== lastSaved.current) {
+            lastSaved.current = watchedValues;
+            save(JSON.parse(watchedValues));
+        }
+    }, [loaded, watchedValues]);
+
+    // Save what is pending when leaving the page
+    useEffect(() => () => {
+        save.flush();
+    }, [save]);
+
+    async function discard() {
+        save.cancel();
+        await deleteSessionDraft(session);
+    }
+
+    return discard;
+}
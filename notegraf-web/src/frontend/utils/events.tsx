@@ -0,0 +1,59 @@
+import {debounce} from "lodash";
+import * as React from "react";
+import {createContext, useContext, useEffect, useRef} from "react";
+import * as types from "../types";
+
+export function changedNoteID(change: types.Change): string {
+    return "Specific" in change.loc ? change.loc.Specific[0] : change.loc.Current;
+}
+
+type ChangeListener = (change: types.Change) => void;
+
+const NoteChangesContext = createContext<Set<ChangeListener> | null>(null);
+
+// Share one stream of changes between all components of a tab
+export function NoteChangesProvider({children}: { children: React.ReactNode }) {
+    const listeners = useRef(new Set<ChangeListener>()).current;
+
+    useEffect(() => {
+        // EventSource reconnects by itself, resuming after the last change received
+        const events = new EventSource("/api/v1/events");
+        events.onmessage = (event: MessageEvent) => {
+            const change: types.Change = JSON.parse(event.data);
+            listeners.forEach((listener) => listener(change));
+        };
+        return () => events.close();
+    }, [listeners]);
+
+    return (
+        <NoteChangesContext.Provider value={listeners}>
+            {children}
+        </NoteChangesContext.Provider>
+    );
+}
+
+// Call onChange when notes are changed, e.g., from another tab or device
+// A burst of changes results in a single call
+export function useNoteChanges(onChange: () => void, filter?: (change: types.Change) => boolean, debounceMS = 500) {
+    const listeners = useContext(NoteChangesContext);
+    if (!listeners) {
+        throw new Error("useNoteChanges must be used within a NoteChangesProvider");
+    }
+    const latest = useRef({onChange, filter});
+    latest.current = {onChange, filter};
+
+    useEffect(() => {
+        const refresh = debounce(() => latest.current.onChange(), debounceMS);
+        const listener = (change: types.Change) => {
+            const filter = latest.current.filter;
+            if (!filter || filter(change)) {
+                refresh();
+            }
+        };
+        listeners.add(listener);
+        return () => {
+            listeners.delete(listener);
+            refresh.cancel();
+        };
+    }, [listeners, debounceMS]);
+}
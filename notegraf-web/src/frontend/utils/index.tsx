@@ -2,6 +2,8 @@ import * as React from "react";
 
 export * from "./autosave";
 export * from "./datetime";
+export * from "./events";
+export * from "./ot";
 
 export function renderTitle(title: string) {
     let className = title ? "" : "italic text-gray-500";
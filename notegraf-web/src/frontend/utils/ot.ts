@@ -0,0 +1,291 @@
+// Operational transformation of plain text, the client side of notegraf::ot and the collaborative
+// editing sessions of the web server
+// Operations are serialized the same way as ot.js: a positive number retains, a negative number
+// deletes, and a string inserts. Lengths are in code points, like Rust chars.
+
+export type Component = number | string;
+
+function codePoints(s: string): string[] {
+    return Array.from(s);
+}
+
+function isRetain(c: Component | undefined): boolean {
+    return typeof c === "number" && c > 0;
+}
+
+function isDelete(c: Component | undefined): boolean {
+    return typeof c === "number" && c < 0;
+}
+
+function isInsert(c: Component | undefined): c is string {
+    return typeof c === "string";
+}
+
+function componentLength(c: Component): number {
+    return isInsert(c) ? codePoints(c).length : Math.abs(c);
+}
+
+export class TextOperation {
+    components: Component[] = [];
+    baseLength = 0;
+    targetLength = 0;
+
+    static fromJSON(components: Component[]): TextOperation {
+        const op = new TextOperation();
+        for (const c of components) {
+            if (isRetain(c)) {
+                op.retain(c as number);
+            } else if (isDelete(c)) {
+                op.delete(-(c as number));
+            } else if (isInsert(c)) {
+                op.insert(c);
+            } else {
+                throw new Error("Invalid component of an operation");
+            }
+        }
+        return op;
+    }
+
+    toJSON(): Component[] {
+        return this.components;
+    }
+
+    isNoop(): boolean {
+        return this.components.every(isRetain);
+    }
+
+    retain(n: number): TextOperation {
+        if (n === 0) {
+            return this;
+        }
+        this.baseLength += n;
+        this.targetLength += n;
+        const last = this.components.length - 1;
+        if (isRetain(this.components[last])) {
+            this.components[last] = (this.components[last] as number) + n;
+        } else {
+            this.components.push(n);
+        }
+        return this;
+    }
+
+    insert(s: string): TextOperation {
+        if (s === "") {
+            return this;
+        }
+        this.targetLength += codePoints(s).length;
+        // Inserts always go before deletes at the same position, as on the server
+        const at = isDelete(this.components[this.components.length - 1]) ? this.components.length - 1 : this.components.length;
+        if (isInsert(this.components[at - 1])) {
+            this.components[at - 1] = (this.components[at - 1] as string) + s;
+        } else {
+            this.components.splice(at, 0, s);
+        }
+        return this;
+    }
+
+    delete(n: number): TextOperation {
+        if (n === 0) {
+            return this;
+        }
+        this.baseLength += n;
+        const last = this.components.length - 1;
+        if (isDelete(this.components[last])) {
+            this.components[last] = (this.components[last] as number) - n;
+        } else {
+            this.components.push(-n);
+        }
+        return this;
+    }
+
+    // An operation that turns oldText into newText, replacing what is between their common prefix and suffix
+    static fromDiff(oldText: string, newText: string): TextOperation {
+        const oldChars = codePoints(oldText);
+        const newChars = codePoints(newText);
+        let prefix = 0;
+        while (prefix < oldChars.length && prefix < newChars.length && oldChars[prefix] === newChars[prefix]) {
+            prefix++;
+        }
+        let suffix = 0;
+        while (suffix < oldChars.length - prefix && suffix < newChars.length - prefix
+        && oldChars[oldChars.length - 1 - suffix] === newChars[newChars.length - 1 - suffix]) {
+            suffix++;
+        }
+        return new TextOperation()
+            .retain(prefix)
+            .insert(newChars.slice(prefix, newChars.length - suffix).join(""))
+            .delete(oldChars.length - suffix - prefix)
+            .retain(suffix);
+    }
+
+    apply(text: string): string {
+        const chars = codePoints(text);
+        if (chars.length !== this.baseLength) {
+            throw new Error(`Operation expects a text of length ${this.baseLength}, not ${chars.length}`);
+        }
+        const result: string[] = [];
+        let i = 0;
+        for (const c of this.components) {
+            if (isInsert(c)) {
+                result.push(c);
+            } else if (isRetain(c)) {
+                result.push(...chars.slice(i, i + (c as number)));
+                i += c as number;
+            } else {
+                i -= c as number;
+            }
+        }
+        return result.join("");
+    }
+
+    // Compose this operation with one applied after it, into a single operation
+    compose(other: TextOperation): TextOperation {
+        if (this.targetLength !== other.baseLength) {
+            throw new Error("Operations can't be composed");
+        }
+        const result = new TextOperation();
+        const a = this.components.slice();
+        const b = other.components.slice();
+        let i = 0, j = 0;
+        let ac = a[i++], bc = b[j++];
+        while (ac !== undefined || bc !== undefined) {
+            if (isDelete(ac)) {
+                result.delete(-(ac as number));
+                ac = a[i++];
+                continue;
+            }
+            if (isInsert(bc)) {
+                result.insert(bc);
+                bc = b[j++];
+                continue;
+            }
+            if (ac === undefined || bc === undefined) {
+                throw new Error("Operations can't be composed");
+            }
+            const n = Math.min(componentLength(ac), componentLength(bc));
+            if (isRetain(bc)) {
+                if (isRetain(ac)) {
+                    result.retain(n);
+                } else {
+                    result.insert(codePoints(ac as string).slice(0, n).join(""));
+                }
+            } else if (isRetain(ac)) {
+                result.delete(n);
+            }
+            // An insert then deleted by the other operation leaves nothing
+            ac = shorten(ac, n) ?? a[i++];
+            bc = shorten(bc, n) ?? b[j++];
+        }
+        return result;
+    }
+
+    // Transform two concurrent operations into [a', b'], so that applying a then b' gives the
+    // same text as applying b then a'. When both insert at the same position, a goes first.
+    static transform(a: TextOperation, b: TextOperation): [TextOperation, TextOperation] {
+        if (a.baseLength !== b.baseLength) {
+            throw new Error("Operations can't be transformed");
+        }
+        const aPrime = new TextOperation();
+        const bPrime = new TextOperation();
+        let i = 0, j = 0;
+        let ac = a.components[i++], bc = b.components[j++];
+        while (ac !== undefined || bc !== undefined) {
+            if (isInsert(ac)) {
+                aPrime.insert(ac);
+                bPrime.retain(codePoints(ac).length);
+                ac = a.components[i++];
+                continue;
+            }
+            if (isInsert(bc)) {
+                aPrime.retain(codePoints(bc).length);
+                bPrime.insert(bc);
+                bc = b.components[j++];
+                continue;
+            }
+            if (ac === undefined || bc === undefined) {
+                throw new Error("Operations can't be transformed");
+            }
+            const n = Math.min(componentLength(ac), componentLength(bc));
+            if (isRetain(ac) && isRetain(bc)) {
+                aPrime.retain(n);
+                bPrime.retain(n);
+            } else if (isDelete(ac) && isRetain(bc)) {
+                aPrime.delete(n);
+            } else if (isRetain(ac) && isDelete(bc)) {
+                bPrime.delete(n);
+            }
+            // Both deleted the same characters otherwise
+            ac = shorten(ac, n) ?? a.components[i++];
+            bc = shorten(bc, n) ?? b.components[j++];
+        }
+        return [aPrime, bPrime];
+    }
+}
+
+// The rest of a component after n characters, if any
+function shorten(c: Component, n: number): Component | undefined {
+    if (isInsert(c)) {
+        const rest = codePoints(c).slice(n).join("");
+        return rest === "" ? undefined : rest;
+    }
+    if (isRetain(c)) {
+        return (c as number) > n ? (c as number) - n : undefined;
+    }
+    return -(c as number) > n ? (c as number) + n : undefined;
+}
+
+// The client of a collaborative editing session, which sends one operation at a time and buffers
+// the rest until the server acknowledges it, like ot.js
+export class CollabClient {
+    version: number;
+    // The operation sent and not yet acknowledged
+    outstanding: TextOperation | null = null;
+    // Local operations made while waiting for the acknowledgement
+    buffer: TextOperation | null = null;
+
+    constructor(version: number,
+                readonly id: string,
+                readonly send: (version: number, operation: TextOperation) => void,
+                readonly applyRemote: (operation: TextOperation) => void) {
+        this.version = version;
+    }
+
+    applyLocal(operation: TextOperation) {
+        if (operation.isNoop()) {
+            return;
+        }
+        if (this.outstanding === null) {
+            this.outstanding = operation;
+            this.send(this.version, operation);
+        } else if (this.buffer === null) {
+            this.buffer = operation;
+        } else {
+            this.buffer = this.buffer.compose(operation);
+        }
+    }
+
+    // Handle an operation event of the session, which turned version - 1 into version
+    receive(version: number, client: string | null, operation: TextOperation) {
+        if (version <= this.version) {
+            return;
+        }
+        this.version = version;
+        if (client === this.id) {
+            // The acknowledgement of the outstanding operation
+            this.outstanding = this.buffer;
+            this.buffer = null;
+            if (this.outstanding !== null) {
+                this.send(this.version, this.outstanding);
+            }
+            return;
+        }
+        let remote = operation;
+        if (this.outstanding !== null) {
+            [this.outstanding, remote] = TextOperation.transform(this.outstanding, remote);
+        }
+        if (this.buffer !== null) {
+            [this.buffer, remote] = TextOperation.transform(this.buffer, remote);
+        }
+        this.applyRemote(remote);
+    }
+}
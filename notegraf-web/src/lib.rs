@@ -3,7 +3,11 @@ extern crate tracing;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod collab;
+pub mod compaction;
 pub mod configuration;
+pub mod events;
+pub mod migration;
 pub mod routes;
 pub mod startup;
 pub mod telemetry;
@@ -28,8 +28,11 @@ async fn main() -> std::io::Result<()> {
         listener,
         CONFIGURATION
             .get_note_store(false, LevelFilter::Debug)
-            .await,
+            .await
+            .map_err(std::io::Error::other)?,
         CONFIGURATION.debug,
+        CONFIGURATION.retention.as_ref(),
+        &CONFIGURATION.collab,
     )?
     .await?;
     opentelemetry::global::shutdown_tracer_provider();
@@ -0,0 +1,44 @@
+use notegraf_web::configuration::{NoteStoreType, CONFIGURATION};
+use notegraf_web::migration::{migrate, migrate_archive};
+use notegraf_web::telemetry::{get_subscriber, init_tracing};
+use std::fs::File;
+use std::io::BufReader;
+use tracing::log::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Copy all notes to the note store configured under the `migration` key, from either another
+/// note store or an archive.
+#[tokio::main]
+async fn main() {
+    init_tracing(get_subscriber(&CONFIGURATION).with(tracing_subscriber::fmt::Layer::default()));
+    let settings = CONFIGURATION
+        .migration
+        .as_ref()
+        .expect("You must configure the keys under migration");
+    let destination = settings
+        .destination
+        .get_note_store(false, LevelFilter::Debug)
+        .await
+        .expect("Failed to open the destination");
+    let remap_ids = settings.destination.notestoretype == NoteStoreType::PostgreSQL;
+    let res = match (&settings.source, &settings.archive) {
+        (Some(source), None) => {
+            assert!(
+                source.notestoretype != NoteStoreType::InMemory || source.populatetestdata,
+                "A new in-memory note store is empty, so export the notes of the running server \
+                 with GET /api/v1/export and set migration.archive instead"
+            );
+            let source = source
+                .get_note_store(false, LevelFilter::Debug)
+                .await
+                .expect("Failed to open the source");
+            migrate(&source, &destination, remap_ids).await
+        }
+        (None, Some(archive)) => {
+            let archive = File::open(archive).expect("Failed to open the archive");
+            migrate_archive(BufReader::new(archive), &destination, remap_ids).await
+        }
+        _ => panic!("You must configure either migration.source or migration.archive"),
+    };
+    res.expect("Failed to migrate notes");
+}
@@ -0,0 +1,61 @@
+//! Copying the full history from one note store to another.
+use crate::NoteType;
+use futures::{stream, StreamExt};
+use notegraf::errors::NoteStoreError;
+use notegraf::notestore::interchange::{export_records, read_records, RecordStream, UuidRemapper};
+use notegraf::notestore::BoxedNoteStore;
+use std::io::BufRead;
+
+/// Copy every revision of every note from `source` to the empty `destination`.
+///
+/// Revisions and timestamps are kept.
+/// If `remap_ids` is set, IDs that are not UUIDs are replaced by new UUIDs (and links to them
+/// are rewritten), which is needed when the destination is PostgreSQL.
+///
+/// Notes are streamed from the source to the destination, and imported as a whole with
+/// [`NoteStore::import_notes`], so a failed migration leaves the destination empty and can be
+/// retried.
+///
+/// [`NoteStore::import_notes`]: notegraf::NoteStore::import_notes
+pub async fn migrate(
+    source: &BoxedNoteStore<NoteType>,
+    destination: &BoxedNoteStore<NoteType>,
+    remap_ids: bool,
+) -> Result<(), NoteStoreError> {
+    if !destination.get_note_ids().await?.is_empty() {
+        return Err(NoteStoreError::StoreNotEmpty);
+    }
+    let records = export_records(source.as_ref()).await?;
+    import_records(records, destination, remap_ids).await
+}
+
+/// Copy every revision of every note in an archive to the empty `destination`, like [`migrate`].
+///
+/// The archive can be a backup, or an export of a running server (`GET /api/v1/export`).
+pub async fn migrate_archive<R: BufRead + Send>(
+    archive: R,
+    destination: &BoxedNoteStore<NoteType>,
+    remap_ids: bool,
+) -> Result<(), NoteStoreError> {
+    if !destination.get_note_ids().await?.is_empty() {
+        return Err(NoteStoreError::StoreNotEmpty);
+    }
+    let records = stream::iter(read_records(archive)?).boxed();
+    import_records(records, destination, remap_ids).await
+}
+
+async fn import_records(
+    records: RecordStream<'_>,
+    destination: &BoxedNoteStore<NoteType>,
+    remap_ids: bool,
+) -> Result<(), NoteStoreError> {
+    let records = if remap_ids {
+        let mut remapper = UuidRemapper::new();
+        records
+            .map(move |r| r.and_then(|r| remapper.remap::<NoteType>(r)))
+            .boxed()
+    } else {
+        records
+    };
+    destination.import_notes(records).await
+}
@@ -1,28 +1,56 @@
+use crate::collab::{CollabError, CollabSessions};
+use crate::events::ChangeFeed;
 use crate::NoteType;
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ETag, EntityTag, Header, IfMatch, IF_MATCH,
+};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::{future, stream, StreamExt};
+use notegraf::diff::diff_revisions;
 use notegraf::errors::NoteStoreError;
+use notegraf::merge::{update_note_merging, MergeOutcome};
 use notegraf::notemetadata::NoteMetadataEditable;
-use notegraf::notestore::BoxedNoteStore;
-use notegraf::{NoteLocator, NoteSerializable};
-use serde::Deserialize;
+use notegraf::notestore::interchange::{export_records, write_header, write_record};
+use notegraf::notestore::{
+    restore_deleted, BoxedNoteStore, Change, ChangeCursor, Link, MoveTarget, SplitTail,
+};
+use notegraf::ot::TextOperation;
+use notegraf::{NoteLocator, NoteSerializable, Revision};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
 
 fn notestore_error_handler(e: &NoteStoreError) -> HttpResponse {
     match e {
         NoteStoreError::NoteNotExist(_) => HttpResponse::NotFound().body(e.to_string()),
         NoteStoreError::NoteIDConflict(_) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::RevisionNotExist(_, _) => HttpResponse::NotFound().body(e.to_string()),
+        NoteStoreError::NoteNotExistAtTime(_, _) => HttpResponse::NotFound().body(e.to_string()),
         NoteStoreError::IOError(_) => {
             error!("Note store internal error {:?}", e);
             HttpResponse::InternalServerError().finish()
         }
         NoteStoreError::SerdeError(_) => HttpResponse::BadRequest().body(e.to_string()),
         NoteStoreError::UpdateOldRevision(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::NotADraft(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::DeleteOldRevision(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::NotAChild(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::ExistingNext(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::ExistingPrev(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::SplitAtHead(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::WouldCreateCycle(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::NotDeleted(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::PurgeCurrentRevision(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::LabelExists(_, _, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::LabelNotExist(_, _) => HttpResponse::NotFound().body(e.to_string()),
+        NoteStoreError::SessionDraftNotExist(_) => HttpResponse::NotFound().body(e.to_string()),
         NoteStoreError::HasBranches(_) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::HasReferences(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::LinkedInHistory(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::ParseError(_) => HttpResponse::BadRequest().body(e.to_string()),
         NoteStoreError::PostgreSQLError(_) => {
             error!("Note store internal error {:?}", e);
@@ -30,6 +58,71 @@ fn notestore_error_handler(e: &NoteStoreError) -> HttpResponse {
         }
         NoteStoreError::NoteInnerError(_) => HttpResponse::BadRequest().body(e.to_string()),
         NoteStoreError::NotUuid(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::StoreNotEmpty => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::Unsupported(_) => HttpResponse::NotImplemented().body(e.to_string()),
+        NoteStoreError::UnsupportedFormat(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::InvalidImport(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::StoreChanged(_) => HttpResponse::Conflict().body(e.to_string()),
+    }
+}
+
+/// The revision of a note as an entity tag.
+fn revision_etag(revision: &Revision) -> ETag {
+    ETag(EntityTag::new_strong(revision.as_ref().to_owned()))
+}
+
+/// The locator of a note to be changed, as constrained by the `If-Match` header.
+///
+/// Without the header (or with `If-Match: *`), the current revision is changed.
+/// Otherwise, the current revision must be one of the listed revisions (weak tags never match).
+/// The matching revision is put in the locator, so [`NoteStore::update_note`] and
+/// [`NoteStore::delete_note`] check again that it is still current in the same transaction as
+/// the change.
+///
+/// [`NoteStore::update_note`]: notegraf::NoteStore::update_note
+/// [`NoteStore::delete_note`]: notegraf::NoteStore::delete_note
+async fn if_match_locator(
+    req: &HttpRequest,
+    store: &BoxedNoteStore<NoteType>,
+    note_id: String,
+) -> Result<NoteLocator, HttpResponse> {
+    let loc = NoteLocator::Current(note_id.into());
+    if !req.headers().contains_key(IF_MATCH) {
+        return Ok(loc);
+    }
+    let if_match =
+        IfMatch::parse(req).map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+    let tags = match if_match {
+        IfMatch::Any => return Ok(loc),
+        IfMatch::Items(tags) => tags,
+    };
+    // If-Match uses the strong comparison, so weak tags never match
+    let tags: Vec<EntityTag> = tags.into_iter().filter(|t| !t.weak).collect();
+    if tags.is_empty() {
+        return Err(HttpResponse::PreconditionFailed().finish());
+    }
+    // Only the current revision matches, so a tag of an old revision or a draft fails here
+    // rather than updating that revision
+    let current = store
+        .get_current_revision(&loc)
+        .await
+        .map_err(|e| notestore_error_handler(&e))?;
+    match current {
+        Some(r) if tags.iter().any(|t| t.tag() == r.as_ref()) => Ok(loc.at_revision(&r)),
+        _ => Err(HttpResponse::PreconditionFailed().finish()),
+    }
+}
+
+/// Like [`notestore_error_handler`], but changes to a revision that is no longer current (or
+/// never existed) fail the `If-Match` precondition.
+fn precondition_error_handler(e: &NoteStoreError) -> HttpResponse {
+    match e {
+        NoteStoreError::UpdateOldRevision(_, _)
+        | NoteStoreError::DeleteOldRevision(_, _)
+        | NoteStoreError::RevisionNotExist(_, _) => {
+            HttpResponse::PreconditionFailed().body(e.to_string())
+        }
+        _ => notestore_error_handler(e),
     }
 }
 
@@ -39,25 +132,52 @@ async fn get_note_by_locator(
 ) -> impl Responder {
     let result = store.as_ref().get_note(loc).await;
     match result {
-        Ok(note) => HttpResponse::Ok().json(NoteSerializable::all_fields(note)),
+        Ok(note) => HttpResponse::Ok()
+            .insert_header(revision_etag(&note.get_revision()))
+            .json(NoteSerializable::all_fields(note)),
         Err(e) => notestore_error_handler(&e),
     }
 }
 
 #[delete("/note/{note_id}")]
 #[instrument(
-    skip(store, params),
+    skip(req, store, params),
     fields(
         note_id = %params.0
     )
 )]
 async fn delete_note_current(
+    req: HttpRequest,
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
+    let loc = match if_match_locator(&req, &store, note_id).await {
+        Ok(loc) => loc,
+        Err(response) => return response,
+    };
     let res = store.delete_note(&loc).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => precondition_error_handler(&e),
+    }
+}
+
+#[delete("/note/{note_id}/revision/{revision_id}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1
+    )
+)]
+async fn purge_revision(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
+    let res = store.purge_revision(&loc).await;
     match res {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => notestore_error_handler(&e),
@@ -81,12 +201,113 @@ async fn get_note_specific(
     get_note_by_locator(store, &loc).await
 }
 
+#[post("/note/{note_id}/revision/{revision_id}/revert")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1
+    )
+)]
+async fn revert_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
+    let res = store.revert_note(&loc).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/note/{note_id}/label")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn list_labels(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.list_labels(&note_id.into()).await;
+    match res {
+        Ok(labels) => HttpResponse::Ok().json(labels),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/note/{note_id}/label/{label}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        label = %params.1
+    )
+)]
+async fn get_note_by_label(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, label) = params.into_inner();
+    let loc = NoteLocator::Label(note_id.into(), label);
+    get_note_by_locator(store, &loc).await
+}
+
+#[post("/note/{note_id}/revision/{revision_id}/label/{label}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1,
+        label = %params.2
+    )
+)]
+async fn add_label(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id, label) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
+    let res = store.add_label(&loc, &label).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[delete("/note/{note_id}/label/{label}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        label = %params.1
+    )
+)]
+async fn remove_label(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, label) = params.into_inner();
+    let res = store.remove_label(&note_id.into(), &label).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
 #[derive(Deserialize)]
 struct NotePostData {
     title: String,
     note_inner: String,
     metadata_tags: String,
     metadata_custom_metadata: String,
+    metadata_author: Option<String>,
+    metadata_message: Option<String>,
 }
 
 struct NoteStoreEditArgument {
@@ -107,12 +328,19 @@ impl TryFrom<NotePostData> for NoteStoreEditArgument {
                 .map(|tag| tag.trim().to_owned())
                 .filter(|tag| !tag.is_empty()),
         );
+        // Blank fields of the form are not recorded
+        let non_empty = |s: Option<String>| {
+            s.map(|s| s.trim().to_owned())
+                .filter(|s: &String| !s.is_empty())
+        };
         Ok(NoteStoreEditArgument {
             title: note.title,
             note_inner: NoteType::from(note.note_inner),
             metadata: NoteMetadataEditable {
                 tags: Some(tags),
                 custom_metadata: Some(custom_metadata),
+                author: non_empty(note.metadata_author),
+                message: non_empty(note.metadata_message),
             },
         })
     }
@@ -163,20 +391,144 @@ async fn get_revisions(
     HttpResponse::Ok().json(revisions)
 }
 
+#[derive(Deserialize, Debug)]
+struct DiffQuery {
+    from: String,
+    to: String,
+}
+
+#[get("/note/{note_id}/diff")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_diff(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    query: web::Query<DiffQuery>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let query = query.into_inner();
+    let res = diff_revisions(
+        store.as_ref().as_ref(),
+        &note_id.into(),
+        &query.from.into(),
+        &query.to.into(),
+    )
+    .await;
+    match res {
+        Ok(diff) => HttpResponse::Ok().json(diff),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/note/{note_id}/sequence")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_sequence(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.get_sequence(&note_id.into()).await;
+    if let Err(e) = res {
+        return notestore_error_handler(&e);
+    }
+    let notes: Vec<NoteSerializable<NoteType>> = res
+        .unwrap()
+        .into_iter()
+        .map(NoteSerializable::all_fields)
+        .collect();
+    HttpResponse::Ok().json(notes)
+}
+
+#[derive(Deserialize, Debug)]
+struct SubtreeQuery {
+    depth: Option<usize>,
+}
+
+#[get("/note/{note_id}/subtree")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_subtree(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    query: web::Query<SubtreeQuery>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.get_subtree(&note_id.into(), query.depth).await;
+    if let Err(e) = res {
+        return notestore_error_handler(&e);
+    }
+    let notes: Vec<NoteSerializable<NoteType>> = res
+        .unwrap()
+        .into_iter()
+        .map(NoteSerializable::all_fields)
+        .collect();
+    HttpResponse::Ok().json(notes)
+}
+
+#[derive(Serialize)]
+struct AncestorSerializable {
+    link: Link,
+    note: NoteSerializable<NoteType>,
+}
+
+#[get("/note/{note_id}/ancestors")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_ancestors(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.get_ancestors(&note_id.into()).await;
+    if let Err(e) = res {
+        return notestore_error_handler(&e);
+    }
+    let ancestors: Vec<AncestorSerializable> = res
+        .unwrap()
+        .into_iter()
+        .map(|a| AncestorSerializable {
+            link: a.link,
+            note: NoteSerializable::all_fields(a.note),
+        })
+        .collect();
+    HttpResponse::Ok().json(ancestors)
+}
+
 #[post("/note/{note_id}/revision")]
 #[instrument(
-    skip(store, params, note),
+    skip(req, store, params, note),
     fields(
         note_id = %params.0
     )
 )]
 async fn update_note(
+    req: HttpRequest,
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
     note: web::Json<NotePostData>,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
+    let loc = match if_match_locator(&req, &store, note_id).await {
+        Ok(loc) => loc,
+        Err(response) => return response,
+    };
     let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
@@ -186,132 +538,767 @@ async fn update_note(
         .update_note(&loc, Some(note.title), Some(note.note_inner), note.metadata)
         .await;
     match res {
-        Ok(loc) => HttpResponse::Ok().json(loc),
-        Err(e) => notestore_error_handler(&e),
+        Ok(loc) => match loc.get_revision() {
+            Some(r) => HttpResponse::Ok().insert_header(revision_etag(r)).json(loc),
+            None => HttpResponse::Ok().json(loc),
+        },
+        Err(e) => precondition_error_handler(&e),
     }
 }
 
-#[post("/note/{note_id}/branch")]
+#[derive(Deserialize, Debug)]
+struct MergeQuery {
+    #[serde(default)]
+    merge: bool,
+}
+
+#[post("/note/{note_id}/revision/{revision_id}")]
 #[instrument(
     skip(store, params, note),
     fields(
-      note_id = %params.0
+        note_id = %params.0,
+        revision_id = %params.1
     )
 )]
-async fn new_branch(
+async fn update_note_specific(
     store: web::Data<BoxedNoteStore<NoteType>>,
-    params: web::Path<(String,)>,
+    params: web::Path<(String, String)>,
+    query: web::Query<MergeQuery>,
     note: web::Json<NotePostData>,
 ) -> impl Responder {
-    let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
+    let (note_id, revision_id) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
     let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
     let note = note.unwrap();
-    let res = store
-        .add_branch(loc.get_id(), note.title, note.note_inner, note.metadata)
-        .await;
+    if !query.merge {
+        let res = store
+            .update_note(&loc, Some(note.title), Some(note.note_inner), note.metadata)
+            .await;
+        return match res {
+            Ok(loc) => HttpResponse::Ok().json(loc),
+            Err(e) => notestore_error_handler(&e),
+        };
+    }
+    let res = update_note_merging(
+        store.as_ref().as_ref(),
+        &loc,
+        Some(note.title),
+        Some(note.note_inner),
+        note.metadata,
+    )
+    .await;
     match res {
-        Ok(loc_child) => HttpResponse::Ok().json(loc_child),
+        Ok(MergeOutcome::Updated(loc)) => HttpResponse::Ok().json(loc),
+        Ok(MergeOutcome::Conflict(conflict)) => HttpResponse::Conflict().json(conflict),
         Err(e) => notestore_error_handler(&e),
     }
 }
 
-#[post("/note/{note_id}/next")]
+#[post("/note/{note_id}/revision/{revision_id}/draft")]
 #[instrument(
     skip(store, params, note),
     fields(
-        note_id = %params.0
+        note_id = %params.0,
+        revision_id = %params.1
     )
 )]
-async fn new_next(
+async fn create_draft(
     store: web::Data<BoxedNoteStore<NoteType>>,
-    params: web::Path<(String,)>,
+    params: web::Path<(String, String)>,
     note: web::Json<NotePostData>,
 ) -> impl Responder {
-    let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
+    let (note_id, revision_id) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
     let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
     let note = note.unwrap();
     let res = store
-        .append_note(loc.get_id(), note.title, note.note_inner, note.metadata)
+        .create_draft(&loc, Some(note.title), Some(note.note_inner), note.metadata)
         .await;
     match res {
-        Ok(loc_next) => HttpResponse::Ok().json(loc_next),
+        Ok(loc) => HttpResponse::Ok().json(loc),
         Err(e) => notestore_error_handler(&e),
     }
 }
 
-#[get("/note/{note_id}")]
+#[get("/note/{note_id}/draft")]
 #[instrument(
     skip(store, params),
     fields(
         note_id = %params.0
     )
 )]
-async fn get_note_current(
+async fn list_drafts(
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
-    get_note_by_locator(store, &loc).await
-}
-
-#[derive(Deserialize, Debug)]
-struct SearchQuery {
-    query: Option<String>,
-}
-
-#[get("/note")]
-#[instrument(skip(store, search))]
-async fn search(
-    store: web::Data<BoxedNoteStore<NoteType>>,
-    search: web::Query<SearchQuery>,
-) -> impl Responder {
-    let search = search.into_inner();
-    let query = search.query;
-    let res = if let Some(q) = query {
-        store.search(&q.into()).await
-    } else {
-        store.search(&"".to_owned().into()).await
-    };
+    let res = store.list_drafts(&note_id.into()).await;
     if let Err(e) = res {
         return notestore_error_handler(&e);
     }
-    let revisions: Vec<NoteSerializable<NoteType>> = res
+    let drafts: Vec<NoteSerializable<NoteType>> = res
         .unwrap()
         .into_iter()
         .map(NoteSerializable::all_fields)
         .collect();
-    HttpResponse::Ok().json(revisions)
+    HttpResponse::Ok().json(drafts)
 }
 
-#[get("/tags")]
-#[instrument(skip(store))]
-async fn get_tags(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
-    let res = store.tags().await;
-
+#[post("/note/{note_id}/revision/{revision_id}/promote")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1
+    )
+)]
+async fn promote_draft(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let loc = NoteLocator::Specific(note_id.into(), revision_id.into());
+    let res = store.promote_draft(&loc).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionDraftPutData {
+    note_id: Option<String>,
+    revision: Option<String>,
+    content: serde_json::Value,
+}
+
+#[put("/draft/{session}")]
+#[instrument(
+    skip(store, params, draft),
+    fields(
+        session = %params.0
+    )
+)]
+async fn put_session_draft(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    draft: web::Json<SessionDraftPutData>,
+) -> impl Responder {
+    let (session,) = params.into_inner();
+    let draft = draft.into_inner();
+    let target = match (draft.note_id, draft.revision) {
+        (Some(note_id), Some(revision)) => {
+            Some(NoteLocator::Specific(note_id.into(), revision.into()))
+        }
+        (Some(note_id), None) => Some(NoteLocator::Current(note_id.into())),
+        (None, None) => None,
+        (None, Some(_)) => {
+            return HttpResponse::BadRequest().body("a revision requires a note_id");
+        }
+    };
+    let res = store
+        .put_session_draft(&session, target.as_ref(), draft.content)
+        .await;
+    match res {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/draft/{session}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        session = %params.0
+    )
+)]
+async fn get_session_draft(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (session,) = params.into_inner();
+    let res = store.get_session_draft(&session).await;
+    match res {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[delete("/draft/{session}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        session = %params.0
+    )
+)]
+async fn delete_session_draft(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (session,) = params.into_inner();
+    let res = store.delete_session_draft(&session).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionDraftsQuery {
+    prefix: Option<String>,
+}
+
+#[get("/draft")]
+#[instrument(skip(store, q))]
+async fn list_session_drafts(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    q: web::Query<SessionDraftsQuery>,
+) -> impl Responder {
+    let prefix = q.into_inner().prefix.unwrap_or_default();
+    let res = store.list_session_drafts(&prefix).await;
+    match res {
+        Ok(drafts) => HttpResponse::Ok().json(drafts),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/branch")]
+#[instrument(
+    skip(store, params, note),
+    fields(
+      note_id = %params.0
+    )
+)]
+async fn new_branch(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    note: web::Json<NotePostData>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let loc = NoteLocator::Current(note_id.into());
+    let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
+    if let Err(e) = note {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let note = note.unwrap();
+    let res = store
+        .add_branch(loc.get_id(), note.title, note.note_inner, note.metadata)
+        .await;
+    match res {
+        Ok(loc_child) => HttpResponse::Ok().json(loc_child),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/next")]
+#[instrument(
+    skip(store, params, note),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn new_next(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    note: web::Json<NotePostData>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let loc = NoteLocator::Current(note_id.into());
+    let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
+    if let Err(e) = note {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let note = note.unwrap();
+    let res = store
+        .append_note(loc.get_id(), note.title, note.note_inner, note.metadata)
+        .await;
+    match res {
+        Ok(loc_next) => HttpResponse::Ok().json(loc_next),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/insert_after")]
+#[instrument(
+    skip(store, params, note),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn insert_after(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    note: web::Json<NotePostData>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
+    if let Err(e) = note {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let note = note.unwrap();
+    let res = store
+        .insert_after(&note_id.into(), note.title, note.note_inner, note.metadata)
+        .await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/insert_before")]
+#[instrument(
+    skip(store, params, note),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn insert_before(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    note: web::Json<NotePostData>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
+    if let Err(e) = note {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let note = note.unwrap();
+    let res = store
+        .insert_before(&note_id.into(), note.title, note.note_inner, note.metadata)
+        .await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/move")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn move_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    target: web::Json<MoveTarget>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.move_note(&note_id.into(), &target.into_inner()).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/split")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn split_sequence(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    tail: web::Json<SplitTail>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store
+        .split_sequence(&note_id.into(), tail.into_inner())
+        .await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/merge/{head_id}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        head_id = %params.1
+    )
+)]
+async fn merge_sequences(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, head_id) = params.into_inner();
+    let res = store
+        .merge_sequences(&note_id.into(), &head_id.into())
+        .await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/note/{note_id}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_note_current(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    query: web::Query<AtQuery>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let loc = match query.into_inner().at {
+        Some(at) => NoteLocator::AtTime(note_id.into(), at),
+        None => NoteLocator::Current(note_id.into()),
+    };
+    get_note_by_locator(store, &loc).await
+}
+
+#[derive(Deserialize, Debug)]
+struct AtQuery {
+    at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchQuery {
+    query: Option<String>,
+    at: Option<DateTime<Utc>>,
+}
+
+#[get("/note")]
+#[instrument(skip(store, search))]
+async fn search(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    search: web::Query<SearchQuery>,
+) -> impl Responder {
+    let search = search.into_inner();
+    let sr = search.query.unwrap_or_default().into();
+    let res = if let Some(at) = search.at {
+        store.search_at(&sr, at).await
+    } else {
+        store.search(&sr).await
+    };
+    if let Err(e) = res {
+        return notestore_error_handler(&e);
+    }
+    let revisions: Vec<NoteSerializable<NoteType>> = res
+        .unwrap()
+        .into_iter()
+        .map(NoteSerializable::all_fields)
+        .collect();
+    HttpResponse::Ok().json(revisions)
+}
+
+#[derive(Serialize)]
+struct DeletedNoteSerializable {
+    deleted_at: DateTime<Utc>,
+    note: NoteSerializable<NoteType>,
+}
+
+#[get("/trash")]
+#[instrument(skip(store))]
+async fn list_deleted(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    let res = store.list_deleted().await;
+    if let Err(e) = res {
+        return notestore_error_handler(&e);
+    }
+    let deleted: Vec<DeletedNoteSerializable> = res
+        .unwrap()
+        .into_iter()
+        .map(|d| DeletedNoteSerializable {
+            deleted_at: d.deleted_at,
+            note: NoteSerializable::all_fields(d.note),
+        })
+        .collect();
+    HttpResponse::Ok().json(deleted)
+}
+
+#[post("/trash/{note_id}/restore")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn restore_deleted_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = restore_deleted(store.as_ref().as_ref(), &note_id.into()).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[delete("/trash/{note_id}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn purge_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.purge_note(&note_id.into()).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/tags")]
+#[instrument(skip(store))]
+async fn get_tags(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    let res = store.tags().await;
+
     if let Err(e) = res {
         return notestore_error_handler(&e);
     }
     HttpResponse::Ok().json(res.unwrap())
 }
 
+/// How many chunks of an export are buffered for a slow client.
+const EXPORT_CHANNEL_CAPACITY: usize = 16;
+
+/// Export every revision of every note in the interchange format.
+///
+/// The archive is streamed while it is read from the note store, so that it doesn't have to fit
+/// in memory.
+/// If reading fails after the response has started, the response is cut short.
+#[get("/export")]
+#[instrument(skip(store))]
+async fn export_notes(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    let (started, start) = oneshot::channel();
+    let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+    actix_web::rt::spawn(run_export(store, started, sender));
+    match start.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return notestore_error_handler(&e),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((chunk, receiver))
+    });
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body)
+}
+
+/// Send an archive over a channel, one line at a time.
+///
+/// Whether the export could start is sent over `started` first.
+async fn run_export(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    started: oneshot::Sender<Result<(), NoteStoreError>>,
+    sender: mpsc::Sender<Result<web::Bytes, actix_web::Error>>,
+) {
+    let mut records = match export_records(store.as_ref().as_ref()).await {
+        Ok(records) => records,
+        Err(e) => {
+            let _ = started.send(Err(e));
+            return;
+        }
+    };
+    let _ = started.send(Ok(()));
+    let mut header = vec![];
+    write_header(&mut header).expect("Failed to serialize the header");
+    if sender.send(Ok(header.into())).await.is_err() {
+        return;
+    }
+    while let Some(record) = records.next().await {
+        let mut line = vec![];
+        let chunk = match record.and_then(|r| write_record(&mut line, &r)) {
+            Ok(()) => Ok(line.into()),
+            Err(e) => {
+                error!("Export failed {:?}", e);
+                Err(actix_web::error::ErrorInternalServerError(e.to_string()))
+            }
+        };
+        let failed = chunk.is_err();
+        // The client went away, or the export failed
+        if sender.send(chunk).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// How often an idle event stream sends a comment, so that proxies keep it open and
+/// disconnected clients are noticed.
+const EVENTS_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    since: Option<ChangeCursor>,
+}
+
+/// Format a change as a server-sent event, with the cursor as the event ID.
+fn change_event(change: &Change) -> web::Bytes {
+    let data = serde_json::to_string(change).expect("Failed to serialize a change");
+    web::Bytes::from(format!("id: {}\ndata: {}\n\n", change.cursor, data))
+}
+
+#[get("/events")]
+#[instrument(skip(req, store, feed, q))]
+async fn events(
+    req: HttpRequest,
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    feed: web::Data<ChangeFeed>,
+    q: web::Query<EventsQuery>,
+) -> impl Responder {
+    // Browsers reconnect with the ID of the last event they got
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+    let since = last_event_id.or(q.since);
+    // Subscribe before catching up, so that nothing is missed in between
+    let subscription = match feed.subscribe().await {
+        Ok(s) => s,
+        Err(e) => return notestore_error_handler(&e),
+    };
+    let missed = match since {
+        Some(cursor) => match store.changes_since(cursor).await {
+            Ok(changes) => changes,
+            Err(e) => return notestore_error_handler(&e),
+        },
+        None => vec![],
+    };
+    let seen = missed.last().map(|c| c.cursor).or(since).unwrap_or(0);
+    let changes = stream::iter(missed)
+        .chain(subscription.filter(move |c| future::ready(c.cursor > seen)))
+        .boxed();
+    // The response ends with the subscription, and the browser reconnects to catch up
+    event_stream(changes.map(|c| change_event(&c)).boxed())
+}
+
+/// Respond with a stream of server-sent events, interleaved with keep-alive comments.
+fn event_stream(body: BoxStream<'static, web::Bytes>) -> HttpResponse {
+    let stream = stream::unfold(
+        (body, time::interval(EVENTS_KEEP_ALIVE)),
+        |(mut body, mut keep_alive)| async move {
+            let event = tokio::select! {
+                event = body.next() => event?,
+                _ = keep_alive.tick() => web::Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((Ok::<_, actix_web::Error>(event), (body, keep_alive)))
+        },
+    );
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(stream)
+}
+
+fn collab_error_handler(e: &CollabError) -> HttpResponse {
+    match e {
+        CollabError::NoSession(_) => HttpResponse::NotFound().body(e.to_string()),
+        CollabError::FutureVersion(_) => HttpResponse::BadRequest().body(e.to_string()),
+        CollabError::StaleVersion(_) => HttpResponse::Conflict().body(e.to_string()),
+        CollabError::Operation(_) => HttpResponse::BadRequest().body(e.to_string()),
+        CollabError::Store(e) => notestore_error_handler(e),
+    }
+}
+
+#[get("/note/{note_id}/collab")]
+#[instrument(skip(sessions, params), fields(note_id = %params.0))]
+async fn join_collab(
+    sessions: web::Data<CollabSessions>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let participant = match sessions.into_inner().join(params.0.clone().into()).await {
+        Ok(p) => p,
+        Err(e) => return collab_error_handler(&e),
+    };
+    // The participant leaves when the client disconnects and the stream is dropped
+    let body = participant.into_stream().map(|event| {
+        let data = serde_json::to_string(&event).expect("Failed to serialize a session event");
+        web::Bytes::from(format!("data: {}\n\n", data))
+    });
+    event_stream(body.boxed())
+}
+
+#[derive(Deserialize)]
+struct CollabOperation {
+    client: String,
+    version: u64,
+    operation: TextOperation,
+}
+
+#[post("/note/{note_id}/collab")]
+#[instrument(skip(sessions, params, op), fields(note_id = %params.0))]
+async fn apply_collab(
+    sessions: web::Data<CollabSessions>,
+    params: web::Path<(String,)>,
+    op: web::Json<CollabOperation>,
+) -> impl Responder {
+    let op = op.into_inner();
+    let res = sessions
+        .apply(
+            &params.0.clone().into(),
+            op.client,
+            op.version,
+            op.operation,
+        )
+        .await;
+    match res {
+        Ok(version) => HttpResponse::Ok().json(version),
+        Err(e) => collab_error_handler(&e),
+    }
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_note_current)
         .service(get_note_specific)
         .service(new_note)
         .service(delete_note_current)
         .service(update_note)
+        .service(update_note_specific)
         .service(get_revisions)
+        .service(get_diff)
+        .service(get_sequence)
+        .service(get_subtree)
+        .service(get_ancestors)
+        .service(revert_note)
+        .service(list_labels)
+        .service(get_note_by_label)
+        .service(add_label)
+        .service(remove_label)
+        .service(create_draft)
+        .service(list_drafts)
+        .service(promote_draft)
+        .service(list_session_drafts)
+        .service(put_session_draft)
+        .service(get_session_draft)
+        .service(delete_session_draft)
         .service(search)
         .service(new_branch)
         .service(new_next)
-        .service(get_tags);
+        .service(insert_after)
+        .service(insert_before)
+        .service(move_note)
+        .service(split_sequence)
+        .service(merge_sequences)
+        .service(list_deleted)
+        .service(restore_deleted_note)
+        .service(purge_note)
+        .service(purge_revision)
+        .service(get_tags)
+        .service(export_notes)
+        .service(events)
+        .service(join_collab)
+        .service(apply_collab);
 }
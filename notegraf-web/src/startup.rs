@@ -1,3 +1,7 @@
+use crate::collab::CollabSessions;
+use crate::compaction::spawn_compaction;
+use crate::configuration::{CollabSettings, RetentionSettings};
+use crate::events::ChangeFeed;
 use crate::routes::*;
 use crate::NoteType;
 use actix_files::{Files, NamedFile};
@@ -17,8 +21,15 @@ pub fn run(
     listener: TcpListener,
     note_store: BoxedNoteStore<NoteType>,
     debug: bool,
+    retention: Option<&RetentionSettings>,
+    collab: &CollabSettings,
 ) -> Result<Server, std::io::Error> {
     let ns: Data<BoxedNoteStore<NoteType>> = Data::new(note_store);
+    if let Some(retention) = retention {
+        spawn_compaction(ns.clone(), retention);
+    }
+    let sessions = Data::new(CollabSessions::new(ns.clone(), collab));
+    let feed = Data::new(ChangeFeed::new(ns.clone()));
     let server = HttpServer::new(move || {
         App::new()
             .wrap(NormalizePath::new(TrailingSlash::Trim))
@@ -34,6 +45,8 @@ pub fn run(
             // https://create-react-app.dev/docs/deployment/#serving-apps-with-client-side-routing
             .service(web::resource("/{tail}*").route(web::get().to(index_file)))
             .app_data(ns.clone())
+            .app_data(sessions.clone())
+            .app_data(feed.clone())
     })
     .listen(listener)?
     .run();
@@ -4,7 +4,9 @@ use common::*;
 use reqwest::{Client, StatusCode};
 
 use notegraf::NoteLocator;
+use notegraf_web::configuration::CollabSettings;
 use serde_json::{json, Value};
+use std::time::Duration;
 
 async fn create_note_helper(
     client: &Client,
@@ -25,7 +27,7 @@ async fn post_note_helper(
     tags: &str,
 ) -> NoteLocator {
     client
-        .post(&format!("{address}/api/v1/{endpoint}"))
+        .post(format!("{address}/api/v1/{endpoint}"))
         .json(&json!({
             "title": title.to_owned(),
             "note_inner": note_inner.to_owned(),
@@ -42,11 +44,7 @@ async fn post_note_helper(
 
 async fn get_note_helper(client: &Client, address: &str, loc: &NoteLocator) -> Value {
     client
-        .get(&format!(
-            "{}/api/v1/note/{}",
-            address,
-            loc.get_id().as_ref()
-        ))
+        .get(format!("{}/api/v1/note/{}", address, loc.get_id().as_ref()))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -98,7 +96,7 @@ async fn note_delete() {
     let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
     get_note_helper(&client, &app.address, &loc1).await;
     client
-        .delete(&format!(
+        .delete(format!(
             "{}/api/v1/note/{}",
             &app.address,
             loc1.get_id().as_ref()
@@ -107,7 +105,7 @@ async fn note_delete() {
         .await
         .expect("Failed to execute request.");
     let response = client
-        .get(&format!(
+        .get(format!(
             "{}/api/v1/note/{}",
             &app.address,
             loc1.get_id().as_ref()
@@ -159,7 +157,7 @@ async fn note_revisions() {
     .await;
 
     let response = client
-        .get(&format!(
+        .get(format!(
             "{}/api/v1/note/{}/revision",
             &app.address,
             loc1.get_id().as_ref()
@@ -180,6 +178,112 @@ async fn note_revisions() {
     );
 }
 
+#[tokio::test]
+async fn note_diff() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "foo\nbar\n", "a").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "New title",
+        "foo\nbaz\n",
+        "b",
+    )
+    .await;
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/diff?from={}&to={}",
+            &app.address,
+            loc1.get_id().as_ref(),
+            loc1.get_revision().unwrap().as_ref(),
+            loc2.get_revision().unwrap().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let diff = response
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(
+        diff["note_inner"],
+        json!([
+            {"tag": "equal", "value": "foo\n"},
+            {"tag": "delete", "value": "bar\n"},
+            {"tag": "insert", "value": "baz\n"}
+        ])
+    );
+    assert_eq!(diff["tags"]["added"], json!(["b"]));
+    assert_eq!(diff["tags"]["removed"], json!(["a"]));
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/diff?from={}",
+            &app.address,
+            loc1.get_id().as_ref(),
+            loc1.get_revision().unwrap().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn revert_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "body", "").await;
+    post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "New title",
+        "New body",
+        "",
+    )
+    .await;
+
+    let loc3: NoteLocator = client
+        .post(format!(
+            "{}/api/v1/note/{}/revision/{}/revert",
+            &app.address,
+            loc1.get_id().as_ref(),
+            loc1.get_revision().unwrap().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let response = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(response["revision"], loc3.get_revision().unwrap().as_ref());
+    assert_eq!(response["title"], "title");
+    assert_eq!(
+        response["metadata"]["reverted_from"],
+        loc1.get_revision().unwrap().as_ref()
+    );
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/revision/{}/revert",
+            &app.address,
+            loc1.get_id().as_ref(),
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn recent_notes() {
     let app = spawn_app().await;
@@ -189,7 +293,7 @@ async fn recent_notes() {
     let loc2 = create_note_helper(&client, &app.address, "title2", "## body text", "").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -211,7 +315,7 @@ async fn search_notes() {
     let loc2 = create_note_helper(&client, &app.address, "bar", "buzz", "").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "fizz")])
         .send()
         .await
@@ -223,7 +327,7 @@ async fn search_notes() {
     assert_eq!(response[0]["id"], loc1.get_id().as_ref());
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "Buzz")])
         .send()
         .await
@@ -306,6 +410,1178 @@ async fn append_note() {
     assert_eq!(response["prev"].as_str().unwrap(), loc1.get_id().as_ref());
 }
 
+#[tokio::test]
+async fn insert_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "first", "", "").await;
+    let loc3 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc1.get_id()),
+        "third",
+        "",
+        "",
+    )
+    .await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/insert_after", loc1.get_id()),
+        "second",
+        "",
+        "",
+    )
+    .await;
+    let loc0 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/insert_before", loc1.get_id()),
+        "zeroth",
+        "",
+        "",
+    )
+    .await;
+
+    let response = get_note_helper(&client, &app.address, &loc0).await;
+    assert!(response["prev"].is_null());
+    assert_eq!(response["next"].as_str().unwrap(), loc1.get_id().as_ref());
+    let response = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(response["next"].as_str().unwrap(), loc2.get_id().as_ref());
+    let response = get_note_helper(&client, &app.address, &loc2).await;
+    assert_eq!(response["next"].as_str().unwrap(), loc3.get_id().as_ref());
+    let response = get_note_helper(&client, &app.address, &loc3).await;
+    assert_eq!(response["prev"].as_str().unwrap(), loc2.get_id().as_ref());
+}
+
+#[tokio::test]
+async fn move_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc1.get_id()),
+        "next title",
+        "New body text",
+        "",
+    )
+    .await;
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/move",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .json(&json!({ "Branch": loc1.get_id().as_ref() }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = get_note_helper(&client, &app.address, &loc2).await;
+    assert_eq!(response["parent"].as_str().unwrap(), loc1.get_id().as_ref());
+    assert!(response["prev"].is_null());
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/move",
+            &app.address,
+            loc1.get_id().as_ref()
+        ))
+        .json(&json!({ "Branch": loc2.get_id().as_ref() }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/move",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .json(&json!("TopLevel"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = get_note_helper(&client, &app.address, &loc1).await;
+    assert!(response["branches"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn split_merge_sequence() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc1.get_id()),
+        "next title",
+        "New body text",
+        "",
+    )
+    .await;
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/split",
+            &app.address,
+            loc1.get_id().as_ref()
+        ))
+        .json(&json!("TopLevel"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/split",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .json(&json!("Branch"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = get_note_helper(&client, &app.address, &loc2).await;
+    assert_eq!(response["parent"].as_str().unwrap(), loc1.get_id().as_ref());
+    assert!(response["prev"].is_null());
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/merge/{}",
+            &app.address,
+            loc1.get_id().as_ref(),
+            loc2.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(response["next"].as_str().unwrap(), loc2.get_id().as_ref());
+    assert!(response["branches"].as_array().unwrap().is_empty());
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/merge/{}",
+            &app.address,
+            loc2.get_id().as_ref(),
+            loc1.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn sequence_subtree() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc1.get_id()),
+        "next title",
+        "New body text",
+        "",
+    )
+    .await;
+    let loc3 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/branch", loc2.get_id()),
+        "branch title",
+        "Branch body text",
+        "",
+    )
+    .await;
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/sequence",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let notes = response.json::<Vec<Value>>().await.unwrap();
+    let ids: Vec<&str> = notes.iter().map(|n| n["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec![loc1.get_id().as_ref(), loc2.get_id().as_ref()]);
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/subtree?depth=1",
+            &app.address,
+            loc1.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let notes = response.json::<Vec<Value>>().await.unwrap();
+    let ids: Vec<&str> = notes.iter().map(|n| n["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec![loc1.get_id().as_ref(), loc2.get_id().as_ref()]);
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/subtree",
+            &app.address,
+            loc1.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let notes = response.json::<Vec<Value>>().await.unwrap();
+    let ids: Vec<&str> = notes.iter().map(|n| n["id"].as_str().unwrap()).collect();
+    assert_eq!(
+        ids,
+        vec![
+            loc1.get_id().as_ref(),
+            loc2.get_id().as_ref(),
+            loc3.get_id().as_ref()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ancestors() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/branch", loc1.get_id()),
+        "branch title",
+        "Branch body text",
+        "",
+    )
+    .await;
+    let loc3 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc2.get_id()),
+        "",
+        "Next body text",
+        "",
+    )
+    .await;
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/ancestors",
+            &app.address,
+            loc3.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let ancestors = response.json::<Vec<Value>>().await.unwrap();
+    assert_eq!(ancestors.len(), 2);
+    assert_eq!(ancestors[0]["link"].as_str().unwrap(), "Sequence");
+    assert_eq!(
+        ancestors[0]["note"]["id"].as_str().unwrap(),
+        loc2.get_id().as_ref()
+    );
+    assert_eq!(ancestors[1]["link"].as_str().unwrap(), "Branch");
+    assert_eq!(ancestors[1]["note"]["title"].as_str().unwrap(), "title");
+}
+
+#[tokio::test]
+async fn trash() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let response = client
+        .delete(format!(
+            "{}/api/v1/note/{}",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(format!("{}/api/v1/trash", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let deleted = response.json::<Vec<Value>>().await.unwrap();
+    let deleted: Vec<&Value> = deleted
+        .iter()
+        .filter(|d| d["note"]["id"].as_str().unwrap() == loc.get_id().as_ref())
+        .collect();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0]["note"]["title"].as_str().unwrap(), "title");
+    assert!(deleted[0]["deleted_at"].is_string());
+
+    let restore_url = format!(
+        "{}/api/v1/trash/{}/restore",
+        &app.address,
+        loc.get_id().as_ref()
+    );
+    let response = client
+        .post(&restore_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = get_note_helper(&client, &app.address, &loc).await;
+    assert_eq!(response["title"].as_str().unwrap(), "title");
+
+    let response = client
+        .post(&restore_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn purge() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "Secret", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "title",
+        "Redacted",
+        "",
+    )
+    .await;
+
+    let revision_url = |loc: &NoteLocator| {
+        format!(
+            "{}/api/v1/note/{}/revision/{}",
+            &app.address,
+            loc.get_id().as_ref(),
+            loc.get_revision().unwrap().as_ref()
+        )
+    };
+    let response = client
+        .delete(revision_url(&loc2))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = client
+        .delete(revision_url(&loc1))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(revision_url(&loc1))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let trash_url = format!("{}/api/v1/trash/{}", &app.address, loc1.get_id().as_ref());
+    let response = client
+        .delete(&trash_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    client
+        .delete(format!(
+            "{}/api/v1/note/{}",
+            &app.address,
+            loc1.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let response = client
+        .delete(&trash_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(revision_url(&loc2))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn note_at_time() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "Apple", "").await;
+    let response = get_note_helper(&client, &app.address, &loc).await;
+    let created = response["metadata"]["modified_at"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc.get_id()),
+        "title",
+        "Banana",
+        "",
+    )
+    .await;
+
+    let note_url = format!("{}/api/v1/note/{}", &app.address, loc.get_id().as_ref());
+    let response: Value = client
+        .get(&note_url)
+        .query(&[("at", &created)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response["note_inner"], "Apple");
+    assert_eq!(response["revision"], loc.get_revision().unwrap().as_ref());
+    let response = client
+        .get(&note_url)
+        .query(&[("at", "2000-01-01T00:00:00Z")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response: Value = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .query(&[("query", "Apple"), ("at", &created)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let notes = response.as_array().unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["id"], loc.get_id().as_ref());
+}
+
+#[tokio::test]
+async fn revision_author_message() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc: NoteLocator = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "title",
+            "note_inner": "Foo",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null",
+            "metadata_author": "alice",
+            "metadata_message": "Create"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    client
+        .post(format!(
+            "{}/api/v1/note/{}/revision",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .json(&json!({
+            "title": "title",
+            "note_inner": "Bar",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null",
+            "metadata_author": " ",
+            "metadata_message": "Fix typo"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let response: Value = client
+        .get(format!(
+            "{}/api/v1/note/{}/revision",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let revisions = response.as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0]["metadata"]["author"], "alice");
+    assert_eq!(revisions[0]["metadata"]["message"], "Create");
+    assert_eq!(revisions[1]["metadata"]["author"], Value::Null);
+    assert_eq!(revisions[1]["metadata"]["message"], "Fix typo");
+}
+
+#[tokio::test]
+async fn revision_labels() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "Draft", "").await;
+    post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "title",
+        "Final",
+        "",
+    )
+    .await;
+    let note_url = format!("{}/api/v1/note/{}", &app.address, loc1.get_id().as_ref());
+    let label_url = format!(
+        "{}/revision/{}/label/submitted-draft",
+        &note_url,
+        loc1.get_revision().unwrap().as_ref()
+    );
+
+    let response = client
+        .post(&label_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response: Value = client
+        .get(format!("{}/label", &note_url))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(
+        response,
+        json!([{
+            "label": "submitted-draft",
+            "revision": loc1.get_revision().unwrap().as_ref()
+        }])
+    );
+    let response: Value = client
+        .get(format!("{}/label/submitted-draft", &note_url))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response["note_inner"], "Draft");
+
+    let response = client
+        .delete(format!("{}/label/submitted-draft", &note_url))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(format!("{}/label/submitted-draft", &note_url))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn drafts() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "Published", "").await;
+    let id = loc1.get_id().as_ref();
+    let draft = post_note_helper(
+        &client,
+        &app.address,
+        &format!(
+            "note/{}/revision/{}/draft",
+            id,
+            loc1.get_revision().unwrap().as_ref()
+        ),
+        "title",
+        "Work in progress",
+        "",
+    )
+    .await;
+    let note = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(note["note_inner"], "Published");
+    let note_url = format!("{}/api/v1/note/{}", &app.address, id);
+    let response: Value = client
+        .get(format!("{}/draft", &note_url))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response.as_array().unwrap().len(), 1);
+    assert_eq!(response[0]["note_inner"], "Work in progress");
+    assert_eq!(response[0]["is_draft"], true);
+    assert_eq!(
+        response[0]["based_on"],
+        loc1.get_revision().unwrap().as_ref()
+    );
+
+    let response = client
+        .post(format!(
+            "{}/revision/{}/promote",
+            &note_url,
+            loc1.get_revision().unwrap().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = client
+        .post(format!(
+            "{}/revision/{}/promote",
+            &note_url,
+            draft.get_revision().unwrap().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let note = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(note["note_inner"], "Work in progress");
+    assert_eq!(note["based_on"], draft.get_revision().unwrap().as_ref());
+}
+
+#[tokio::test]
+async fn update_note_merge() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "Foo\nBar\nBaz\n", "").await;
+    post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "title",
+        "Foo\nBar\nBaz\nQux\n",
+        "",
+    )
+    .await;
+    let edit_url = format!(
+        "{}/api/v1/note/{}/revision/{}",
+        &app.address,
+        loc1.get_id().as_ref(),
+        loc1.get_revision().unwrap().as_ref()
+    );
+    let edit = |note_inner: &str| {
+        json!({
+            "title": "title",
+            "note_inner": note_inner,
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        })
+    };
+
+    // Without merging, an old revision can't be updated
+    let response = client
+        .post(&edit_url)
+        .json(&edit("Foo\nBar!\nBaz\n"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = client
+        .post(format!("{}?merge=true", &edit_url))
+        .json(&edit("Foo\nBar!\nBaz\n"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let note = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(note["note_inner"], "Foo\nBar!\nBaz\nQux\n");
+
+    let response = client
+        .post(format!("{}?merge=true", &edit_url))
+        .json(&edit("Foo\nBar?\nBaz\n"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let conflict: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(conflict["base"], loc1.get_revision().unwrap().as_ref());
+    assert_eq!(conflict["theirs"], note["revision"]);
+    assert_eq!(
+        conflict["note_inner"],
+        json!({
+            "base": "Foo\nBar\nBaz\n",
+            "ours": "Foo\nBar?\nBaz\n",
+            "theirs": "Foo\nBar!\nBaz\nQux\n"
+        })
+    );
+}
+
+#[tokio::test]
+async fn if_match() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let note_url = format!("{}/api/v1/note/{}", &app.address, loc1.get_id().as_ref());
+    let etag1 = format!("\"{}\"", loc1.get_revision().unwrap().as_ref());
+    let response = client
+        .get(&note_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.headers()["etag"], etag1.as_str());
+    let edit = |note_inner: &str| {
+        json!({
+            "title": "title",
+            "note_inner": note_inner,
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        })
+    };
+
+    let response = client
+        .post(format!("{}/revision", &note_url))
+        .header("If-Match", &etag1)
+        .json(&edit("First tab"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag2 = response.headers()["etag"].to_str().unwrap().to_owned();
+    let loc2: NoteLocator = response.json().await.expect("Failed to parse response");
+    assert_eq!(
+        etag2,
+        format!("\"{}\"", loc2.get_revision().unwrap().as_ref())
+    );
+
+    // The second tab still has the first revision
+    let response = client
+        .post(format!("{}/revision", &note_url))
+        .header("If-Match", &etag1)
+        .json(&edit("Second tab"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = client
+        .delete(&note_url)
+        .header("If-Match", &etag1)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let note = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(note["note_inner"], "First tab");
+
+    // Weak tags never match
+    let response = client
+        .post(format!("{}/revision", &note_url))
+        .header("If-Match", format!("W/{}", &etag2))
+        .json(&edit("Third tab"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    // A draft is never current
+    let draft = post_note_helper(
+        &client,
+        &app.address,
+        &format!(
+            "note/{}/revision/{}/draft",
+            loc1.get_id().as_ref(),
+            loc2.get_revision().unwrap().as_ref()
+        ),
+        "title",
+        "Draft",
+        "",
+    )
+    .await;
+    let etag_draft = format!("\"{}\"", draft.get_revision().unwrap().as_ref());
+    let response = client
+        .post(format!("{}/revision", &note_url))
+        .header("If-Match", &etag_draft)
+        .json(&edit("Draft tab"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = client
+        .delete(&note_url)
+        .header("If-Match", &etag_draft)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let note = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(note["note_inner"], "First tab");
+    assert_eq!(note["revision"], loc2.get_revision().unwrap().as_ref());
+
+    // Any of the listed revisions may be current
+    let response = client
+        .delete(&note_url)
+        .header("If-Match", format!("{}, {}", &etag1, &etag2))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(&note_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+/// Read server-sent events from a response until `count` of them are parsed.
+async fn read_events<T>(
+    response: &mut reqwest::Response,
+    count: usize,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Vec<T> {
+    let mut body = String::new();
+    let read = async {
+        loop {
+            // Only look at complete events
+            let complete = &body[..body.rfind("\n\n").map_or(0, |i| i + 2)];
+            let events: Vec<T> = complete.split("\n\n").filter_map(&parse).collect();
+            if events.len() >= count {
+                return events;
+            }
+            let chunk = response
+                .chunk()
+                .await
+                .expect("Failed to read response")
+                .expect("The event stream ended");
+            body.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), read)
+        .await
+        .expect("Timed out waiting for events")
+}
+
+/// Read server-sent events from a response until there are `count` changes.
+async fn read_changes(response: &mut reqwest::Response, count: usize) -> Vec<(String, Value)> {
+    read_events(response, count, |event| {
+        let mut lines = event.lines();
+        let id = lines.next()?.strip_prefix("id: ")?.to_owned();
+        let data = lines.next()?.strip_prefix("data: ")?;
+        Some((id, serde_json::from_str(data).unwrap()))
+    })
+    .await
+}
+
+#[tokio::test]
+async fn events() {
+    let app = spawn_app().await;
+    let client = Client::new();
+    let events_url = format!("{}/api/v1/events", &app.address);
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let mut response = client
+        .get(format!("{}?since=0", &events_url))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/branch", loc1.get_id()),
+        "branch",
+        "",
+        "",
+    )
+    .await;
+
+    // The note created before connecting is caught up on, the branch is streamed
+    let changes = read_changes(&mut response, 2).await;
+    assert_eq!(changes[0].1["kind"], "create");
+    assert_eq!(changes[0].1["loc"]["Specific"][0], loc1.get_id().as_ref());
+    assert_eq!(changes[1].1["kind"], "branch");
+    assert_eq!(changes[1].1["loc"]["Specific"][0], loc2.get_id().as_ref());
+    assert_eq!(changes[1].0, changes[1].1["cursor"].to_string());
+
+    // Reconnecting browsers resume after the last event they got
+    let mut resumed_response = client
+        .get(&events_url)
+        .header("Last-Event-ID", &changes[0].0)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let resumed = read_changes(&mut resumed_response, 1).await;
+    assert_eq!(resumed[0].0, changes[1].0);
+
+    // Every open stream gets new changes
+    let loc3 = create_note_helper(&client, &app.address, "another", "", "").await;
+    for response in [&mut response, &mut resumed_response] {
+        let changes = read_changes(response, 1).await;
+        assert_eq!(changes[0].1["loc"]["Specific"][0], loc3.get_id().as_ref());
+    }
+}
+
+/// Read events of a collaborative editing session from a response until there are `count`.
+async fn read_session_events(response: &mut reqwest::Response, count: usize) -> Vec<Value> {
+    read_events(response, count, |event| {
+        let data = event.strip_prefix("data: ")?;
+        Some(serde_json::from_str(data).unwrap())
+    })
+    .await
+}
+
+#[tokio::test]
+async fn collab() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "Hello", "").await;
+    let collab_url = format!("{}/api/v1/note/{}/collab", &app.address, loc.get_id());
+    let response = client
+        .post(&collab_url)
+        .json(&json!({"client": "a", "version": 0, "operation": [5, " world"]}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut events = client
+        .get(&collab_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(events.status(), StatusCode::OK);
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+    let snapshot = &read_session_events(&mut events, 1).await[0];
+    assert_eq!(snapshot["type"], "snapshot");
+    assert_eq!(snapshot["version"], 0);
+    assert_eq!(snapshot["text"], "Hello");
+
+    // Two concurrent operations on version 0
+    for (client_id, operation, version) in
+        [("a", json!([5, " world"]), 1), ("b", json!(["Oh, ", 5]), 2)]
+    {
+        let response = client
+            .post(&collab_url)
+            .json(&json!({"client": client_id, "version": 0, "operation": operation}))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.json::<Value>().await.unwrap(), version);
+    }
+    let response = client
+        .post(&collab_url)
+        .json(&json!({"client": "a", "version": 3, "operation": [15, "!"]}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The operation of b is transformed against the one of a
+    let received = read_session_events(&mut events, 2).await;
+    assert_eq!(received[0]["type"], "operation");
+    assert_eq!(received[0]["client"], "a");
+    assert_eq!(received[1]["version"], 2);
+    assert_eq!(received[1]["operation"], json!(["Oh, ", 11]));
+
+    // The note body is committed periodically
+    let committed = async {
+        loop {
+            let note = get_note_helper(&client, &app.address, &loc.current()).await;
+            if note["note_inner"] == "Oh, Hello world" {
+                return note;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    let note = tokio::time::timeout(Duration::from_secs(10), committed)
+        .await
+        .expect("Timed out waiting for the commit");
+    assert_eq!(note["metadata"]["message"], "Collaborative editing session");
+}
+
+#[tokio::test]
+async fn collab_commit_on_leave() {
+    let app = spawn_app_with_collab(CollabSettings {
+        commit_interval_secs: 0,
+    })
+    .await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "Hello", "").await;
+    let collab_url = format!("{}/api/v1/note/{}/collab", &app.address, loc.get_id());
+    let mut events = client
+        .get(&collab_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_session_events(&mut events, 1).await;
+    let response = client
+        .post(&collab_url)
+        .json(&json!({"client": "a", "version": 0, "operation": [5, " world"]}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let note = get_note_helper(&client, &app.address, &loc.current()).await;
+    assert_eq!(note["note_inner"], "Hello");
+
+    // Nothing is committed until the last participant leaves
+    drop(events);
+    let committed = async {
+        loop {
+            let note = get_note_helper(&client, &app.address, &loc.current()).await;
+            if note["note_inner"] == "Hello world" {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    // The server notices that the stream was closed by the next keep-alive comment
+    tokio::time::timeout(Duration::from_secs(30), committed)
+        .await
+        .expect("Timed out waiting for the commit");
+}
+
+#[tokio::test]
+async fn session_drafts() {
+    let app = spawn_app().await;
+    let client = Client::new();
+    let draft_url = |session: &str| format!("{}/api/v1/draft/{}", &app.address, session);
+
+    let loc = create_note_helper(&client, &app.address, "title", "body", "").await;
+    let edit = format!("note.{}.edit.1", loc.get_id());
+    let response = client
+        .put(draft_url(&edit))
+        .json(&json!({
+            "note_id": loc.get_id(),
+            "revision": loc.get_revision(),
+            "content": {"title": "new title", "note_inner": "new body"}
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .put(draft_url("note.new.1"))
+        .json(&json!({"content": {"title": "another"}}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .put(draft_url("note.new.2"))
+        .json(&json!({"revision": loc.get_revision(), "content": {}}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let draft = client
+        .get(draft_url(&edit))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(draft["id"], loc.get_id().as_ref());
+    assert_eq!(draft["base"], loc.get_revision().unwrap().as_ref());
+    assert_eq!(draft["content"]["note_inner"], "new body");
+    let drafts = client
+        .get(format!("{}/api/v1/draft", &app.address))
+        .query(&[("prefix", "note.new.")])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(drafts.as_array().unwrap().len(), 1);
+    assert_eq!(drafts[0]["session"], "note.new.1");
+
+    // The draft is not a revision of the note
+    let revisions = client
+        .get(format!(
+            "{}/api/v1/note/{}/revision",
+            &app.address,
+            loc.get_id()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(revisions.as_array().unwrap().len(), 1);
+
+    let response = client
+        .delete(draft_url(&edit))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(draft_url(&edit))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn export() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "## body text", "").await;
+    let response = client
+        .get(format!("{}/api/v1/export", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let archive = response.text().await.unwrap();
+    let mut lines = archive.lines();
+    let header: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(header["format"], "notegraf");
+    let records: Vec<Value> = lines.map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(records
+        .iter()
+        .any(|r| r["id"] == loc.get_id().as_ref() && r["title"] == "title"));
+}
+
 #[tokio::test]
 async fn orphan_reference() {
     let app = spawn_app().await;
@@ -322,7 +1598,7 @@ async fn orphan_reference() {
     .await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "!orphan")])
         .send()
         .await
@@ -351,7 +1627,7 @@ async fn orphan_prev() {
     .await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "!orphan")])
         .send()
         .await
@@ -380,7 +1656,7 @@ async fn orphan_parent() {
     .await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "!orphan")])
         .send()
         .await
@@ -401,7 +1677,7 @@ async fn tags() {
     create_note_helper(&client, &app.address, "foo", "", "tag2").await;
 
     let response = client
-        .get(&format!("{}/api/v1/tags", &app.address))
+        .get(format!("{}/api/v1/tags", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -423,7 +1699,7 @@ async fn limit_override() {
     let loc2 = create_note_helper(&client, &app.address, "title2", "## body text", "").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -434,7 +1710,7 @@ async fn limit_override() {
 
     // recent note comes first
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "!limit=1")])
         .send()
         .await
@@ -455,7 +1731,7 @@ async fn tag_exclude() {
     let loc2 = create_note_helper(&client, &app.address, "foo", "", "tag2").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -465,7 +1741,7 @@ async fn tag_exclude() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "foo")])
         .send()
         .await
@@ -476,7 +1752,7 @@ async fn tag_exclude() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "foo -#tag1")])
         .send()
         .await
@@ -488,7 +1764,7 @@ async fn tag_exclude() {
     assert_eq!(response[0]["id"], loc2.get_id().as_ref());
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "foo -#tag2")])
         .send()
         .await
@@ -509,7 +1785,7 @@ async fn lexeme_exclude() {
     let loc2 = create_note_helper(&client, &app.address, "goodbye world", "", "tag2").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -519,7 +1795,7 @@ async fn lexeme_exclude() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "world")])
         .send()
         .await
@@ -530,7 +1806,7 @@ async fn lexeme_exclude() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "world -hello")])
         .send()
         .await
@@ -542,7 +1818,7 @@ async fn lexeme_exclude() {
     assert_eq!(response[0]["id"], loc2.get_id().as_ref());
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "world -goodbye")])
         .send()
         .await
@@ -563,7 +1839,7 @@ async fn issue_158() {
     let loc2 = create_note_helper(&client, &app.address, "goodbye world", "", "tag2").await;
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .send()
         .await
         .expect("Failed to execute request.")
@@ -573,7 +1849,7 @@ async fn issue_158() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "world")])
         .send()
         .await
@@ -584,7 +1860,7 @@ async fn issue_158() {
     assert_eq!(response.as_array().unwrap().len(), 2);
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "-hello")])
         .send()
         .await
@@ -596,7 +1872,7 @@ async fn issue_158() {
     assert_eq!(response[0]["id"], loc2.get_id().as_ref());
 
     let response = client
-        .get(&format!("{}/api/v1/note", &app.address))
+        .get(format!("{}/api/v1/note", &app.address))
         .query(&[("query", "-goodbye")])
         .send()
         .await
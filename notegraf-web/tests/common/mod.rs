@@ -1,5 +1,5 @@
 use lazy_static::lazy_static;
-use notegraf_web::configuration::CONFIGURATION;
+use notegraf_web::configuration::{CollabSettings, CONFIGURATION};
 use notegraf_web::startup::run;
 use notegraf_web::telemetry::{get_subscriber, init_tracing};
 use std::net::TcpListener;
@@ -19,13 +19,25 @@ pub struct TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    // Commit collaborative editing sessions quickly, so that tests don't wait
+    spawn_app_with_collab(CollabSettings {
+        commit_interval_secs: 1,
+    })
+    .await
+}
+
+pub async fn spawn_app_with_collab(collab: CollabSettings) -> TestApp {
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
     // We retrieve the port assigned to us by the OS
     let port = listener.local_addr().unwrap().port();
     let address = format!("http://127.0.0.1:{port}");
     lazy_static::initialize(&TRACING);
-    let store = CONFIGURATION.get_note_store(true, LevelFilter::Debug).await;
-    let server = run(listener, store, CONFIGURATION.debug).expect("Failed to bind address");
+    let store = CONFIGURATION
+        .get_note_store(true, LevelFilter::Debug)
+        .await
+        .unwrap();
+    let server =
+        run(listener, store, CONFIGURATION.debug, None, &collab).expect("Failed to bind address");
     drop(tokio::spawn(server));
     TestApp { address }
 }
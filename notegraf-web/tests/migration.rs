@@ -0,0 +1,165 @@
+use notegraf::errors::NoteStoreError;
+use notegraf::notemetadata::NoteMetadataEditable;
+use notegraf::notestore::interchange::{write_record, RevisionRecord};
+use notegraf::notestore::util::populate_test_data;
+use notegraf::notestore::{export, BoxedNoteStore};
+use notegraf::{InMemoryStore, Note, NoteLocator};
+use notegraf_web::configuration::CONFIGURATION;
+use notegraf_web::migration::{migrate, migrate_archive};
+use notegraf_web::NoteType;
+use tracing::log::LevelFilter;
+
+async fn get_head(store: &BoxedNoteStore<NoteType>) -> Box<dyn Note<NoteType>> {
+    let mut notes = store.search(&"!nolimit".to_owned().into()).await.unwrap();
+    assert_eq!(notes.len(), 3);
+    notes.retain(|n| n.get_prev().is_none());
+    assert_eq!(notes.len(), 1);
+    notes.pop().unwrap()
+}
+
+#[tokio::test]
+async fn migrate_sequence() {
+    let source: BoxedNoteStore<NoteType> = Box::new(InMemoryStore::new());
+    populate_test_data(&source).await;
+    let destination = CONFIGURATION
+        .get_note_store(true, LevelFilter::Debug)
+        .await
+        .unwrap();
+    migrate(&source, &destination, true).await.unwrap();
+
+    let mut note = get_head(&source).await;
+    let mut note_migrated = get_head(&destination).await;
+    loop {
+        assert_eq!(note.get_title(), note_migrated.get_title());
+        assert_eq!(note.get_note_inner(), note_migrated.get_note_inner());
+        let (metadata, metadata_migrated) = (note.get_metadata(), note_migrated.get_metadata());
+        // PostgreSQL stores timestamps in microseconds
+        assert_eq!(
+            metadata.created_at.timestamp_micros(),
+            metadata_migrated.created_at.timestamp_micros()
+        );
+        assert_eq!(
+            metadata.modified_at.timestamp_micros(),
+            metadata_migrated.modified_at.timestamp_micros()
+        );
+        assert_eq!(metadata.tags, metadata_migrated.tags);
+        match (note.get_next(), note_migrated.get_next()) {
+            (Some(next), Some(next_migrated)) => {
+                note = source.get_note(&NoteLocator::Current(next)).await.unwrap();
+                note_migrated = destination
+                    .get_note(&NoteLocator::Current(next_migrated))
+                    .await
+                    .unwrap();
+            }
+            (None, None) => break,
+            _ => panic!("Sequences differ in length"),
+        }
+    }
+
+    assert!(matches!(
+        migrate(&source, &destination, true).await,
+        Err(NoteStoreError::StoreNotEmpty)
+    ));
+}
+
+#[tokio::test]
+async fn migrate_reverted_note() {
+    let source: BoxedNoteStore<NoteType> = Box::new(InMemoryStore::new());
+    let loc = source
+        .new_note(
+            "title".to_owned(),
+            NoteType::from("Foo".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    source
+        .update_note(
+            &loc,
+            None,
+            Some(NoteType::from("Bar".to_owned())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    source.revert_note(&loc).await.unwrap();
+    let destination = CONFIGURATION
+        .get_note_store(true, LevelFilter::Debug)
+        .await
+        .unwrap();
+    migrate(&source, &destination, true).await.unwrap();
+
+    let mut notes = destination
+        .search(&"!nolimit".to_owned().into())
+        .await
+        .unwrap();
+    assert_eq!(notes.len(), 1);
+    let note = notes.pop().unwrap();
+    assert_eq!(note.get_note_inner(), NoteType::from("Foo".to_owned()));
+    let revisions = destination
+        .get_revisions(&NoteLocator::Current(note.get_id()))
+        .await
+        .unwrap();
+    assert_eq!(revisions.len(), 3);
+    assert_eq!(
+        note.get_metadata().reverted_from,
+        Some(revisions[0].get_revision())
+    );
+}
+
+#[tokio::test]
+async fn migrate_from_archive() {
+    let source: BoxedNoteStore<NoteType> = Box::new(InMemoryStore::new());
+    populate_test_data(&source).await;
+    let mut archive: Vec<u8> = vec![];
+    export(source.as_ref(), &mut archive).await.unwrap();
+    let destination = CONFIGURATION
+        .get_note_store(true, LevelFilter::Debug)
+        .await
+        .unwrap();
+    migrate_archive(archive.as_slice(), &destination, true)
+        .await
+        .unwrap();
+
+    let source_head = get_head(&source).await;
+    let destination_head = get_head(&destination).await;
+    assert_eq!(source_head.get_title(), destination_head.get_title());
+    assert!(matches!(
+        migrate_archive(archive.as_slice(), &destination, true).await,
+        Err(NoteStoreError::StoreNotEmpty)
+    ));
+}
+
+#[tokio::test]
+async fn failed_migration() {
+    let source: BoxedNoteStore<NoteType> = Box::new(InMemoryStore::new());
+    populate_test_data(&source).await;
+    let mut archive: Vec<u8> = vec![];
+    export(source.as_ref(), &mut archive).await.unwrap();
+    // The last note follows a note missing from the archive
+    let head = get_head(&source).await;
+    let mut record = RevisionRecord::from_note(head.as_ref());
+    record.id = "missing-prev".into();
+    record.revision = "revision-0".into();
+    record.prev = Some("missing".into());
+    let mut bad_archive = archive.clone();
+    write_record(&mut bad_archive, &record).unwrap();
+    let destination = CONFIGURATION
+        .get_note_store(true, LevelFilter::Debug)
+        .await
+        .unwrap();
+    assert!(matches!(
+        migrate_archive(bad_archive.as_slice(), &destination, true).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+    assert!(destination.get_note_ids().await.unwrap().is_empty());
+
+    // Nothing was left behind, so the migration can be retried
+    migrate_archive(archive.as_slice(), &destination, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_head(&source).await.get_title(),
+        get_head(&destination).await.get_title()
+    );
+}
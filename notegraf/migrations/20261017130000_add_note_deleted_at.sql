@@ -0,0 +1,3 @@
+-- When a note was last deleted
+-- NULL for notes that are not deleted, or whose deletion time is unknown
+ALTER TABLE note ADD COLUMN deleted_at timestamptz;
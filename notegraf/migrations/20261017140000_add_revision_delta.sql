@@ -0,0 +1,15 @@
+-- A revision that is not current can be stored as a delta against a keyframe, i.e., a revision of
+-- the same note with the full text.
+-- The delta is a JSON array, where [start, length] copies characters from the keyframe, and a
+-- string inserts text.
+-- note_inner of such a revision is empty, and delta_text_searchable keeps the search vector of its
+-- full text, since text_searchable is generated from note_inner.
+-- Existing revisions are kept in full.
+ALTER TABLE revision
+    ADD COLUMN delta_base            uuid,
+    ADD COLUMN delta                 jsonb,
+    ADD COLUMN delta_text_searchable tsvector,
+    ADD FOREIGN KEY (delta_base) REFERENCES revision (revision),
+    ADD CONSTRAINT revision_delta_with_base CHECK ((delta_base IS NULL) = (delta IS NULL));
+
+CREATE INDEX revision_idx_delta_base ON revision USING HASH (delta_base);
@@ -0,0 +1,16 @@
+-- The revision of each note that was current at a point in time, i.e., the one with the latest
+-- modification time up to that point
+-- Notes that had been deleted by then are left out, where the deletion time falls back to the
+-- modification time of the last revision
+CREATE FUNCTION revision_as_of(t timestamptz) RETURNS SETOF revision AS
+$$
+SELECT DISTINCT ON (revision.id) revision.*
+FROM revision
+         JOIN note ON note.id = revision.id
+         LEFT JOIN current_revision cr ON cr.id = revision.id
+WHERE revision.metadata_modified_at <= t
+  AND (cr.id IS NOT NULL
+    OR t < COALESCE(note.deleted_at,
+                    (SELECT max(r.metadata_modified_at) FROM revision r WHERE r.id = revision.id)))
+ORDER BY revision.id, revision.metadata_modified_at DESC
+$$ LANGUAGE sql STABLE;
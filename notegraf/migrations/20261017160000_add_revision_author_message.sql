@@ -0,0 +1,36 @@
+-- Who made a revision and why
+ALTER TABLE revision
+    ADD COLUMN metadata_author  text,
+    ADD COLUMN metadata_message text;
+
+-- Views have to be recreated to pick up the new columns
+DROP VIEW revision_only_current;
+DROP VIEW revision_is_current;
+
+CREATE VIEW revision_is_current AS
+    SELECT
+        revision.revision,
+        revision.id,
+        revision.title,
+        revision.note_inner,
+        revision.text_searchable,
+        revision.parent,
+        revision.prev,
+        revision.referents,
+        revision.metadata_schema_version,
+        revision.metadata_created_at,
+        revision.metadata_modified_at,
+        revision.metadata_tags,
+        revision.metadata_custom_metadata,
+        revision.metadata_reverted_from,
+        revision.metadata_author,
+        revision.metadata_message,
+        cr.current_revision IS NOT NULL AS is_current
+    FROM revision
+    LEFT JOIN current_revision cr on revision.revision = cr.current_revision;
+
+CREATE VIEW revision_only_current AS
+    SELECT
+        *
+    FROM revision_is_current
+    WHERE is_current;
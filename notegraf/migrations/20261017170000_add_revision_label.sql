@@ -0,0 +1,12 @@
+-- Human-readable labels of revisions, unique within a note
+CREATE TABLE revision_label
+(
+    id       uuid NOT NULL,
+    FOREIGN KEY (id) REFERENCES note (id),
+    label    text NOT NULL,
+    PRIMARY KEY (id, label),
+    revision uuid NOT NULL,
+    FOREIGN KEY (revision) REFERENCES revision (revision)
+);
+
+CREATE INDEX revision_label_idx_revision ON revision_label USING HASH (revision);
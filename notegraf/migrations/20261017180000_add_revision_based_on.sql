@@ -0,0 +1,70 @@
+-- The history of revisions is no longer linear
+-- A revision records the revision of the same note that it is based on, and a draft is a
+-- revision that is not part of the main line until it's promoted
+ALTER TABLE revision
+    ADD COLUMN based_on uuid,
+    ADD COLUMN is_draft boolean NOT NULL DEFAULT FALSE;
+
+CREATE INDEX revision_idx_based_on ON revision USING HASH (based_on);
+
+-- Existing revisions form a line, each based on the one before it
+WITH ordered AS (SELECT revision.revision,
+                        lag(revision.revision) OVER (PARTITION BY revision.id ORDER BY revision.metadata_modified_at) AS based_on
+                 FROM revision)
+UPDATE revision
+SET based_on = ordered.based_on
+FROM ordered
+WHERE revision.revision = ordered.revision
+  AND ordered.based_on IS NOT NULL;
+
+-- Views have to be recreated to pick up the new columns
+DROP VIEW revision_only_current;
+DROP VIEW revision_is_current;
+
+CREATE VIEW revision_is_current AS
+    SELECT
+        revision.revision,
+        revision.id,
+        revision.title,
+        revision.note_inner,
+        revision.text_searchable,
+        revision.parent,
+        revision.prev,
+        revision.referents,
+        revision.metadata_schema_version,
+        revision.metadata_created_at,
+        revision.metadata_modified_at,
+        revision.metadata_tags,
+        revision.metadata_custom_metadata,
+        revision.metadata_reverted_from,
+        revision.metadata_author,
+        revision.metadata_message,
+        revision.based_on,
+        revision.is_draft,
+        cr.current_revision IS NOT NULL AS is_current
+    FROM revision
+    LEFT JOIN current_revision cr on revision.revision = cr.current_revision;
+
+CREATE VIEW revision_only_current AS
+    SELECT
+        *
+    FROM revision_is_current
+    WHERE is_current;
+
+-- Drafts were never current
+CREATE OR REPLACE FUNCTION revision_as_of(t timestamptz) RETURNS SETOF revision AS
+$$
+SELECT DISTINCT ON (revision.id) revision.*
+FROM revision
+         JOIN note ON note.id = revision.id
+         LEFT JOIN current_revision cr ON cr.id = revision.id
+WHERE revision.metadata_modified_at <= t
+  AND NOT revision.is_draft
+  AND (cr.id IS NOT NULL
+    OR t < COALESCE(note.deleted_at,
+                    (SELECT max(r.metadata_modified_at)
+                     FROM revision r
+                     WHERE r.id = revision.id
+                       AND NOT r.is_draft)))
+ORDER BY revision.id, revision.metadata_modified_at DESC
+$$ LANGUAGE sql STABLE;
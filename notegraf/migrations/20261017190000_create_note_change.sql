@@ -0,0 +1,19 @@
+-- The change feed, in the order that changes were committed
+-- Rows are not tied to notes or revisions, so that the feed outlives purged notes
+-- A change is recorded without a cursor, and is given one from note_change_cursor once the
+-- transaction that recorded it (recorded_by) and every transaction started before have finished,
+-- so that a client never sees a cursor before the smaller ones, without serializing writes
+CREATE SEQUENCE note_change_cursor;
+
+CREATE TABLE note_change
+(
+    seq         bigserial PRIMARY KEY,
+    cursor      bigint UNIQUE,
+    recorded_by xid8        NOT NULL DEFAULT pg_current_xact_id(),
+    kind        text        NOT NULL CHECK (kind IN ('create', 'update', 'delete', 'append', 'branch')),
+    id          uuid        NOT NULL,
+    revision    uuid,
+    changed_at  timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX note_change_idx_unsequenced ON note_change (seq) WHERE cursor IS NULL;
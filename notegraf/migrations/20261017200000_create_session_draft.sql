@@ -0,0 +1,14 @@
+-- Drafts of editor sessions, kept out of the history of notes
+CREATE TABLE session_draft
+(
+    session  text        NOT NULL PRIMARY KEY,
+    id       uuid,
+    FOREIGN KEY (id) REFERENCES note (id),
+    base     uuid,
+    FOREIGN KEY (base) REFERENCES revision (revision),
+    content  jsonb       NOT NULL,
+    saved_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX session_draft_idx_id ON session_draft USING HASH (id);
+CREATE INDEX session_draft_idx_base ON session_draft USING HASH (base);
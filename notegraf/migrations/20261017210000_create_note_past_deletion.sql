@@ -0,0 +1,34 @@
+-- Periods during which a note was deleted, before it was restored
+-- note.deleted_at only keeps the deletion of a note that is currently deleted
+CREATE TABLE note_past_deletion
+(
+    id          uuid        NOT NULL,
+    FOREIGN KEY (id) REFERENCES note (id),
+    deleted_at  timestamptz NOT NULL,
+    restored_at timestamptz NOT NULL
+);
+
+CREATE INDEX note_past_deletion_idx_id ON note_past_deletion USING HASH (id);
+
+-- Notes that were deleted at that time but restored later are left out too
+CREATE OR REPLACE FUNCTION revision_as_of(t timestamptz) RETURNS SETOF revision AS
+$$
+SELECT DISTINCT ON (revision.id) revision.*
+FROM revision
+         JOIN note ON note.id = revision.id
+         LEFT JOIN current_revision cr ON cr.id = revision.id
+WHERE revision.metadata_modified_at <= t
+  AND NOT revision.is_draft
+  AND (cr.id IS NOT NULL
+    OR t < COALESCE(note.deleted_at,
+                    (SELECT max(r.metadata_modified_at)
+                     FROM revision r
+                     WHERE r.id = revision.id
+                       AND NOT r.is_draft)))
+  AND NOT EXISTS (SELECT 1
+                  FROM note_past_deletion d
+                  WHERE d.id = revision.id
+                    AND d.deleted_at <= t
+                    AND t < d.restored_at)
+ORDER BY revision.id, revision.metadata_modified_at DESC
+$$ LANGUAGE sql STABLE;
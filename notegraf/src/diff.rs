@@ -0,0 +1,187 @@
+//! Differences between two revisions of a note.
+//!
+//! The title is compared word by word, and the note body and custom metadata line by line.
+use crate::errors::NoteStoreError;
+use crate::{Note, NoteID, NoteLocator, NoteStore, NoteType, Revision};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
+
+/// Whether a piece of text is kept, inserted or deleted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffTag {
+    Equal,
+    Insert,
+    Delete,
+}
+
+impl From<ChangeTag> for DiffTag {
+    fn from(tag: ChangeTag) -> Self {
+        match tag {
+            ChangeTag::Equal => DiffTag::Equal,
+            ChangeTag::Insert => DiffTag::Insert,
+            ChangeTag::Delete => DiffTag::Delete,
+        }
+    }
+}
+
+/// A run of consecutive words or lines with the same [`DiffTag`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiffChunk {
+    pub tag: DiffTag,
+    pub value: String,
+}
+
+/// Tags added to and removed from a note.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TagsDiff {
+    pub added: BTreeSet<String>,
+    pub removed: BTreeSet<String>,
+}
+
+/// Differences between two revisions of a note.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NoteDiff {
+    pub id: NoteID,
+    pub from: Revision,
+    pub to: Revision,
+    pub title: Vec<DiffChunk>,
+    pub note_inner: Vec<DiffChunk>,
+    pub tags: TagsDiff,
+    pub custom_metadata: Vec<DiffChunk>,
+}
+
+impl NoteDiff {
+    /// Whether the two revisions have the same content.
+    pub fn is_unchanged(&self) -> bool {
+        self.tags.added.is_empty()
+            && self.tags.removed.is_empty()
+            && [&self.title, &self.note_inner, &self.custom_metadata]
+                .iter()
+                .all(|chunks| chunks.iter().all(|c| c.tag == DiffTag::Equal))
+    }
+}
+
+fn to_chunks<'a>(diff: &TextDiff<'a, 'a, 'a, str>) -> Vec<DiffChunk> {
+    let mut chunks: Vec<DiffChunk> = vec![];
+    for change in diff.iter_all_changes() {
+        let tag = change.tag().into();
+        match chunks.last_mut() {
+            Some(last) if last.tag == tag => last.value.push_str(change.value()),
+            _ => chunks.push(DiffChunk {
+                tag,
+                value: change.value().to_owned(),
+            }),
+        }
+    }
+    chunks
+}
+
+fn diff_words(old: &str, new: &str) -> Vec<DiffChunk> {
+    to_chunks(&TextDiff::from_words(old, new))
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffChunk> {
+    to_chunks(&TextDiff::from_lines(old, new))
+}
+
+/// Compare two notes.
+///
+/// The notes are usually two revisions of the same note, but this is not required.
+pub fn diff_notes<T: NoteType>(from: &dyn Note<T>, to: &dyn Note<T>) -> NoteDiff {
+    let from_metadata = from.get_metadata();
+    let to_metadata = to.get_metadata();
+    let from_inner: String = from.get_note_inner().into();
+    let to_inner: String = to.get_note_inner().into();
+    // Maps are serialized with sorted keys, so the lines are stable
+    let from_custom = serde_json::to_string_pretty(&from_metadata.custom_metadata).unwrap();
+    let to_custom = serde_json::to_string_pretty(&to_metadata.custom_metadata).unwrap();
+    NoteDiff {
+        id: to.get_id(),
+        from: from.get_revision(),
+        to: to.get_revision(),
+        title: diff_words(&from.get_title(), &to.get_title()),
+        note_inner: diff_lines(&from_inner, &to_inner),
+        tags: TagsDiff {
+            added: to_metadata
+                .tags
+                .difference(&from_metadata.tags)
+                .cloned()
+                .collect(),
+            removed: from_metadata
+                .tags
+                .difference(&to_metadata.tags)
+                .cloned()
+                .collect(),
+        },
+        custom_metadata: diff_lines(&from_custom, &to_custom),
+    }
+}
+
+/// Compare two revisions of a note in a [`NoteStore`].
+pub async fn diff_revisions<T, S>(
+    store: &S,
+    id: &NoteID,
+    from: &Revision,
+    to: &Revision,
+) -> Result<NoteDiff, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    let from_note = store
+        .get_note(&NoteLocator::Specific(id.clone(), from.clone()))
+        .await?;
+    let to_note = store
+        .get_note(&NoteLocator::Specific(id.clone(), to.clone()))
+        .await?;
+    Ok(diff_notes(from_note.as_ref(), to_note.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(tag: DiffTag, value: &str) -> DiffChunk {
+        DiffChunk {
+            tag,
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            diff_lines("Foo\nBar\nBaz\n", "Foo\nQux\nBaz\n"),
+            vec![
+                chunk(DiffTag::Equal, "Foo\n"),
+                chunk(DiffTag::Delete, "Bar\n"),
+                chunk(DiffTag::Insert, "Qux\n"),
+                chunk(DiffTag::Equal, "Baz\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            diff_words("a quick fox", "a slow brown fox"),
+            vec![
+                chunk(DiffTag::Equal, "a "),
+                chunk(DiffTag::Delete, "quick"),
+                chunk(DiffTag::Insert, "slow brown"),
+                chunk(DiffTag::Equal, " fox"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(
+            diff_lines("Foo\n", "Foo\n"),
+            vec![chunk(DiffTag::Equal, "Foo\n")]
+        );
+        assert!(diff_lines("", "").is_empty());
+    }
+}
@@ -2,6 +2,7 @@ use thiserror::Error;
 use url::ParseError;
 
 use crate::{NoteID, Revision};
+use chrono::{DateTime, Utc};
 
 #[derive(Error, Debug)]
 pub enum NoteStoreError {
@@ -11,22 +12,47 @@ pub enum NoteStoreError {
     NoteIDConflict(NoteID),
     #[error("revision`{1}` of note `{0}` doesn't exist")]
     RevisionNotExist(NoteID, Revision),
+    #[error("note `{0}` doesn't exist at {1}")]
+    NoteNotExistAtTime(NoteID, DateTime<Utc>),
     #[error("io error")]
     IOError(#[from] std::io::Error),
     #[error("serde error")]
     SerdeError(#[from] serde_json::Error),
     #[error("attempt to update non-current revision `{1}` of note `{0}`")]
     UpdateOldRevision(NoteID, Revision),
+    #[error("revision `{1}` of note `{0}` is not a draft")]
+    NotADraft(NoteID, Revision),
     #[error("attempt to delete non-current revision `{1}` of note `{0}`")]
     DeleteOldRevision(NoteID, Revision),
     #[error("inconsistency detected: note `{1}` is not a child of note `{0}`")]
     NotAChild(NoteID, NoteID),
     #[error("cannot append note `{1}` to note `{0}`, because note `{0}` is not the last note in a sequence")]
     ExistingNext(NoteID, NoteID),
+    #[error("cannot append note `{1}` to a sequence, because it follows note `{0}`")]
+    ExistingPrev(NoteID, NoteID),
+    #[error("cannot split the sequence at note `{0}`, because it is the first note")]
+    SplitAtHead(NoteID),
+    #[error("cannot move note `{0}` to note `{1}`, because it would create a cycle")]
+    WouldCreateCycle(NoteID, NoteID),
+    #[error("note `{0}` is not deleted")]
+    NotDeleted(NoteID),
+    #[error("cannot purge revision `{1}` of note `{0}`, because it is the current revision")]
+    PurgeCurrentRevision(NoteID, Revision),
+    #[error("label `{1}` of note `{0}` is already attached to revision `{2}`")]
+    LabelExists(NoteID, String, Revision),
+    #[error("note `{0}` doesn't have label `{1}`")]
+    LabelNotExist(NoteID, String),
+    #[error("session `{0}` doesn't have a draft")]
+    SessionDraftNotExist(String),
     #[error("cannot delete note `{0}`, because it has branches")]
     HasBranches(NoteID),
     #[error("cannot delete note `{0}`, because other notes refer to it")]
     HasReferences(NoteID),
+    #[error(
+        "cannot purge note `{0}`, because older revisions of notes {} are linked to it",
+        .1.iter().map(|id| format!("`{id}`")).collect::<Vec<_>>().join(", ")
+    )]
+    LinkedInHistory(NoteID, Vec<NoteID>),
     #[error("note cannot be parsed: `{0}`")]
     ParseError(String),
     #[error("PostgreSQL error")]
@@ -35,6 +61,16 @@ pub enum NoteStoreError {
     NoteInnerError(String),
     #[error("id or revision is not UUID")]
     NotUuid(String),
+    #[error("cannot restore into a note store that is not empty")]
+    StoreNotEmpty,
+    #[error("not supported by this note store: `{0}`")]
+    Unsupported(String),
+    #[error("unsupported interchange format: `{0}`")]
+    UnsupportedFormat(String),
+    #[error("invalid import: `{0}`")]
+    InvalidImport(String),
+    #[error("note store changed during the export: `{0}`")]
+    StoreChanged(String),
 }
 
 /// Error type for Notegraf URL parsing.
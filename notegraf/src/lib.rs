@@ -3,13 +3,16 @@
 #[allow(unused_imports)]
 extern crate tracing;
 
+pub mod diff;
 pub mod errors;
+pub mod merge;
 pub mod note;
 pub mod notemetadata;
 pub mod notestore;
 pub mod notetype;
+pub mod ot;
 pub mod url;
 
-pub use note::{Note, NoteID, NoteLocator, NoteSerializable, Revision};
+pub use note::{Note, NoteID, NoteIdentity, NoteLocator, NoteSerializable, Revision};
 pub use notestore::{InMemoryStore, NoteStore, PostgreSQLStore, PostgreSQLStoreBuilder};
 pub use notetype::{MarkdownNote, NoteType, PlainNote};
@@ -0,0 +1,283 @@
+//! Three-way merges of concurrent edits to a note.
+//!
+//! An edit based on a revision that is no longer current is merged with the current revision,
+//! using the revision it was based on as the common ancestor.
+//! The title is merged word by word, and the note body line by line.
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadataEditable;
+use crate::{NoteID, NoteLocator, NoteStore, NoteType, Revision};
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+/// How many times [`update_note_merging`] tries to save an edit while other edits keep coming in.
+const MAX_MERGE_ATTEMPTS: usize = 3;
+
+/// The three versions of a piece of text that could not be merged.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MergeVersions {
+    /// The version in the revision that the edit was based on.
+    pub base: String,
+    /// The version in the edit.
+    pub ours: String,
+    /// The version in the current revision.
+    pub theirs: String,
+}
+
+/// An edit that conflicts with the current revision of a note.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub id: NoteID,
+    /// The revision that the edit was based on.
+    pub base: Revision,
+    /// The current revision.
+    pub theirs: Revision,
+    pub title: MergeVersions,
+    pub note_inner: MergeVersions,
+}
+
+/// The result of [`update_note_merging`].
+#[derive(Debug, Serialize, Deserialize)]
+pub enum MergeOutcome {
+    /// The edit was saved as a new revision, merged with the current revision if necessary.
+    Updated(NoteLocator),
+    /// The edit was not saved.
+    Conflict(MergeConflict),
+}
+
+/// For each item of `base`, the position of the same item in `other`, if it's kept.
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut m = vec![None; base.len()];
+    for op in capture_diff_slices(Algorithm::Myers, base, other) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            for x in 0..len {
+                m[old_index + x] = Some(new_index + x);
+            }
+        }
+    }
+    m
+}
+
+/// Merge two sequences changed from `base`, or `None` if both changed the same region
+/// differently.
+fn merge_slices(base: &[&str], ours: &[&str], theirs: &[&str]) -> Option<String> {
+    let ours_m = matches(base, ours);
+    let theirs_m = matches(base, theirs);
+    let mut merged = String::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // The next item kept by both sides ends the region changed by either side
+        let stable = (i..base.len()).find_map(|b| Some((b, ours_m[b]?, theirs_m[b]?)));
+        let (b, o, t) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (base_chunk, ours_chunk, theirs_chunk) = (&base[i..b], &ours[j..o], &theirs[k..t]);
+        let chunk = if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+            theirs_chunk
+        } else if theirs_chunk == base_chunk {
+            ours_chunk
+        } else {
+            return None;
+        };
+        merged.extend(chunk.iter().copied());
+        match stable {
+            Some(_) => {
+                merged.push_str(base[b]);
+                (i, j, k) = (b + 1, o + 1, t + 1);
+            }
+            None => return Some(merged),
+        }
+    }
+}
+
+fn split_lines(s: &str) -> Vec<&str> {
+    s.split_inclusive('\n').collect()
+}
+
+/// Split text into words and the whitespace between them.
+fn split_words(s: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let end = chars.peek().map_or(s.len(), |(i, _)| *i);
+        let boundary = chars
+            .peek()
+            .is_none_or(|(_, next)| next.is_whitespace() != c.is_whitespace());
+        if boundary {
+            words.push(&s[start..end]);
+            start = end;
+        }
+    }
+    words
+}
+
+/// Merge two versions of a text changed from `base` line by line.
+pub fn merge_lines(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    merge_slices(&split_lines(base), &split_lines(ours), &split_lines(theirs))
+}
+
+/// Merge two versions of a text changed from `base` word by word.
+pub fn merge_words(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    merge_slices(&split_words(base), &split_words(ours), &split_words(theirs))
+}
+
+/// Update a note like [`NoteStore::update_note`], merging the edit with the current revision
+/// if the locator points to an older revision.
+///
+/// The revision specified by the locator is the common ancestor of the edit and the current
+/// revision.
+/// If both the title and the note body merge cleanly, the merged note is saved as a new
+/// revision, with the metadata changes of the edit applied.
+/// Otherwise, nothing is saved and the conflicting versions are returned.
+/// If the current revision keeps changing while saving, the edit is given up with
+/// [`NoteStoreError::UpdateOldRevision`] after a few attempts.
+///
+/// Locators of the current revision and revisions of deleted notes are passed to
+/// [`NoteStore::update_note`] as is, and a draft is continued with [`NoteStore::create_draft`].
+pub async fn update_note_merging<T, S>(
+    store: &S,
+    loc: &NoteLocator,
+    title: Option<String>,
+    note_inner: Option<T>,
+    note_metadata: NoteMetadataEditable,
+) -> Result<MergeOutcome, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    let (base_revision, base_title, base_inner, is_draft) = {
+        let base = store.get_note(loc).await?;
+        let base_inner: String = base.get_note_inner().into();
+        (
+            base.get_revision(),
+            base.get_title(),
+            base_inner,
+            base.is_draft(),
+        )
+    };
+    if is_draft {
+        let new_loc = store
+            .create_draft(loc, title, note_inner, note_metadata)
+            .await?;
+        return Ok(MergeOutcome::Updated(new_loc));
+    }
+    let ours_title = title.unwrap_or_else(|| base_title.clone());
+    let ours_inner: String = note_inner.map_or_else(|| base_inner.clone(), Into::into);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let current = store.get_current_revision(loc).await?;
+        let theirs_revision = match current {
+            Some(r) if r != base_revision => r,
+            _ => {
+                let res = store
+                    .update_note(
+                        &loc.at_revision(&base_revision),
+                        Some(ours_title.clone()),
+                        Some(T::from(ours_inner.clone())),
+                        note_metadata.clone(),
+                    )
+                    .await;
+                match res {
+                    Ok(new_loc) => return Ok(MergeOutcome::Updated(new_loc)),
+                    // The base was current a moment ago
+                    Err(NoteStoreError::UpdateOldRevision(..)) if attempts < MAX_MERGE_ATTEMPTS => {
+                        continue
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        let theirs_loc = loc.at_revision(&theirs_revision);
+        let (theirs_title, theirs_inner) = {
+            let theirs = store.get_note(&theirs_loc).await?;
+            let theirs_inner: String = theirs.get_note_inner().into();
+            (theirs.get_title(), theirs_inner)
+        };
+        let merged_title = merge_words(&base_title, &ours_title, &theirs_title);
+        let merged_inner = merge_lines(&base_inner, &ours_inner, &theirs_inner);
+        let (merged_title, merged_inner) = match (merged_title, merged_inner) {
+            (Some(t), Some(n)) => (t, n),
+            _ => {
+                return Ok(MergeOutcome::Conflict(MergeConflict {
+                    id: loc.get_id().clone(),
+                    base: base_revision,
+                    theirs: theirs_revision,
+                    title: MergeVersions {
+                        base: base_title,
+                        ours: ours_title,
+                        theirs: theirs_title,
+                    },
+                    note_inner: MergeVersions {
+                        base: base_inner,
+                        ours: ours_inner,
+                        theirs: theirs_inner,
+                    },
+                }))
+            }
+        };
+        let res = store
+            .update_note(
+                &theirs_loc,
+                Some(merged_title),
+                Some(T::from(merged_inner)),
+                note_metadata.clone(),
+            )
+            .await;
+        match res {
+            Ok(new_loc) => return Ok(MergeOutcome::Updated(new_loc)),
+            // Another edit came in between, merge with that one instead
+            Err(NoteStoreError::UpdateOldRevision(..)) if attempts < MAX_MERGE_ATTEMPTS => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let base = "Foo\nBar\nBaz\n";
+        assert_eq!(
+            merge_lines(base, "Foo\nBar!\nBaz\n", "Foo\nBar\nBaz\nQux\n").as_deref(),
+            Some("Foo\nBar!\nBaz\nQux\n")
+        );
+        assert_eq!(
+            merge_lines(base, "Bar\nBaz\n", "Foo\nBar\n").as_deref(),
+            Some("Bar\n")
+        );
+        // The same change on both sides
+        assert_eq!(
+            merge_lines(base, "Foo\nQux\nBaz\n", "Foo\nQux\nBaz\n").as_deref(),
+            Some("Foo\nQux\nBaz\n")
+        );
+        assert_eq!(
+            merge_lines(base, "Foo\nQux\nBaz\n", "Foo\nQuux\nBaz\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            split_words("a  quick\tfox "),
+            vec!["a", "  ", "quick", "\t", "fox", " "]
+        );
+        assert_eq!(
+            merge_words(
+                "Meeting notes",
+                "Weekly Meeting notes",
+                "Meeting notes 2024"
+            )
+            .as_deref(),
+            Some("Weekly Meeting notes 2024")
+        );
+        assert_eq!(merge_words("Foo", "Bar", "Baz"), None);
+        assert_eq!(merge_words("", "", "Foo").as_deref(), Some("Foo"));
+    }
+}
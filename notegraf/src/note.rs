@@ -2,6 +2,7 @@
 use crate::errors::NoteStoreError;
 use crate::notemetadata::NoteMetadata;
 use crate::notetype::NoteType;
+use chrono::{DateTime, Utc};
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
 use std::collections::HashSet;
@@ -169,6 +170,8 @@ pub trait Note<T: NoteType>: Debug {
     fn get_referents(&self) -> HashSet<NoteID>;
     fn get_metadata(&self) -> NoteMetadata;
     fn is_current(&self) -> bool;
+    fn get_based_on(&self) -> Option<Revision>;
+    fn is_draft(&self) -> bool;
 }
 
 pub enum NoteField {
@@ -184,6 +187,8 @@ pub enum NoteField {
     Referents,
     Metadata,
     IsCurrent,
+    BasedOn,
+    IsDraft,
 }
 
 pub struct NoteFieldSelector {
@@ -211,6 +216,8 @@ impl Default for NoteFieldSelector {
             NoteField::Referents,
             NoteField::Metadata,
             NoteField::IsCurrent,
+            NoteField::BasedOn,
+            NoteField::IsDraft,
         ])
     }
 }
@@ -267,6 +274,12 @@ where
                 NoteField::IsCurrent => {
                     s.serialize_field("is_current", &self.n.is_current())?;
                 }
+                NoteField::BasedOn => {
+                    s.serialize_field("based_on", &self.n.get_based_on())?;
+                }
+                NoteField::IsDraft => {
+                    s.serialize_field("is_draft", &self.n.is_draft())?;
+                }
             }
         }
         s.end()
@@ -282,11 +295,34 @@ impl<T> NoteSerializable<T> {
     }
 }
 
+/// Caller-supplied identity of a new note.
+///
+/// Normally, a [`NoteStore`](crate::NoteStore) assigns the [`NoteID`] and [`Revision`] of a new
+/// note, and stamps it with the current time.
+/// When importing notes from elsewhere, the original ones can be kept instead, so that existing
+/// links and history dates stay valid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteIdentity {
+    pub id: NoteID,
+    pub revision: Revision,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
 /// A type for locating a note.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NoteLocator {
     Current(NoteID),
     Specific(NoteID, Revision),
+    /// The revision that was current at a point in time.
+    ///
+    /// Note stores resolve this to the revision with the latest modification time up to that
+    /// point, unless the note had been deleted by then.
+    AtTime(NoteID, DateTime<Utc>),
+    /// The revision with a label attached, see [`NoteStore::add_label`].
+    ///
+    /// [`NoteStore::add_label`]: crate::NoteStore::add_label
+    Label(NoteID, String),
 }
 
 impl NoteLocator {
@@ -295,6 +331,30 @@ impl NoteLocator {
         match self {
             NoteLocator::Current(id) => id,
             NoteLocator::Specific(id, _) => id,
+            NoteLocator::AtTime(id, _) => id,
+            NoteLocator::Label(id, _) => id,
+        }
+    }
+
+    /// Get the label of the locator.
+    ///
+    /// If the locator is not specifying a label, returns `None`.
+    pub fn get_label(&self) -> Option<&str> {
+        if let NoteLocator::Label(_, label) = self {
+            Some(label)
+        } else {
+            None
+        }
+    }
+
+    /// Get the point in time of the locator.
+    ///
+    /// If the locator is not specifying a point in time, returns `None`.
+    pub fn get_time(&self) -> Option<&DateTime<Utc>> {
+        if let NoteLocator::AtTime(_, at) = self {
+            Some(at)
+        } else {
+            None
         }
     }
 
@@ -310,6 +370,9 @@ impl NoteLocator {
     }
 
     /// Return a ([`NoteID`], [`Revision`]) tuple.
+    ///
+    /// [`NoteLocator::AtTime`] and [`NoteLocator::Label`] should be resolved to a specific
+    /// revision first, since they are unpacked the same way as [`NoteLocator::Current`].
     pub fn unpack(&self) -> (&NoteID, Option<&Revision>) {
         (self.get_id(), self.get_revision())
     }
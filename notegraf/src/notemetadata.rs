@@ -1,3 +1,4 @@
+use crate::{NoteIdentity, Revision};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -11,6 +12,15 @@ pub struct NoteMetadata {
     pub modified_at: DateTime<Utc>,
     pub tags: HashSet<String>,
     pub custom_metadata: serde_json::Value,
+    /// The revision whose content this revision restored, if it was created by reverting.
+    #[serde(default)]
+    pub reverted_from: Option<Revision>,
+    /// Who made this revision, if known.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Why this revision was made, like a commit message.
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 impl Default for NoteMetadata {
@@ -22,6 +32,9 @@ impl Default for NoteMetadata {
             modified_at: now,
             tags: HashSet::new(),
             custom_metadata: serde_json::json!({}),
+            reverted_from: None,
+            author: None,
+            message: None,
         }
     }
 }
@@ -36,6 +49,9 @@ impl NoteMetadata {
             modified_at: Utc::now(),
             tags: self.tags.clone(),
             custom_metadata: self.custom_metadata.clone(),
+            reverted_from: None,
+            author: None,
+            message: None,
         }
     }
 
@@ -45,6 +61,16 @@ impl NoteMetadata {
         nm
     }
 
+    /// Metadata of a new note, keeping the timestamps of the identity if supplied.
+    pub(crate) fn on_new_note(m: NoteMetadataEditable, identity: Option<&NoteIdentity>) -> Self {
+        let mut nm = NoteMetadata::from_editable(m);
+        if let Some(i) = identity {
+            nm.created_at = i.created_at;
+            nm.modified_at = i.modified_at;
+        }
+        nm
+    }
+
     pub fn apply_editable(mut self, m: NoteMetadataEditable) -> Self {
         m.apply(&mut self);
         self
@@ -55,6 +81,12 @@ impl NoteMetadata {
 pub struct NoteMetadataEditable {
     pub tags: Option<HashSet<String>>,
     pub custom_metadata: Option<serde_json::Value>,
+    /// The author of the new revision.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// The change message of the new revision.
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 impl NoteMetadataEditable {
@@ -65,12 +97,20 @@ impl NoteMetadataEditable {
         if let Some(c) = self.custom_metadata {
             n.custom_metadata = c;
         }
+        if let Some(a) = self.author {
+            n.author = Some(a);
+        }
+        if let Some(m) = self.message {
+            n.message = Some(m);
+        }
     }
 
     pub fn unchanged() -> Self {
         NoteMetadataEditable {
             tags: None,
             custom_metadata: None,
+            author: None,
+            message: None,
         }
     }
 }
@@ -103,6 +143,8 @@ mod tests {
         let new_metadata = NoteMetadataEditable {
             tags: Some(tags),
             custom_metadata: None,
+            author: None,
+            message: None,
         };
         let loc2 = store
             .update_note(&loc1, None, None, new_metadata)
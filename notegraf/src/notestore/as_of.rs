@@ -0,0 +1,46 @@
+//! Viewing a note store as it was at a point in time.
+use crate::errors::NoteStoreError;
+use crate::notestore::search::SearchRequest;
+use crate::notestore::Revisions;
+use crate::{Note, NoteID, NoteLocator, NoteStore, NoteType};
+use chrono::{DateTime, Utc};
+use std::marker::PhantomData;
+
+/// A read-only view of a note store as of a point in time.
+///
+/// Each note is seen at the revision that was current at that time, and the relationships
+/// between notes are computed between those revisions.
+/// Notes that were created later, or had been deleted by then, are not seen.
+pub struct AsOf<'a, T, S: ?Sized> {
+    store: &'a S,
+    at: DateTime<Utc>,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T, S> AsOf<'a, T, S>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    pub fn new(store: &'a S, at: DateTime<Utc>) -> Self {
+        AsOf {
+            store,
+            at,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+
+    pub async fn get_note(&self, id: &NoteID) -> Result<Box<dyn Note<T>>, NoteStoreError> {
+        self.store
+            .get_note(&NoteLocator::AtTime(id.clone(), self.at))
+            .await
+    }
+
+    pub async fn search(&self, sr: &SearchRequest) -> Result<Revisions<T>, NoteStoreError> {
+        self.store.search_at(sr, self.at).await
+    }
+}
@@ -0,0 +1,60 @@
+//! Backend-neutral backup format.
+//!
+//! A backup is a folder containing a file in the [`interchange`](super::interchange) format,
+//! which holds every revision of every note (including deleted notes), and a file of
+//! [`SessionDraft`]s, one JSON object per line.
+//! Backups produced by one [`NoteStore`](crate::NoteStore) can be restored by any other.
+//! Backups made before session drafts were backed up don't have the second file, and are restored
+//! without session drafts.
+use crate::errors::NoteStoreError;
+use crate::notestore::interchange::{read_records, write_header, write_record, RevisionRecord};
+use crate::notestore::session_draft::SessionDraft;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Name of the backup file within the backup folder.
+pub static BACKUP_FILE_NAME: &str = "notegraf_backup.ndjson";
+/// Name of the file of session drafts within the backup folder.
+pub static SESSION_DRAFTS_FILE_NAME: &str = "notegraf_session_drafts.ndjson";
+
+/// Write records and session drafts to the backup files under `path`.
+pub fn write_backup<P: AsRef<Path>>(
+    path: P,
+    records: &[RevisionRecord],
+    session_drafts: &[SessionDraft],
+) -> Result<(), NoteStoreError> {
+    let p = path.as_ref().join(BACKUP_FILE_NAME);
+    let mut f = BufWriter::new(File::create(p)?);
+    write_header(&mut f)?;
+    for r in records {
+        write_record(&mut f, r)?;
+    }
+    f.flush()?;
+    let p = path.as_ref().join(SESSION_DRAFTS_FILE_NAME);
+    let mut f = BufWriter::new(File::create(p)?);
+    for d in session_drafts {
+        serde_json::to_writer(&mut f, d)?;
+        f.write_all(b"\n")?;
+    }
+    f.flush()?;
+    Ok(())
+}
+
+/// Read records and session drafts from the backup files under `path`.
+pub fn read_backup<P: AsRef<Path>>(
+    path: P,
+) -> Result<(Vec<RevisionRecord>, Vec<SessionDraft>), NoteStoreError> {
+    let p = path.as_ref().join(BACKUP_FILE_NAME);
+    let records = read_records(BufReader::new(File::open(p)?))?.collect::<Result<_, _>>()?;
+    let p = path.as_ref().join(SESSION_DRAFTS_FILE_NAME);
+    if !p.exists() {
+        return Ok((records, vec![]));
+    }
+    let session_drafts = BufReader::new(File::open(p)?)
+        .lines()
+        .filter(|l| !matches!(l, Ok(s) if s.trim().is_empty()))
+        .map(|l| Ok(serde_json::from_str(&l?)?))
+        .collect::<Result<_, NoteStoreError>>()?;
+    Ok((records, session_drafts))
+}
@@ -0,0 +1,97 @@
+//! The change feed of a note store.
+//!
+//! Every change to the notes made through a [`NoteStore`] is recorded with a [`ChangeCursor`],
+//! which increases in the order that the changes take effect.
+//! [`NoteStore::subscribe`] streams the changes as they happen, and
+//! [`NoteStore::changes_since`] gets the changes after a cursor, e.g., for clients that were
+//! offline.
+//!
+//! A client that wants to miss nothing subscribes first, then gets the changes since the last
+//! cursor it has seen, and skips the streamed changes with a cursor it has seen already.
+//! Creating drafts, labels, purging, importing and restoring are not recorded.
+//!
+//! [`NoteStore`]: crate::NoteStore
+//! [`NoteStore::subscribe`]: crate::NoteStore::subscribe
+//! [`NoteStore::changes_since`]: crate::NoteStore::changes_since
+use crate::NoteLocator;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// The position of a change in the change feed.
+///
+/// The first change has a cursor larger than 0, so `changes_since(0)` gets all changes.
+pub type ChangeCursor = u64;
+
+/// A stream of changes, see [`NoteStore::subscribe`].
+///
+/// [`NoteStore::subscribe`]: crate::NoteStore::subscribe
+pub type ChangeStream = BoxStream<'static, Change>;
+
+/// What happened to a note.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// A top-level note was created.
+    Create,
+    /// A note got a new current revision, by an update, revert, move, promoted draft, or by
+    /// splitting or merging sequences.
+    ///
+    /// Notes next to a note that is added, moved or deleted may get new revisions too,
+    /// and each of them is recorded as an update.
+    Update,
+    /// A note was deleted.
+    Delete,
+    /// A note was added to a sequence, at the end or in the middle.
+    Append,
+    /// A note was added as a branch of another note.
+    Branch,
+}
+
+impl ChangeKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Append => "append",
+            ChangeKind::Branch => "branch",
+        }
+    }
+
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "create" => Some(ChangeKind::Create),
+            "update" => Some(ChangeKind::Update),
+            "delete" => Some(ChangeKind::Delete),
+            "append" => Some(ChangeKind::Append),
+            "branch" => Some(ChangeKind::Branch),
+            _ => None,
+        }
+    }
+}
+
+/// A change to a note.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub cursor: ChangeCursor,
+    pub kind: ChangeKind,
+    /// The revision created by the change, or the deleted revision for
+    /// [`ChangeKind::Delete`].
+    ///
+    /// Splitting or merging sequences changes the position of a note, which doesn't always
+    /// create a revision of that note, so the locator points to its current revision instead.
+    pub loc: NoteLocator,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Stream the changes sent to a broadcast channel.
+///
+/// The stream ends if the receiver falls behind.
+pub(crate) fn broadcast_stream(receiver: broadcast::Receiver<Change>) -> ChangeStream {
+    Box::pin(stream::unfold(receiver, |mut receiver| async move {
+        let change = receiver.recv().await.ok()?;
+        Some((change, receiver))
+    }))
+}
@@ -2,19 +2,31 @@
 use crate::errors::NoteStoreError;
 use crate::note::NoteLocator;
 use crate::notemetadata::{NoteMetadata, NoteMetadataEditable};
+use crate::notestore::backup::{read_backup, write_backup, BACKUP_FILE_NAME};
+use crate::notestore::changes::{broadcast_stream, Change, ChangeCursor, ChangeKind, ChangeStream};
+use crate::notestore::interchange::{
+    check_note_records, group_by_note, RecordStream, RevisionRecord,
+};
+use crate::notestore::label::RevisionLabel;
+use crate::notestore::position::{Ancestor, Link, MoveTarget, SplitTail};
 use crate::notestore::search::SearchRequest;
+use crate::notestore::session_draft::SessionDraft;
+use crate::notestore::trash::{DeletedNote, DeletionPeriod};
 use crate::notestore::Revisions;
-use crate::{Note, NoteID, NoteStore, NoteType, Revision};
+use crate::{Note, NoteID, NoteIdentity, NoteStore, NoteType, Revision};
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::marker::PhantomData;
 use std::path::Path;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+/// How many changes a subscriber can fall behind before its stream ends.
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InMemoryNoteStored<T> {
@@ -25,6 +37,10 @@ pub struct InMemoryNoteStored<T> {
     branches: HashSet<NoteID>,
     next: Option<NoteID>,
     metadata: NoteMetadata,
+    #[serde(default)]
+    based_on: Option<Revision>,
+    #[serde(default)]
+    is_draft: bool,
     _phantom: PhantomData<T>,
 }
 
@@ -42,6 +58,8 @@ struct InMemoryNoteComputed<T> {
     referents: HashSet<NoteID>,
     references: HashSet<NoteID>,
     metadata: NoteMetadata,
+    based_on: Option<Revision>,
+    is_draft: bool,
 }
 
 impl<T> Note<T> for InMemoryNoteComputed<T>
@@ -95,17 +113,33 @@ where
     fn is_current(&self) -> bool {
         self.is_current
     }
+
+    fn get_based_on(&self) -> Option<Revision> {
+        self.based_on.clone()
+    }
+
+    fn is_draft(&self) -> bool {
+        self.is_draft
+    }
 }
 
 /// In-memory storage.
 ///
 /// This is mostly designed for development use, because there is no persistence layer.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 struct InMemoryStoreInner<T> {
     pub notes: HashMap<NoteID, HashMap<Revision, InMemoryNoteStored<T>>>,
     current_revision: HashMap<NoteID, Revision>,
+    deleted_at: HashMap<NoteID, DateTime<Utc>>,
+    past_deletions: HashMap<NoteID, Vec<DeletionPeriod>>,
+    labels: HashMap<NoteID, BTreeMap<String, Revision>>,
+    session_drafts: BTreeMap<String, SessionDraft>,
     note_id_counter: u64,
     revision_id_counter: HashMap<NoteID, u64>,
+    changes: Vec<Change>,
+    /// The cursor of the last change pruned.
+    pruned_cursor: ChangeCursor,
+    change_sender: broadcast::Sender<Change>,
 }
 
 impl<T: NoteType> Default for InMemoryStoreInner<T> {
@@ -113,8 +147,15 @@ impl<T: NoteType> Default for InMemoryStoreInner<T> {
         InMemoryStoreInner {
             notes: Default::default(),
             current_revision: Default::default(),
+            deleted_at: Default::default(),
+            past_deletions: Default::default(),
+            labels: Default::default(),
+            session_drafts: Default::default(),
             note_id_counter: 0,
             revision_id_counter: Default::default(),
+            changes: Default::default(),
+            pruned_cursor: 0,
+            change_sender: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
         }
     }
 }
@@ -154,21 +195,35 @@ impl<T: NoteType> InMemoryStoreInner<T> {
 
     /// Generate a new [`NoteID`].
     ///
-    /// We use a deterministic sequential format for easy testing
+    /// We use a deterministic sequential format for easy testing.
+    /// IDs that are already taken (e.g., restored from a backup) are skipped.
     fn get_new_noteid(&mut self) -> NoteID {
-        let note_id = NoteID::new(format!("note-{}", self.note_id_counter));
-        self.note_id_counter += 1;
-        note_id
+        loop {
+            let note_id = NoteID::new(format!("note-{}", self.note_id_counter));
+            self.note_id_counter += 1;
+            if !self.notes.contains_key(&note_id) {
+                return note_id;
+            }
+        }
     }
 
     /// Generate a new [`Revision`].
     ///
-    /// We use a deterministic sequential format for easy testing
+    /// We use a deterministic sequential format for easy testing.
+    /// Revisions that are already taken (e.g., restored from a backup) are skipped.
     fn get_new_revision(&mut self, note_id: &NoteID) -> Revision {
         let revision_counter = self.revision_id_counter.entry(note_id.clone()).or_insert(0);
-        let revision = Revision::new(format!("revision-{}", *revision_counter));
-        *revision_counter += 1;
-        revision
+        loop {
+            let revision = Revision::new(format!("revision-{}", *revision_counter));
+            *revision_counter += 1;
+            if !self
+                .notes
+                .get(note_id)
+                .is_some_and(|rs| rs.contains_key(&revision))
+            {
+                return revision;
+            }
+        }
     }
 
     /// Does the locator points to a current revision
@@ -218,7 +273,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     {
         let (id, rev) = loc.unpack();
         let is_resurrecting = self.is_deleted(loc)?;
-        let old_note = if is_resurrecting || self.is_current(loc)? {
+        let mut old_note = if is_resurrecting || self.is_current(loc)? {
             self.get_note_stored(loc)?
         } else {
             return Err(NoteStoreError::UpdateOldRevision(
@@ -226,6 +281,11 @@ impl<T: NoteType> InMemoryStoreInner<T> {
                 rev.unwrap().clone(),
             ));
         };
+        let deleted_at = if is_resurrecting {
+            self.get_deleted_at(id)
+        } else {
+            None
+        };
         // get new revision number
         let new_revision = self.get_new_revision(id);
         let note_revisions = self
@@ -235,9 +295,11 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         // sanity check
         assert!(!note_revisions.contains_key(&new_revision));
         // update note
+        old_note.metadata = old_note.metadata.on_update_note();
+        old_note.based_on = Some(old_note.revision.clone());
+        old_note.is_draft = false;
         let mut updated_note = op(&old_note)?;
         updated_note.revision = new_revision.clone();
-        updated_note.metadata = updated_note.metadata.on_update_note();
         if is_resurrecting {
             // If a note has branches, it cannot be deleted in the first place
             assert!(updated_note.branches.is_empty());
@@ -245,9 +307,20 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             // note now has a prev
             updated_note.next = None;
         }
+        let restored_at = updated_note.metadata.modified_at;
         note_revisions.insert(new_revision.clone(), updated_note);
         self.current_revision
             .insert(id.clone(), new_revision.clone());
+        if let Some(deleted_at) = deleted_at {
+            self.past_deletions
+                .entry(id.clone())
+                .or_default()
+                .push(DeletionPeriod {
+                    deleted_at,
+                    restored_at,
+                });
+        }
+        self.deleted_at.remove(id);
         Ok(NoteLocator::Specific(id.clone(), new_revision))
     }
 
@@ -267,7 +340,11 @@ impl<T: NoteType> InMemoryStoreInner<T> {
 
     fn get_note_stored(&self, loc: &NoteLocator) -> Result<InMemoryNoteStored<T>, NoteStoreError> {
         let (id, rev) = loc.unpack();
-        if let Some(r) = rev {
+        if let Some(at) = loc.get_time() {
+            self.get_note_at(id, at, &self.heads_at(at))
+        } else if let Some(label) = loc.get_label() {
+            self.get_note_by_revision(id, &self.get_label_revision(id, label)?)
+        } else if let Some(r) = rev {
             self.get_note_by_revision(id, r)
         } else {
             let cr = self.get_current_revision(loc)?;
@@ -282,9 +359,95 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         }
     }
 
+    fn get_note_at(
+        &self,
+        id: &NoteID,
+        at: &DateTime<Utc>,
+        heads: &HashMap<NoteID, Revision>,
+    ) -> Result<InMemoryNoteStored<T>, NoteStoreError> {
+        if !self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        match heads.get(id) {
+            Some(r) => self.get_note_by_revision(id, r),
+            None => Err(NoteStoreError::NoteNotExistAtTime(id.clone(), *at)),
+        }
+    }
+
+    /// The revision of each note that was current at a point in time.
+    ///
+    /// Notes that were deleted at that time are left out, even if they were restored later, and so
+    /// are drafts.
+    /// As in [`InMemoryStoreInner::list_deleted`], the deletion time of a note falls back to the
+    /// modification time of its last revision.
+    fn heads_at(&self, at: &DateTime<Utc>) -> HashMap<NoteID, Revision> {
+        let mut heads = HashMap::new();
+        for (id, revisions) in &self.notes {
+            let mainline = || revisions.values().filter(|n| !n.is_draft);
+            let head = mainline()
+                .filter(|n| &n.metadata.modified_at <= at)
+                .max_by_key(|n| n.metadata.modified_at);
+            let last = mainline().max_by_key(|n| n.metadata.modified_at);
+            if let (Some(head), Some(last)) = (head, last) {
+                if !self.current_revision.contains_key(id) {
+                    let deleted_at = self
+                        .deleted_at
+                        .get(id)
+                        .unwrap_or(&last.metadata.modified_at);
+                    if deleted_at <= at {
+                        continue;
+                    }
+                }
+                // Notes that were deleted at that time but restored later
+                if self
+                    .past_deletions
+                    .get(id)
+                    .into_iter()
+                    .flatten()
+                    .any(|d| &d.deleted_at <= at && at < &d.restored_at)
+                {
+                    continue;
+                }
+                heads.insert(id.clone(), head.revision.clone());
+            }
+        }
+        heads
+    }
+
+    fn get_label_revision(&self, id: &NoteID, label: &str) -> Result<Revision, NoteStoreError> {
+        if !self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        self.labels
+            .get(id)
+            .and_then(|labels| labels.get(label))
+            .cloned()
+            .ok_or_else(|| NoteStoreError::LabelNotExist(id.clone(), label.to_owned()))
+    }
+
+    /// Turn a [`NoteLocator::AtTime`] or a [`NoteLocator::Label`] into a locator of the specific
+    /// revision.
+    fn resolve(&self, loc: &NoteLocator) -> Result<NoteLocator, NoteStoreError> {
+        Ok(match loc {
+            NoteLocator::AtTime(..) | NoteLocator::Label(..) => {
+                loc.at_revision(&self.get_note_stored(loc)?.revision)
+            }
+            NoteLocator::Specific(_, r) => loc.at_revision(r),
+            NoteLocator::Current(_) => loc.current(),
+        })
+    }
+
     fn get_references(&self, referent: &NoteID) -> HashSet<NoteID> {
+        self.get_references_in(&self.current_revision, referent)
+    }
+
+    fn get_references_in(
+        &self,
+        heads: &HashMap<NoteID, Revision>,
+        referent: &NoteID,
+    ) -> HashSet<NoteID> {
         let mut references = HashSet::new();
-        for (id, revision) in &self.current_revision {
+        for (id, revision) in heads {
             let note = self.get_note_by_revision(id, revision).unwrap();
             if T::from(note.note_inner)
                 .get_referents()
@@ -298,7 +461,11 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     }
 
     fn get_parent(&self, child: &NoteID) -> Option<NoteID> {
-        for (id, revision) in &self.current_revision {
+        self.get_parent_in(&self.current_revision, child)
+    }
+
+    fn get_parent_in(&self, heads: &HashMap<NoteID, Revision>, child: &NoteID) -> Option<NoteID> {
+        for (id, revision) in heads {
             let note = self.get_note_by_revision(id, revision).unwrap();
             if note.branches.contains(child) {
                 return Some(note.id);
@@ -308,7 +475,11 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     }
 
     fn get_prev(&self, next: &NoteID) -> Option<NoteID> {
-        for (id, revision) in &self.current_revision {
+        self.get_prev_in(&self.current_revision, next)
+    }
+
+    fn get_prev_in(&self, heads: &HashMap<NoteID, Revision>, next: &NoteID) -> Option<NoteID> {
+        for (id, revision) in heads {
             let note = self.get_note_by_revision(id, revision).unwrap();
             if let Some(ref nn) = note.next {
                 if nn == next {
@@ -320,20 +491,46 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     }
 
     fn get_all_current_notes(&self) -> Vec<InMemoryNoteStored<T>> {
-        self.current_revision
+        self.get_all_notes_in(&self.current_revision)
+    }
+
+    fn get_all_notes_in(&self, heads: &HashMap<NoteID, Revision>) -> Vec<InMemoryNoteStored<T>> {
+        heads
             .iter()
             .map(|(id, revision)| self.get_note_by_revision(id, revision).unwrap())
             .collect()
     }
 
+    /// Record a change in the change feed and send it to the subscribers.
+    fn record_change(&mut self, kind: ChangeKind, loc: &NoteLocator) {
+        let change = Change {
+            cursor: self.changes.last().map_or(self.pruned_cursor, |c| c.cursor) + 1,
+            kind,
+            loc: loc.clone(),
+            changed_at: Utc::now(),
+        };
+        self.changes.push(change.clone());
+        // There may be no subscriber
+        let _ = self.change_sender.send(change);
+    }
+
     fn new_note_helper(
         &mut self,
+        identity: Option<NoteIdentity>,
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
-    ) -> NoteLocator {
-        let id = self.get_new_noteid();
-        let revision = self.get_new_revision(&id);
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let (id, revision) = if let Some(i) = &identity {
+            if self.notes.contains_key(&i.id) {
+                return Err(NoteStoreError::NoteIDConflict(i.id.clone()));
+            }
+            (i.id.clone(), i.revision.clone())
+        } else {
+            let id = self.get_new_noteid();
+            let revision = self.get_new_revision(&id);
+            (id, revision)
+        };
         let note = InMemoryNoteStored {
             title,
             note_inner: note_inner.into(),
@@ -341,7 +538,9 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             revision: revision.clone(),
             branches: Default::default(),
             next: None,
-            metadata: NoteMetadata::from_editable(metadata),
+            metadata: NoteMetadata::on_new_note(metadata, identity.as_ref()),
+            based_on: None,
+            is_draft: false,
             _phantom: PhantomData,
         };
         assert!(!self.notes.contains_key(&id));
@@ -353,31 +552,43 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             .insert(revision.clone(), note);
         assert!(!self.current_revision.contains_key(&id));
         self.current_revision.insert(id.clone(), revision.clone());
-        NoteLocator::Specific(id, revision)
+        Ok(NoteLocator::Specific(id, revision))
     }
 
     // The methods above are helper methods
     // The methods below are to implement the NoteStore interface
     fn new_note(
         &mut self,
+        identity: Option<NoteIdentity>,
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
-        Ok(self.new_note_helper(title, note_inner, metadata))
+        let loc = self.new_note_helper(identity, title, note_inner, metadata)?;
+        self.record_change(ChangeKind::Create, &loc);
+        Ok(loc)
     }
 
     fn compute_stored_note(
         &self,
         s: InMemoryNoteStored<T>,
+    ) -> Result<InMemoryNoteComputed<T>, NoteStoreError> {
+        self.compute_stored_note_in(&self.current_revision, s)
+    }
+
+    /// Compute the relationships of a note between the given revisions of each note.
+    fn compute_stored_note_in(
+        &self,
+        heads: &HashMap<NoteID, Revision>,
+        s: InMemoryNoteStored<T>,
     ) -> Result<InMemoryNoteComputed<T>, NoteStoreError> {
         let note_inner = T::from(s.note_inner);
         let referents = note_inner
             .get_referents()
             .map_err(|e| NoteStoreError::ParseError(format!("{e:?}")))?;
-        let references = self.get_references(&s.id);
-        let parent = self.get_parent(&s.id);
-        let prev = self.get_prev(&s.id);
+        let references = self.get_references_in(heads, &s.id);
+        let parent = self.get_parent_in(heads, &s.id);
+        let prev = self.get_prev_in(heads, &s.id);
         let current_revision = self.get_current_revision(&NoteLocator::Current(s.id.clone()))?;
         let is_current = if let Some(r) = current_revision {
             s.revision == r
@@ -397,10 +608,17 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             referents,
             references,
             metadata: s.metadata,
+            based_on: s.based_on,
+            is_draft: s.is_draft,
         })
     }
 
     fn get_note(&self, loc: &NoteLocator) -> Result<Box<dyn Note<T>>, NoteStoreError> {
+        if let NoteLocator::AtTime(id, at) = loc {
+            let heads = self.heads_at(at);
+            let note_stored = self.get_note_at(id, at, &heads)?;
+            return Ok(Box::new(self.compute_stored_note_in(&heads, note_stored)?));
+        }
         let note_stored = self.get_note_stored(loc)?;
         Ok(Box::new(self.compute_stored_note(note_stored)?) as Box<dyn Note<T>>)
     }
@@ -412,7 +630,8 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         note_inner: Option<T>,
         note_metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
-        self.update_note_helper(loc, |old_note| {
+        let loc = &self.resolve(loc)?;
+        let new_loc = self.update_note_helper(loc, |old_note| {
             let mut note = old_note.clone();
             if let Some(t) = title {
                 note.title = t;
@@ -423,10 +642,316 @@ impl<T: NoteType> InMemoryStoreInner<T> {
 
             note.metadata = note.metadata.apply_editable(note_metadata);
             Ok(note)
-        })
+        })?;
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(new_loc)
+    }
+
+    fn revert_note(&mut self, loc: &NoteLocator) -> Result<NoteLocator, NoteStoreError> {
+        let loc = &self.resolve(loc)?;
+        let target = self.get_note_stored(loc)?;
+        let base = if self.is_deleted(loc)? {
+            loc.at_revision(&target.revision)
+        } else {
+            loc.current()
+        };
+        let new_loc = self.update_note_helper(&base, |old_note| {
+            let mut note = old_note.clone();
+            note.title = target.title;
+            note.note_inner = target.note_inner;
+            note.metadata.tags = target.metadata.tags;
+            note.metadata.custom_metadata = target.metadata.custom_metadata;
+            note.metadata.reverted_from = Some(target.revision);
+            Ok(note)
+        })?;
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(new_loc)
+    }
+
+    fn create_draft(
+        &mut self,
+        loc: &NoteLocator,
+        title: Option<String>,
+        note_inner: Option<T>,
+        note_metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let base = self.get_note_stored(loc)?;
+        let id = &base.id;
+        let new_revision = self.get_new_revision(id);
+        let mut note = base.clone();
+        note.revision = new_revision.clone();
+        if let Some(t) = title {
+            note.title = t;
+        }
+        if let Some(n) = note_inner {
+            note.note_inner = n.into();
+        }
+        note.metadata = base.metadata.on_update_note().apply_editable(note_metadata);
+        // A draft is not part of any sequence or tree
+        note.branches = Default::default();
+        note.next = None;
+        note.based_on = Some(base.revision);
+        note.is_draft = true;
+        self.notes
+            .get_mut(id)
+            .unwrap()
+            .insert(new_revision.clone(), note);
+        Ok(NoteLocator::Specific(id.clone(), new_revision))
+    }
+
+    fn list_drafts(&self, id: &NoteID) -> Result<Revisions<T>, NoteStoreError> {
+        let revisions = self
+            .notes
+            .get(id)
+            .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))?;
+        let mut drafts: Vec<&InMemoryNoteStored<T>> = revisions
+            .values()
+            .filter(|n| {
+                n.is_draft
+                    && !revisions
+                        .values()
+                        .any(|r| r.based_on.as_ref() == Some(&n.revision))
+            })
+            .collect();
+        drafts.sort_by_key(|n| n.metadata.modified_at);
+        drafts
+            .into_iter()
+            .map(|n| {
+                self.compute_stored_note(n.clone())
+                    .map(|n_computed| Box::new(n_computed) as Box<dyn Note<T>>)
+            })
+            .collect()
+    }
+
+    fn promote_draft(&mut self, loc: &NoteLocator) -> Result<NoteLocator, NoteStoreError> {
+        let loc = &self.resolve(loc)?;
+        let draft = self.get_note_stored(loc)?;
+        if !draft.is_draft {
+            return Err(NoteStoreError::NotADraft(draft.id, draft.revision));
+        }
+        let base = if self.is_deleted(loc)? {
+            loc.at_revision(&draft.revision)
+        } else {
+            loc.current()
+        };
+        let new_loc = self.update_note_helper(&base, |old_note| {
+            let mut note = old_note.clone();
+            note.title = draft.title;
+            note.note_inner = draft.note_inner;
+            note.metadata.tags = draft.metadata.tags;
+            note.metadata.custom_metadata = draft.metadata.custom_metadata;
+            note.metadata.author = draft.metadata.author;
+            note.metadata.message = draft.metadata.message;
+            note.based_on = Some(draft.revision);
+            Ok(note)
+        })?;
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(new_loc)
+    }
+
+    fn changes_since(&self, cursor: ChangeCursor) -> Vec<Change> {
+        let start = self.changes.partition_point(|c| c.cursor <= cursor);
+        self.changes[start..].to_vec()
+    }
+
+    fn prune_changes(&mut self, before: &DateTime<Utc>) -> u64 {
+        let end = self.changes.partition_point(|c| c.changed_at < *before);
+        if end > 0 {
+            self.pruned_cursor = self.changes[end - 1].cursor;
+        }
+        self.changes.drain(..end).count() as u64
+    }
+
+    /// Let the next note of a note take over its position, before the note is removed from it.
+    ///
+    /// Since only next is stored, our next note is not aware of us.
+    /// But we want to make sure our prev note or parent is consistent.
+    fn hand_over_position(
+        &mut self,
+        id: &NoteID,
+        next: &Option<NoteID>,
+    ) -> Result<(), NoteStoreError> {
+        if let Some(prev_id) = self.get_prev(id) {
+            let new_loc = self.update_note_helper(&NoteLocator::Current(prev_id), |old_note| {
+                let mut parent_note = old_note.clone();
+                assert_eq!(parent_note.next.as_ref(), Some(id));
+                parent_note.next.clone_from(next);
+                Ok(parent_note)
+            })?;
+            self.record_change(ChangeKind::Update, &new_loc);
+        }
+        if let Some(parent_id) = self.get_parent(id) {
+            let new_loc =
+                self.update_note_helper(&NoteLocator::Current(parent_id), |old_note| {
+                    let mut parent_note = old_note.clone();
+                    assert!(parent_note.branches.contains(id));
+                    parent_note.branches.remove(id);
+                    if let Some(next_id) = next {
+                        parent_note.branches.insert(next_id.clone());
+                    }
+                    Ok(parent_note)
+                })?;
+            self.record_change(ChangeKind::Update, &new_loc);
+        }
+        Ok(())
+    }
+
+    /// Check that a note can be attached to the target once it leaves its current position.
+    ///
+    /// If `hand_over` is set, the next note of the note takes over its position, as in
+    /// [`InMemoryStoreInner::hand_over_position`].
+    /// Otherwise, the next notes move together with the note.
+    fn check_acyclic(
+        &self,
+        id: &NoteID,
+        target: &NoteID,
+        hand_over: bool,
+    ) -> Result<(), NoteStoreError> {
+        let mut cursor = target.clone();
+        let mut via_prev = false;
+        loop {
+            // Reaching the note through its next note is fine, because the next note will take
+            // over the position of the note
+            if &cursor == id && !(hand_over && via_prev) {
+                return Err(NoteStoreError::WouldCreateCycle(id.clone(), target.clone()));
+            }
+            if let Some(prev) = self.get_prev(&cursor) {
+                cursor = prev;
+                via_prev = true;
+            } else if let Some(parent) = self.get_parent(&cursor) {
+                cursor = parent;
+                via_prev = false;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Get the last note of the sequence containing a note, ignoring the note being moved.
+    fn get_sequence_end(
+        &self,
+        start: &NoteID,
+        moving: &InMemoryNoteStored<T>,
+    ) -> Result<NoteID, NoteStoreError> {
+        let mut cursor = start.clone();
+        loop {
+            let mut next = self
+                .get_note_stored(&NoteLocator::Current(cursor.clone()))?
+                .next;
+            if next.as_ref() == Some(&moving.id) {
+                next.clone_from(&moving.next);
+            }
+            match next {
+                Some(n) => cursor = n,
+                None => return Ok(cursor),
+            }
+        }
+    }
+
+    fn move_note(
+        &mut self,
+        id: &NoteID,
+        target: &MoveTarget,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let loc = NoteLocator::Current(id.clone());
+        let note = self.get_note_stored(&loc)?;
+        // Check everything before making any change
+        match target {
+            MoveTarget::Branch(parent_id) => {
+                self.get_note_stored(&NoteLocator::Current(parent_id.clone()))?;
+                self.check_acyclic(id, parent_id, true)?;
+            }
+            MoveTarget::TopLevel => {}
+            MoveTarget::EndOf(sequence_id) => {
+                if sequence_id == id {
+                    return Err(NoteStoreError::WouldCreateCycle(id.clone(), id.clone()));
+                }
+                self.get_note_stored(&NoteLocator::Current(sequence_id.clone()))?;
+            }
+        }
+        let end = if let MoveTarget::EndOf(sequence_id) = target {
+            let end = self.get_sequence_end(sequence_id, &note)?;
+            self.check_acyclic(id, &end, true)?;
+            Some(end)
+        } else {
+            None
+        };
+        let new_loc = self.update_note_helper(&loc, |old_note| {
+            let mut n = old_note.clone();
+            n.next = None;
+            Ok(n)
+        })?;
+        self.hand_over_position(id, &note.next)?;
+        if let MoveTarget::Branch(parent_id) = target {
+            let parent_loc =
+                self.update_note_helper(&NoteLocator::Current(parent_id.clone()), |old_note| {
+                    let mut parent_note = old_note.clone();
+                    parent_note.branches.insert(id.clone());
+                    Ok(parent_note)
+                })?;
+            self.record_change(ChangeKind::Update, &parent_loc);
+        }
+        if let Some(end_id) = end {
+            let end_loc = self.update_note_helper(&NoteLocator::Current(end_id), |old_note| {
+                let mut prev_note = old_note.clone();
+                assert!(prev_note.next.is_none());
+                prev_note.next = Some(id.clone());
+                Ok(prev_note)
+            })?;
+            self.record_change(ChangeKind::Update, &end_loc);
+        }
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(new_loc)
+    }
+
+    fn split_sequence(&mut self, at: &NoteID, tail: SplitTail) -> Result<(), NoteStoreError> {
+        self.get_note_stored(&NoteLocator::Current(at.clone()))?;
+        let prev = self
+            .get_prev(at)
+            .ok_or_else(|| NoteStoreError::SplitAtHead(at.clone()))?;
+        let new_loc = self.update_note_helper(&NoteLocator::Current(prev), |old_note| {
+            let mut prev_note = old_note.clone();
+            prev_note.next = None;
+            if tail == SplitTail::Branch {
+                prev_note.branches.insert(at.clone());
+            }
+            Ok(prev_note)
+        })?;
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(())
+    }
+
+    fn merge_sequences(&mut self, last: &NoteID, head: &NoteID) -> Result<(), NoteStoreError> {
+        let last_loc = NoteLocator::Current(last.clone());
+        let last_note = self.get_note_stored(&last_loc)?;
+        self.get_note_stored(&NoteLocator::Current(head.clone()))?;
+        if let Some(n) = last_note.next {
+            return Err(NoteStoreError::ExistingNext(last.clone(), n));
+        }
+        if let Some(p) = self.get_prev(head) {
+            return Err(NoteStoreError::ExistingPrev(p, head.clone()));
+        }
+        self.check_acyclic(head, last, false)?;
+        if let Some(parent_id) = self.get_parent(head) {
+            let parent_loc =
+                self.update_note_helper(&NoteLocator::Current(parent_id), |old_note| {
+                    let mut parent_note = old_note.clone();
+                    parent_note.branches.remove(head);
+                    Ok(parent_note)
+                })?;
+            self.record_change(ChangeKind::Update, &parent_loc);
+        }
+        let new_loc = self.update_note_helper(&last_loc, |old_note| {
+            let mut note = old_note.clone();
+            note.next = Some(head.clone());
+            Ok(note)
+        })?;
+        self.record_change(ChangeKind::Update, &new_loc);
+        Ok(())
     }
 
     fn delete_note(&mut self, loc: &NoteLocator) -> Result<(), NoteStoreError> {
+        let loc = &self.resolve(loc)?;
         let (id, rev) = loc.unpack();
         if self.is_current(loc)? {
             let note = self.get_note_stored(loc).unwrap();
@@ -441,30 +966,12 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             // It's not possible to be in the middle of a note sequence
             // And vice versa
             assert!(self.get_parent(id).is_none() || self.get_prev(id).is_none());
-            // Since only next is stored, our next note is not aware of us
-            // But we want to make sure our prev note is consistent
-            if let Some(prev_id) = self.get_prev(id) {
-                self.update_note_helper(&NoteLocator::Current(prev_id), |old_note| {
-                    let mut parent_note = old_note.clone();
-                    assert_eq!(parent_note.next.as_ref(), Some(id));
-                    parent_note.next.clone_from(&note.next);
-                    Ok(parent_note)
-                })?;
-            }
-            if let Some(parent_id) = self.get_parent(id) {
-                self.update_note_helper(&NoteLocator::Current(parent_id), |old_note| {
-                    let mut parent_note = old_note.clone();
-                    assert!(parent_note.branches.contains(id));
-                    parent_note.branches.remove(id);
-                    if let Some(next_id) = &note.next {
-                        parent_note.branches.insert(next_id.clone());
-                    }
-                    Ok(parent_note)
-                })?;
-            }
+            self.hand_over_position(id, &note.next)?;
             // Mark the note as delete at last to avoid the previous steps from referring to
             // a delete note
             self.current_revision.remove(id).unwrap();
+            self.deleted_at.insert(id.clone(), Utc::now());
+            self.record_change(ChangeKind::Delete, &loc.at_revision(&note.revision));
             Ok(())
         } else {
             Err(NoteStoreError::DeleteOldRevision(
@@ -474,82 +981,415 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         }
     }
 
-    fn get_current_revision(&self, loc: &NoteLocator) -> Result<Option<Revision>, NoteStoreError> {
+    fn purge_note_helper(&mut self, id: &NoteID) -> Result<(), NoteStoreError> {
+        if !self.get_references(id).is_empty() {
+            return Err(NoteStoreError::HasReferences(id.clone()));
+        }
+        // Relationships are recorded by the parent and previous note, so the revisions of other
+        // notes would lose them, and references in older revisions would be left dangling
+        let mut linked: Vec<NoteID> = vec![];
+        for (other, revisions) in &self.notes {
+            if other == id {
+                continue;
+            }
+            for n in revisions.values() {
+                let referents = T::from(n.note_inner.clone())
+                    .get_referents()
+                    .map_err(|e| NoteStoreError::ParseError(format!("{e:?}")))?;
+                if n.branches.contains(id) || n.next.as_ref() == Some(id) || referents.contains(id)
+                {
+                    linked.push(other.clone());
+                    break;
+                }
+            }
+        }
+        if !linked.is_empty() {
+            linked.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+            return Err(NoteStoreError::LinkedInHistory(id.clone(), linked));
+        }
+        self.notes.remove(id);
+        self.revision_id_counter.remove(id);
+        self.deleted_at.remove(id);
+        self.past_deletions.remove(id);
+        self.labels.remove(id);
+        for draft in self.session_drafts.values_mut() {
+            if draft.id.as_ref() == Some(id) {
+                draft.id = None;
+                draft.base = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn purge_note(&mut self, id: &NoteID) -> Result<(), NoteStoreError> {
+        if !self.is_deleted(&NoteLocator::Current(id.clone()))? {
+            return Err(NoteStoreError::NotDeleted(id.clone()));
+        }
+        self.purge_note_helper(id)
+    }
+
+    fn purge_revision(&mut self, loc: &NoteLocator) -> Result<(), NoteStoreError> {
+        let note = self.get_note_stored(loc)?;
         let id = loc.get_id();
-        if let Some(r) = self.current_revision.get(id) {
-            Ok(Some(r.clone()))
-        } else if self.notes.contains_key(id) {
-            Ok(None)
+        if self.current_revision.get(id) == Some(&note.revision) {
+            return Err(NoteStoreError::PurgeCurrentRevision(
+                id.clone(),
+                note.revision,
+            ));
+        }
+        let revisions = self.notes.get_mut(id).unwrap();
+        if revisions.len() == 1 {
+            self.purge_note_helper(id)
         } else {
-            Err(NoteStoreError::NoteNotExist(id.clone()))
+            revisions.remove(&note.revision);
+            // Keep the history connected
+            for r in revisions.values_mut() {
+                if r.based_on.as_ref() == Some(&note.revision) {
+                    r.based_on.clone_from(&note.based_on);
+                }
+                if r.metadata.reverted_from.as_ref() == Some(&note.revision) {
+                    r.metadata.reverted_from = None;
+                }
+            }
+            if let Some(labels) = self.labels.get_mut(id) {
+                labels.retain(|_, r| r != &note.revision);
+            }
+            for draft in self.session_drafts.values_mut() {
+                if draft.base.as_ref() == Some(&note.revision) {
+                    draft.base = None;
+                }
+            }
+            Ok(())
         }
     }
 
-    /// Get all revisions of a note with actual notes
-    fn get_revisions(&self, loc: &NoteLocator) -> Result<Revisions<T>, NoteStoreError> {
+    fn add_label(&mut self, loc: &NoteLocator, label: &str) -> Result<(), NoteStoreError> {
+        let revision = self.get_note_stored(loc)?.revision;
         let id = loc.get_id();
-        let notes: Vec<InMemoryNoteStored<T>> = self
-            .notes
+        let labels = self.labels.entry(id.clone()).or_default();
+        match labels.get(label) {
+            Some(r) if r != &revision => Err(NoteStoreError::LabelExists(
+                id.clone(),
+                label.to_owned(),
+                r.clone(),
+            )),
+            _ => {
+                labels.insert(label.to_owned(), revision);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_label(&mut self, id: &NoteID, label: &str) -> Result<(), NoteStoreError> {
+        self.get_label_revision(id, label)?;
+        self.labels.get_mut(id).unwrap().remove(label);
+        Ok(())
+    }
+
+    fn list_labels(&self, id: &NoteID) -> Result<Vec<RevisionLabel>, NoteStoreError> {
+        if !self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        Ok(self
+            .labels
             .get(id)
-            .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))
-            .map(|rs| {
-                let mut v: Vec<InMemoryNoteStored<T>> = rs.values().cloned().collect();
-                v.sort_by_key(|n| n.metadata.modified_at);
-                v
-            })?;
-        notes
             .into_iter()
-            .map(|n| {
-                self.compute_stored_note(n)
-                    .map(|n_computed| Box::new(n_computed) as Box<dyn Note<T>>)
+            .flatten()
+            .map(|(label, revision)| RevisionLabel {
+                label: label.clone(),
+                revision: revision.clone(),
             })
-            .collect()
+            .collect())
     }
 
-    fn append_note(
+    fn put_session_draft(
         &mut self,
-        last: &NoteID,
-        title: String,
-        note_inner: T,
-        metadata: NoteMetadataEditable,
-    ) -> Result<NoteLocator, NoteStoreError> {
-        let last_loc = NoteLocator::Current(last.clone());
+        session: &str,
+        target: Option<&NoteLocator>,
+        content: serde_json::Value,
+    ) -> Result<SessionDraft, NoteStoreError> {
+        let (id, base) = match target {
+            None => (None, None),
+            Some(loc @ NoteLocator::Current(id)) => {
+                self.get_note_stored(loc)?;
+                (Some(id.clone()), None)
+            }
+            Some(loc) => {
+                let note = self.get_note_stored(loc)?;
+                (Some(note.id), Some(note.revision))
+            }
+        };
+        let draft = SessionDraft {
+            session: session.to_owned(),
+            id,
+            base,
+            content,
+            saved_at: Utc::now(),
+        };
+        self.session_drafts
+            .insert(session.to_owned(), draft.clone());
+        Ok(draft)
+    }
+
+    fn get_session_draft(&self, session: &str) -> Result<SessionDraft, NoteStoreError> {
+        self.session_drafts
+            .get(session)
+            .cloned()
+            .ok_or_else(|| NoteStoreError::SessionDraftNotExist(session.to_owned()))
+    }
+
+    fn delete_session_draft(&mut self, session: &str) -> Result<(), NoteStoreError> {
+        self.session_drafts
+            .remove(session)
+            .map(|_| ())
+            .ok_or_else(|| NoteStoreError::SessionDraftNotExist(session.to_owned()))
+    }
+
+    fn list_session_drafts(&self, prefix: &str) -> Vec<SessionDraft> {
+        self.session_drafts
+            .range(prefix.to_owned()..)
+            .take_while(|(session, _)| session.starts_with(prefix))
+            .map(|(_, draft)| draft.clone())
+            .collect()
+    }
+
+    fn list_deleted(&self) -> Result<Vec<DeletedNote<T>>, NoteStoreError> {
+        let mut deleted = vec![];
+        for (id, revisions) in &self.notes {
+            if self.current_revision.contains_key(id) {
+                continue;
+            }
+            let last = match revisions
+                .values()
+                .filter(|n| !n.is_draft)
+                .max_by_key(|n| n.metadata.modified_at)
+            {
+                Some(n) => n.clone(),
+                None => continue,
+            };
+            let deleted_at = self
+                .deleted_at
+                .get(id)
+                .copied()
+                .unwrap_or(last.metadata.modified_at);
+            deleted.push(DeletedNote {
+                note: Box::new(self.compute_stored_note(last)?),
+                deleted_at,
+            });
+        }
+        deleted.sort_by_key(|d| Reverse(d.deleted_at));
+        Ok(deleted)
+    }
+
+    fn list_past_deletions(&self, id: &NoteID) -> Result<Vec<DeletionPeriod>, NoteStoreError> {
+        if !self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        Ok(self.past_deletions.get(id).cloned().unwrap_or_default())
+    }
+
+    /// When a deleted note was deleted, falling back to the modification time of its last
+    /// revision, as in [`InMemoryStoreInner::list_deleted`].
+    fn get_deleted_at(&self, id: &NoteID) -> Option<DateTime<Utc>> {
+        self.deleted_at.get(id).copied().or_else(|| {
+            self.notes
+                .get(id)?
+                .values()
+                .filter(|n| !n.is_draft)
+                .map(|n| n.metadata.modified_at)
+                .max()
+        })
+    }
+
+    fn get_sequence(&self, id: &NoteID) -> Result<Revisions<T>, NoteStoreError> {
+        let mut head = self.get_note_stored(&NoteLocator::Current(id.clone()))?.id;
+        while let Some(prev) = self.get_prev(&head) {
+            head = prev;
+        }
+        let mut notes = vec![];
+        let mut cursor = Some(head);
+        while let Some(c) = cursor {
+            let note = self.get_note(&NoteLocator::Current(c))?;
+            cursor = note.get_next();
+            notes.push(note);
+        }
+        Ok(notes)
+    }
+
+    fn get_subtree(
+        &self,
+        id: &NoteID,
+        depth: Option<usize>,
+    ) -> Result<Revisions<T>, NoteStoreError> {
+        let mut notes = vec![];
+        let mut queue = VecDeque::from([(id.clone(), 0)]);
+        while let Some((cursor, level)) = queue.pop_front() {
+            let note = self.get_note(&NoteLocator::Current(cursor))?;
+            if depth.is_none_or(|d| level < d) {
+                let children = note.get_branches().into_iter().chain(note.get_next());
+                queue.extend(children.map(|child| (child, level + 1)));
+            }
+            notes.push((level, note));
+        }
+        notes.sort_by_key(|(level, note)| (*level, note.get_metadata().created_at));
+        Ok(notes.into_iter().map(|(_, note)| note).collect())
+    }
+
+    fn get_ancestors(&self, id: &NoteID) -> Result<Vec<Ancestor<T>>, NoteStoreError> {
+        let mut cursor = self.get_note_stored(&NoteLocator::Current(id.clone()))?.id;
+        let mut ancestors = vec![];
+        loop {
+            let (link, above) = if let Some(prev) = self.get_prev(&cursor) {
+                (Link::Sequence, prev)
+            } else if let Some(parent) = self.get_parent(&cursor) {
+                (Link::Branch, parent)
+            } else {
+                return Ok(ancestors);
+            };
+            ancestors.push(Ancestor {
+                link,
+                note: self.get_note(&NoteLocator::Current(above.clone()))?,
+            });
+            cursor = above;
+        }
+    }
+
+    fn get_current_revision(&self, loc: &NoteLocator) -> Result<Option<Revision>, NoteStoreError> {
+        let id = loc.get_id();
+        if let Some(r) = self.current_revision.get(id) {
+            Ok(Some(r.clone()))
+        } else if self.notes.contains_key(id) {
+            Ok(None)
+        } else {
+            Err(NoteStoreError::NoteNotExist(id.clone()))
+        }
+    }
+
+    /// Get all revisions of a note with actual notes
+    fn get_revisions(&self, loc: &NoteLocator) -> Result<Revisions<T>, NoteStoreError> {
+        let id = loc.get_id();
+        let notes: Vec<InMemoryNoteStored<T>> = self
+            .notes
+            .get(id)
+            .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))
+            .map(|rs| {
+                let mut v: Vec<InMemoryNoteStored<T>> = rs.values().cloned().collect();
+                v.sort_by_key(|n| n.metadata.modified_at);
+                v
+            })?;
+        notes
+            .into_iter()
+            .map(|n| {
+                self.compute_stored_note(n)
+                    .map(|n_computed| Box::new(n_computed) as Box<dyn Note<T>>)
+            })
+            .collect()
+    }
+
+    fn append_note(
+        &mut self,
+        last: &NoteID,
+        identity: Option<NoteIdentity>,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let last_loc = NoteLocator::Current(last.clone());
         let last_note = self.get_note_stored(&last_loc)?;
         if let Some(n) = last_note.next {
             return Err(NoteStoreError::ExistingNext(last_note.id, n));
         }
-        let loc = self.new_note_helper(title, note_inner, metadata);
-        self.update_note_helper(&last_loc, |old_note| {
+        let loc = self.new_note_helper(identity, title, note_inner, metadata)?;
+        let last_loc = self.update_note_helper(&last_loc, |old_note| {
+            let mut note = old_note.clone();
+            note.next = Some(loc.get_id().clone());
+            Ok(note)
+        })?;
+        self.record_change(ChangeKind::Update, &last_loc);
+        self.record_change(ChangeKind::Append, &loc);
+        Ok(loc)
+    }
+
+    fn insert_after(
+        &mut self,
+        prev: &NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let prev_loc = NoteLocator::Current(prev.clone());
+        let prev_note = self.get_note_stored(&prev_loc)?;
+        let loc = self.new_note_helper(None, title, note_inner, metadata)?;
+        // The note is brand new, so there is no need for another revision
+        self.get_current_stored_mut(loc.get_id())?.next = prev_note.next;
+        let prev_loc = self.update_note_helper(&prev_loc, |old_note| {
             let mut note = old_note.clone();
             note.next = Some(loc.get_id().clone());
             Ok(note)
         })?;
+        self.record_change(ChangeKind::Update, &prev_loc);
+        self.record_change(ChangeKind::Append, &loc);
+        Ok(loc)
+    }
+
+    fn insert_before(
+        &mut self,
+        next: &NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        self.get_note_stored(&NoteLocator::Current(next.clone()))?;
+        let loc = self.new_note_helper(None, title, note_inner, metadata)?;
+        // Hand over the position first, so that the note has a single prev note at any time
+        self.hand_over_position(next, &Some(loc.get_id().clone()))?;
+        self.get_current_stored_mut(loc.get_id())?.next = Some(next.clone());
+        self.record_change(ChangeKind::Append, &loc);
         Ok(loc)
     }
 
     fn add_branch(
         &mut self,
         parent: &NoteID,
+        identity: Option<NoteIdentity>,
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
         let parent_loc = NoteLocator::Current(parent.clone());
-        let child_loc = self.new_note_helper(title, note_inner, metadata);
-        self.update_note_helper(&parent_loc, |old_note| {
+        let child_loc = self.new_note_helper(identity, title, note_inner, metadata)?;
+        let parent_loc = self.update_note_helper(&parent_loc, |old_note| {
             let mut note = old_note.clone();
             note.branches.insert(child_loc.get_id().clone());
             Ok(note)
         })?;
+        self.record_change(ChangeKind::Update, &parent_loc);
+        self.record_change(ChangeKind::Branch, &child_loc);
         Ok(child_loc)
     }
 
     fn search(&self, sr: &SearchRequest) -> Result<Revisions<T>, NoteStoreError> {
-        let notes: Vec<InMemoryNoteStored<T>> = self.get_all_current_notes();
+        self.search_in(&self.current_revision, sr)
+    }
+
+    fn search_at(
+        &self,
+        sr: &SearchRequest,
+        at: &DateTime<Utc>,
+    ) -> Result<Revisions<T>, NoteStoreError> {
+        self.search_in(&self.heads_at(at), sr)
+    }
+
+    fn search_in(
+        &self,
+        heads: &HashMap<NoteID, Revision>,
+        sr: &SearchRequest,
+    ) -> Result<Revisions<T>, NoteStoreError> {
+        let notes: Vec<InMemoryNoteStored<T>> = self.get_all_notes_in(heads);
         let revisions: Result<Revisions<T>, NoteStoreError> = notes
             .into_iter()
             .map(|x| {
-                self.compute_stored_note(x)
+                self.compute_stored_note_in(heads, x)
                     .map(|x_computed| Box::new(x_computed) as Box<dyn Note<T>>)
             })
             .collect();
@@ -592,24 +1432,232 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     }
 
     fn backup<P: AsRef<Path>>(&self, path: P) -> Result<(), NoteStoreError> {
-        let p = path.as_ref().join("notegraf_in_memory.json");
+        let mut records = vec![];
+        for id in self.get_note_ids() {
+            let labels = self.labels.get(&id);
+            let deleted_at = self.deleted_at.get(&id).cloned();
+            let past_deletions = self.past_deletions.get(&id);
+            for note in self.get_revisions(&NoteLocator::Current(id.clone()))? {
+                let mut record = RevisionRecord::from_note(note.as_ref());
+                record.labels = labels
+                    .iter()
+                    .flat_map(|ls| ls.iter())
+                    .filter(|(_, r)| **r == record.revision)
+                    .map(|(l, _)| l.clone())
+                    .collect();
+                record.deleted_at = deleted_at;
+                record.past_deletions = past_deletions.cloned().unwrap_or_default();
+                records.push(record);
+            }
+        }
+        let session_drafts: Vec<SessionDraft> = self.session_drafts.values().cloned().collect();
+        write_backup(path, &records, &session_drafts)
+    }
+
+    fn get_note_ids(&self) -> Vec<NoteID> {
+        let mut ids: Vec<NoteID> = self.notes.keys().cloned().collect();
+        ids.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        ids
+    }
 
-        let mut f = File::create(p).map_err(NoteStoreError::IOError)?;
-        f.write_all(&serde_json::to_vec(&self).map_err(NoteStoreError::SerdeError)?)
-            .map_err(NoteStoreError::IOError)?;
+    fn import_note(&mut self, records: Vec<RevisionRecord>) -> Result<(), NoteStoreError> {
+        let (id, current_record) = check_note_records(&records)?;
+        if self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteIDConflict(id.clone()));
+        }
+        // Check the relationships before changing anything
+        if let Some(r) = current_record {
+            if let Some(parent) = &r.parent {
+                self.get_current_stored_mut(parent)?;
+            }
+            if let Some(prev) = &r.prev {
+                if let Some(next) = &self.get_current_stored_mut(prev)?.next {
+                    return Err(NoteStoreError::ExistingNext(prev.clone(), next.clone()));
+                }
+            }
+        }
+        self.restore_records(records)
+    }
+
+    /// Remove notes that have just been imported, along with the relationships to them.
+    fn remove_imported(&mut self, ids: &HashSet<NoteID>) {
+        for id in ids {
+            self.notes.remove(id);
+            self.current_revision.remove(id);
+            self.deleted_at.remove(id);
+            self.past_deletions.remove(id);
+            self.labels.remove(id);
+        }
+        for (id, revision) in &self.current_revision {
+            if let Some(note) = self.notes.get_mut(id).and_then(|rs| rs.get_mut(revision)) {
+                note.branches.retain(|b| !ids.contains(b));
+                if note.next.as_ref().is_some_and(|n| ids.contains(n)) {
+                    note.next = None;
+                }
+            }
+        }
+    }
+
+    fn restore_records(&mut self, records: Vec<RevisionRecord>) -> Result<(), NoteStoreError> {
+        for r in &records {
+            let note = InMemoryNoteStored {
+                title: r.title.clone(),
+                note_inner: r.note_inner.clone(),
+                id: r.id.clone(),
+                revision: r.revision.clone(),
+                branches: Default::default(),
+                next: None,
+                metadata: r.metadata.clone(),
+                based_on: r.based_on.clone(),
+                is_draft: r.is_draft,
+                _phantom: PhantomData,
+            };
+            self.notes
+                .entry(r.id.clone())
+                .or_default()
+                .insert(r.revision.clone(), note);
+            if r.is_current {
+                self.current_revision
+                    .insert(r.id.clone(), r.revision.clone());
+            }
+            for label in &r.labels {
+                self.labels
+                    .entry(r.id.clone())
+                    .or_default()
+                    .insert(label.clone(), r.revision.clone());
+            }
+            if let Some(deleted_at) = r.deleted_at {
+                self.deleted_at.insert(r.id.clone(), deleted_at);
+            }
+            if !r.past_deletions.is_empty() {
+                self.past_deletions
+                    .insert(r.id.clone(), r.past_deletions.clone());
+            }
+        }
+        // Only backward relationships are recorded, so we recover the forward relationships
+        // stored by us from the current revisions
+        for r in records.iter().filter(|r| r.is_current) {
+            if let Some(parent) = &r.parent {
+                self.get_current_stored_mut(parent)?
+                    .branches
+                    .insert(r.id.clone());
+            }
+            if let Some(prev) = &r.prev {
+                self.get_current_stored_mut(prev)?.next = Some(r.id.clone());
+            }
+        }
         Ok(())
     }
 
-    fn restore<P: AsRef<Path>>(path: P) -> Result<Self, NoteStoreError>
-    where
-        Self: Sized,
-    {
-        let p = path.as_ref().join("notegraf_in_memory.json");
-        let contents = fs::read_to_string(p).map_err(NoteStoreError::IOError)?;
-        serde_json::from_str(&contents).map_err(NoteStoreError::SerdeError)
+    fn get_current_stored_mut(
+        &mut self,
+        id: &NoteID,
+    ) -> Result<&mut InMemoryNoteStored<T>, NoteStoreError> {
+        let revision = self
+            .current_revision
+            .get(id)
+            .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))?;
+        self.notes
+            .get_mut(id)
+            .and_then(|rs| rs.get_mut(revision))
+            .ok_or_else(|| NoteStoreError::RevisionNotExist(id.clone(), revision.clone()))
+    }
+
+    fn restore<P: AsRef<Path>>(&mut self, path: P) -> Result<(), NoteStoreError> {
+        if !self.notes.is_empty() {
+            return Err(NoteStoreError::StoreNotEmpty);
+        }
+        let res = if path.as_ref().join(LEGACY_BACKUP_FILE_NAME).exists()
+            && !path.as_ref().join(BACKUP_FILE_NAME).exists()
+        {
+            self.restore_legacy(path)
+        } else {
+            read_backup(path).and_then(|(records, session_drafts)| {
+                self.session_drafts = session_drafts
+                    .into_iter()
+                    .map(|d| (d.session.clone(), d))
+                    .collect();
+                self.restore_records(records)
+            })
+        };
+        if res.is_err() {
+            // Don't leave a partially restored store behind
+            *self = Self::new();
+        }
+        res
+    }
+
+    /// Restore a backup made by an earlier version.
+    ///
+    /// Such a backup stored the forward relationships (branches and next note) of every revision.
+    /// The backward relationships of the current revisions are derived from them, and older
+    /// revisions keep the forward relationships as they were stored.
+    fn restore_legacy<P: AsRef<Path>>(&mut self, path: P) -> Result<(), NoteStoreError> {
+        let contents = fs::read_to_string(path.as_ref().join(LEGACY_BACKUP_FILE_NAME))?;
+        let backup: LegacyBackup<T> = serde_json::from_str(&contents)?;
+        let mut parents = HashMap::new();
+        let mut prevs = HashMap::new();
+        for (id, revision) in &backup.current_revision {
+            let note = &backup.notes[id][revision];
+            for branch in &note.branches {
+                parents.insert(branch.clone(), id.clone());
+            }
+            if let Some(next) = &note.next {
+                prevs.insert(next.clone(), id.clone());
+            }
+        }
+        let mut records = vec![];
+        let mut old_revisions = vec![];
+        for (id, revisions) in backup.notes {
+            let current = backup.current_revision.get(&id);
+            for (revision, note) in revisions {
+                let is_current = current == Some(&revision);
+                records.push(RevisionRecord {
+                    is_current,
+                    parent: parents.get(&id).filter(|_| is_current).cloned(),
+                    prev: prevs.get(&id).filter(|_| is_current).cloned(),
+                    id: id.clone(),
+                    revision: revision.clone(),
+                    title: note.title.clone(),
+                    note_inner: note.note_inner.clone(),
+                    metadata: note.metadata.clone(),
+                    based_on: note.based_on.clone(),
+                    is_draft: note.is_draft,
+                    labels: vec![],
+                    deleted_at: None,
+                    past_deletions: vec![],
+                });
+                if !is_current {
+                    old_revisions.push(note);
+                }
+            }
+        }
+        self.restore_records(records)?;
+        for note in old_revisions {
+            let stored = self
+                .notes
+                .get_mut(&note.id)
+                .and_then(|rs| rs.get_mut(&note.revision))
+                .ok_or_else(|| {
+                    NoteStoreError::RevisionNotExist(note.id.clone(), note.revision.clone())
+                })?;
+            stored.branches = note.branches;
+            stored.next = note.next;
+        }
+        Ok(())
     }
 }
 
+/// Name of the file of backups made by earlier versions, which serialized the whole store.
+static LEGACY_BACKUP_FILE_NAME: &str = "notegraf_in_memory.json";
+
+/// The parts of a backup made by an earlier version that are still needed.
+#[derive(Deserialize)]
+struct LegacyBackup<T> {
+    notes: HashMap<NoteID, HashMap<Revision, InMemoryNoteStored<T>>>,
+    current_revision: HashMap<NoteID, Revision>,
+}
+
 pub struct InMemoryStore<T> {
     ims: RwLock<InMemoryStoreInner<T>>,
 }
@@ -634,10 +1682,10 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
-    ) -> BoxFuture<Result<NoteLocator, NoteStoreError>> {
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>> {
         Box::pin(async move {
             let mut ims = self.ims.write().await;
-            ims.new_note(title, note_inner, metadata)
+            ims.new_note(None, title, note_inner, metadata)
         })
     }
 
@@ -664,6 +1712,49 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.revert_note(loc)
+        })
+    }
+
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        target: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.move_note(id, target)
+        })
+    }
+
+    fn split_sequence<'a>(
+        &'a self,
+        at: &'a NoteID,
+        tail: SplitTail,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.split_sequence(at, tail)
+        })
+    }
+
+    fn merge_sequences<'a>(
+        &'a self,
+        last: &'a NoteID,
+        head: &'a NoteID,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.merge_sequences(last, head)
+        })
+    }
+
     fn delete_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -674,6 +1765,174 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.purge_note(id)
+        })
+    }
+
+    fn purge_revision<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.purge_revision(loc)
+        })
+    }
+
+    fn add_label<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.add_label(loc, label)
+        })
+    }
+
+    fn remove_label<'a>(
+        &'a self,
+        id: &'a NoteID,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.remove_label(id, label)
+        })
+    }
+
+    fn list_labels<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<RevisionLabel>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.list_labels(id)
+        })
+    }
+
+    fn create_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        title: Option<String>,
+        note_inner: Option<T>,
+        note_metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.create_draft(loc, title, note_inner, note_metadata)
+        })
+    }
+
+    fn list_drafts<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.list_drafts(id)
+        })
+    }
+
+    fn promote_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.promote_draft(loc)
+        })
+    }
+
+    fn put_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+        target: Option<&'a NoteLocator>,
+        content: serde_json::Value,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.put_session_draft(session, target, content)
+        })
+    }
+
+    fn get_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.get_session_draft(session)
+        })
+    }
+
+    fn delete_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.delete_session_draft(session)
+        })
+    }
+
+    fn list_session_drafts<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SessionDraft>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            Ok(ims.list_session_drafts(prefix))
+        })
+    }
+
+    fn subscribe(&self) -> BoxFuture<'_, Result<ChangeStream, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            Ok(broadcast_stream(ims.change_sender.subscribe()))
+        })
+    }
+
+    fn changes_since(
+        &self,
+        cursor: ChangeCursor,
+    ) -> BoxFuture<'_, Result<Vec<Change>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            Ok(ims.changes_since(cursor))
+        })
+    }
+
+    fn prune_changes<'a>(
+        &'a self,
+        before: &'a DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<u64, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            Ok(ims.prune_changes(before))
+        })
+    }
+
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.list_deleted()
+        })
+    }
+
+    fn list_past_deletions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<DeletionPeriod>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.list_past_deletions(id)
+        })
+    }
+
     fn get_revisions<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -694,6 +1953,37 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn get_sequence<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.get_sequence(id)
+        })
+    }
+
+    fn get_subtree<'a>(
+        &'a self,
+        id: &'a NoteID,
+        depth: Option<usize>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.get_subtree(id, depth)
+        })
+    }
+
+    fn get_ancestors<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<Ancestor<T>>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.get_ancestors(id)
+        })
+    }
+
     fn append_note<'a>(
         &'a self,
         last: &'a NoteID,
@@ -703,7 +1993,7 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
         Box::pin(async move {
             let mut ims = self.ims.write().await;
-            ims.append_note(last, title, note_inner, metadata)
+            ims.append_note(last, None, title, note_inner, metadata)
         })
     }
 
@@ -716,7 +2006,85 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
         Box::pin(async move {
             let mut ims = self.ims.write().await;
-            ims.add_branch(parent, title, note_inner, metadata)
+            ims.add_branch(parent, None, title, note_inner, metadata)
+        })
+    }
+
+    fn insert_after<'a>(
+        &'a self,
+        prev: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.insert_after(prev, title, note_inner, metadata)
+        })
+    }
+
+    fn insert_before<'a>(
+        &'a self,
+        next: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.insert_before(next, title, note_inner, metadata)
+        })
+    }
+
+    fn new_note_with_identity(
+        &self,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.new_note(Some(identity), title, note_inner, metadata)
+        })
+    }
+
+    fn append_note_with_identity<'a>(
+        &'a self,
+        last: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.append_note(last, Some(identity), title, note_inner, metadata)
+        })
+    }
+
+    fn add_branch_with_identity<'a>(
+        &'a self,
+        parent: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.add_branch(parent, Some(identity), title, note_inner, metadata)
+        })
+    }
+
+    fn search_at<'a>(
+        &'a self,
+        sr: &'a SearchRequest,
+        at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.search_at(sr, &at)
         })
     }
 
@@ -730,14 +2098,58 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
-    fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>> {
+    fn tags(&self) -> BoxFuture<'_, Result<Vec<String>, NoteStoreError>> {
         Box::pin(async move {
             let ims = self.ims.read().await;
             ims.tags()
         })
     }
 
-    fn backup(&self, path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>> {
+    fn get_note_ids(&self) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            Ok(ims.get_note_ids())
+        })
+    }
+
+    fn import_note(
+        &self,
+        records: Vec<RevisionRecord>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.import_note(records)
+        })
+    }
+
+    fn import_notes<'a>(
+        &'a self,
+        records: RecordStream<'a>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            let mut imported = HashSet::new();
+            let mut notes = group_by_note(records);
+            while let Some(records) = notes.next().await {
+                let res = records.and_then(|records| {
+                    let id = records.first().map(|r| r.id.clone());
+                    ims.import_note(records)?;
+                    imported.extend(id);
+                    Ok(())
+                });
+                if let Err(e) = res {
+                    ims.remove_imported(&imported);
+                    return Err(e);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn backup(
+        &self,
+        path: Box<dyn AsRef<Path> + Send>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>> {
         Box::pin(async move {
             let ims = self.ims.read().await;
             ims.backup(&*path)
@@ -745,8 +2157,17 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
     }
 
     fn restore<P: AsRef<Path>>(path: P) -> Result<Self, NoteStoreError> {
+        let mut ims = InMemoryStoreInner::new();
+        ims.restore(path)?;
         Ok(InMemoryStore {
-            ims: RwLock::new(InMemoryStoreInner::restore(path)?),
+            ims: RwLock::new(ims),
+        })
+    }
+
+    fn restore_into<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.restore(path)
         })
     }
 }
@@ -754,9 +2175,8 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::note::NoteSerializable;
     use crate::notestore::tests as common_tests;
-    use crate::notetype::PlainNote;
+    use crate::PlainNote;
     use std::env;
 
     #[tokio::test]
@@ -775,36 +2195,219 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn backup() {
-        let store: InMemoryStore<PlainNote> = InMemoryStore::new();
-        let loc1 = store
-            .new_note(
-                "".to_owned(),
-                PlainNote::new("Foo".into()),
-                NoteMetadataEditable::unchanged(),
-            )
+    async fn backup_restore() {
+        common_tests::backup_restore(InMemoryStore::new(), InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn restore_legacy_backup() {
+        let metadata = serde_json::json!({
+            "schema_version": 0,
+            "created_at": "2022-01-02T03:04:05Z",
+            "modified_at": "2022-01-02T03:04:05Z",
+            "tags": ["foo"],
+            "custom_metadata": {}
+        });
+        let stored =
+            |id: &str, revision: &str, title: &str, branches: &[&str], next: Option<&str>| {
+                serde_json::json!({
+                    "title": title,
+                    "note_inner": String::from(PlainNote::new(title.into())),
+                    "id": id,
+                    "revision": revision,
+                    "branches": branches,
+                    "next": next,
+                    "metadata": metadata,
+                    "_phantom": null
+                })
+            };
+        let backup = serde_json::json!({
+            "notes": {
+                "note-0": {
+                    "revision-0": stored("note-0", "revision-0", "Old", &["note-1"], None),
+                    "revision-1": stored("note-0", "revision-1", "Parent", &["note-1"], Some("note-2")),
+                },
+                "note-1": {"revision-0": stored("note-1", "revision-0", "Branch", &[], None)},
+                "note-2": {"revision-0": stored("note-2", "revision-0", "Next", &[], None)},
+            },
+            "current_revision": {"note-0": "revision-1", "note-1": "revision-0", "note-2": "revision-0"},
+            "note_id_counter": 3,
+            "revision_id_counter": {"note-0": 2, "note-1": 1, "note-2": 1}
+        });
+        let path = env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&path).unwrap();
+        fs::write(
+            path.join(LEGACY_BACKUP_FILE_NAME),
+            serde_json::to_vec(&backup).unwrap(),
+        )
+        .unwrap();
+        let store: InMemoryStore<PlainNote> = InMemoryStore::restore(path).unwrap();
+        let parent = store
+            .get_note(&NoteLocator::Current("note-0".into()))
+            .await
+            .unwrap();
+        assert_eq!(parent.get_title(), "Parent");
+        assert_eq!(parent.get_next(), Some("note-2".into()));
+        assert_eq!(parent.get_branches(), HashSet::from(["note-1".into()]));
+        assert!(parent.get_metadata().tags.contains("foo"));
+        let old = store
+            .get_note(&NoteLocator::Specific("note-0".into(), "revision-0".into()))
+            .await
+            .unwrap();
+        assert_eq!(old.get_title(), "Old");
+        // Older revisions keep the relationships they had
+        assert_eq!(old.get_next(), None);
+        assert_eq!(old.get_branches(), HashSet::from(["note-1".into()]));
+        let branch = store
+            .get_note(&NoteLocator::Current("note-1".into()))
             .await
             .unwrap();
-        let loc2 = store
-            .new_note(
-                "".to_owned(),
-                PlainNote::new("Bar".into()),
-                NoteMetadataEditable::unchanged(),
-            )
+        assert_eq!(branch.get_parent(), Some("note-0".into()));
+        let next = store
+            .get_note(&NoteLocator::Current("note-2".into()))
             .await
             .unwrap();
+        assert_eq!(next.get_prev(), Some("note-0".into()));
+    }
 
-        store.backup(Box::new(env::temp_dir())).await.unwrap();
-        let store_restore: InMemoryStore<PlainNote> =
-            InMemoryStore::restore(env::temp_dir()).unwrap();
-        for loc in [loc1, loc2].iter() {
-            let note = store.get_note(loc).await.unwrap();
-            let note_restore = store_restore.get_note(loc).await.unwrap();
-            assert_eq!(
-                serde_json::to_string(&NoteSerializable::all_fields(note)).unwrap(),
-                serde_json::to_string(&NoteSerializable::all_fields(note_restore)).unwrap()
-            );
-        }
+    #[tokio::test]
+    async fn new_note_with_identity() {
+        common_tests::new_note_with_identity(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn export_import() {
+        common_tests::export_import(InMemoryStore::new(), InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn diff() {
+        common_tests::diff(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn revert_note() {
+        common_tests::revert_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn revert_deleted_note() {
+        common_tests::revert_deleted_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn move_note() {
+        common_tests::move_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn move_note_hand_over_branch() {
+        common_tests::move_note_hand_over_branch(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn insert_after() {
+        common_tests::insert_after(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn insert_before() {
+        common_tests::insert_before(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn split_sequence() {
+        common_tests::split_sequence(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn merge_sequences() {
+        common_tests::merge_sequences(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn get_sequence() {
+        common_tests::get_sequence(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn get_subtree() {
+        common_tests::get_subtree(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn get_ancestors() {
+        common_tests::get_ancestors(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn list_deleted() {
+        common_tests::list_deleted(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn purge_note() {
+        common_tests::purge_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn purge_revision() {
+        common_tests::purge_revision(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn compact() {
+        common_tests::compact(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn note_at_time() {
+        common_tests::note_at_time(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn note_at_time_restored() {
+        common_tests::note_at_time_restored(InMemoryStore::new(), InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn revision_author_message() {
+        common_tests::revision_author_message(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn revision_labels() {
+        common_tests::revision_labels(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn drafts() {
+        common_tests::drafts(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn update_note_merging_concurrent() {
+        common_tests::update_note_merging_concurrent(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn change_feed() {
+        common_tests::change_feed(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn change_feed_neighbours() {
+        common_tests::change_feed_neighbours(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn prune_changes() {
+        common_tests::prune_changes(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn session_drafts() {
+        common_tests::session_drafts(InMemoryStore::new()).await;
     }
 
     #[tokio::test]
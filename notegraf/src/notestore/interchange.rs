@@ -0,0 +1,460 @@
+//! Portable interchange format of note stores.
+//!
+//! The format is newline-delimited JSON (NDJSON), so that archives can be streamed and diffed.
+//!
+//! The first line is a [`Header`], e.g., `{"format":"notegraf","version":2}`.
+//! Every following line is a [`RevisionRecord`], i.e., one revision of a note.
+//!
+//! The version is bumped whenever the meaning of records changes, so that older readers reject
+//! archives they would misread, while archives of older versions can still be read:
+//! - Version 1 has no drafts, labels or deletion times, and no `reverted_from`, `author` or
+//!   `message` in the metadata.
+//! - Version 2 adds `based_on`, `is_draft`, `labels`, `deleted_at` and `past_deletions`, and
+//!   `reverted_from`, `author` and `message` in the metadata.
+//!
+//! Records of the same note are contiguous, and ordered from older to newer revisions.
+//! Notes are ordered so that the parent or previous note of a (non-deleted) note always comes
+//! before the note itself.
+//! Deleted notes are included, and can be told apart by none of their records being current.
+//! Their records also carry the deletion time, if known.
+//! The records of a note that was deleted and restored carry the periods during which it was
+//! deleted.
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadata;
+use crate::notestore::trash::DeletionPeriod;
+use crate::notestore::NoteStore;
+use crate::{Note, NoteID, NoteLocator, NoteType, Revision};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::{future, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use uuid::Uuid;
+
+/// Name of the format, as written in the [`Header`].
+pub static FORMAT_NAME: &str = "notegraf";
+/// Version of the format written by this library.
+pub static FORMAT_VERSION: u64 = 2;
+/// The oldest version of the format that this library reads.
+pub static MIN_FORMAT_VERSION: u64 = 1;
+
+/// The first line of an archive.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Header {
+    pub format: String,
+    pub version: u64,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            format: FORMAT_NAME.to_owned(),
+            version: FORMAT_VERSION,
+        }
+    }
+}
+
+/// A single revision of a note.
+///
+/// Only the relationships pointing backwards (parent and prev) are recorded.
+/// The forward relationships (branches and next) can always be recovered from them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RevisionRecord {
+    pub id: NoteID,
+    pub revision: Revision,
+    pub title: String,
+    pub note_inner: String,
+    pub parent: Option<NoteID>,
+    pub prev: Option<NoteID>,
+    pub metadata: NoteMetadata,
+    pub is_current: bool,
+    /// The revision (of the same note) that this revision is based on.
+    #[serde(default)]
+    pub based_on: Option<Revision>,
+    #[serde(default)]
+    pub is_draft: bool,
+    /// The labels attached to this revision.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// When the note was deleted, on every revision of a deleted note whose deletion time is
+    /// known.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// The periods during which the note was deleted before it was restored, on every revision of
+    /// the note.
+    #[serde(default)]
+    pub past_deletions: Vec<DeletionPeriod>,
+}
+
+impl RevisionRecord {
+    pub fn from_note<T: NoteType>(note: &dyn Note<T>) -> Self {
+        RevisionRecord {
+            id: note.get_id(),
+            revision: note.get_revision(),
+            title: note.get_title(),
+            note_inner: note.get_note_inner().into(),
+            parent: note.get_parent(),
+            prev: note.get_prev(),
+            metadata: note.get_metadata(),
+            is_current: note.is_current(),
+            based_on: note.get_based_on(),
+            is_draft: note.is_draft(),
+            labels: vec![],
+            deleted_at: None,
+            past_deletions: vec![],
+        }
+    }
+}
+
+/// Replace [`NoteID`]s and [`Revision`]s that are not UUIDs with new random UUIDs.
+///
+/// This is needed when copying records from a store that doesn't use UUIDs (e.g.,
+/// [`InMemoryStore`](crate::InMemoryStore)) into one that does (e.g.,
+/// [`PostgreSQLStore`](crate::PostgreSQLStore)).
+/// UUIDs are kept as is, and links to renamed notes are rewritten in the note body.
+#[derive(Debug, Default)]
+pub struct UuidRemapper {
+    ids: HashMap<NoteID, NoteID>,
+    // Revisions are only unique within a note
+    revisions: HashMap<(NoteID, Revision), Revision>,
+}
+
+impl UuidRemapper {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Get the new [`NoteID`] of a note.
+    pub fn map_id(&mut self, id: &NoteID) -> NoteID {
+        if id.to_uuid().is_some() {
+            return id.clone();
+        }
+        self.ids
+            .entry(id.clone())
+            .or_insert_with(|| Uuid::new_v4().into())
+            .clone()
+    }
+
+    /// Get the new [`Revision`] of a revision of a note.
+    pub fn map_revision(&mut self, id: &NoteID, revision: &Revision) -> Revision {
+        if revision.to_uuid().is_some() {
+            return revision.clone();
+        }
+        self.revisions
+            .entry((id.clone(), revision.clone()))
+            .or_insert_with(|| Uuid::new_v4().into())
+            .clone()
+    }
+
+    pub fn remap<T: NoteType>(
+        &mut self,
+        mut record: RevisionRecord,
+    ) -> Result<RevisionRecord, NoteStoreError> {
+        let mut note_inner = T::from(record.note_inner);
+        let referents = note_inner
+            .get_referents()
+            .map_err(|e| NoteStoreError::NoteInnerError(e.to_string()))?;
+        for referent in referents {
+            let new_referent = self.map_id(&referent);
+            if new_referent != referent {
+                note_inner
+                    .update_referent(referent, new_referent)
+                    .map_err(|e| NoteStoreError::NoteInnerError(e.to_string()))?;
+            }
+        }
+        record.note_inner = note_inner.into();
+        record.revision = self.map_revision(&record.id, &record.revision);
+        record.metadata.reverted_from = record
+            .metadata
+            .reverted_from
+            .map(|x| self.map_revision(&record.id, &x));
+        record.based_on = record.based_on.map(|x| self.map_revision(&record.id, &x));
+        record.id = self.map_id(&record.id);
+        record.parent = record.parent.map(|x| self.map_id(&x));
+        record.prev = record.prev.map(|x| self.map_id(&x));
+        Ok(record)
+    }
+}
+
+/// Check that the records can be imported as a single note.
+///
+/// Returns the [`NoteID`] of the note and the current record (if any).
+pub(crate) fn check_note_records(
+    records: &[RevisionRecord],
+) -> Result<(&NoteID, Option<&RevisionRecord>), NoteStoreError> {
+    let id = match records.first() {
+        Some(r) => &r.id,
+        None => return Err(NoteStoreError::InvalidImport("no revision".to_owned())),
+    };
+    if records.iter().any(|r| &r.id != id) {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "revisions of note `{id}` are mixed with other notes"
+        )));
+    }
+    let mut current = records.iter().filter(|r| r.is_current);
+    let current_record = current.next();
+    if current.next().is_some() {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "note `{id}` has more than one current revision"
+        )));
+    }
+    if current_record.is_some_and(|r| r.is_draft) {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "the current revision of note `{id}` is a draft"
+        )));
+    }
+    if current_record.is_some() && records.iter().any(|r| r.deleted_at.is_some()) {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "note `{id}` has a current revision and a deletion time"
+        )));
+    }
+    let mut labels = HashSet::new();
+    if let Some(l) = records
+        .iter()
+        .flat_map(|r| &r.labels)
+        .find(|l| !labels.insert(*l))
+    {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "label `{l}` of note `{id}` is attached to more than one revision"
+        )));
+    }
+    let revisions: HashSet<&Revision> = records.iter().map(|r| &r.revision).collect();
+    if let Some(r) = records
+        .iter()
+        .find(|r| r.based_on.as_ref().is_some_and(|b| !revisions.contains(b)))
+    {
+        return Err(NoteStoreError::InvalidImport(format!(
+            "revision `{}` of note `{id}` is based on a missing revision",
+            r.revision
+        )));
+    }
+    Ok((id, current_record))
+}
+
+/// Write the [`Header`].
+pub fn write_header<W: Write>(writer: &mut W) -> Result<(), NoteStoreError> {
+    serde_json::to_writer(&mut *writer, &Header::default())?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Write a single [`RevisionRecord`].
+pub fn write_record<W: Write>(
+    writer: &mut W,
+    record: &RevisionRecord,
+) -> Result<(), NoteStoreError> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Read an archive lazily, checking the [`Header`] first.
+///
+/// Empty lines are skipped.
+/// Fields missing from archives of older versions take their default values.
+pub fn read_records<R: BufRead>(
+    reader: R,
+) -> Result<impl Iterator<Item = Result<RevisionRecord, NoteStoreError>>, NoteStoreError> {
+    let mut lines = reader
+        .lines()
+        .filter(|l| !matches!(l, Ok(s) if s.trim().is_empty()));
+    let header: Header = match lines.next() {
+        Some(l) => serde_json::from_str(&l?)?,
+        None => {
+            return Err(NoteStoreError::UnsupportedFormat(
+                "missing header".to_owned(),
+            ))
+        }
+    };
+    if header.format != FORMAT_NAME
+        || !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&header.version)
+    {
+        return Err(NoteStoreError::UnsupportedFormat(format!(
+            "{} version {}",
+            header.format, header.version
+        )));
+    }
+    Ok(lines.map(|l| Ok(serde_json::from_str(&l?)?)))
+}
+
+/// Order notes so that the predecessor (parent or previous note) of a note comes first.
+fn order_notes(ids: Vec<NoteID>, predecessors: &HashMap<NoteID, NoteID>) -> Vec<NoteID> {
+    let mut visited: HashSet<NoteID> = HashSet::new();
+    let mut ordered = vec![];
+    for id in ids {
+        let mut chain = vec![];
+        let mut cursor = Some(id);
+        while let Some(c) = cursor {
+            if !visited.insert(c.clone()) {
+                break;
+            }
+            cursor = predecessors.get(&c).cloned();
+            chain.push(c);
+        }
+        ordered.extend(chain.into_iter().rev());
+    }
+    ordered
+}
+
+/// A stream of records in the order of an archive.
+pub type RecordStream<'a> = BoxStream<'a, Result<RevisionRecord, NoteStoreError>>;
+
+/// Export every revision of every note in a [`NoteStore`] as a stream of records, in the order
+/// of an archive.
+///
+/// Records are read one note at a time, but the parent or previous note of every note and the
+/// deletion time of every deleted note are collected first.
+/// Collecting the latter lists the deleted notes, which loads their current revisions.
+///
+/// The store is read through many separate calls rather than from a snapshot, so it shouldn't be
+/// changed during the export.
+/// If a note is found to come before its parent or previous note, e.g., because it was moved
+/// meanwhile, the stream ends with [`NoteStoreError::StoreChanged`].
+/// Other concurrent changes are not detected.
+pub async fn export_records<'a, T, S>(store: &'a S) -> Result<RecordStream<'a>, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + Sync + ?Sized,
+{
+    let ids = store.get_note_ids().await?;
+    let mut predecessors = HashMap::new();
+    for id in &ids {
+        let loc = NoteLocator::Current(id.clone());
+        if store.get_current_revision(&loc).await?.is_none() {
+            continue;
+        }
+        let predecessor = {
+            let note = store.get_note(&loc).await?;
+            note.get_parent().or_else(|| note.get_prev())
+        };
+        if let Some(p) = predecessor {
+            predecessors.insert(id.clone(), p);
+        }
+    }
+    let deleted_at: HashMap<NoteID, DateTime<Utc>> = store
+        .list_deleted()
+        .await?
+        .into_iter()
+        .map(|d| (d.note.get_id(), d.deleted_at))
+        .collect();
+    let mut written = HashSet::new();
+    let notes = stream::iter(order_notes(ids, &predecessors))
+        .then(move |id| {
+            let deleted_at = deleted_at.get(&id).cloned();
+            note_records(store, id, deleted_at)
+        })
+        .map(move |records| {
+            let records = records?;
+            let id = match records.first() {
+                Some(r) => r.id.clone(),
+                None => return Ok(records),
+            };
+            for r in records.iter().filter(|r| r.is_current) {
+                let mut predecessors = r.parent.iter().chain(&r.prev);
+                if let Some(p) = predecessors.find(|p| !written.contains(*p)) {
+                    return Err(NoteStoreError::StoreChanged(format!(
+                        "note `{id}` comes before note `{p}`"
+                    )));
+                }
+            }
+            written.insert(id);
+            Ok(records)
+        });
+    // Stop at the first error
+    let records = notes
+        .scan(false, |failed, records| {
+            let item = (!*failed).then_some(records);
+            *failed = matches!(item, Some(Err(_)));
+            future::ready(item)
+        })
+        .flat_map(|records| match records {
+            Ok(records) => stream::iter(records.into_iter().map(Ok)).left_stream(),
+            Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+        });
+    Ok(records.boxed())
+}
+
+/// Get the records of every revision of a note.
+async fn note_records<T, S>(
+    store: &S,
+    id: NoteID,
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<Vec<RevisionRecord>, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    let labels = store.list_labels(&id).await?;
+    let past_deletions = store.list_past_deletions(&id).await?;
+    let revisions = store.get_revisions(&NoteLocator::Current(id)).await?;
+    Ok(revisions
+        .iter()
+        .map(|note| {
+            let mut record = RevisionRecord::from_note(note.as_ref());
+            record.labels = labels
+                .iter()
+                .filter(|l| l.revision == record.revision)
+                .map(|l| l.label.clone())
+                .collect();
+            record.deleted_at = deleted_at;
+            record.past_deletions = past_deletions.clone();
+            record
+        })
+        .collect())
+}
+
+/// Export every revision of every note in a [`NoteStore`], as described in [`export_records`].
+pub async fn export<T, S, W>(store: &S, writer: &mut W) -> Result<(), NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + Sync + ?Sized,
+    W: Write,
+{
+    write_header(writer)?;
+    let mut records = export_records(store).await?;
+    while let Some(record) = records.next().await {
+        write_record(writer, &record?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Group records in the order of an archive into the records of each note.
+pub(crate) fn group_by_note(
+    records: RecordStream<'_>,
+) -> BoxStream<'_, Result<Vec<RevisionRecord>, NoteStoreError>> {
+    stream::unfold(
+        (records, None),
+        |(mut records, next): (_, Option<RevisionRecord>)| async move {
+            let mut batch: Vec<RevisionRecord> = next.into_iter().collect();
+            loop {
+                match records.next().await {
+                    Some(Ok(r)) if batch.first().is_some_and(|b| b.id != r.id) => {
+                        return Some((Ok(batch), (records, Some(r))))
+                    }
+                    Some(Ok(r)) => batch.push(r),
+                    Some(Err(e)) => return Some((Err(e), (records, None))),
+                    None if batch.is_empty() => return None,
+                    None => return Some((Ok(batch), (records, None))),
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Import an archive into a [`NoteStore`].
+///
+/// [`NoteID`]s, [`Revision`]s and metadata (including timestamps) are kept as is.
+/// The archive is imported as a whole using [`NoteStore::import_notes`], so if a note fails to
+/// import, none of the notes are imported.
+pub async fn import<T, S, R>(store: &S, reader: R) -> Result<(), NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+    R: BufRead + Send,
+{
+    let records = read_records(reader)?;
+    store.import_notes(stream::iter(records).boxed()).await
+}
@@ -0,0 +1,13 @@
+//! Labels attached to revisions.
+use crate::Revision;
+use serde::{Deserialize, Serialize};
+
+/// A label of a note, as returned by [`NoteStore::list_labels`].
+///
+/// [`NoteStore::list_labels`]: crate::NoteStore::list_labels
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RevisionLabel {
+    pub label: String,
+    /// The revision that the label is attached to.
+    pub revision: Revision,
+}
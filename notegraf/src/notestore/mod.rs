@@ -3,19 +3,38 @@ use crate::errors::NoteStoreError;
 use crate::note::*;
 use crate::notemetadata::NoteMetadataEditable;
 use crate::notetype::NoteType;
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use std::path::Path;
 
+pub mod as_of;
+pub mod backup;
+pub mod changes;
 mod in_memory;
+pub mod interchange;
+pub mod label;
+pub mod position;
 mod postgresql;
+pub mod retention;
 pub mod search;
+pub mod session_draft;
 #[cfg(test)]
 mod tests;
+pub mod trash;
 pub mod util;
 
+use crate::notestore::interchange::{RecordStream, RevisionRecord};
 use crate::notestore::search::SearchRequest;
+pub use as_of::AsOf;
+pub use changes::{Change, ChangeCursor, ChangeKind, ChangeStream};
 pub use in_memory::InMemoryStore;
+pub use interchange::{export, import};
+pub use label::RevisionLabel;
+pub use position::{Ancestor, Link, MoveTarget, SplitTail};
 pub use postgresql::{PostgreSQLStore, PostgreSQLStoreBuilder};
+pub use retention::{compact, RetentionPolicy};
+pub use session_draft::SessionDraft;
+pub use trash::{restore_deleted, DeletedNote, DeletionPeriod};
 
 pub type Revisions<T> = Vec<Box<dyn Note<T>>>;
 
@@ -35,11 +54,13 @@ where
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
-    ) -> BoxFuture<Result<NoteLocator, NoteStoreError>>;
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>>;
     /// Get a note.
     ///
-    /// Using different variants of the [`NoteLocator`], one can get a specific revision or
-    /// the current revision.
+    /// Using different variants of the [`NoteLocator`], one can get a specific revision,
+    /// the current revision, or the revision that was current at a point in time.
+    /// For the latter, the relationships (branches, next, references) are computed as of that
+    /// point in time too.
     fn get_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -50,11 +71,10 @@ where
     ///
     /// If a revision is specified, that revision should be the current revision.
     /// This can be used to prevent racy updates to the same note.
+    /// Drafts are never current, so to continue a draft, use [`NoteStore::create_draft`].
     ///
-    /// There is no separate operations for rolling back a note to a specific revision.
-    /// The idea is that you can just copy that revision and update the current revision to
-    /// that one.
-    /// Similarly, you can resurrect a deleted note by updating the note.
+    /// To roll back a note to a specific revision, use [`NoteStore::revert_note`].
+    /// You can resurrect a deleted note by updating the note.
     ///
     /// When resurrecting a note, it will become a standalone note and losing any
     /// parent-children or previous-next relationship.
@@ -69,6 +89,59 @@ where
         note_inner: Option<T>,
         note_metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Revert a note to the revision specified by the locator.
+    ///
+    /// The title, note_inner, tags and custom metadata of that revision are copied into a new
+    /// revision, which becomes the current revision.
+    /// The new revision records the restored revision in [`NoteMetadata::reverted_from`].
+    ///
+    /// Relationships are handled as in [`NoteStore::update_note`].
+    /// If the note is deleted, it is resurrected as a standalone note.
+    ///
+    /// [`NoteMetadata::reverted_from`]: crate::notemetadata::NoteMetadata::reverted_from
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Move a note to a different position, as described by [`MoveTarget`].
+    ///
+    /// Only the note itself is moved, together with its branches.
+    /// The position it leaves is taken over by its next note, the same way as in
+    /// [`NoteStore::delete_note`].
+    ///
+    /// A note never has both a parent and a previous note after the move.
+    /// Moving a note under itself or its branches fails with
+    /// [`NoteStoreError::WouldCreateCycle`].
+    ///
+    /// Returns the new revision of the moved note.
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        target: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Split a sequence right before a note.
+    ///
+    /// The note and the notes following it form the tail, which is detached from the note
+    /// before it, and becomes a sequence on its own or a branch of that note.
+    /// Splitting at the first note of a sequence fails with [`NoteStoreError::SplitAtHead`].
+    fn split_sequence<'a>(
+        &'a self,
+        at: &'a NoteID,
+        tail: SplitTail,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Merge two sequences by appending the sequence starting at `head` to the note `last`.
+    ///
+    /// `last` must be the last note of a sequence ([`NoteStoreError::ExistingNext`] otherwise),
+    /// and `head` must be the first note of a sequence ([`NoteStoreError::ExistingPrev`]
+    /// otherwise).
+    /// If `head` is a branch, it is removed from its parent.
+    /// Appending a sequence to itself or its branches fails with
+    /// [`NoteStoreError::WouldCreateCycle`].
+    fn merge_sequences<'a>(
+        &'a self,
+        last: &'a NoteID,
+        head: &'a NoteID,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
     /// Delete a note.
     ///
     /// If a revision is specified, that revision should be the current revision.
@@ -89,6 +162,154 @@ where
     /// A note cannot be deleted if it is the referent of any other note.
     fn delete_note<'a>(&'a self, loc: &'a NoteLocator)
         -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// List all deleted notes, the most recently deleted first.
+    ///
+    /// Deleted notes can be restored with [`trash::restore_deleted`].
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>>;
+    /// Get the periods during which a note was deleted before it was restored, from older to
+    /// newer.
+    ///
+    /// The note didn't exist during these periods, e.g., for [`NoteLocator::AtTime`].
+    fn list_past_deletions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<DeletionPeriod>, NoteStoreError>>;
+    /// Permanently remove a deleted note and all its revisions.
+    ///
+    /// If the note is not deleted, [`NoteStoreError::NotDeleted`] is returned.
+    /// A note cannot be purged if it is the referent of any other note.
+    /// Nor can it be purged if older revisions of other notes refer to it, or are linked to it as
+    /// their parent, previous note, branch or next note ([`NoteStoreError::LinkedInHistory`],
+    /// listing those notes), so that the history of other notes is never changed or left dangling.
+    /// Those revisions can be purged with [`NoteStore::purge_revision`] first.
+    /// The session drafts of the note are kept without the note and base revision.
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Permanently remove a revision of a note.
+    ///
+    /// The current revision cannot be purged ([`NoteStoreError::PurgeCurrentRevision`]).
+    /// Purging the only remaining revision of a deleted note purges the note, as in
+    /// [`NoteStore::purge_note`].
+    /// Revisions that reverted to the purged revision no longer record it in
+    /// [`NoteMetadata::reverted_from`].
+    ///
+    /// [`NoteMetadata::reverted_from`]: crate::notemetadata::NoteMetadata::reverted_from
+    fn purge_revision<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Attach a label to a revision of a note, so that the revision can be located by
+    /// [`NoteLocator::Label`].
+    ///
+    /// Labels are unique within a note.
+    /// If the label is already attached to another revision of the note,
+    /// [`NoteStoreError::LabelExists`] is returned.
+    /// Labels are removed together with the revision when it is purged.
+    fn add_label<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Remove a label from a note.
+    ///
+    /// If the note doesn't have the label, [`NoteStoreError::LabelNotExist`] is returned.
+    fn remove_label<'a>(
+        &'a self,
+        id: &'a NoteID,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Get the labels of a note, ordered by label.
+    fn list_labels<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<RevisionLabel>, NoteStoreError>>;
+    /// Start a draft from any revision of a note, including old revisions and other drafts.
+    ///
+    /// The draft is a new revision based on the revision specified by the locator, with the
+    /// changes applied as in [`NoteStore::update_note`].
+    /// It never becomes the current revision by itself, and it is left out when looking up the
+    /// current revision of a note or the revision that was current at a point in time.
+    ///
+    /// Returns the locator of the draft.
+    fn create_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        title: Option<String>,
+        note_inner: Option<T>,
+        note_metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Get the drafts of a note that no other revision is based on yet, in the order from older
+    /// to newer.
+    ///
+    /// A draft that has been promoted or updated is no longer listed.
+    fn list_drafts<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
+    /// Make the content of a draft the current revision of a note.
+    ///
+    /// The title, note_inner, tags, custom metadata, author and message of the draft are copied
+    /// into a new revision based on the draft, which becomes the current revision.
+    /// If the revision is not a draft, [`NoteStoreError::NotADraft`] is returned.
+    ///
+    /// Relationships are handled as in [`NoteStore::revert_note`].
+    fn promote_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Save the draft of an editor session, replacing the previous draft of the session.
+    ///
+    /// The draft is linked to the note that `target` locates, if any, and also to the located
+    /// revision unless `target` is a [`NoteLocator::Current`].
+    /// See [`session_draft`] for how session drafts differ from the drafts of
+    /// [`NoteStore::create_draft`].
+    fn put_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+        target: Option<&'a NoteLocator>,
+        content: serde_json::Value,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>>;
+    /// Get the draft of an editor session.
+    ///
+    /// If the session has no draft, [`NoteStoreError::SessionDraftNotExist`] is returned.
+    fn get_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>>;
+    /// Delete the draft of an editor session.
+    ///
+    /// If the session has no draft, [`NoteStoreError::SessionDraftNotExist`] is returned.
+    fn delete_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Get the drafts of the editor sessions whose key starts with `prefix`, ordered by key.
+    fn list_session_drafts<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SessionDraft>, NoteStoreError>>;
+    /// Stream the changes to the notes from now on.
+    ///
+    /// The stream ends if the subscriber falls too far behind, or if the store can no longer
+    /// deliver changes.
+    /// The changes missed can be recovered with [`NoteStore::changes_since`].
+    /// See [`changes`] for what is recorded.
+    fn subscribe(&self) -> BoxFuture<'_, Result<ChangeStream, NoteStoreError>>;
+    /// Get the changes after a cursor, in the order that they took effect.
+    ///
+    /// Changes pruned with [`NoteStore::prune_changes`] are not included.
+    /// Changes that can't be ordered yet, because an earlier write is still in progress, might be
+    /// left to a later call, and are delivered to the subscribers once they can.
+    fn changes_since(
+        &self,
+        cursor: ChangeCursor,
+    ) -> BoxFuture<'_, Result<Vec<Change>, NoteStoreError>>;
+    /// Delete the changes recorded before `before` from the change feed, and return how many.
+    ///
+    /// Clients with a cursor older than the changes kept should reload instead of catching up.
+    fn prune_changes<'a>(
+        &'a self,
+        before: &'a DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<u64, NoteStoreError>>;
     /// Get all revisions of a note, in the order from older (smaller timestamp) to newer (larger
     /// timestamp).
     ///
@@ -104,6 +325,31 @@ where
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Option<Revision>, NoteStoreError>>;
+    /// Get the current revisions of all notes in the sequence that a note belongs to, in the order
+    /// from the first note to the last note.
+    fn get_sequence<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
+    /// Get the current revisions of a note and all notes below it.
+    ///
+    /// The branches and the next note of a note are one level below the note.
+    /// Only notes at most `depth` levels below the note are included, or all notes if `depth` is
+    /// `None`.
+    /// The notes are ordered by level, starting with the note itself, and then by creation time.
+    fn get_subtree<'a>(
+        &'a self,
+        id: &'a NoteID,
+        depth: Option<usize>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
+    /// Get the ancestors of a note by following the previous note or the parent of each note,
+    /// starting from the note right above the note, up to the top-level note.
+    ///
+    /// A top-level note has no ancestors.
+    fn get_ancestors<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<Ancestor<T>>, NoteStoreError>>;
     /// Append a note to the last (or only) note in a sequence
     ///
     /// It is implementation defined whether the previous note is updated.
@@ -130,19 +376,124 @@ where
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Insert a new note right after a note in a sequence.
+    ///
+    /// Unlike [`NoteStore::append_note`], the note doesn't have to be the last note.
+    /// If it has a next note, that note will follow the new note instead.
+    fn insert_after<'a>(
+        &'a self,
+        prev: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Insert a new note right before a note.
+    ///
+    /// The new note takes over the position of the note, i.e., its parent or previous note,
+    /// and the note will follow the new note.
+    fn insert_before<'a>(
+        &'a self,
+        next: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Create a new note with a caller-supplied [`NoteIdentity`].
+    ///
+    /// Same as [`NoteStore::new_note`], except that the [`NoteID`], [`Revision`] and timestamps
+    /// are taken from the identity.
+    /// If the [`NoteID`] is already taken, [`NoteStoreError::NoteIDConflict`] is returned.
+    fn new_note_with_identity(
+        &self,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>>;
+    /// Append a note with a caller-supplied [`NoteIdentity`].
+    ///
+    /// See [`NoteStore::append_note`] and [`NoteStore::new_note_with_identity`].
+    fn append_note_with_identity<'a>(
+        &'a self,
+        last: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Add a branch with a caller-supplied [`NoteIdentity`].
+    ///
+    /// See [`NoteStore::add_branch`] and [`NoteStore::new_note_with_identity`].
+    fn add_branch_with_identity<'a>(
+        &'a self,
+        parent: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Search for notes as they were at a point in time.
+    ///
+    /// Like [`NoteStore::search`], but over the revisions that were current at `at`, with the
+    /// relationships (branches, next, references) computed between those revisions.
+    fn search_at<'a>(
+        &'a self,
+        sr: &'a SearchRequest,
+        at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
     /// Search for a note
     fn search<'a>(
         &'a self,
         sr: &'a SearchRequest,
     ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
     /// List all known tags
-    fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>>;
+    fn tags(&self) -> BoxFuture<'_, Result<Vec<String>, NoteStoreError>>;
+    /// Get the IDs of all notes, including deleted notes.
+    fn get_note_ids(&self) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>>;
+    /// Import all revisions of a note, keeping [`NoteID`]s, [`Revision`]s and metadata as is.
+    ///
+    /// All records must belong to the same note, and at most one of them is current.
+    /// If the note already exists, [`NoteStoreError::NoteIDConflict`] is returned.
+    ///
+    /// If the note is not deleted, its parent or previous note (if any) must already exist and
+    /// not be deleted, so that the relationships can be recovered.
+    /// The previous note must not already have a next note.
+    fn import_note(
+        &self,
+        records: Vec<RevisionRecord>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>>;
+    /// Import the notes of an archive as a whole, from a stream of records in the order described
+    /// in [`interchange`].
+    ///
+    /// Every note is imported as with [`NoteStore::import_note`].
+    /// If any note fails to import, none of the notes are imported.
+    fn import_notes<'a>(
+        &'a self,
+        records: RecordStream<'a>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
     /// Backup the storage to a folder on some filesystem.
-    fn backup(&self, path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>>;
+    ///
+    /// Every revision of every note, including deleted notes, is written in the format described
+    /// in [`backup`], so that the backup can be restored by any [`NoteStore`].
+    fn backup(
+        &self,
+        path: Box<dyn AsRef<Path> + Send>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>>;
     /// Restore the storage from a folder on some filesystem.
+    ///
+    /// Stores that can't be constructed from a backup alone, e.g., [`PostgreSQLStore`], return
+    /// [`NoteStoreError::Unsupported`], and backups are restored into them with
+    /// [`NoteStore::restore_into`].
     fn restore<P: AsRef<Path>>(path: P) -> Result<Self, NoteStoreError>
     where
         Self: Sized;
+    /// Restore a backup from a folder on some filesystem into this storage.
+    ///
+    /// Unlike [`NoteStore::restore`], this works for stores that can't be constructed from a
+    /// backup alone, e.g., those connected to a database.
+    /// The storage must be empty, otherwise [`NoteStoreError::StoreNotEmpty`] is returned.
+    /// [`NoteID`]s, [`Revision`]s and metadata (including timestamps) are kept as is.
+    fn restore_into<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NoteStoreError>>;
 }
 
 pub type BoxedNoteStore<T> = Box<dyn NoteStore<T> + Sync + Send>;
@@ -0,0 +1,41 @@
+//! Positions of notes in the branch and sequence structure.
+use crate::{Note, NoteID};
+use serde::{Deserialize, Serialize};
+
+/// Where to move a note with [`NoteStore::move_note`](crate::NoteStore::move_note).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MoveTarget {
+    /// Make the note a branch of the given note.
+    Branch(NoteID),
+    /// Make the note a top-level note, i.e., without a parent or a previous note.
+    TopLevel,
+    /// Append the note to the end of the sequence that the given note belongs to.
+    EndOf(NoteID),
+}
+
+/// What the tail of a sequence becomes with
+/// [`NoteStore::split_sequence`](crate::NoteStore::split_sequence).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SplitTail {
+    /// A sequence on its own.
+    TopLevel,
+    /// A branch of the note it used to follow.
+    Branch,
+}
+
+/// How a note is linked to the note above it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Link {
+    /// The note is the next note of the note above it.
+    Sequence,
+    /// The note is a branch of the note above it.
+    Branch,
+}
+
+/// An ancestor of a note, as returned by [`NoteStore::get_ancestors`](crate::NoteStore::get_ancestors).
+pub struct Ancestor<T> {
+    /// How the note right below links to this ancestor.
+    pub link: Link,
+    /// The current revision of this ancestor.
+    pub note: Box<dyn Note<T>>,
+}
@@ -0,0 +1,107 @@
+//! Deltas of revisions against keyframes.
+//!
+//! A delta is a JSON array, where `[start, length]` copies characters (not bytes) from the
+//! keyframe, and a string inserts text.
+use crate::errors::NoteStoreError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+enum Op {
+    Copy(usize, usize),
+    Insert(String),
+}
+
+fn push(ops: &mut Vec<Op>, op: Op) {
+    match (ops.last_mut(), op) {
+        (Some(Op::Copy(start, len)), Op::Copy(s, l)) if *start + *len == s => *len += l,
+        (Some(Op::Insert(text)), Op::Insert(t)) => text.push_str(&t),
+        (_, op) => ops.push(op),
+    }
+}
+
+/// Compute the delta that turns `base` into `target`.
+///
+/// Text is compared word by word, since the body of some note types is serialized into a single
+/// line.
+pub(super) fn compute(base: &str, target: &str) -> Value {
+    let mut ops: Vec<Op> = vec![];
+    // Position in base, in characters
+    let mut pos = 0;
+    for change in TextDiff::from_words(base, target).iter_all_changes() {
+        let len = change.value().chars().count();
+        match change.tag() {
+            ChangeTag::Equal => {
+                push(&mut ops, Op::Copy(pos, len));
+                pos += len;
+            }
+            ChangeTag::Delete => pos += len,
+            ChangeTag::Insert => push(&mut ops, Op::Insert(change.value().to_owned())),
+        }
+    }
+    serde_json::to_value(ops).unwrap()
+}
+
+/// Apply a delta to `base`, or `None` if the delta is malformed.
+pub(super) fn apply(base: &str, delta: &Value) -> Option<String> {
+    let ops: Vec<Op> = serde_json::from_value(delta.clone()).ok()?;
+    let chars: Vec<char> = base.chars().collect();
+    let mut target = String::new();
+    for op in ops {
+        match op {
+            Op::Copy(start, len) => target.extend(chars.get(start..start.checked_add(len)?)?),
+            Op::Insert(text) => target.push_str(&text),
+        }
+    }
+    Some(target)
+}
+
+/// The full text of a revision, given the columns of its row and the text of its keyframe.
+pub(super) fn rebuild(
+    note_inner: String,
+    delta: Option<Value>,
+    base: Option<String>,
+) -> Result<String, NoteStoreError> {
+    match (delta, base) {
+        (Some(d), Some(b)) => apply(&b, &d)
+            .ok_or_else(|| NoteStoreError::NoteInnerError("malformed revision delta".to_owned())),
+        _ => Ok(note_inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let cases = [
+            ("", ""),
+            ("", "Foo\n"),
+            ("Foo\n", ""),
+            ("Foo\nBar\nBaz", "Foo\nQux\nBaz"),
+            ("Foo\nBar\n", "Bar\nFoo\n"),
+            ("Ünïcödé\n日本語\n", "Ünïcödé\n中文\n日本語"),
+        ];
+        for (base, target) in cases {
+            assert_eq!(apply(base, &compute(base, target)).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn merge_ops() {
+        assert_eq!(
+            compute("Foo\nBar\nBaz\n", "Foo\nBar\nQux\n"),
+            json!([[0, 8], "Qux", [11, 1]])
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(apply("Foo", &json!([[2, 5]])), None);
+        assert_eq!(apply("Foo", &json!({"copy": 1})), None);
+    }
+}
@@ -1,16 +1,29 @@
 use crate::errors::NoteStoreError;
 use crate::notemetadata::{NoteMetadata, NoteMetadataEditable};
+use crate::notestore::backup::{read_backup, write_backup};
+use crate::notestore::changes::{broadcast_stream, Change, ChangeCursor, ChangeKind, ChangeStream};
+use crate::notestore::interchange::{group_by_note, RecordStream, RevisionRecord};
+use crate::notestore::label::RevisionLabel;
+use crate::notestore::position::{Ancestor, Link, MoveTarget, SplitTail};
+use crate::notestore::session_draft::SessionDraft;
+use crate::notestore::trash::{DeletedNote, DeletionPeriod};
 use crate::notestore::Revisions;
-use crate::{Note, NoteID, NoteLocator, NoteStore, NoteType, Revision};
+use crate::{Note, NoteID, NoteIdentity, NoteLocator, NoteStore, NoteType, Revision};
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
-use sqlx::postgres::PgConnectOptions;
+use futures::StreamExt;
+use sqlx::postgres::{PgConnectOptions, PgListener};
 use sqlx::{query, PgPool, Postgres, Transaction};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::ops::DerefMut;
 use std::path::Path;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+mod delta;
 mod queries;
 use crate::notestore::search::SearchRequest;
 use queries::*;
@@ -26,8 +39,92 @@ fn get_new_revision() -> Uuid {
     Uuid::new_v4()
 }
 
+/// How many changes a subscriber can fall behind before its stream ends.
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+/// How often to look again for changes that wait for an older transaction to finish.
+const UNSEQUENCED_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait for the changes committed so far to get a cursor, before leaving the rest to
+/// the subscribers.
+const UNSEQUENCED_MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// Give cursors to the committed changes, and return whether some of them still wait for an
+/// older transaction to finish.
+///
+/// If `wait` is true, wait until the changes committed so far have a cursor, but no longer than
+/// [`UNSEQUENCED_MAX_WAIT`], since an older transaction (e.g., an import) might take a while.
+async fn sequence_committed_changes(db_pool: &PgPool, wait: bool) -> Result<bool, NoteStoreError> {
+    let deadline = tokio::time::Instant::now() + UNSEQUENCED_MAX_WAIT;
+    let mut waiting_for = None;
+    loop {
+        let mut transaction = db_pool.begin().await?;
+        let unsequenced = sequence_changes(&mut transaction).await?;
+        transaction.commit().await?;
+        let last = *waiting_for.get_or_insert(unsequenced.map_or(0, |(_, last)| last));
+        match unsequenced {
+            Some((first, _)) if wait && first <= last && tokio::time::Instant::now() < deadline => {
+                tokio::time::sleep(UNSEQUENCED_POLL_INTERVAL).await
+            }
+            _ => return Ok(unsequenced.is_some()),
+        }
+    }
+}
+
+async fn read_changes_since(
+    db_pool: &PgPool,
+    cursor: ChangeCursor,
+) -> Result<Vec<Change>, NoteStoreError> {
+    let mut transaction = db_pool.begin().await?;
+    read_only(&mut transaction).await?;
+    let changes = changes_since(&mut transaction, cursor).await?;
+    transaction.commit().await?;
+    Ok(changes)
+}
+
+/// Send the changes after a cursor to the subscribers whenever some are notified, until there is
+/// no subscriber left or the changes can no longer be delivered.
+async fn run_change_feed(
+    mut listener: PgListener,
+    db_pool: PgPool,
+    mut cursor: ChangeCursor,
+    mut unsequenced: bool,
+    sender: broadcast::Sender<Change>,
+) {
+    loop {
+        let notified = if unsequenced {
+            // Nothing may be notified when the older transaction finishes
+            tokio::time::timeout(UNSEQUENCED_POLL_INTERVAL, listener.recv())
+                .await
+                .map_or(true, |n| n.is_ok())
+        } else {
+            listener.recv().await.is_ok()
+        };
+        if !notified || sender.receiver_count() == 0 {
+            return;
+        }
+        let Ok(u) = sequence_committed_changes(&db_pool, false).await else {
+            return;
+        };
+        unsequenced = u;
+        let Ok(changes) = read_changes_since(&db_pool, cursor).await else {
+            return;
+        };
+        for change in changes {
+            cursor = change.cursor;
+            if sender.send(change).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Every revision is stored in full by default.
+const DEFAULT_KEYFRAME_INTERVAL: u32 = 1;
+
 pub struct PostgreSQLStoreBuilder<T> {
     db_options: PgConnectOptions,
+    keyframe_interval: u32,
     _phantom: PhantomData<T>,
 }
 
@@ -45,6 +142,8 @@ struct PostgreSQLNote<T> {
     references: HashSet<NoteID>,
     metadata: NoteMetadata,
     is_current: bool,
+    based_on: Option<Revision>,
+    is_draft: bool,
 }
 
 impl<T> Note<T> for PostgreSQLNote<T>
@@ -98,16 +197,36 @@ where
     fn is_current(&self) -> bool {
         self.is_current
     }
+
+    fn get_based_on(&self) -> Option<Revision> {
+        self.based_on.clone()
+    }
+
+    fn is_draft(&self) -> bool {
+        self.is_draft
+    }
 }
 
 impl<T: NoteType> PostgreSQLStoreBuilder<T> {
     pub fn new(db_options: PgConnectOptions) -> Self {
         Self {
             db_options,
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
             _phantom: PhantomData,
         }
     }
 
+    /// Store revisions that are not current as deltas against keyframes, keeping a keyframe with
+    /// the full text for every `keyframe_interval` revisions of a note.
+    ///
+    /// With an interval of 1 (or 0), the default, every revision is stored in full.
+    /// Changing the interval does not affect revisions already stored, unless they are converted
+    /// with [`PostgreSQLStore::compact_deltas`].
+    pub fn keyframe_interval(mut self, keyframe_interval: u32) -> Self {
+        self.keyframe_interval = keyframe_interval;
+        self
+    }
+
     pub async fn build(self) -> PostgreSQLStore<T> {
         let connection_pool = PgPool::connect_with(self.db_options)
             .await
@@ -118,6 +237,8 @@ impl<T: NoteType> PostgreSQLStoreBuilder<T> {
             .expect("Failed to migrate the database");
         PostgreSQLStore {
             db_pool: connection_pool,
+            keyframe_interval: self.keyframe_interval,
+            change_feed: Default::default(),
             _phantom: PhantomData,
         }
     }
@@ -125,25 +246,79 @@ impl<T: NoteType> PostgreSQLStoreBuilder<T> {
 
 pub struct PostgreSQLStore<T> {
     db_pool: PgPool,
+    keyframe_interval: u32,
+    /// The change feed shared by the subscribers, which lives as long as one of them.
+    change_feed: Mutex<Option<(broadcast::WeakSender<Change>, JoinHandle<()>)>>,
     _phantom: PhantomData<T>,
 }
 
+impl<T> Drop for PostgreSQLStore<T> {
+    fn drop(&mut self) {
+        if let Some((_, task)) = self.change_feed.get_mut() {
+            task.abort();
+        }
+    }
+}
+
 impl<T: NoteType> PostgreSQLStore<T> {
+    /// The sender of the change feed, if it still has a subscriber.
+    async fn live_change_feed(&self) -> Option<broadcast::Sender<Change>> {
+        let change_feed = self.change_feed.lock().await;
+        change_feed.as_ref().and_then(|(s, _)| s.upgrade())
+    }
+
+    /// Store the revisions that are not current as deltas according to the keyframe interval,
+    /// including those saved in full before deltas were enabled, and return how many were
+    /// converted.
+    ///
+    /// Each note is converted in its own transaction.
+    pub async fn compact_deltas(&self) -> Result<u64, NoteStoreError> {
+        let mut converted = 0;
+        if self.keyframe_interval <= 1 {
+            return Ok(converted);
+        }
+        let mut transaction = self.db_pool.begin().await?;
+        read_only(&mut transaction).await?;
+        let ids = get_note_ids(&mut transaction).await?;
+        transaction.commit().await?;
+        for id in ids {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            for revision in get_full_revisions(&mut transaction, id).await? {
+                if compress_revision(&mut transaction, revision, self.keyframe_interval).await? {
+                    converted += 1;
+                }
+            }
+            transaction.commit().await?;
+        }
+        Ok(converted)
+    }
+
     async fn new_note_helper(
-        &self,
         transaction: &mut Transaction<'_, Postgres>,
+        identity: Option<NoteIdentity>,
         title: String,
         note_inner: T,
         prev: Option<Uuid>,
         parent: Option<Uuid>,
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
-        let id = get_new_noteid();
-        let revision = get_new_revision();
-        // reborrowing hack to prevent transaction from moving
-        query!(r#"INSERT INTO note(id) VALUES ($1)"#, &id)
-            .execute(&mut **transaction)
-            .await?;
+        let (id, revision) = if let Some(i) = &identity {
+            let id = i.id.try_to_uuid()?;
+            let revision = i.revision.try_to_uuid()?;
+            // A note referenced by imported notes may exist without any revision yet
+            if has_revisions(transaction, id).await? {
+                return Err(NoteStoreError::NoteIDConflict(i.id.clone()));
+            }
+            if !noteid_exist(transaction, id).await? {
+                insert_note(transaction, id).await?;
+            }
+            (id, revision)
+        } else {
+            let id = get_new_noteid();
+            insert_note(transaction, id).await?;
+            (id, get_new_revision())
+        };
         let n = PostgreSQLNoteEditable {
             id,
             revision,
@@ -151,12 +326,441 @@ impl<T: NoteType> PostgreSQLStore<T> {
             note_inner,
             prev,
             parent,
-            metadata: NoteMetadata::from_editable(metadata),
+            metadata: NoteMetadata::on_new_note(metadata, identity.as_ref()),
+            based_on: None,
+            is_draft: false,
         };
         insert_revision(transaction, n).await?;
         upsert_current_revision(transaction, id, revision).await?;
         Ok(NoteLocator::Specific(id.into(), revision.into()))
     }
+
+    /// Let the next note of a note take over its position, before the note is removed from it.
+    ///
+    /// Since only prev is stored, our prev note is not aware of us.
+    /// But we want to make sure our next note is consistent.
+    /// The next note basically inherits our prev and parent.
+    async fn hand_over_position(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        note: &PostgreSQLNote<T>,
+    ) -> Result<(), NoteStoreError> {
+        if let Some(next) = &note.next {
+            let new_loc = update_note_helper::<_, T>(
+                transaction,
+                self.keyframe_interval,
+                &NoteLocator::Current(next.clone()),
+                |old_note| {
+                    let mut new_note = old_note.clone();
+                    // The below two lines shouldn't both have effects
+                    new_note.prev = note.prev.as_ref().map(|x| x.to_uuid().unwrap());
+                    new_note.parent = note.parent.as_ref().map(|x| x.to_uuid().unwrap());
+                    Ok(new_note)
+                },
+            )
+            .await?;
+            record_change(transaction, ChangeKind::Update, &new_loc).await?;
+        }
+        Ok(())
+    }
+
+    /// Check that a note can be attached to the target once it leaves its current position.
+    ///
+    /// If `hand_over` is set, the next note of the note takes over its position, as in
+    /// [`PostgreSQLStore::hand_over_position`].
+    /// Otherwise, the next notes move together with the note.
+    async fn check_acyclic(
+        transaction: &mut Transaction<'_, Postgres>,
+        id: &NoteID,
+        target: &NoteID,
+        hand_over: bool,
+    ) -> Result<(), NoteStoreError> {
+        let mut cursor = target.clone();
+        let mut via_prev = false;
+        loop {
+            // Reaching the note through its next note is fine, because the next note will take
+            // over the position of the note
+            if &cursor == id && !(hand_over && via_prev) {
+                return Err(NoteStoreError::WouldCreateCycle(id.clone(), target.clone()));
+            }
+            let note: PostgreSQLNote<T> =
+                get_note_by_loc(transaction, &NoteLocator::Current(cursor.clone()))
+                    .await?
+                    .into_note()?;
+            if let Some(prev) = note.prev {
+                cursor = prev;
+                via_prev = true;
+            } else if let Some(parent) = note.parent {
+                cursor = parent;
+                via_prev = false;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Get the last note of the sequence containing a note, ignoring the note being moved.
+    async fn get_sequence_end(
+        transaction: &mut Transaction<'_, Postgres>,
+        start: &NoteID,
+        moving: &PostgreSQLNote<T>,
+    ) -> Result<NoteID, NoteStoreError> {
+        let mut cursor = start.clone();
+        loop {
+            let note: PostgreSQLNote<T> =
+                get_note_by_loc(transaction, &NoteLocator::Current(cursor.clone()))
+                    .await?
+                    .into_note()?;
+            let mut next = note.next;
+            if next.as_ref() == Some(&moving.id) {
+                next.clone_from(&moving.next);
+            }
+            match next {
+                Some(n) => cursor = n,
+                None => return Ok(cursor),
+            }
+        }
+    }
+
+    async fn move_note_inner(
+        &self,
+        id: &NoteID,
+        target: &MoveTarget,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let loc = NoteLocator::Current(id.clone());
+        let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, &loc).await?.into_note()?;
+        let (parent, prev) = match target {
+            MoveTarget::Branch(parent_id) => {
+                get_note_by_loc(&mut transaction, &NoteLocator::Current(parent_id.clone())).await?;
+                Self::check_acyclic(&mut transaction, id, parent_id, true).await?;
+                (Some(parent_id.try_to_uuid()?), None)
+            }
+            MoveTarget::TopLevel => (None, None),
+            MoveTarget::EndOf(sequence_id) => {
+                if sequence_id == id {
+                    return Err(NoteStoreError::WouldCreateCycle(id.clone(), id.clone()));
+                }
+                let end = Self::get_sequence_end(&mut transaction, sequence_id, &note).await?;
+                Self::check_acyclic(&mut transaction, id, &end, true).await?;
+                (None, Some(end.try_to_uuid()?))
+            }
+        };
+        self.hand_over_position(&mut transaction, &note).await?;
+        let new_loc = update_note_helper::<_, T>(
+            &mut transaction,
+            self.keyframe_interval,
+            &loc,
+            |old_note| {
+                let mut new_note = old_note.clone();
+                new_note.parent = parent;
+                new_note.prev = prev;
+                Ok(new_note)
+            },
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
+        transaction.commit().await?;
+        Ok(new_loc)
+    }
+
+    async fn new_note_inner(
+        &self,
+        identity: Option<NoteIdentity>,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let loc = Self::new_note_helper(
+            &mut transaction,
+            identity,
+            title,
+            note_inner,
+            None,
+            None,
+            metadata,
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Create, &loc).await?;
+        transaction.commit().await?;
+        Ok(loc)
+    }
+
+    async fn append_note_inner(
+        &self,
+        last: &NoteID,
+        identity: Option<NoteIdentity>,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let last_note: PostgreSQLNote<T> =
+            get_note_by_loc(&mut transaction, &NoteLocator::Current(last.clone()))
+                .await?
+                .into_note()?;
+        let last_note_next = last_note.get_next();
+        if let Some(n) = last_note_next {
+            transaction.rollback().await?;
+            return Err(NoteStoreError::ExistingNext(last.clone(), n));
+        }
+        let last_uuid = last.try_to_uuid()?;
+        let loc = Self::new_note_helper(
+            &mut transaction,
+            identity,
+            title,
+            note_inner,
+            Some(last_uuid),
+            None,
+            metadata,
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Append, &loc).await?;
+        transaction.commit().await?;
+        Ok(loc)
+    }
+
+    async fn split_sequence_inner(
+        &self,
+        at: &NoteID,
+        tail: SplitTail,
+    ) -> Result<(), NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let loc = NoteLocator::Current(at.clone());
+        let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, &loc).await?.into_note()?;
+        let prev = note
+            .prev
+            .ok_or_else(|| NoteStoreError::SplitAtHead(at.clone()))?
+            .try_to_uuid()?;
+        let new_loc = update_note_helper::<_, T>(
+            &mut transaction,
+            self.keyframe_interval,
+            &loc,
+            |old_note| {
+                let mut new_note = old_note.clone();
+                new_note.prev = None;
+                if tail == SplitTail::Branch {
+                    new_note.parent = Some(prev);
+                }
+                Ok(new_note)
+            },
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn merge_sequences_inner(
+        &self,
+        last: &NoteID,
+        head: &NoteID,
+    ) -> Result<(), NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let last_note: PostgreSQLNote<T> =
+            get_note_by_loc(&mut transaction, &NoteLocator::Current(last.clone()))
+                .await?
+                .into_note()?;
+        let head_loc = NoteLocator::Current(head.clone());
+        let head_note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, &head_loc)
+            .await?
+            .into_note()?;
+        if let Some(n) = last_note.next {
+            return Err(NoteStoreError::ExistingNext(last.clone(), n));
+        }
+        if let Some(p) = head_note.prev {
+            return Err(NoteStoreError::ExistingPrev(p, head.clone()));
+        }
+        Self::check_acyclic(&mut transaction, head, last, false).await?;
+        let last_uuid = last.try_to_uuid()?;
+        let new_loc = update_note_helper::<_, T>(
+            &mut transaction,
+            self.keyframe_interval,
+            &head_loc,
+            |old_note| {
+                let mut new_note = old_note.clone();
+                new_note.prev = Some(last_uuid);
+                new_note.parent = None;
+                Ok(new_note)
+            },
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn purge_note_helper(
+        transaction: &mut Transaction<'_, Postgres>,
+        id: &NoteID,
+    ) -> Result<(), NoteStoreError> {
+        let uuid = id.try_to_uuid()?;
+        if has_references(transaction, uuid).await? {
+            return Err(NoteStoreError::HasReferences(id.clone()));
+        }
+        let linked = get_linked_in_history(transaction, uuid).await?;
+        if !linked.is_empty() {
+            return Err(NoteStoreError::LinkedInHistory(
+                id.clone(),
+                linked.into_iter().map(|x| x.into()).collect(),
+            ));
+        }
+        purge_note_rows(transaction, uuid).await
+    }
+
+    async fn purge_note_inner(&self, id: &NoteID) -> Result<(), NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        if !is_deleted(&mut transaction, id.try_to_uuid()?).await? {
+            return Err(NoteStoreError::NotDeleted(id.clone()));
+        }
+        Self::purge_note_helper(&mut transaction, id).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn purge_revision_inner(&self, loc: &NoteLocator) -> Result<(), NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc).await?.into_note()?;
+        if note.is_current {
+            return Err(NoteStoreError::PurgeCurrentRevision(note.id, note.revision));
+        }
+        purge_revision_row(&mut transaction, note.revision.try_to_uuid()?).await?;
+        if !has_revisions(&mut transaction, note.id.try_to_uuid()?).await? {
+            Self::purge_note_helper(&mut transaction, &note.id).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn add_label_inner(&self, loc: &NoteLocator, label: &str) -> Result<(), NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let note = get_note_by_loc(&mut transaction, loc).await?;
+        insert_label(&mut transaction, note.id, label, note.revision).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_after_inner(
+        &self,
+        prev: &NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        // Get the next note before it has two prev notes
+        let prev_note: PostgreSQLNote<T> =
+            get_note_by_loc(&mut transaction, &NoteLocator::Current(prev.clone()))
+                .await?
+                .into_note()?;
+        let loc = Self::new_note_helper(
+            &mut transaction,
+            None,
+            title,
+            note_inner,
+            Some(prev.try_to_uuid()?),
+            None,
+            metadata,
+        )
+        .await?;
+        let new_uuid = loc.get_id().try_to_uuid()?;
+        if let Some(next) = prev_note.next {
+            let next_loc = update_note_helper::<_, T>(
+                &mut transaction,
+                self.keyframe_interval,
+                &NoteLocator::Current(next),
+                |old_note| {
+                    let mut new_note = old_note.clone();
+                    new_note.prev = Some(new_uuid);
+                    Ok(new_note)
+                },
+            )
+            .await?;
+            record_change(&mut transaction, ChangeKind::Update, &next_loc).await?;
+        }
+        record_change(&mut transaction, ChangeKind::Append, &loc).await?;
+        transaction.commit().await?;
+        Ok(loc)
+    }
+
+    async fn insert_before_inner(
+        &self,
+        next: &NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let next_loc = NoteLocator::Current(next.clone());
+        let next_note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, &next_loc)
+            .await?
+            .into_note()?;
+        // The new note takes over the position of the next note
+        let loc = Self::new_note_helper(
+            &mut transaction,
+            None,
+            title,
+            note_inner,
+            next_note.prev.map(|x| x.try_to_uuid()).transpose()?,
+            next_note.parent.map(|x| x.try_to_uuid()).transpose()?,
+            metadata,
+        )
+        .await?;
+        let new_uuid = loc.get_id().try_to_uuid()?;
+        let next_loc = update_note_helper::<_, T>(
+            &mut transaction,
+            self.keyframe_interval,
+            &next_loc,
+            |old_note| {
+                let mut new_note = old_note.clone();
+                new_note.prev = Some(new_uuid);
+                new_note.parent = None;
+                Ok(new_note)
+            },
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Update, &next_loc).await?;
+        record_change(&mut transaction, ChangeKind::Append, &loc).await?;
+        transaction.commit().await?;
+        Ok(loc)
+    }
+
+    async fn add_branch_inner(
+        &self,
+        parent: &NoteID,
+        identity: Option<NoteIdentity>,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut transaction = self.db_pool.begin().await?;
+        read_write(&mut transaction).await?;
+        let parent_uuid = parent.try_to_uuid()?;
+        let loc = Self::new_note_helper(
+            &mut transaction,
+            identity,
+            title,
+            note_inner,
+            None,
+            Some(parent_uuid),
+            metadata,
+        )
+        .await?;
+        record_change(&mut transaction, ChangeKind::Branch, &loc).await?;
+        transaction.commit().await?;
+        Ok(loc)
+    }
 }
 
 impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
@@ -165,16 +769,8 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
-    ) -> BoxFuture<Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
-            let mut transaction = self.db_pool.begin().await?;
-            read_write(&mut transaction).await?;
-            let loc = self
-                .new_note_helper(&mut transaction, title, note_inner, None, None, metadata)
-                .await;
-            transaction.commit().await?;
-            loc
-        })
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.new_note_inner(None, title, note_inner, metadata))
     }
 
     fn get_note<'a>(
@@ -184,7 +780,8 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
-            let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc).await?.into_note();
+            let note: PostgreSQLNote<T> =
+                get_note_by_loc(&mut transaction, loc).await?.into_note()?;
             transaction.commit().await?;
             Ok(Box::new(note) as Box<dyn Note<T>>)
         })
@@ -200,24 +797,67 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
             read_write(&mut transaction).await?;
-            let new_loc = update_note_helper(&mut transaction, loc, |old_note| {
-                let mut note = old_note.clone();
-                if let Some(t) = title {
-                    note.title = t;
-                }
-                if let Some(n) = note_inner {
-                    note.note_inner = n;
-                }
+            let loc = &resolve_locator(&mut transaction, loc).await?;
+            let new_loc =
+                update_note_helper(&mut transaction, self.keyframe_interval, loc, |old_note| {
+                    let mut note = old_note.clone();
+                    if let Some(t) = title {
+                        note.title = t;
+                    }
+                    if let Some(n) = note_inner {
+                        note.note_inner = n;
+                    }
 
-                note.metadata = note.metadata.apply_editable(note_metadata);
-                Ok(note)
-            })
-            .await?;
+                    note.metadata = note.metadata.apply_editable(note_metadata);
+                    Ok(note)
+                })
+                .await?;
+            record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
             transaction.commit().await?;
             Ok(new_loc)
         })
     }
 
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let loc = &resolve_locator(&mut transaction, loc).await?;
+            let new_loc =
+                revert_note_helper::<T>(&mut transaction, self.keyframe_interval, loc).await?;
+            record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
+            transaction.commit().await?;
+            Ok(new_loc)
+        })
+    }
+
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        target: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.move_note_inner(id, target))
+    }
+
+    fn split_sequence<'a>(
+        &'a self,
+        at: &'a NoteID,
+        tail: SplitTail,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(self.split_sequence_inner(at, tail))
+    }
+
+    fn merge_sequences<'a>(
+        &'a self,
+        last: &'a NoteID,
+        head: &'a NoteID,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(self.merge_sequences_inner(last, head))
+    }
+
     fn delete_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -225,6 +865,7 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
             read_write(&mut transaction).await?;
+            let loc = &resolve_locator(&mut transaction, loc).await?;
             let (id, rev) = loc.unpack();
             if !is_current(&mut transaction, loc).await? {
                 return Err(NoteStoreError::DeleteOldRevision(
@@ -232,7 +873,8 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
                     rev.unwrap().clone(),
                 ));
             }
-            let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc).await?.into_note();
+            let note: PostgreSQLNote<T> =
+                get_note_by_loc(&mut transaction, loc).await?.into_note()?;
             if !note.branches.is_empty() {
                 return Err(NoteStoreError::HasBranches(id.clone()));
             }
@@ -243,28 +885,273 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
             // It's not possible to be in the middle of a note sequence
             // And vice versa
             assert!(note.prev.is_none() || note.parent.is_none());
-            // Since only prev is stored, our prev note is not aware of us
-            // But we want to make sure our next note is consistent
-            // The next note basically inherits our prev and parent
-            if note.next.is_some() {
-                update_note_helper::<_, T>(
-                    &mut transaction,
-                    &NoteLocator::Current(note.next.unwrap()),
-                    |old_note| {
-                        let mut new_note = old_note.clone();
-                        // Thw below two lines shouldn't both have effects
-                        // See the above assertion
-                        new_note.prev = note.prev.map(|x| x.to_uuid().unwrap());
-                        new_note.parent = note.parent.map(|x| x.to_uuid().unwrap());
-                        Ok(new_note)
-                    },
-                )
-                .await?;
-            }
+            self.hand_over_position(&mut transaction, &note).await?;
+            let deleted = loc.at_revision(&note.revision);
+            record_change(&mut transaction, ChangeKind::Delete, &deleted).await?;
             delete_revision(transaction, loc).await
         })
     }
 
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(self.purge_note_inner(id))
+    }
+
+    fn purge_revision<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(self.purge_revision_inner(loc))
+    }
+
+    fn add_label<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(self.add_label_inner(loc, label))
+    }
+
+    fn remove_label<'a>(
+        &'a self,
+        id: &'a NoteID,
+        label: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            delete_label(&mut transaction, id.try_to_uuid()?, label).await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn list_labels<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<RevisionLabel>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let labels = list_labels(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            Ok(labels)
+        })
+    }
+
+    fn create_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        title: Option<String>,
+        note_inner: Option<T>,
+        note_metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let loc = &resolve_locator(&mut transaction, loc).await?;
+            let new_loc = create_draft_helper(&mut transaction, loc, |base| {
+                let mut note = base.clone();
+                if let Some(t) = title {
+                    note.title = t;
+                }
+                if let Some(n) = note_inner {
+                    note.note_inner = n;
+                }
+                note.metadata = note.metadata.apply_editable(note_metadata);
+                Ok(note)
+            })
+            .await?;
+            transaction.commit().await?;
+            Ok(new_loc)
+        })
+    }
+
+    fn list_drafts<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let notes = list_drafts(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            notes
+                .into_iter()
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
+        })
+    }
+
+    fn promote_draft<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let loc = &resolve_locator(&mut transaction, loc).await?;
+            let new_loc =
+                promote_draft_helper::<T>(&mut transaction, self.keyframe_interval, loc).await?;
+            record_change(&mut transaction, ChangeKind::Update, &new_loc).await?;
+            transaction.commit().await?;
+            Ok(new_loc)
+        })
+    }
+
+    fn put_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+        target: Option<&'a NoteLocator>,
+        content: serde_json::Value,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let (id, base) = match target {
+                None => (None, None),
+                Some(loc) => {
+                    let note = get_note_by_loc(&mut transaction, loc).await?;
+                    let base = match loc {
+                        NoteLocator::Current(_) => None,
+                        _ => Some(note.revision),
+                    };
+                    (Some(note.id), base)
+                }
+            };
+            let draft = upsert_session_draft(&mut transaction, session, id, base, content).await?;
+            transaction.commit().await?;
+            Ok(draft)
+        })
+    }
+
+    fn get_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<SessionDraft, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let draft = get_session_draft(&mut transaction, session).await?;
+            transaction.commit().await?;
+            Ok(draft)
+        })
+    }
+
+    fn delete_session_draft<'a>(
+        &'a self,
+        session: &'a str,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            delete_session_draft(&mut transaction, session).await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn list_session_drafts<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SessionDraft>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let drafts = list_session_drafts(&mut transaction, prefix).await?;
+            transaction.commit().await?;
+            Ok(drafts)
+        })
+    }
+
+    fn subscribe(&self) -> BoxFuture<'_, Result<ChangeStream, NoteStoreError>> {
+        Box::pin(async move {
+            if let Some(sender) = self.live_change_feed().await {
+                return Ok(broadcast_stream(sender.subscribe()));
+            }
+            // One connection listens for all subscribers
+            let mut listener = PgListener::connect_with(&self.db_pool).await?;
+            listener.listen(CHANGE_CHANNEL).await?;
+            // Changes recorded from now on are notified, and those that don't have a cursor yet
+            // are looked for again by the change feed
+            let unsequenced = sequence_committed_changes(&self.db_pool, false).await?;
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let cursor = latest_change_cursor(&mut transaction).await?;
+            transaction.commit().await?;
+            let mut change_feed = self.change_feed.lock().await;
+            // Another subscriber might have started the change feed meanwhile
+            if let Some(sender) = change_feed.as_ref().and_then(|(s, _)| s.upgrade()) {
+                return Ok(broadcast_stream(sender.subscribe()));
+            }
+            let (sender, receiver) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+            let weak_sender = sender.downgrade();
+            let task = tokio::spawn(run_change_feed(
+                listener,
+                self.db_pool.clone(),
+                cursor,
+                unsequenced,
+                sender,
+            ));
+            if let Some((_, old_task)) = change_feed.replace((weak_sender, task)) {
+                old_task.abort();
+            }
+            Ok(broadcast_stream(receiver))
+        })
+    }
+
+    fn changes_since(
+        &self,
+        cursor: ChangeCursor,
+    ) -> BoxFuture<'_, Result<Vec<Change>, NoteStoreError>> {
+        Box::pin(async move {
+            sequence_committed_changes(&self.db_pool, true).await?;
+            read_changes_since(&self.db_pool, cursor).await
+        })
+    }
+
+    fn prune_changes<'a>(
+        &'a self,
+        before: &'a DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<u64, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let pruned = prune_changes(&mut transaction, before).await?;
+            transaction.commit().await?;
+            Ok(pruned)
+        })
+    }
+
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let rows: Vec<PostgreSQLDeletedRow> = list_deleted(&mut transaction).await?;
+            transaction.commit().await?;
+            rows.into_iter()
+                .map(|r| {
+                    Ok(DeletedNote {
+                        note: Box::new(r.note.into_note()?) as Box<dyn Note<T>>,
+                        deleted_at: r.deleted_at,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn list_past_deletions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<DeletionPeriod>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let deletions = list_past_deletions(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            Ok(deletions)
+        })
+    }
+
     fn get_revisions<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -275,10 +1162,70 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
             let notes: Vec<PostgreSQLNoteRowJoined> =
                 get_revisions(&mut transaction, loc.get_id().try_to_uuid()?).await?;
             transaction.commit().await?;
-            Ok(notes
+            notes
+                .into_iter()
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
+        })
+    }
+
+    fn get_sequence<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let notes: Vec<PostgreSQLNoteRowJoined> =
+                get_sequence(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            notes
                 .into_iter()
-                .map(|n| Box::new(n.into_note()) as Box<dyn Note<T>>)
-                .collect())
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
+        })
+    }
+
+    fn get_subtree<'a>(
+        &'a self,
+        id: &'a NoteID,
+        depth: Option<usize>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let notes: Vec<PostgreSQLNoteRowJoined> =
+                get_subtree(&mut transaction, id.try_to_uuid()?, depth).await?;
+            transaction.commit().await?;
+            notes
+                .into_iter()
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
+        })
+    }
+
+    fn get_ancestors<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<Vec<Ancestor<T>>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let rows: Vec<PostgreSQLAncestorRow> =
+                get_ancestors(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            rows.into_iter()
+                .map(|r| {
+                    Ok(Ancestor {
+                        link: if r.in_sequence {
+                            Link::Sequence
+                        } else {
+                            Link::Branch
+                        },
+                        note: Box::new(r.note.into_note()?) as Box<dyn Note<T>>,
+                    })
+                })
+                .collect()
         })
     }
 
@@ -327,32 +1274,7 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
-            let mut transaction = self.db_pool.begin().await?;
-            read_write(&mut transaction).await?;
-            let last_note: PostgreSQLNote<T> =
-                get_note_by_loc(&mut transaction, &NoteLocator::Current(last.clone()))
-                    .await?
-                    .into_note();
-            let last_note_next = last_note.get_next();
-            if let Some(n) = last_note_next {
-                transaction.rollback().await?;
-                return Err(NoteStoreError::ExistingNext(last.clone(), n));
-            }
-            let last_uuid = last.try_to_uuid()?;
-            let loc = self
-                .new_note_helper(
-                    &mut transaction,
-                    title,
-                    note_inner,
-                    Some(last_uuid),
-                    None,
-                    metadata,
-                )
-                .await?;
-            transaction.commit().await?;
-            Ok(loc)
-        })
+        Box::pin(self.append_note_inner(last, None, title, note_inner, metadata))
     }
 
     fn add_branch<'a>(
@@ -362,22 +1284,76 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.add_branch_inner(parent, None, title, note_inner, metadata))
+    }
+
+    fn insert_after<'a>(
+        &'a self,
+        prev: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.insert_after_inner(prev, title, note_inner, metadata))
+    }
+
+    fn insert_before<'a>(
+        &'a self,
+        next: &'a NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.insert_before_inner(next, title, note_inner, metadata))
+    }
+
+    fn new_note_with_identity(
+        &self,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'_, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.new_note_inner(Some(identity), title, note_inner, metadata))
+    }
+
+    fn append_note_with_identity<'a>(
+        &'a self,
+        last: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.append_note_inner(last, Some(identity), title, note_inner, metadata))
+    }
+
+    fn add_branch_with_identity<'a>(
+        &'a self,
+        parent: &'a NoteID,
+        identity: NoteIdentity,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(self.add_branch_inner(parent, Some(identity), title, note_inner, metadata))
+    }
+
+    fn search_at<'a>(
+        &'a self,
+        sr: &'a SearchRequest,
+        at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
-            read_write(&mut transaction).await?;
-            let parent_uuid = parent.try_to_uuid()?;
-            let loc = self
-                .new_note_helper(
-                    &mut transaction,
-                    title,
-                    note_inner,
-                    None,
-                    Some(parent_uuid),
-                    metadata,
-                )
-                .await?;
+            read_only(&mut transaction).await?;
+            let notes: Vec<PostgreSQLNoteRowJoined> =
+                search(&mut transaction, sr, Some(at)).await?;
             transaction.commit().await?;
-            Ok(loc)
+            notes
+                .into_iter()
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
         })
     }
 
@@ -388,16 +1364,16 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
-            let notes: Vec<PostgreSQLNoteRowJoined> = search(&mut transaction, sr).await?;
+            let notes: Vec<PostgreSQLNoteRowJoined> = search(&mut transaction, sr, None).await?;
             transaction.commit().await?;
-            Ok(notes
+            notes
                 .into_iter()
-                .map(|n| Box::new(n.into_note()) as Box<dyn Note<T>>)
-                .collect())
+                .map(|n| Ok(Box::new(n.into_note()?) as Box<dyn Note<T>>))
+                .collect()
         })
     }
 
-    fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>> {
+    fn tags(&self) -> BoxFuture<'_, Result<Vec<String>, NoteStoreError>> {
         Box::pin(async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
@@ -407,14 +1383,110 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         })
     }
 
-    fn backup(&self, _path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>> {
-        unimplemented!("Please use PostgreSQL's own backup utilities.")
+    fn get_note_ids(&self) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let ids = get_note_ids(&mut transaction).await?;
+            transaction.commit().await?;
+            Ok(ids.into_iter().map(|x| x.into()).collect())
+        })
+    }
+
+    fn import_note(
+        &self,
+        records: Vec<RevisionRecord>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            import_note_helper::<T>(&mut transaction, self.keyframe_interval, records).await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn import_notes<'a>(
+        &'a self,
+        records: RecordStream<'a>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            // A single transaction, so that a failed import leaves nothing behind
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let mut notes = group_by_note(records);
+            while let Some(records) = notes.next().await {
+                import_note_helper::<T>(&mut transaction, self.keyframe_interval, records?).await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn backup(
+        &self,
+        path: Box<dyn AsRef<Path> + Send>,
+    ) -> BoxFuture<'_, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let notes: Vec<PostgreSQLNoteRowJoined> = get_all_revisions(&mut transaction).await?;
+            let labels = get_all_labels(&mut transaction).await?;
+            let deleted_at: HashMap<Uuid, DateTime<Utc>> = get_all_deleted_at(&mut transaction)
+                .await?
+                .into_iter()
+                .collect();
+            let mut past_deletions: HashMap<Uuid, Vec<DeletionPeriod>> = HashMap::new();
+            for (id, deletion) in get_all_past_deletions(&mut transaction).await? {
+                past_deletions.entry(id).or_default().push(deletion);
+            }
+            let session_drafts = list_session_drafts(&mut transaction, "").await?;
+            transaction.commit().await?;
+            let records: Vec<RevisionRecord> = notes
+                .into_iter()
+                .map(|n| {
+                    let (id, revision) = (n.id, n.revision);
+                    let mut record = RevisionRecord::from_note(&n.into_note::<T>()?);
+                    record.labels = labels
+                        .iter()
+                        .filter(|(_, _, r)| *r == revision)
+                        .map(|(_, l, _)| l.clone())
+                        .collect();
+                    record.deleted_at = deleted_at.get(&id).cloned();
+                    record.past_deletions = past_deletions.get(&id).cloned().unwrap_or_default();
+                    Ok(record)
+                })
+                .collect::<Result<_, NoteStoreError>>()?;
+            write_backup(&*path, &records, &session_drafts)
+        })
     }
 
     fn restore<P: AsRef<Path>>(_path: P) -> Result<Self, NoteStoreError>
     where
         Self: Sized,
     {
-        unimplemented!("Please use PostgreSQL's own restore utilities.")
+        Err(NoteStoreError::Unsupported(
+            "a store connected to a database can't be constructed from a backup, \
+             use NoteStore::restore_into instead"
+                .to_owned(),
+        ))
+    }
+
+    fn restore_into<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let (records, session_drafts) = read_backup(path)?;
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            if !is_empty(&mut transaction).await? {
+                transaction.rollback().await?;
+                return Err(NoteStoreError::StoreNotEmpty);
+            }
+            insert_records::<T>(&mut transaction, self.keyframe_interval, records).await?;
+            for draft in &session_drafts {
+                insert_session_draft(&mut transaction, draft).await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
     }
 }
@@ -1,8 +1,13 @@
-use super::PostgreSQLNote;
+use super::{delta, PostgreSQLNote};
 use crate::errors::NoteStoreError;
 use crate::notemetadata::NoteMetadata;
+use crate::notestore::changes::{Change, ChangeCursor, ChangeKind};
+use crate::notestore::interchange::{check_note_records, RevisionRecord};
+use crate::notestore::label::RevisionLabel;
 use crate::notestore::postgresql::get_new_revision;
 use crate::notestore::search::SearchRequest;
+use crate::notestore::session_draft::SessionDraft;
+use crate::notestore::trash::DeletionPeriod;
 use crate::{NoteID, NoteLocator, NoteType};
 use chrono::{DateTime, Utc};
 use sqlx::postgres::PgQueryResult;
@@ -20,6 +25,8 @@ pub(super) struct PostgreSQLNoteEditable<T> {
     pub(super) parent: Option<Uuid>,
     pub(super) prev: Option<Uuid>,
     pub(super) metadata: NoteMetadata,
+    pub(super) based_on: Option<Uuid>,
+    pub(super) is_draft: bool,
 }
 
 #[derive(sqlx::FromRow)]
@@ -36,6 +43,13 @@ struct PostgreSQLNoteRow {
     metadata_modified_at: DateTime<Utc>,
     metadata_tags: Vec<String>,
     metadata_custom_metadata: serde_json::Value,
+    metadata_reverted_from: Option<Uuid>,
+    metadata_author: Option<String>,
+    metadata_message: Option<String>,
+    based_on: Option<Uuid>,
+    is_draft: bool,
+    delta: Option<serde_json::Value>,
+    delta_base_inner: Option<String>,
 }
 
 impl<T> TryFrom<PostgreSQLNoteEditable<T>> for PostgreSQLNoteRow
@@ -53,6 +67,12 @@ where
         .map(|x| x.try_to_uuid())
         .collect::<Result<Vec<Uuid>, NoteStoreError>>()?;
         let tags: Vec<String> = n.metadata.tags.iter().cloned().collect();
+        let reverted_from = n
+            .metadata
+            .reverted_from
+            .as_ref()
+            .map(|r| r.try_to_uuid())
+            .transpose()?;
         let note_inner: String = n.note_inner.clone().into();
         Ok(PostgreSQLNoteRow {
             revision: n.revision,
@@ -67,24 +87,36 @@ where
             metadata_modified_at: n.metadata.modified_at,
             metadata_tags: tags,
             metadata_custom_metadata: n.metadata.custom_metadata,
+            metadata_reverted_from: reverted_from,
+            metadata_author: n.metadata.author,
+            metadata_message: n.metadata.message,
+            based_on: n.based_on,
+            is_draft: n.is_draft,
+            delta: None,
+            delta_base_inner: None,
         })
     }
 }
 
-impl<T> From<PostgreSQLNoteRow> for PostgreSQLNoteEditable<T>
+impl<T> TryFrom<PostgreSQLNoteRow> for PostgreSQLNoteEditable<T>
 where
     T: NoteType,
 {
-    fn from(n: PostgreSQLNoteRow) -> Self {
+    type Error = NoteStoreError;
+
+    fn try_from(n: PostgreSQLNoteRow) -> Result<Self, Self::Error> {
         let metadata = NoteMetadata {
             schema_version: n.metadata_schema_version as u64,
             created_at: n.metadata_created_at,
             modified_at: n.metadata_modified_at,
             tags: HashSet::from_iter(n.metadata_tags.iter().cloned()),
             custom_metadata: n.metadata_custom_metadata,
+            reverted_from: n.metadata_reverted_from.map(|x| x.into()),
+            author: n.metadata_author,
+            message: n.metadata_message,
         };
-        let note_inner: T = T::from(n.note_inner);
-        PostgreSQLNoteEditable {
+        let note_inner: T = T::from(delta::rebuild(n.note_inner, n.delta, n.delta_base_inner)?);
+        Ok(PostgreSQLNoteEditable {
             id: n.id,
             revision: n.revision,
             title: n.title,
@@ -92,7 +124,9 @@ where
             parent: n.parent,
             prev: n.prev,
             metadata,
-        }
+            based_on: n.based_on,
+            is_draft: n.is_draft,
+        })
     }
 }
 
@@ -113,12 +147,23 @@ pub(super) struct PostgreSQLNoteRowJoined {
     pub(super) metadata_modified_at: DateTime<Utc>,
     pub(super) metadata_tags: Vec<String>,
     pub(super) metadata_custom_metadata: serde_json::Value,
+    pub(super) metadata_reverted_from: Option<Uuid>,
+    pub(super) metadata_author: Option<String>,
+    pub(super) metadata_message: Option<String>,
+    pub(super) based_on: Option<Uuid>,
+    pub(super) is_draft: bool,
+    pub(super) delta: Option<serde_json::Value>,
+    pub(super) delta_base_inner: Option<String>,
     pub(super) is_current: bool,
 }
 
 impl PostgreSQLNoteRowJoined {
-    pub(super) fn into_note<T: NoteType>(self) -> PostgreSQLNote<T> {
-        let note_inner: T = T::from(self.note_inner);
+    pub(super) fn into_note<T: NoteType>(self) -> Result<PostgreSQLNote<T>, NoteStoreError> {
+        let note_inner: T = T::from(delta::rebuild(
+            self.note_inner,
+            self.delta,
+            self.delta_base_inner,
+        )?);
         let parent: Option<NoteID> = self.parent.map(|x| x.into());
         let branches: HashSet<NoteID> = match self.branches {
             Some(b) => HashSet::from_iter(b.iter().map(|x| x.into())),
@@ -148,8 +193,11 @@ impl PostgreSQLNoteRowJoined {
             modified_at: self.metadata_modified_at,
             tags: HashSet::from_iter(self.metadata_tags.iter().cloned()),
             custom_metadata: self.metadata_custom_metadata,
+            reverted_from: self.metadata_reverted_from.map(|x| x.into()),
+            author: self.metadata_author,
+            message: self.metadata_message,
         };
-        PostgreSQLNote {
+        Ok(PostgreSQLNote {
             title: self.title,
             note_inner,
             id: self.id.into(),
@@ -162,7 +210,9 @@ impl PostgreSQLNoteRowJoined {
             references,
             metadata,
             is_current: self.is_current,
-        }
+            based_on: self.based_on.map(|x| x.into()),
+            is_draft: self.is_draft,
+        })
     }
 }
 
@@ -174,6 +224,31 @@ fn get_note_query(
     havings: Vec<String>,
     orders: Vec<String>,
     limit: Option<u64>,
+) -> String {
+    get_note_query_from(
+        "revision_only_current",
+        columns,
+        joins,
+        conditions,
+        groupbys,
+        havings,
+        orders,
+        limit,
+    )
+}
+
+/// Like [`get_note_query`], but the relationships (branches, next, references) are computed
+/// between the revisions from `heads` instead of the current revisions.
+#[allow(clippy::too_many_arguments)]
+fn get_note_query_from(
+    heads: &str,
+    columns: Vec<String>,
+    joins: Vec<String>,
+    conditions: Vec<String>,
+    groupbys: Vec<String>,
+    havings: Vec<String>,
+    orders: Vec<String>,
+    limit: Option<u64>,
 ) -> String {
     let select_clause = if columns.is_empty() {
         "".to_string()
@@ -230,19 +305,27 @@ fn get_note_query(
             revision.metadata_modified_at,
             revision.metadata_tags,
             revision.metadata_custom_metadata,
+            revision.metadata_reverted_from,
+            revision.metadata_author,
+            revision.metadata_message,
+            revision.based_on,
+            revision.is_draft,
+            revision.delta,
+            base.note_inner AS delta_base_inner,
             cr.current_revision IS NOT NULL AS is_current{select_clause}
         FROM
             revision
         LEFT JOIN current_revision cr ON revision.revision = cr.current_revision
-        LEFT JOIN revision_only_current AS revision1 ON revision1.parent = revision.id
-        LEFT JOIN revision_only_current AS revision2 ON revision2.prev = revision.id
+        LEFT JOIN revision AS base ON base.revision = revision.delta_base
+        LEFT JOIN {heads} AS revision1 ON revision1.parent = revision.id
+        LEFT JOIN {heads} AS revision2 ON revision2.prev = revision.id
         -- https://stackoverflow.com/a/29245753
         -- indexes are bound to operators, and the indexed expression must be to the left of
         -- the operator
-        LEFT JOIN revision_only_current AS revision3 ON revision3.referents @> ARRAY[revision.id]
+        LEFT JOIN {heads} AS revision3 ON revision3.referents @> ARRAY[revision.id]
         {join_clause}
         {where_clause}
-        GROUP BY revision.revision, cr.current_revision{groupby_clause}
+        GROUP BY revision.revision, cr.current_revision, base.revision{groupby_clause}
         {having_clause}
         {orderby_clause}
         {limit_clause}
@@ -304,6 +387,36 @@ async fn get_note_specific(
     }
 }
 
+async fn get_note_at(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    at: DateTime<Utc>,
+) -> Result<PostgreSQLNoteRowJoined, NoteStoreError> {
+    let res = sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&get_note_query_from(
+        "revision_as_of($2)",
+        vec![],
+        vec!["JOIN revision_as_of($2) AS head ON head.revision = revision.revision".to_owned()],
+        vec!["revision.id = $1".to_owned()],
+        vec![],
+        vec![],
+        vec![],
+        None,
+    ))
+    .bind(id)
+    .bind(at)
+    .fetch_one(transaction.deref_mut())
+    .await;
+    if let Err(sqlx::Error::RowNotFound) = res {
+        if noteid_exist(transaction, id).await? {
+            Err(NoteStoreError::NoteNotExistAtTime(id.into(), at))
+        } else {
+            Err(NoteStoreError::NoteNotExist(id.into()))
+        }
+    } else {
+        res.map_err(NoteStoreError::PostgreSQLError)
+    }
+}
+
 pub(super) async fn get_revisions(
     transaction: &mut Transaction<'_, Postgres>,
     id: Uuid,
@@ -327,9 +440,190 @@ pub(super) async fn get_revisions(
     }
 }
 
+pub(super) async fn get_all_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<PostgreSQLNoteRowJoined>, NoteStoreError> {
+    sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&get_note_query(
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![
+            "revision.id ASC".to_owned(),
+            "revision.metadata_modified_at ASC".to_owned(),
+        ],
+        None,
+    ))
+    .fetch_all(transaction.deref_mut())
+    .await
+    .map_err(NoteStoreError::PostgreSQLError)
+}
+
+pub(super) async fn get_sequence(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<PostgreSQLNoteRowJoined>, NoteStoreError> {
+    // Walk up to the first note of the sequence, and then walk down from there
+    let res = sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&get_note_query(
+        vec![],
+        vec![r#"
+        JOIN (
+            WITH RECURSIVE head(id, prev) AS (
+                SELECT id, prev FROM revision_only_current WHERE id = $1
+                UNION ALL
+                SELECT r.id, r.prev FROM revision_only_current r JOIN head ON r.id = head.prev
+            ), seq(id, idx) AS (
+                SELECT id, 0 FROM head WHERE prev IS NULL
+                UNION ALL
+                SELECT r.id, seq.idx + 1
+                FROM revision_only_current r JOIN seq ON r.prev = seq.id
+            )
+            SELECT id, idx FROM seq
+        ) AS seq ON seq.id = revision.id"#
+            .to_owned()],
+        vec!["cr.current_revision IS NOT NULL".to_owned()],
+        vec!["seq.idx".to_owned()],
+        vec![],
+        vec!["seq.idx ASC".to_owned()],
+        None,
+    ))
+    .bind(id)
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    if res.is_empty() {
+        Err(NoteStoreError::NoteNotExist(id.into()))
+    } else {
+        Ok(res)
+    }
+}
+
+pub(super) async fn get_subtree(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    depth: Option<usize>,
+) -> Result<Vec<PostgreSQLNoteRowJoined>, NoteStoreError> {
+    let res = sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&get_note_query(
+        vec![],
+        vec![r#"
+        JOIN (
+            WITH RECURSIVE subtree(id, level) AS (
+                SELECT id, 0 FROM revision_only_current WHERE id = $1
+                UNION ALL
+                SELECT r.id, subtree.level + 1
+                FROM revision_only_current r
+                JOIN subtree ON r.parent = subtree.id OR r.prev = subtree.id
+                WHERE $2::bigint IS NULL OR subtree.level < $2
+            )
+            SELECT id, level FROM subtree
+        ) AS subtree ON subtree.id = revision.id"#
+            .to_owned()],
+        vec!["cr.current_revision IS NOT NULL".to_owned()],
+        vec!["subtree.level".to_owned()],
+        vec![],
+        vec![
+            "subtree.level ASC".to_owned(),
+            "revision.metadata_created_at ASC".to_owned(),
+        ],
+        None,
+    ))
+    .bind(id)
+    .bind(depth.map(|d| d as i64))
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    if res.is_empty() {
+        Err(NoteStoreError::NoteNotExist(id.into()))
+    } else {
+        Ok(res)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct PostgreSQLAncestorRow {
+    pub(super) in_sequence: bool,
+    #[sqlx(flatten)]
+    pub(super) note: PostgreSQLNoteRowJoined,
+}
+
+pub(super) async fn get_ancestors(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<PostgreSQLAncestorRow>, NoteStoreError> {
+    get_note_current(transaction, id).await?;
+    sqlx::query_as::<_, PostgreSQLAncestorRow>(&get_note_query(
+        vec!["ancestors.in_sequence".to_owned()],
+        vec![r#"
+        JOIN (
+            WITH RECURSIVE ancestors(id, in_sequence, level) AS (
+                SELECT COALESCE(prev, parent), prev IS NOT NULL, 1
+                FROM revision_only_current
+                WHERE id = $1 AND (prev IS NOT NULL OR parent IS NOT NULL)
+                UNION ALL
+                SELECT COALESCE(r.prev, r.parent), r.prev IS NOT NULL, ancestors.level + 1
+                FROM revision_only_current r JOIN ancestors ON r.id = ancestors.id
+                WHERE r.prev IS NOT NULL OR r.parent IS NOT NULL
+            )
+            SELECT id, in_sequence, level FROM ancestors
+        ) AS ancestors ON ancestors.id = revision.id"#
+            .to_owned()],
+        vec!["cr.current_revision IS NOT NULL".to_owned()],
+        vec![
+            "ancestors.level".to_owned(),
+            "ancestors.in_sequence".to_owned(),
+        ],
+        vec![],
+        vec!["ancestors.level ASC".to_owned()],
+        None,
+    ))
+    .bind(id)
+    .fetch_all(transaction.deref_mut())
+    .await
+    .map_err(NoteStoreError::PostgreSQLError)
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct PostgreSQLDeletedRow {
+    pub(super) deleted_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    pub(super) note: PostgreSQLNoteRowJoined,
+}
+
+pub(super) async fn list_deleted(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<PostgreSQLDeletedRow>, NoteStoreError> {
+    // The last revision (other than drafts) of each note without a current revision
+    sqlx::query_as::<_, PostgreSQLDeletedRow>(&get_note_query(
+        vec!["COALESCE(note.deleted_at, revision.metadata_modified_at) AS deleted_at".to_owned()],
+        vec![
+            "JOIN note ON note.id = revision.id".to_owned(),
+            "LEFT JOIN current_revision note_cr ON note_cr.id = revision.id".to_owned(),
+        ],
+        vec![
+            "note_cr.id IS NULL".to_owned(),
+            "NOT revision.is_draft".to_owned(),
+            r#"NOT EXISTS (
+                SELECT 1 FROM revision later
+                WHERE later.id = revision.id
+                AND NOT later.is_draft
+                AND later.metadata_modified_at > revision.metadata_modified_at
+            )"#
+            .to_owned(),
+        ],
+        vec!["note.deleted_at".to_owned()],
+        vec![],
+        vec!["deleted_at DESC".to_owned()],
+        None,
+    ))
+    .fetch_all(transaction.deref_mut())
+    .await
+    .map_err(NoteStoreError::PostgreSQLError)
+}
+
+/// Search the current revisions, or the revisions that were current at `at`.
 pub(super) async fn search(
     transaction: &mut Transaction<'_, Postgres>,
     sr: &SearchRequest,
+    at: Option<DateTime<Utc>>,
 ) -> Result<Vec<PostgreSQLNoteRowJoined>, NoteStoreError> {
     let mut columns = vec![];
     let mut joins = vec![];
@@ -337,8 +631,17 @@ pub(super) async fn search(
     let mut groupbys = vec![];
     let mut havings = vec![];
     let mut orders = vec![];
-    // only search current versions
-    conditions.push("cr.current_revision IS NOT NULL".to_owned());
+    let heads = if at.is_some() {
+        joins.push(
+            "JOIN revision_as_of($3) AS head ON head.revision = revision.revision".to_owned(),
+        );
+        "revision_as_of($3)"
+    } else {
+        // only search current versions
+        conditions.push("cr.current_revision IS NOT NULL".to_owned());
+        "revision_only_current"
+    };
+    let query_param = if at.is_some() { "$4" } else { "$3" };
     if sr.sort_by_created_at() {
         orders.push("revision.metadata_created_at DESC".to_owned());
     }
@@ -354,19 +657,28 @@ pub(super) async fn search(
     }
     let is_tsquery = !sr.lexemes.is_empty() || !sr.lexemes_excluded.is_empty();
     if is_tsquery {
-        columns.push("ts_rank(revision.text_searchable, query.query) AS rank".to_string());
-        joins.push(
-            "JOIN to_tsquery($3) query ON revision.text_searchable @@ query.query".to_string(),
-        );
+        // Current revisions are never stored as deltas
+        let searchable = if at.is_some() {
+            "coalesce(revision.delta_text_searchable, revision.text_searchable)"
+        } else {
+            "revision.text_searchable"
+        };
+        columns.push(format!("ts_rank({searchable}, query.query) AS rank"));
+        joins.push(format!(
+            "JOIN to_tsquery({query_param}) query ON {searchable} @@ query.query"
+        ));
         groupbys.push("query.query".to_owned());
         orders.push("rank DESC".to_owned());
     }
-    let query_statement = get_note_query(
-        columns, joins, conditions, groupbys, havings, orders, sr.limit,
+    let query_statement = get_note_query_from(
+        heads, columns, joins, conditions, groupbys, havings, orders, sr.limit,
     );
     let mut q = sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&query_statement)
         .bind(&sr.tags)
         .bind(&sr.tags_excluded);
+    if let Some(at) = at {
+        q = q.bind(at);
+    }
     if is_tsquery {
         let mut terms = sr.lexemes.clone();
         let prefixed = sr.lexemes_excluded.iter().map(|x| "! ".to_owned() + x);
@@ -400,9 +712,17 @@ async fn get_row_current(
                 revision.metadata_created_at,
                 revision.metadata_modified_at,
                 revision.metadata_tags,
-                revision.metadata_custom_metadata
+                revision.metadata_custom_metadata,
+                revision.metadata_reverted_from,
+                revision.metadata_author,
+                revision.metadata_message,
+                revision.based_on,
+                revision.is_draft,
+                revision.delta,
+                base.note_inner AS "delta_base_inner?"
             FROM revision
             LEFT JOIN current_revision cr on revision.revision = cr.current_revision
+            LEFT JOIN revision base ON base.revision = revision.delta_base
             WHERE revision.id = $1 AND cr.current_revision IS NOT NULL
             "#,
         id,
@@ -425,20 +745,28 @@ async fn get_row_specific(
         PostgreSQLNoteRow,
         r#"
             SELECT
-                revision,
-                id,
-                title,
-                note_inner,
-                parent,
-                prev,
-                referents,
-                metadata_schema_version,
-                metadata_created_at,
-                metadata_modified_at,
-                metadata_tags,
-                metadata_custom_metadata
+                revision.revision,
+                revision.id,
+                revision.title,
+                revision.note_inner,
+                revision.parent,
+                revision.prev,
+                revision.referents,
+                revision.metadata_schema_version,
+                revision.metadata_created_at,
+                revision.metadata_modified_at,
+                revision.metadata_tags,
+                revision.metadata_custom_metadata,
+                revision.metadata_reverted_from,
+                revision.metadata_author,
+                revision.metadata_message,
+                revision.based_on,
+                revision.is_draft,
+                revision.delta,
+                base.note_inner AS "delta_base_inner?"
             FROM revision
-            WHERE id = $1 AND revision = $2
+            LEFT JOIN revision base ON base.revision = revision.delta_base
+            WHERE revision.id = $1 AND revision.revision = $2
             "#,
         id,
         revision
@@ -446,7 +774,7 @@ async fn get_row_specific(
     .fetch_one(transaction.deref_mut())
     .await;
     if let Err(sqlx::Error::RowNotFound) = res {
-        Err(NoteStoreError::NoteNotExist(id.into()))
+        Err(NoteStoreError::RevisionNotExist(id.into(), revision.into()))
     } else {
         res.map_err(NoteStoreError::PostgreSQLError)
     }
@@ -457,12 +785,41 @@ pub(super) async fn get_note_by_loc(
     loc: &NoteLocator,
 ) -> Result<PostgreSQLNoteRowJoined, NoteStoreError> {
     let (id, revision) = loc.unpack_uuid()?;
+    if let Some(at) = loc.get_time() {
+        return get_note_at(transaction, id, *at).await;
+    }
+    if let Some(label) = loc.get_label() {
+        let revision = get_label_revision(transaction, id, label).await?;
+        return get_note_specific(transaction, id, revision).await;
+    }
     match revision {
         Some(r) => get_note_specific(transaction, id, r).await,
         None => get_note_current(transaction, id).await,
     }
 }
 
+/// Turn a [`NoteLocator::AtTime`] or a [`NoteLocator::Label`] into a locator of the specific
+/// revision.
+pub(super) async fn resolve_locator(
+    transaction: &mut Transaction<'_, Postgres>,
+    loc: &NoteLocator,
+) -> Result<NoteLocator, NoteStoreError> {
+    Ok(match loc {
+        NoteLocator::AtTime(id, at) => {
+            let revision = get_note_at(transaction, id.try_to_uuid()?, *at)
+                .await?
+                .revision;
+            loc.at_revision(&revision.into())
+        }
+        NoteLocator::Label(id, label) => {
+            let revision = get_label_revision(transaction, id.try_to_uuid()?, label).await?;
+            loc.at_revision(&revision.into())
+        }
+        NoteLocator::Specific(_, r) => loc.at_revision(r),
+        NoteLocator::Current(_) => loc.current(),
+    })
+}
+
 async fn get_row_by_loc(
     transaction: &mut Transaction<'_, Postgres>,
     loc: &NoteLocator,
@@ -474,6 +831,17 @@ async fn get_row_by_loc(
     }
 }
 
+pub(super) async fn insert_note(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<(), NoteStoreError> {
+    query!(r#"INSERT INTO note(id) VALUES ($1)"#, id)
+        .execute(transaction.deref_mut())
+        .await
+        .map_err(NoteStoreError::PostgreSQLError)?;
+    Ok(())
+}
+
 pub(super) async fn insert_revision<T: NoteType>(
     transaction: &mut Transaction<'_, Postgres>,
     n: PostgreSQLNoteEditable<T>,
@@ -485,9 +853,11 @@ pub(super) async fn insert_revision<T: NoteType>(
                 revision(
                     revision, id, title, note_inner, parent, prev, referents,
                     metadata_schema_version, metadata_created_at,
-                    metadata_modified_at, metadata_tags, metadata_custom_metadata
+                    metadata_modified_at, metadata_tags, metadata_custom_metadata,
+                    metadata_reverted_from, metadata_author, metadata_message,
+                    based_on, is_draft
                 )
-            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
             "#,
         row.revision,
         row.id,
@@ -500,7 +870,12 @@ pub(super) async fn insert_revision<T: NoteType>(
         row.metadata_created_at,
         row.metadata_modified_at,
         &row.metadata_tags,
-        row.metadata_custom_metadata
+        row.metadata_custom_metadata,
+        row.metadata_reverted_from,
+        row.metadata_author,
+        row.metadata_message,
+        row.based_on,
+        row.is_draft
     )
     .execute(transaction.deref_mut())
     .await
@@ -527,6 +902,158 @@ pub(super) async fn upsert_current_revision(
     .await
 }
 
+pub(super) async fn is_empty(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, NoteStoreError> {
+    let row = query!(r#"SELECT EXISTS(SELECT 1 FROM note) AS "exists!""#)
+        .fetch_one(transaction.deref_mut())
+        .await
+        .map_err(NoteStoreError::PostgreSQLError)?;
+    Ok(!row.exists)
+}
+
+/// Insert revisions as is, except that revisions that are not current are compressed.
+///
+/// Notes that are referred to but don't exist yet are created without any revision, so that
+/// they can be imported later.
+pub(super) async fn insert_records<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    keyframe_interval: u32,
+    records: Vec<RevisionRecord>,
+) -> Result<(), NoteStoreError> {
+    let mut ids: HashSet<Uuid> = HashSet::new();
+    for r in &records {
+        ids.insert(r.id.try_to_uuid()?);
+        for x in r.parent.iter().chain(r.prev.iter()) {
+            ids.insert(x.try_to_uuid()?);
+        }
+    }
+    for id in ids {
+        query!(
+            r#"INSERT INTO note(id) VALUES ($1) ON CONFLICT DO NOTHING"#,
+            id
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+    }
+    // Every record of a note carries the same past deletions
+    let mut with_past_deletions = HashSet::new();
+    for r in records {
+        let id = r.id.try_to_uuid()?;
+        let revision = r.revision.try_to_uuid()?;
+        let n = PostgreSQLNoteEditable {
+            id,
+            revision,
+            title: r.title,
+            note_inner: T::from(r.note_inner),
+            parent: r.parent.map(|x| x.try_to_uuid()).transpose()?,
+            prev: r.prev.map(|x| x.try_to_uuid()).transpose()?,
+            metadata: r.metadata,
+            based_on: r.based_on.map(|x| x.try_to_uuid()).transpose()?,
+            is_draft: r.is_draft,
+        };
+        insert_revision(transaction, n).await?;
+        if r.is_current {
+            upsert_current_revision(transaction, id, revision).await?;
+        } else {
+            compress_revision(transaction, revision, keyframe_interval).await?;
+        }
+        for label in &r.labels {
+            insert_label(transaction, id, label, revision).await?;
+        }
+        if r.deleted_at.is_some() {
+            set_deleted_at(transaction, id, r.deleted_at).await?;
+        }
+        if with_past_deletions.insert(id) {
+            for d in &r.past_deletions {
+                insert_past_deletion(transaction, id, d).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Import all revisions of a note, checking them as described in [`NoteStore::import_note`].
+///
+/// [`NoteStore::import_note`]: crate::NoteStore::import_note
+pub(super) async fn import_note_helper<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    keyframe_interval: u32,
+    records: Vec<RevisionRecord>,
+) -> Result<(), NoteStoreError> {
+    let (id, current_record) = check_note_records(&records)?;
+    if has_revisions(transaction, id.try_to_uuid()?).await? {
+        return Err(NoteStoreError::NoteIDConflict(id.clone()));
+    }
+    // Check the relationships before changing anything
+    if let Some(r) = current_record {
+        if let Some(parent) = &r.parent {
+            get_note_by_loc(transaction, &NoteLocator::Current(parent.clone())).await?;
+        }
+        if let Some(prev) = &r.prev {
+            let prev_note: PostgreSQLNote<T> =
+                get_note_by_loc(transaction, &NoteLocator::Current(prev.clone()))
+                    .await?
+                    .into_note()?;
+            if let Some(next) = prev_note.next {
+                return Err(NoteStoreError::ExistingNext(prev.clone(), next));
+            }
+        }
+    }
+    insert_records::<T>(transaction, keyframe_interval, records).await
+}
+
+/// Get the labels of all notes, as (note, label, revision).
+pub(super) async fn get_all_labels(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<(Uuid, String, Uuid)>, NoteStoreError> {
+    let rows = query!(r#"SELECT id, label, revision FROM revision_label"#)
+        .fetch_all(transaction.deref_mut())
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.id, r.label, r.revision))
+        .collect())
+}
+
+/// Get the deletion times of the deleted notes whose deletion time is known.
+pub(super) async fn get_all_deleted_at(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<(Uuid, DateTime<Utc>)>, NoteStoreError> {
+    let rows = query!(
+        r#"
+        SELECT id, deleted_at AS "deleted_at!"
+        FROM note
+        WHERE deleted_at IS NOT NULL
+        "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.id, r.deleted_at)).collect())
+}
+
+pub(super) async fn has_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<bool, NoteStoreError> {
+    let row = query!(
+        r#"SELECT EXISTS(SELECT 1 FROM revision WHERE id = $1) AS "exists!""#,
+        id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    Ok(row.exists)
+}
+
+pub(super) async fn get_note_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Uuid>, NoteStoreError> {
+    let rows = query!(r#"SELECT DISTINCT id FROM revision ORDER BY id"#)
+        .fetch_all(transaction.deref_mut())
+        .await?;
+    Ok(rows.into_iter().map(|r| r.id).collect())
+}
+
 pub(super) async fn noteid_exist(
     transaction: &mut Transaction<'_, Postgres>,
     id: Uuid,
@@ -616,8 +1143,13 @@ pub(super) async fn delete_revision(
                 id.clone(),
                 revision.clone(),
             )),
+            NoteLocator::AtTime(id, at) => Err(NoteStoreError::NoteNotExistAtTime(id.clone(), *at)),
+            NoteLocator::Label(id, label) => {
+                Err(NoteStoreError::LabelNotExist(id.clone(), label.clone()))
+            }
         }
     } else {
+        set_deleted_at(&mut transaction, id, Some(Utc::now())).await?;
         transaction
             .commit()
             .await
@@ -626,71 +1158,884 @@ pub(super) async fn delete_revision(
     }
 }
 
-async fn is_deleted(
+pub(super) async fn set_deleted_at(
     transaction: &mut Transaction<'_, Postgres>,
     id: Uuid,
-) -> Result<bool, NoteStoreError> {
-    // NULL not correctly inferred by sqlx https://github.com/launchbadge/sqlx/issues/367
-    let res = query!(
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<(), NoteStoreError> {
+    query!(
+        r#"UPDATE note SET deleted_at = $2 WHERE id = $1"#,
+        id,
+        deleted_at
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+/// Record that a deleted note is restored by a revision modified at `restored_at`.
+///
+/// Call this before the revision is inserted, since the deletion time falls back to the
+/// modification time of the last revision, as in [`list_deleted`].
+pub(super) async fn record_past_deletion(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    restored_at: DateTime<Utc>,
+) -> Result<(), NoteStoreError> {
+    query!(
         r#"
-            SELECT note.id, cr.current_revision AS "current_revision?"
-            FROM note
-            LEFT JOIN current_revision cr on cr.id = note.id
-            WHERE note.id = $1
-            "#,
-        id
+        INSERT INTO note_past_deletion (id, deleted_at, restored_at)
+        SELECT note.id,
+               COALESCE(note.deleted_at,
+                        (SELECT max(r.metadata_modified_at)
+                         FROM revision r
+                         WHERE r.id = note.id
+                           AND NOT r.is_draft)),
+               $2
+        FROM note
+        WHERE note.id = $1
+        "#,
+        id,
+        restored_at
     )
-    .fetch_one(transaction.deref_mut())
-    .await;
-    match res {
-        Ok(row) => Ok(row.current_revision.is_none()),
-        Err(e) => {
-            if matches!(e, sqlx::Error::RowNotFound) {
-                Err(NoteStoreError::NoteNotExist(id.into()))
-            } else {
-                Err(NoteStoreError::PostgreSQLError(e))
-            }
-        }
-    }
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
 }
 
-pub(super) async fn update_note_helper<F, T>(
+pub(super) async fn insert_past_deletion(
     transaction: &mut Transaction<'_, Postgres>,
-    loc: &NoteLocator,
-    op: F,
-) -> Result<NoteLocator, NoteStoreError>
-where
-    F: FnOnce(&PostgreSQLNoteEditable<T>) -> Result<PostgreSQLNoteEditable<T>, NoteStoreError>,
-    T: NoteType,
-{
-    let (id, rev) = loc.unpack_uuid()?;
-    let is_resurrecting = is_deleted(transaction, id).await?;
-    let old_note_row: PostgreSQLNoteRow = if is_resurrecting || is_current(transaction, loc).await?
-    {
-        get_row_by_loc(transaction, loc).await?
-    } else {
-        return Err(NoteStoreError::UpdateOldRevision(
+    id: Uuid,
+    deletion: &DeletionPeriod,
+) -> Result<(), NoteStoreError> {
+    query!(
+        r#"INSERT INTO note_past_deletion (id, deleted_at, restored_at) VALUES ($1, $2, $3)"#,
+        id,
+        deletion.deleted_at,
+        deletion.restored_at
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+pub(super) async fn list_past_deletions(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<DeletionPeriod>, NoteStoreError> {
+    if !noteid_exist(transaction, id).await? {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
+    }
+    let rows = query!(
+        r#"
+        SELECT deleted_at, restored_at
+        FROM note_past_deletion
+        WHERE id = $1
+        ORDER BY deleted_at
+        "#,
+        id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| DeletionPeriod {
+            deleted_at: r.deleted_at,
+            restored_at: r.restored_at,
+        })
+        .collect())
+}
+
+/// Get the past deletions of all notes, from older to newer.
+pub(super) async fn get_all_past_deletions(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<(Uuid, DeletionPeriod)>, NoteStoreError> {
+    let rows = query!(
+        r#"
+        SELECT id, deleted_at, restored_at
+        FROM note_past_deletion
+        ORDER BY deleted_at
+        "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.id,
+                DeletionPeriod {
+                    deleted_at: r.deleted_at,
+                    restored_at: r.restored_at,
+                },
+            )
+        })
+        .collect())
+}
+
+pub(super) async fn has_references(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<bool, NoteStoreError> {
+    let row = query!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM revision_only_current WHERE referents @> ARRAY[$1]::uuid[]
+            ) AS "exists!"
+            "#,
+        id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    Ok(row.exists)
+}
+
+/// Get the other notes with revisions that have the note as the parent or previous note, or that
+/// refer to it, ordered by ID.
+pub(super) async fn get_linked_in_history(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<Uuid>, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT DISTINCT id
+            FROM revision
+            WHERE (parent = $1 OR prev = $1 OR referents @> ARRAY[$1]::uuid[])
+              AND id <> $1
+            ORDER BY id
+            "#,
+        id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows.into_iter().map(|r| r.id).collect())
+}
+
+pub(super) async fn purge_note_rows(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<(), NoteStoreError> {
+    query!(r#"DELETE FROM revision_label WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"DELETE FROM note_past_deletion WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(
+        r#"UPDATE session_draft SET id = NULL, base = NULL WHERE id = $1"#,
+        id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query!(r#"DELETE FROM revision WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"DELETE FROM note WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn purge_revision_row(
+    transaction: &mut Transaction<'_, Postgres>,
+    revision: Uuid,
+) -> Result<(), NoteStoreError> {
+    rebase_dependents(transaction, revision).await?;
+    // Keep the history connected
+    query!(
+        r#"
+            UPDATE revision
+            SET based_on = (SELECT based_on FROM revision WHERE revision = $1)
+            WHERE based_on = $1
+            "#,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query!(
+        r#"UPDATE revision SET metadata_reverted_from = NULL WHERE metadata_reverted_from = $1"#,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query!(
+        r#"DELETE FROM revision_label WHERE revision = $1"#,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query!(
+        r#"UPDATE session_draft SET base = NULL WHERE base = $1"#,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query!(r#"DELETE FROM revision WHERE revision = $1"#, revision)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(())
+}
+
+async fn get_label_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    label: &str,
+) -> Result<Uuid, NoteStoreError> {
+    let row = query!(
+        r#"SELECT revision FROM revision_label WHERE id = $1 AND label = $2"#,
+        id,
+        label
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    match row {
+        Some(r) => Ok(r.revision),
+        None if noteid_exist(transaction, id).await? => {
+            Err(NoteStoreError::LabelNotExist(id.into(), label.to_owned()))
+        }
+        None => Err(NoteStoreError::NoteNotExist(id.into())),
+    }
+}
+
+pub(super) async fn insert_label(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    label: &str,
+    revision: Uuid,
+) -> Result<(), NoteStoreError> {
+    match get_label_revision(transaction, id, label).await {
+        Ok(r) if r == revision => return Ok(()),
+        Ok(r) => {
+            return Err(NoteStoreError::LabelExists(
+                id.into(),
+                label.to_owned(),
+                r.into(),
+            ))
+        }
+        Err(NoteStoreError::LabelNotExist(..)) => {}
+        Err(e) => return Err(e),
+    }
+    query!(
+        r#"INSERT INTO revision_label (id, label, revision) VALUES ($1, $2, $3)"#,
+        id,
+        label,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+pub(super) async fn delete_label(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    label: &str,
+) -> Result<(), NoteStoreError> {
+    get_label_revision(transaction, id, label).await?;
+    query!(
+        r#"DELETE FROM revision_label WHERE id = $1 AND label = $2"#,
+        id,
+        label
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+pub(super) async fn list_labels(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<RevisionLabel>, NoteStoreError> {
+    if !noteid_exist(transaction, id).await? {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
+    }
+    // Byte order, the same as other note stores
+    let rows = query!(
+        r#"
+            SELECT label, revision
+            FROM revision_label
+            WHERE id = $1
+            ORDER BY label COLLATE "C"
+            "#,
+        id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| RevisionLabel {
+            label: r.label,
+            revision: r.revision.into(),
+        })
+        .collect())
+}
+
+pub(super) async fn upsert_session_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    session: &str,
+    id: Option<Uuid>,
+    base: Option<Uuid>,
+    content: serde_json::Value,
+) -> Result<SessionDraft, NoteStoreError> {
+    let row = query!(
+        r#"
+            INSERT INTO session_draft (session, id, base, content)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (session) DO UPDATE
+            SET id = EXCLUDED.id, base = EXCLUDED.base, content = EXCLUDED.content,
+                saved_at = now()
+            RETURNING saved_at
+            "#,
+        session,
+        id,
+        base,
+        content
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    Ok(SessionDraft {
+        session: session.to_owned(),
+        id: id.map(Into::into),
+        base: base.map(Into::into),
+        content,
+        saved_at: row.saved_at,
+    })
+}
+
+/// Insert a session draft as is, e.g., when restoring a backup.
+pub(super) async fn insert_session_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft: &SessionDraft,
+) -> Result<(), NoteStoreError> {
+    query!(
+        r#"
+            INSERT INTO session_draft (session, id, base, content, saved_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        draft.session,
+        draft.id.as_ref().map(|x| x.try_to_uuid()).transpose()?,
+        draft.base.as_ref().map(|x| x.try_to_uuid()).transpose()?,
+        draft.content,
+        draft.saved_at
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+pub(super) async fn get_session_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    session: &str,
+) -> Result<SessionDraft, NoteStoreError> {
+    let row = query!(
+        r#"SELECT id, base, content, saved_at FROM session_draft WHERE session = $1"#,
+        session
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?
+    .ok_or_else(|| NoteStoreError::SessionDraftNotExist(session.to_owned()))?;
+    Ok(SessionDraft {
+        session: session.to_owned(),
+        id: row.id.map(Into::into),
+        base: row.base.map(Into::into),
+        content: row.content,
+        saved_at: row.saved_at,
+    })
+}
+
+pub(super) async fn delete_session_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    session: &str,
+) -> Result<(), NoteStoreError> {
+    let res = query!(r#"DELETE FROM session_draft WHERE session = $1"#, session)
+        .execute(transaction.deref_mut())
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(NoteStoreError::SessionDraftNotExist(session.to_owned()));
+    }
+    Ok(())
+}
+
+pub(super) async fn list_session_drafts(
+    transaction: &mut Transaction<'_, Postgres>,
+    prefix: &str,
+) -> Result<Vec<SessionDraft>, NoteStoreError> {
+    // Byte order, the same as other note stores
+    let rows = query!(
+        r#"
+            SELECT session, id, base, content, saved_at
+            FROM session_draft
+            WHERE starts_with(session, $1)
+            ORDER BY session COLLATE "C"
+            "#,
+        prefix
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| SessionDraft {
+            session: r.session,
+            id: r.id.map(Into::into),
+            base: r.base.map(Into::into),
+            content: r.content,
+            saved_at: r.saved_at,
+        })
+        .collect())
+}
+
+/// Store the full text of a revision, or a delta against a keyframe instead.
+async fn set_note_inner(
+    transaction: &mut Transaction<'_, Postgres>,
+    revision: Uuid,
+    note_inner: &str,
+    delta: Option<(Uuid, serde_json::Value)>,
+) -> Result<(), NoteStoreError> {
+    let (delta_base, delta) = delta.unzip();
+    query!(
+        r#"
+            UPDATE revision
+            SET note_inner = CASE WHEN $3::uuid IS NULL THEN $2 ELSE '' END,
+                delta_base = $3,
+                delta = $4,
+                delta_text_searchable = CASE
+                    WHEN $3::uuid IS NULL THEN NULL
+                    ELSE to_tsvector('english', title || ' ' || $2)
+                END
+            WHERE revision = $1
+            "#,
+        revision,
+        note_inner,
+        delta_base,
+        delta
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+/// Store a revision that is not current as a delta against the latest keyframe of the note.
+///
+/// The revision is kept in full, becoming a keyframe itself, if there is no earlier keyframe,
+/// if the keyframe already has `keyframe_interval - 1` deltas, or if the revision is the keyframe
+/// of other revisions.
+/// Returns whether the revision is stored as a delta.
+pub(super) async fn compress_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    revision: Uuid,
+    keyframe_interval: u32,
+) -> Result<bool, NoteStoreError> {
+    if keyframe_interval <= 1 {
+        return Ok(false);
+    }
+    let row = query!(
+        r#"
+            SELECT
+                revision.note_inner,
+                keyframe.revision AS "keyframe?",
+                keyframe.note_inner AS "keyframe_inner?",
+                (SELECT count(*) FROM revision d WHERE d.delta_base = keyframe.revision)
+                    AS "deltas!"
+            FROM revision
+            LEFT JOIN LATERAL (
+                SELECT k.revision, k.note_inner
+                FROM revision k
+                LEFT JOIN current_revision cr ON cr.current_revision = k.revision
+                WHERE k.id = revision.id
+                AND k.revision <> revision.revision
+                AND k.delta_base IS NULL
+                AND cr.current_revision IS NULL
+                AND k.metadata_modified_at <= revision.metadata_modified_at
+                ORDER BY k.metadata_modified_at DESC
+                LIMIT 1
+            ) keyframe ON TRUE
+            WHERE revision.revision = $1
+            AND revision.delta_base IS NULL
+            AND NOT EXISTS (SELECT 1 FROM revision d WHERE d.delta_base = revision.revision)
+            AND NOT EXISTS (SELECT 1 FROM current_revision WHERE current_revision = $1)
+            "#,
+        revision
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    if let Some(row) = row {
+        if let (Some(keyframe), Some(keyframe_inner)) = (row.keyframe, row.keyframe_inner) {
+            if row.deltas + 1 < keyframe_interval as i64 {
+                let d = delta::compute(&keyframe_inner, &row.note_inner);
+                set_note_inner(transaction, revision, &row.note_inner, Some((keyframe, d))).await?;
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Revisions of a note that are stored in full and could be stored as deltas, from older to
+/// newer, e.g., revisions saved before deltas were enabled.
+pub(super) async fn get_full_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<Uuid>, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT revision.revision
+            FROM revision
+            LEFT JOIN current_revision cr ON cr.current_revision = revision.revision
+            WHERE revision.id = $1
+            AND revision.delta_base IS NULL
+            AND cr.current_revision IS NULL
+            ORDER BY revision.metadata_modified_at ASC
+            "#,
+        id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows.into_iter().map(|r| r.revision).collect())
+}
+
+/// Before a keyframe is removed, make the first revision stored as a delta against it the new
+/// keyframe, and the others deltas against the new keyframe.
+async fn rebase_dependents(
+    transaction: &mut Transaction<'_, Postgres>,
+    revision: Uuid,
+) -> Result<(), NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT d.revision, d.delta AS "delta!", base.note_inner
+            FROM revision d
+            JOIN revision base ON base.revision = d.delta_base
+            WHERE d.delta_base = $1
+            ORDER BY d.metadata_modified_at ASC
+            "#,
+        revision
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let mut keyframe: Option<(Uuid, String)> = None;
+    for r in rows {
+        let note_inner = delta::rebuild(String::new(), Some(r.delta), Some(r.note_inner))?;
+        match &keyframe {
+            Some((k, k_inner)) => {
+                let d = delta::compute(k_inner, &note_inner);
+                set_note_inner(transaction, r.revision, &note_inner, Some((*k, d))).await?;
+            }
+            None => {
+                set_note_inner(transaction, r.revision, &note_inner, None).await?;
+                keyframe = Some((r.revision, note_inner));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(super) async fn is_deleted(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<bool, NoteStoreError> {
+    // NULL not correctly inferred by sqlx https://github.com/launchbadge/sqlx/issues/367
+    let res = query!(
+        r#"
+            SELECT note.id, cr.current_revision AS "current_revision?"
+            FROM note
+            LEFT JOIN current_revision cr on cr.id = note.id
+            WHERE note.id = $1
+            "#,
+        id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await;
+    match res {
+        Ok(row) => Ok(row.current_revision.is_none()),
+        Err(e) => {
+            if matches!(e, sqlx::Error::RowNotFound) {
+                Err(NoteStoreError::NoteNotExist(id.into()))
+            } else {
+                Err(NoteStoreError::PostgreSQLError(e))
+            }
+        }
+    }
+}
+
+pub(super) async fn update_note_helper<F, T>(
+    transaction: &mut Transaction<'_, Postgres>,
+    keyframe_interval: u32,
+    loc: &NoteLocator,
+    op: F,
+) -> Result<NoteLocator, NoteStoreError>
+where
+    F: FnOnce(&PostgreSQLNoteEditable<T>) -> Result<PostgreSQLNoteEditable<T>, NoteStoreError>,
+    T: NoteType,
+{
+    let (id, rev) = loc.unpack_uuid()?;
+    let is_resurrecting = is_deleted(transaction, id).await?;
+    let old_note_row: PostgreSQLNoteRow = if is_resurrecting || is_current(transaction, loc).await?
+    {
+        get_row_by_loc(transaction, loc).await?
+    } else {
+        return Err(NoteStoreError::UpdateOldRevision(
             id.into(),
             rev.unwrap().into(),
         ));
     };
-    let old_note: PostgreSQLNoteEditable<T> = old_note_row.into();
+    let old_revision = old_note_row.revision;
+    let mut old_note: PostgreSQLNoteEditable<T> = old_note_row.try_into()?;
+    old_note.metadata = old_note.metadata.on_update_note();
+    old_note.based_on = Some(old_revision);
+    old_note.is_draft = false;
     let new_revision = get_new_revision();
     let mut updated_note = op(&old_note)?;
     updated_note.revision = new_revision;
-    updated_note.metadata = updated_note.metadata.on_update_note();
     if is_resurrecting {
         // If a note previously has a prev note, we will clear the attribute, in case the prev
         // note now has a next
         // Similarly for branches
         updated_note.parent = None;
         updated_note.prev = None;
+        record_past_deletion(transaction, id, updated_note.metadata.modified_at).await?;
+        set_deleted_at(transaction, id, None).await?;
     }
     let new_loc = insert_revision(transaction, updated_note).await?;
     upsert_current_revision(transaction, id, new_revision).await?;
+    if !is_resurrecting {
+        compress_revision(transaction, old_revision, keyframe_interval).await?;
+    }
     Ok(new_loc)
 }
 
+/// Copy the content of the revision specified by the locator into a new current revision.
+pub(super) async fn revert_note_helper<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    keyframe_interval: u32,
+    loc: &NoteLocator,
+) -> Result<NoteLocator, NoteStoreError> {
+    let target: PostgreSQLNoteEditable<T> = get_row_by_loc(transaction, loc).await?.try_into()?;
+    let base = if is_deleted(transaction, target.id).await? {
+        loc.at_revision(&target.revision.into())
+    } else {
+        loc.current()
+    };
+    update_note_helper(transaction, keyframe_interval, &base, |old_note| {
+        let mut note = old_note.clone();
+        note.title = target.title;
+        note.note_inner = target.note_inner;
+        note.metadata.tags = target.metadata.tags;
+        note.metadata.custom_metadata = target.metadata.custom_metadata;
+        note.metadata.reverted_from = Some(target.revision.into());
+        Ok(note)
+    })
+    .await
+}
+
+/// Create a draft based on the revision specified by the locator.
+///
+/// Only [`NoteLocator::Current`] and [`NoteLocator::Specific`] are supported.
+pub(super) async fn create_draft_helper<F, T>(
+    transaction: &mut Transaction<'_, Postgres>,
+    loc: &NoteLocator,
+    op: F,
+) -> Result<NoteLocator, NoteStoreError>
+where
+    F: FnOnce(&PostgreSQLNoteEditable<T>) -> Result<PostgreSQLNoteEditable<T>, NoteStoreError>,
+    T: NoteType,
+{
+    let base_row = get_row_by_loc(transaction, loc).await?;
+    let base_revision = base_row.revision;
+    let mut base: PostgreSQLNoteEditable<T> = base_row.try_into()?;
+    base.metadata = base.metadata.on_update_note();
+    base.based_on = Some(base_revision);
+    base.is_draft = true;
+    let mut draft = op(&base)?;
+    draft.revision = get_new_revision();
+    insert_revision(transaction, draft).await
+}
+
+pub(super) async fn list_drafts(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Vec<PostgreSQLNoteRowJoined>, NoteStoreError> {
+    if !noteid_exist(transaction, id).await? {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
+    }
+    sqlx::query_as::<_, PostgreSQLNoteRowJoined>(&get_note_query(
+        vec![],
+        vec![],
+        vec![
+            "revision.id = $1".to_owned(),
+            "revision.is_draft".to_owned(),
+            "NOT EXISTS (SELECT 1 FROM revision later WHERE later.based_on = revision.revision)"
+                .to_owned(),
+        ],
+        vec![],
+        vec![],
+        vec!["revision.metadata_modified_at ASC".to_owned()],
+        None,
+    ))
+    .bind(id)
+    .fetch_all(transaction.deref_mut())
+    .await
+    .map_err(NoteStoreError::PostgreSQLError)
+}
+
+/// Copy the content of the draft specified by the locator into a new current revision.
+pub(super) async fn promote_draft_helper<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    keyframe_interval: u32,
+    loc: &NoteLocator,
+) -> Result<NoteLocator, NoteStoreError> {
+    let draft: PostgreSQLNoteEditable<T> = get_row_by_loc(transaction, loc).await?.try_into()?;
+    if !draft.is_draft {
+        return Err(NoteStoreError::NotADraft(
+            draft.id.into(),
+            draft.revision.into(),
+        ));
+    }
+    let base = if is_deleted(transaction, draft.id).await? {
+        loc.at_revision(&draft.revision.into())
+    } else {
+        loc.current()
+    };
+    update_note_helper(transaction, keyframe_interval, &base, |old_note| {
+        let mut note = old_note.clone();
+        note.title = draft.title;
+        note.note_inner = draft.note_inner;
+        note.metadata.tags = draft.metadata.tags;
+        note.metadata.custom_metadata = draft.metadata.custom_metadata;
+        note.metadata.author = draft.metadata.author;
+        note.metadata.message = draft.metadata.message;
+        note.based_on = Some(draft.revision);
+        Ok(note)
+    })
+    .await
+}
+
+/// The channel notified when changes are recorded.
+pub(super) const CHANGE_CHANNEL: &str = "note_change";
+
+/// Record a change in the change feed, and notify the subscribers once committed.
+pub(super) async fn record_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    kind: ChangeKind,
+    loc: &NoteLocator,
+) -> Result<(), NoteStoreError> {
+    let (id, revision) = loc.unpack_uuid()?;
+    // The cursor is given by sequence_changes after commit
+    query!(
+        r#"
+        INSERT INTO note_change (kind, id, revision)
+        VALUES ($1, $2, $3)
+        "#,
+        kind.as_str(),
+        id,
+        revision
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    query("SELECT pg_notify($1, '')")
+        .bind(CHANGE_CHANNEL)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(())
+}
+
+/// Give cursors to the changes whose transaction, and every transaction that got its transaction
+/// ID before, have finished, and return the smallest and largest `seq` of the committed changes
+/// that still wait for an older transaction.
+///
+/// Must be the first statement of its transaction.
+pub(super) async fn sequence_changes(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<(i64, i64)>, NoteStoreError> {
+    // Each statement sees the changes committed by a previous call
+    transaction
+        .execute("SET TRANSACTION ISOLATION LEVEL READ COMMITTED READ WRITE")
+        .await?;
+    query!("SELECT pg_advisory_xact_lock(hashtext('note_change'))")
+        .execute(transaction.deref_mut())
+        .await?;
+    // Uncommitted changes are invisible, and the transactions that may still record changes
+    // have a transaction ID of at least the snapshot's xmin
+    query!(
+        r#"
+        UPDATE note_change
+        SET cursor = sequenced.cursor
+        FROM (SELECT seq, nextval('note_change_cursor') AS cursor
+              FROM (SELECT seq
+                    FROM note_change
+                    WHERE cursor IS NULL
+                      AND recorded_by < pg_snapshot_xmin(pg_current_snapshot())
+                    ORDER BY seq) AS committed) AS sequenced
+        WHERE note_change.seq = sequenced.seq
+        "#
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    let row = query!(
+        r#"
+        SELECT min(seq) AS first, max(seq) AS last
+        FROM note_change
+        WHERE cursor IS NULL
+        "#
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    Ok(row.first.zip(row.last))
+}
+
+/// Delete the changes recorded before `before` that have a cursor, and return how many.
+pub(super) async fn prune_changes(
+    transaction: &mut Transaction<'_, Postgres>,
+    before: &DateTime<Utc>,
+) -> Result<u64, NoteStoreError> {
+    let result = query!(
+        r#"
+        DELETE FROM note_change
+        WHERE changed_at < $1 AND cursor IS NOT NULL
+        "#,
+        before
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub(super) async fn changes_since(
+    transaction: &mut Transaction<'_, Postgres>,
+    cursor: ChangeCursor,
+) -> Result<Vec<Change>, NoteStoreError> {
+    let rows = query!(
+        r#"
+        SELECT cursor AS "cursor!", kind, id, revision, changed_at
+        FROM note_change
+        WHERE cursor > $1
+        ORDER BY cursor
+        "#,
+        i64::try_from(cursor).unwrap_or(i64::MAX)
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Change {
+            cursor: r.cursor as ChangeCursor,
+            kind: ChangeKind::parse(&r.kind).expect("Unknown kind of change"),
+            loc: match r.revision {
+                Some(revision) => NoteLocator::Specific(r.id.into(), revision.into()),
+                None => NoteLocator::Current(r.id.into()),
+            },
+            changed_at: r.changed_at,
+        })
+        .collect())
+}
+
+pub(super) async fn latest_change_cursor(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<ChangeCursor, NoteStoreError> {
+    let row = query!(r#"SELECT coalesce(max(cursor), 0) AS "cursor!" FROM note_change"#)
+        .fetch_one(transaction.deref_mut())
+        .await?;
+    Ok(row.cursor as ChangeCursor)
+}
+
 pub(super) async fn read_write(
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<(), NoteStoreError> {
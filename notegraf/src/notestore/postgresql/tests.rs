@@ -1,6 +1,8 @@
 use super::*;
 use crate::notestore::tests as common_tests;
 use crate::notetype::PlainNote;
+use crate::InMemoryStore;
+use futures::StreamExt;
 use sqlx::{Connection, Executor, PgConnection};
 use std::env;
 
@@ -28,7 +30,7 @@ fn get_connect_options() -> PgConnectOptions {
     }
 }
 
-async fn get_store() -> PostgreSQLStore<PlainNote> {
+async fn get_builder() -> PostgreSQLStoreBuilder<PlainNote> {
     let options = get_connect_options();
     let mut connection = PgConnection::connect_with(&options)
         .await
@@ -39,8 +41,10 @@ async fn get_store() -> PostgreSQLStore<PlainNote> {
         .await
         .expect("Failed to create database.");
     PostgreSQLStoreBuilder::new(options.database(&db_name))
-        .build()
-        .await
+}
+
+async fn get_store() -> PostgreSQLStore<PlainNote> {
+    get_builder().await.build().await
 }
 
 #[tokio::test]
@@ -58,6 +62,136 @@ async fn new_note_retrieve() {
     common_tests::new_note_retrieve(get_store().await).await;
 }
 
+#[tokio::test]
+async fn diff() {
+    common_tests::diff(get_store().await).await;
+}
+
+#[tokio::test]
+async fn revert_note() {
+    common_tests::revert_note(get_store().await).await;
+}
+
+#[tokio::test]
+async fn revert_deleted_note() {
+    common_tests::revert_deleted_note(get_store().await).await;
+}
+
+#[tokio::test]
+async fn move_note() {
+    common_tests::move_note(get_store().await).await;
+}
+
+#[tokio::test]
+async fn move_note_hand_over_branch() {
+    common_tests::move_note_hand_over_branch(get_store().await).await;
+}
+
+#[tokio::test]
+async fn insert_after() {
+    common_tests::insert_after(get_store().await).await;
+}
+
+#[tokio::test]
+async fn insert_before() {
+    common_tests::insert_before(get_store().await).await;
+}
+
+#[tokio::test]
+async fn split_sequence() {
+    common_tests::split_sequence(get_store().await).await;
+}
+
+#[tokio::test]
+async fn merge_sequences() {
+    common_tests::merge_sequences(get_store().await).await;
+}
+
+#[tokio::test]
+async fn get_sequence() {
+    common_tests::get_sequence(get_store().await).await;
+}
+
+#[tokio::test]
+async fn get_subtree() {
+    common_tests::get_subtree(get_store().await).await;
+}
+
+#[tokio::test]
+async fn get_ancestors() {
+    common_tests::get_ancestors(get_store().await).await;
+}
+
+#[tokio::test]
+async fn list_deleted() {
+    common_tests::list_deleted(get_store().await).await;
+}
+
+#[tokio::test]
+async fn purge_note() {
+    common_tests::purge_note(get_store().await).await;
+}
+
+#[tokio::test]
+async fn purge_revision() {
+    common_tests::purge_revision(get_store().await).await;
+}
+
+#[tokio::test]
+async fn compact() {
+    common_tests::compact(get_store().await).await;
+}
+
+#[tokio::test]
+async fn note_at_time() {
+    common_tests::note_at_time(get_store().await).await;
+}
+
+#[tokio::test]
+async fn note_at_time_restored() {
+    common_tests::note_at_time_restored(get_store().await, get_store().await).await;
+}
+
+#[tokio::test]
+async fn revision_author_message() {
+    common_tests::revision_author_message(get_store().await).await;
+}
+
+#[tokio::test]
+async fn revision_labels() {
+    common_tests::revision_labels(get_store().await).await;
+}
+
+#[tokio::test]
+async fn drafts() {
+    common_tests::drafts(get_store().await).await;
+}
+
+#[tokio::test]
+async fn update_note_merging_concurrent() {
+    common_tests::update_note_merging_concurrent(get_store().await).await;
+}
+
+#[tokio::test]
+async fn change_feed() {
+    common_tests::change_feed(get_store().await).await;
+}
+
+#[tokio::test]
+async fn change_feed_neighbours() {
+    common_tests::change_feed_neighbours(get_store().await).await;
+}
+
+#[tokio::test]
+async fn prune_changes() {
+    common_tests::prune_changes(get_store().await).await;
+}
+
+#[tokio::test]
+async fn session_drafts() {
+    common_tests::session_drafts(get_store().await).await;
+}
+
 #[tokio::test]
 async fn update_note() {
     common_tests::update_note(get_store().await).await;
@@ -182,3 +316,328 @@ async fn search_lexeme_exclude() {
 async fn issue_158() {
     common_tests::issue_158(get_store().await).await;
 }
+
+#[tokio::test]
+async fn backup_restore() {
+    common_tests::backup_restore(get_store().await, get_store().await).await;
+}
+
+#[tokio::test]
+async fn backup_restore_in_memory() {
+    common_tests::backup_restore(get_store().await, InMemoryStore::new()).await;
+}
+
+#[test]
+fn restore_unsupported() {
+    assert!(matches!(
+        PostgreSQLStore::<PlainNote>::restore(env::temp_dir()),
+        Err(NoteStoreError::Unsupported(_))
+    ));
+}
+
+#[tokio::test]
+async fn new_note_with_identity() {
+    common_tests::new_note_with_identity(get_store().await).await;
+}
+
+#[tokio::test]
+async fn export_import() {
+    common_tests::export_import(get_store().await, get_store().await).await;
+}
+
+#[tokio::test]
+async fn export_import_in_memory() {
+    common_tests::export_import(get_store().await, InMemoryStore::new()).await;
+}
+
+async fn delta_bases(store: &PostgreSQLStore<PlainNote>, id: &NoteID) -> Vec<Option<Uuid>> {
+    sqlx::query_as::<_, (Option<Uuid>,)>(
+        "SELECT delta_base FROM revision WHERE id = $1 ORDER BY metadata_modified_at",
+    )
+    .bind(id.to_uuid().unwrap())
+    .fetch_all(&store.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.0)
+    .collect()
+}
+
+async fn revision_texts(store: &PostgreSQLStore<PlainNote>, id: &NoteID) -> Vec<PlainNote> {
+    store
+        .get_revisions(&NoteLocator::Current(id.clone()))
+        .await
+        .unwrap()
+        .iter()
+        .map(|n| n.get_note_inner())
+        .collect()
+}
+
+#[tokio::test]
+async fn delta_revisions() {
+    let store = get_builder().await.keyframe_interval(3).build().await;
+    let text = |i: usize| -> PlainNote {
+        PlainNote::new(
+            (0..20)
+                .map(|line| format!("Line {line} of version {}\n", if line == i { i } else { 0 }))
+                .collect(),
+        )
+    };
+    let loc = store
+        .new_note("".to_owned(), text(0), NoteMetadataEditable::unchanged())
+        .await
+        .unwrap();
+    let id = loc.get_id().clone();
+    let mut revisions = vec![loc.get_revision().unwrap().to_uuid().unwrap()];
+    for i in 1..7 {
+        let loc = store
+            .update_note(
+                &NoteLocator::Current(id.clone()),
+                None,
+                Some(text(i)),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        revisions.push(loc.get_revision().unwrap().to_uuid().unwrap());
+    }
+    let mut texts: Vec<PlainNote> = (0..7).map(text).collect();
+    // Two deltas per keyframe, and the current revision is in full
+    assert_eq!(
+        delta_bases(&store, &id).await,
+        vec![
+            None,
+            Some(revisions[0]),
+            Some(revisions[0]),
+            None,
+            Some(revisions[3]),
+            Some(revisions[3]),
+            None
+        ]
+    );
+    assert_eq!(revision_texts(&store, &id).await, texts);
+    // Deltas can still be found by their full text
+    let (searchable,): (i64,) = sqlx::query_as(
+        "SELECT count(*) FROM revision WHERE delta IS NOT NULL AND delta_text_searchable @@ 'line'",
+    )
+    .fetch_one(&store.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(searchable, 4);
+    let note = store
+        .get_note(&NoteLocator::Specific(id.clone(), revisions[4].into()))
+        .await
+        .unwrap();
+    assert_eq!(note.get_note_inner(), text(4));
+
+    // Reverting to a delta
+    store
+        .revert_note(&NoteLocator::Specific(id.clone(), revisions[1].into()))
+        .await
+        .unwrap();
+    texts.push(text(1));
+    assert_eq!(revision_texts(&store, &id).await, texts);
+
+    // Purging a keyframe makes the next revision the keyframe
+    store
+        .purge_revision(&NoteLocator::Specific(id.clone(), revisions[0].into()))
+        .await
+        .unwrap();
+    texts.remove(0);
+    assert_eq!(
+        delta_bases(&store, &id).await,
+        vec![
+            None,
+            Some(revisions[1]),
+            None,
+            Some(revisions[3]),
+            Some(revisions[3]),
+            None,
+            None
+        ]
+    );
+    assert_eq!(revision_texts(&store, &id).await, texts);
+}
+
+#[tokio::test]
+async fn malformed_delta() {
+    let store = get_builder().await.keyframe_interval(3).build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    for text in ["Bar", "Baz"] {
+        store
+            .update_note(
+                &loc.current(),
+                None,
+                Some(PlainNote::new(text.into())),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+    }
+    sqlx::query("UPDATE revision SET delta = '[[1000, 5]]' WHERE delta IS NOT NULL")
+        .execute(&store.db_pool)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.get_revisions(&loc.current()).await,
+        Err(NoteStoreError::NoteInnerError(_))
+    ));
+}
+
+#[tokio::test]
+async fn delta_revisions_disabled() {
+    let store = get_builder().await.keyframe_interval(1).build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        store
+            .update_note(
+                &loc.current(),
+                None,
+                None,
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(delta_bases(&store, loc.get_id()).await, vec![None; 4]);
+}
+
+#[tokio::test]
+async fn compact_deltas() {
+    // Revisions saved in full are only converted when asked to
+    let store = get_store().await;
+    let text = |i: usize| PlainNote::new(format!("Shared line\nVersion word{i}\n"));
+    let loc = store
+        .new_note("".to_owned(), text(0), NoteMetadataEditable::unchanged())
+        .await
+        .unwrap();
+    let id = loc.get_id().clone();
+    let mut revisions = vec![loc.get_revision().unwrap().to_uuid().unwrap()];
+    for i in 1..7 {
+        let loc = store
+            .update_note(
+                &NoteLocator::Current(id.clone()),
+                None,
+                Some(text(i)),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        revisions.push(loc.get_revision().unwrap().to_uuid().unwrap());
+    }
+    assert_eq!(delta_bases(&store, &id).await, vec![None; 7]);
+    assert_eq!(store.compact_deltas().await.unwrap(), 0);
+
+    let store: PostgreSQLStore<PlainNote> = PostgreSQLStore {
+        db_pool: store.db_pool.clone(),
+        keyframe_interval: 3,
+        change_feed: Default::default(),
+        _phantom: PhantomData,
+    };
+    assert_eq!(store.compact_deltas().await.unwrap(), 4);
+    assert_eq!(
+        delta_bases(&store, &id).await,
+        vec![
+            None,
+            Some(revisions[0]),
+            Some(revisions[0]),
+            None,
+            Some(revisions[3]),
+            Some(revisions[3]),
+            None
+        ]
+    );
+    assert_eq!(
+        revision_texts(&store, &id).await,
+        (0..7).map(text).collect::<Vec<_>>()
+    );
+    assert_eq!(store.compact_deltas().await.unwrap(), 0);
+    // Converted revisions can still be found by their full text
+    let old = store
+        .get_note(&NoteLocator::Specific(id.clone(), revisions[1].into()))
+        .await
+        .unwrap();
+    let found = store
+        .search_at(&"word1".to_owned().into(), old.get_metadata().modified_at)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_revision(), revisions[1].into());
+}
+
+#[tokio::test]
+async fn subscribers_share_connection() {
+    let store = get_store().await;
+    let subscribers = store.db_pool.options().get_max_connections() + 2;
+    let mut subscriptions = Vec::new();
+    for _ in 0..subscribers {
+        subscriptions.push(store.subscribe().await.unwrap());
+    }
+    let loc = store
+        .new_note(
+            "Foo".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    for subscription in subscriptions.iter_mut() {
+        let change = tokio::time::timeout(Duration::from_secs(10), subscription.next())
+            .await
+            .expect("Change not delivered")
+            .unwrap();
+        assert_eq!(change.loc.get_revision(), loc.get_revision());
+    }
+}
+
+#[tokio::test]
+async fn changes_behind_long_transaction() {
+    let store = get_store().await;
+    let mut subscription = store.subscribe().await.unwrap();
+    // A transaction that started before the change, e.g., a long import
+    let mut blocker = store.db_pool.begin().await.unwrap();
+    blocker
+        .execute("SELECT pg_current_xact_id()")
+        .await
+        .unwrap();
+    let loc = store
+        .new_note(
+            "Foo".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    // Neither waits for the transaction to finish
+    let changes = tokio::time::timeout(Duration::from_secs(5), store.changes_since(0))
+        .await
+        .expect("Waited for an older transaction")
+        .unwrap();
+    assert!(changes.is_empty());
+    let mut other_subscription = tokio::time::timeout(Duration::from_secs(5), store.subscribe())
+        .await
+        .expect("Waited for an older transaction")
+        .unwrap();
+    blocker.rollback().await.unwrap();
+    for subscription in [&mut subscription, &mut other_subscription] {
+        let change = tokio::time::timeout(Duration::from_secs(10), subscription.next())
+            .await
+            .expect("Change not delivered")
+            .unwrap();
+        assert_eq!(change.loc.get_revision(), loc.get_revision());
+    }
+}
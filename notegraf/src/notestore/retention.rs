@@ -0,0 +1,172 @@
+//! Retention policies and compaction of old revisions.
+//!
+//! A [`RetentionPolicy`] thins out old revisions of notes, e.g., "keep all revisions for 7 days,
+//! then one per day, then one per month after 90 days".
+//! [`compact`] enforces a policy on a note store.
+use crate::errors::NoteStoreError;
+use crate::{Note, NoteLocator, NoteStore, NoteType, Revision};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// How many revisions to keep within an age tier.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Keep {
+    /// The last revision of each hour.
+    Hourly,
+    /// The last revision of each day.
+    Daily,
+    /// The last revision of each ISO week.
+    Weekly,
+    /// The last revision of each month.
+    Monthly,
+}
+
+impl Keep {
+    fn period(&self, t: &DateTime<Utc>) -> String {
+        let fmt = match self {
+            Keep::Hourly => "%Y-%m-%dT%H",
+            Keep::Daily => "%Y-%m-%d",
+            Keep::Weekly => "%G-W%V",
+            Keep::Monthly => "%Y-%m",
+        };
+        t.format(fmt).to_string()
+    }
+}
+
+/// Revisions at least `after_days` days old are thinned out as specified by `keep`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionTier {
+    pub after_days: u32,
+    pub keep: Keep,
+}
+
+/// A list of age tiers.
+///
+/// Revisions younger than all tiers are kept.
+/// Otherwise, the tier with the largest `after_days` that a revision is old enough for applies.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub tiers: Vec<RetentionTier>,
+}
+
+impl RetentionPolicy {
+    pub fn new(mut tiers: Vec<RetentionTier>) -> Self {
+        tiers.sort_by_key(|t| t.after_days);
+        RetentionPolicy { tiers }
+    }
+
+    fn tier(&self, age: Duration) -> Option<(usize, &RetentionTier)> {
+        self.tiers
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, t)| age >= Duration::days(t.after_days as i64))
+    }
+
+    /// Select the revisions of a note to remove under this policy.
+    ///
+    /// `revisions` are all revisions of a note, in the order returned by
+    /// [`NoteStore::get_revisions`].
+    /// The following revisions are never selected:
+    /// - the current revision;
+    /// - the last revision of a deleted note, so that it can be restored;
+    /// - revisions that other revisions were reverted from;
+    /// - drafts that no other revision is based on.
+    pub fn select_expired<T: NoteType>(
+        &self,
+        revisions: &[Box<dyn Note<T>>],
+        now: DateTime<Utc>,
+    ) -> Vec<Revision> {
+        let mut protected: HashSet<Revision> = revisions
+            .iter()
+            .filter_map(|n| n.get_metadata().reverted_from)
+            .collect();
+        let based_on: HashSet<Revision> =
+            revisions.iter().filter_map(|n| n.get_based_on()).collect();
+        let open_drafts: HashSet<Revision> = revisions
+            .iter()
+            .filter(|n| n.is_draft() && !based_on.contains(&n.get_revision()))
+            .map(|n| n.get_revision())
+            .collect();
+        match revisions.iter().find(|n| n.is_current()) {
+            Some(n) => protected.insert(n.get_revision()),
+            None => match revisions.iter().rfind(|n| !n.is_draft()) {
+                Some(n) => protected.insert(n.get_revision()),
+                None => false,
+            },
+        };
+        // The last revision of each period of each tier survives
+        let mut survivors: HashMap<(usize, String), Revision> = HashMap::new();
+        let mut candidates = vec![];
+        // Open drafts don't take the place of other revisions either
+        for n in revisions
+            .iter()
+            .filter(|n| !open_drafts.contains(&n.get_revision()))
+        {
+            let modified_at = n.get_metadata().modified_at;
+            if let Some((i, tier)) = self.tier(now - modified_at) {
+                let key = (i, tier.keep.period(&modified_at));
+                if let Some(previous) = survivors.insert(key, n.get_revision()) {
+                    candidates.push(previous);
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .filter(|r| !protected.contains(r))
+            .collect()
+    }
+}
+
+/// Summary of a run of [`compact`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Number of notes examined.
+    pub notes: usize,
+    /// Number of revisions removed.
+    pub revisions_purged: usize,
+}
+
+/// Remove the revisions of every note (including deleted notes) that have expired under a
+/// retention policy, as of `now`.
+///
+/// See [`RetentionPolicy::select_expired`] for revisions that are always kept.
+/// Revisions with labels are kept as well.
+pub async fn compact<T, S>(
+    store: &S,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Result<CompactionReport, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    let mut report = CompactionReport::default();
+    for id in store.get_note_ids().await? {
+        let labelled: HashSet<Revision> = store
+            .list_labels(&id)
+            .await?
+            .into_iter()
+            .map(|l| l.revision)
+            .collect();
+        let expired = {
+            let revisions = store
+                .get_revisions(&NoteLocator::Current(id.clone()))
+                .await?;
+            policy
+                .select_expired(&revisions, now)
+                .into_iter()
+                .filter(|r| !labelled.contains(r))
+                .collect::<Vec<_>>()
+        };
+        for revision in expired {
+            store
+                .purge_revision(&NoteLocator::Specific(id.clone(), revision))
+                .await?;
+            report.revisions_purged += 1;
+        }
+        report.notes += 1;
+    }
+    Ok(report)
+}
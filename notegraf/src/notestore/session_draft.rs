@@ -0,0 +1,36 @@
+//! Drafts of editor sessions.
+//!
+//! A session draft keeps what is being written in an editor, so that the session can be picked up
+//! again later or on another device.
+//! Unlike the drafts started by [`NoteStore::create_draft`], session drafts are not revisions:
+//! they are kept out of the history of notes, and saving a session replaces its draft.
+//! A client publishes a session draft by creating or updating a note as usual, and then deletes
+//! the session draft.
+//!
+//! Session drafts are included in backups, but not in exports.
+//!
+//! [`NoteStore::create_draft`]: crate::NoteStore::create_draft
+use crate::{NoteID, Revision};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The draft of an editor session, as returned by [`NoteStore::get_session_draft`].
+///
+/// [`NoteStore::get_session_draft`]: crate::NoteStore::get_session_draft
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionDraft {
+    /// The key of the session, chosen by the client.
+    pub session: String,
+    /// The note that the draft is for, e.g., the note being updated, or the note that a new note
+    /// will be appended to.
+    ///
+    /// If the note is purged, the draft is kept without it and the base revision.
+    pub id: Option<NoteID>,
+    /// The revision that the draft is based on.
+    ///
+    /// If the revision is purged, the draft is kept without it.
+    pub base: Option<Revision>,
+    /// What is in the editor, in a format of the client's choosing.
+    pub content: serde_json::Value,
+    pub saved_at: DateTime<Utc>,
+}
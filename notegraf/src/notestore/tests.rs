@@ -1,8 +1,23 @@
+use crate::diff::{diff_revisions, DiffChunk, DiffTag};
 use crate::errors::NoteStoreError;
-use crate::notemetadata::NoteMetadataEditable;
-use crate::{NoteLocator, NoteStore, PlainNote};
+use crate::merge::{update_note_merging, MergeOutcome, MergeVersions};
+use crate::notemetadata::{NoteMetadata, NoteMetadataEditable};
+use crate::notestore::interchange::{write_record, RevisionRecord};
+use crate::notestore::retention::{self, CompactionReport, Keep, RetentionPolicy, RetentionTier};
+use crate::notestore::{
+    export, import, restore_deleted, AsOf, Change, ChangeCursor, ChangeKind, DeletedNote, Link,
+    MoveTarget, RevisionLabel, SessionDraft, SplitTail,
+};
+use crate::{
+    Note, NoteID, NoteIdentity, NoteLocator, NoteSerializable, NoteStore, PlainNote, Revision,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::StreamExt;
 use std::collections::HashSet;
 use std::option::Option::None;
+use std::path::PathBuf;
+use std::{env, fs};
+use uuid::Uuid;
 
 async fn is_deleted(
     store: &impl NoteStore<PlainNote>,
@@ -12,6 +27,13 @@ async fn is_deleted(
     Ok(cr.is_none())
 }
 
+/// Create an empty folder for backups
+fn backup_dir() -> PathBuf {
+    let path = env::temp_dir().join(Uuid::new_v4().to_string());
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
 pub(super) async fn unique_id(store: impl NoteStore<PlainNote>) {
     let loc1 = store
         .new_note(
@@ -32,6 +54,100 @@ pub(super) async fn unique_id(store: impl NoteStore<PlainNote>) {
     assert_ne!(loc1.get_id(), loc2.get_id());
 }
 
+fn test_identity(created_year: i32) -> NoteIdentity {
+    NoteIdentity {
+        id: Uuid::new_v4().into(),
+        revision: Uuid::new_v4().into(),
+        created_at: Utc.with_ymd_and_hms(created_year, 1, 2, 3, 4, 5).unwrap(),
+        modified_at: Utc.with_ymd_and_hms(created_year, 6, 7, 8, 9, 10).unwrap(),
+    }
+}
+
+pub(super) async fn new_note_with_identity(store: impl NoteStore<PlainNote>) {
+    let identity = test_identity(2001);
+    let loc = store
+        .new_note_with_identity(
+            identity.clone(),
+            "Foo".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(loc.get_id(), &identity.id);
+    assert_eq!(loc.get_revision(), Some(&identity.revision));
+    let note = store.get_note(&loc.current()).await.unwrap();
+    assert_eq!(note.get_revision(), identity.revision);
+    assert_eq!(note.get_metadata().created_at, identity.created_at);
+    assert_eq!(note.get_metadata().modified_at, identity.modified_at);
+    // Collision with an existing note
+    let mut conflict = test_identity(2002);
+    conflict.id = identity.id.clone();
+    assert!(matches!(
+        store
+            .new_note_with_identity(
+                conflict.clone(),
+                "Bar".to_owned(),
+                PlainNote::new("Bar".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await,
+        Err(NoteStoreError::NoteIDConflict(_))
+    ));
+    assert_eq!(
+        store.get_note(&loc.current()).await.unwrap().get_title(),
+        "Foo"
+    );
+    // Append and branch
+    let next_identity = test_identity(2003);
+    let next_loc = store
+        .append_note_with_identity(
+            &identity.id,
+            next_identity.clone(),
+            "Next".to_owned(),
+            PlainNote::new("Next".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(next_loc.get_id(), &next_identity.id);
+    let next = store.get_note(&next_loc.current()).await.unwrap();
+    assert_eq!(next.get_prev(), Some(identity.id.clone()));
+    assert_eq!(next.get_metadata().created_at, next_identity.created_at);
+    let branch_identity = test_identity(2004);
+    let branch_loc = store
+        .add_branch_with_identity(
+            &identity.id,
+            branch_identity.clone(),
+            "Branch".to_owned(),
+            PlainNote::new("Branch".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(branch_loc.get_id(), &branch_identity.id);
+    let branch = store.get_note(&branch_loc.current()).await.unwrap();
+    assert_eq!(branch.get_parent(), Some(identity.id.clone()));
+    assert_eq!(
+        branch.get_metadata().modified_at,
+        branch_identity.modified_at
+    );
+    assert!(matches!(
+        store
+            .add_branch_with_identity(
+                &identity.id,
+                conflict,
+                "Bar".to_owned(),
+                PlainNote::new("Bar".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await,
+        Err(NoteStoreError::NoteIDConflict(_))
+    ));
+    let note = store.get_note(&loc.current()).await.unwrap();
+    assert_eq!(note.get_branches().len(), 1);
+}
+
 pub(super) async fn new_note_revision(store: impl NoteStore<PlainNote>) {
     let loc = store
         .new_note(
@@ -76,6 +192,68 @@ pub(super) async fn new_note_retrieve(store: impl NoteStore<PlainNote>) {
     );
 }
 
+pub(super) async fn diff(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "Old title".to_owned(),
+            PlainNote::new("Foo\nBar\nBaz\n".into()),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["a".to_owned(), "b".to_owned()])),
+                custom_metadata: Some(serde_json::json!({"x": 1})),
+                author: None,
+                message: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("New title".to_owned()),
+            Some(PlainNote::new("Foo\nQux\nBaz\n".into())),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["b".to_owned(), "c".to_owned()])),
+                custom_metadata: None,
+                author: None,
+                message: None,
+            },
+        )
+        .await
+        .unwrap();
+    let id = loc1.get_id();
+    let rev1 = loc1.get_revision().unwrap();
+    let rev2 = loc2.get_revision().unwrap();
+    let diff = diff_revisions(&store, id, rev1, rev2).await.unwrap();
+    assert_eq!(&diff.from, rev1);
+    assert_eq!(&diff.to, rev2);
+    assert!(!diff.is_unchanged());
+    let chunk = |tag, value: &str| DiffChunk {
+        tag,
+        value: value.to_owned(),
+    };
+    assert_eq!(
+        diff.title,
+        vec![
+            chunk(DiffTag::Delete, "Old"),
+            chunk(DiffTag::Insert, "New"),
+            chunk(DiffTag::Equal, " title"),
+        ]
+    );
+    assert!(diff.note_inner.iter().any(|c| c.tag == DiffTag::Delete));
+    assert!(diff.note_inner.iter().any(|c| c.tag == DiffTag::Insert));
+    assert_eq!(diff.tags.added.iter().collect::<Vec<_>>(), vec!["c"]);
+    assert_eq!(diff.tags.removed.iter().collect::<Vec<_>>(), vec!["a"]);
+    assert!(diff.custom_metadata.iter().all(|c| c.tag == DiffTag::Equal));
+    assert!(diff_revisions(&store, id, rev2, rev2)
+        .await
+        .unwrap()
+        .is_unchanged());
+    assert!(matches!(
+        diff_revisions(&store, id, rev1, &Uuid::new_v4().into()).await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+}
+
 pub(super) async fn update_note(store: impl NoteStore<PlainNote>) {
     let loc1 = store
         .new_note(
@@ -546,274 +724,870 @@ pub(super) async fn resurrect_note_in_sequence(store: impl NoteStore<PlainNote>)
     );
 }
 
-pub(super) async fn search_recent(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    store
-        .new_note(
-            "".to_owned(),
-            note_inner.clone(),
-            NoteMetadataEditable::unchanged(),
-        )
-        .await
-        .unwrap();
-    let notes = store.search(&("".into())).await.unwrap();
-    assert_eq!(notes[0].get_note_inner(), note_inner);
-    assert_eq!(notes.len(), 1);
-}
-
-pub(super) async fn search_fulltext(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    store
+pub(super) async fn revert_note(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
         .new_note(
-            "hello world".to_owned(),
-            note_inner.clone(),
-            NoteMetadataEditable::unchanged(),
+            "Title1".to_owned(),
+            PlainNote::new("Foo1".into()),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["a".to_owned()])),
+                custom_metadata: None,
+                author: None,
+                message: None,
+            },
         )
         .await
         .unwrap();
-    store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
-            NoteMetadataEditable::unchanged(),
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Title2".to_owned()),
+            Some(PlainNote::new("Foo2".into())),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["b".to_owned()])),
+                custom_metadata: None,
+                author: None,
+                message: None,
+            },
         )
         .await
         .unwrap();
-    let notes = store.search(&("hello".into())).await.unwrap();
-    assert_eq!(notes[0].get_title(), "hello world");
-    assert_eq!(notes.len(), 1);
-    let notes = store.search(&("foo".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
-}
-
-pub(super) async fn search_nonexist(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    store
-        .new_note(
-            "hello world".to_owned(),
-            note_inner.clone(),
+    let next_loc = store
+        .append_note(
+            loc1.get_id(),
+            "".to_owned(),
+            PlainNote::new("Next".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    let loc3 = store.revert_note(&loc1).await.unwrap();
+    let rev1 = loc1.get_revision().unwrap();
+    let rev3 = loc3.get_revision().unwrap();
+    assert_ne!(rev3, rev1);
+    assert_ne!(rev3, loc2.get_revision().unwrap());
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(&note.get_revision(), rev3);
+    assert_eq!(note.get_title(), "Title1");
+    assert_eq!(note.get_note_inner(), PlainNote::new("Foo1".into()));
+    assert_eq!(note.get_metadata().tags, HashSet::from(["a".to_owned()]));
+    assert_eq!(note.get_metadata().reverted_from.as_ref(), Some(rev1));
+    // Relationships are kept
+    assert_eq!(note.get_next().as_ref(), Some(next_loc.get_id()));
+    // Later updates are not reverts
     store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
+        .update_note(
+            &loc1.current(),
+            None,
+            Some(PlainNote::new("Foo3".into())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("fizzbuzz".into())).await.unwrap();
-    assert_eq!(notes.len(), 0);
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(note.get_metadata().reverted_from, None);
+    assert!(matches!(
+        store
+            .revert_note(&loc1.at_revision(&Uuid::new_v4().into()))
+            .await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
 }
 
-pub(super) async fn backlink(store: impl NoteStore<PlainNote>) {
-    let note_inner_1 = PlainNote::new("Hello world".into());
+pub(super) async fn revert_deleted_note(store: impl NoteStore<PlainNote>) {
     let loc1 = store
         .new_note(
             "".to_owned(),
-            note_inner_1.clone(),
+            PlainNote::new("Head".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let mut note_inner_2 = PlainNote::new("Goodbye world".into());
-    note_inner_2.add_referent(loc1.get_id().clone());
     let loc2 = store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner_2.clone(),
+        .append_note(
+            loc1.get_id(),
+            "".to_owned(),
+            PlainNote::new("Middle".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let note = store.get_note(&loc1.current()).await.unwrap();
-    let references = note.get_references();
-    assert!(references.contains(loc2.get_id()));
+    store.delete_note(&loc2.current()).await.unwrap();
+    let loc3 = store.revert_note(&loc2).await.unwrap();
+    let note = store.get_note(&loc2.current()).await.unwrap();
+    assert_eq!(note.get_revision(), loc3.get_revision().unwrap().clone());
+    assert_eq!(note.get_note_inner(), PlainNote::new("Middle".into()));
+    assert_eq!(
+        note.get_metadata().reverted_from.as_ref(),
+        loc2.get_revision()
+    );
+    // Resurrected as a standalone note
+    assert_eq!(note.get_prev(), None);
+    assert_eq!(note.get_parent(), None);
+    assert_eq!(
+        store.get_note(&loc1.current()).await.unwrap().get_next(),
+        None
+    );
 }
 
-pub(super) async fn search_tags(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
+async fn new_note_helper(
+    store: &impl NoteStore<PlainNote>,
+    body: &str,
+    prev: Option<&NoteLocator>,
+) -> NoteLocator {
+    let note_inner = PlainNote::new(body.into());
+    match prev {
+        Some(p) => store
+            .append_note(
+                p.get_id(),
+                "".to_owned(),
+                note_inner,
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap(),
+        None => store
+            .new_note("".to_owned(), note_inner, NoteMetadataEditable::unchanged())
+            .await
+            .unwrap(),
+    }
+}
+
+pub(super) async fn move_note(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_b = new_note_helper(&store, "B", Some(&loc_a)).await;
+    let loc_c = new_note_helper(&store, "C", Some(&loc_b)).await;
+    let loc_d = new_note_helper(&store, "D", None).await;
+    let (a, b, c, d) = (
+        loc_a.get_id(),
+        loc_b.get_id(),
+        loc_c.get_id(),
+        loc_d.get_id(),
+    );
+    // Branch out from the middle of a sequence
+    let loc = store
+        .move_note(b, &MoveTarget::Branch(d.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        &store.get_current_revision(&loc).await.unwrap().unwrap(),
+        loc.get_revision().unwrap()
+    );
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_a.get_next().as_ref(), Some(c));
+    assert_eq!(note_c.get_prev().as_ref(), Some(a));
+    assert_eq!(note_b.get_parent().as_ref(), Some(d));
+    assert_eq!(note_b.get_prev(), None);
+    assert_eq!(note_b.get_next(), None);
+    assert_eq!(note_d.get_branches(), HashSet::from([b.clone()]));
+    // Move to the end of another sequence
     store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .move_note(c, &MoveTarget::EndOf(d.clone()))
         .await
         .unwrap();
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_a.get_next(), None);
+    assert_eq!(note_c.get_prev().as_ref(), Some(d));
+    assert_eq!(note_d.get_next().as_ref(), Some(c));
+    // D -> C, B is a branch of D
+    // Cycles
+    assert!(matches!(
+        store.move_note(d, &MoveTarget::Branch(b.clone())).await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    assert!(matches!(
+        store.move_note(d, &MoveTarget::Branch(d.clone())).await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    assert!(matches!(
+        store.move_note(d, &MoveTarget::EndOf(d.clone())).await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    assert!(matches!(
+        store
+            .move_note(d, &MoveTarget::Branch(Uuid::new_v4().into()))
+            .await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+    // C takes over the position of D, so D can be moved after C, taking B along
     store
-        .new_note("goodbye world".to_owned(), note_inner.clone(), md)
+        .move_note(d, &MoveTarget::EndOf(c.clone()))
         .await
         .unwrap();
-    let notes = store.search(&("hello #tag1".into())).await.unwrap();
-    assert_eq!(notes[0].get_title(), "hello world");
-    assert_eq!(notes.len(), 1);
-    let notes = store.search(&("#tag1".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_c.get_prev(), None);
+    assert_eq!(note_c.get_next().as_ref(), Some(d));
+    assert_eq!(note_d.get_prev().as_ref(), Some(c));
+    assert_eq!(note_d.get_next(), None);
+    assert_eq!(note_d.get_branches(), HashSet::from([b.clone()]));
+    // Detach
+    store.move_note(d, &MoveTarget::TopLevel).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_c.get_next(), None);
+    assert_eq!(note_d.get_prev(), None);
+    assert_eq!(note_d.get_parent(), None);
 }
 
-pub(super) async fn search_orphan(store: impl NoteStore<PlainNote>) {
-    let loc1 = store
-        .new_note(
+pub(super) async fn move_note_hand_over_branch(store: impl NoteStore<PlainNote>) {
+    let loc_x = new_note_helper(&store, "X", None).await;
+    let loc_y = store
+        .add_branch(
+            loc_x.get_id(),
             "".to_owned(),
-            PlainNote::new("Head".into()),
+            PlainNote::new("Y".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    let loc_z = new_note_helper(&store, "Z", Some(&loc_y)).await;
     store
-        .append_note(
-            loc1.get_id(),
-            "".to_owned(),
-            PlainNote::new("Middle".into()),
-            NoteMetadataEditable::unchanged(),
-        )
+        .move_note(loc_y.get_id(), &MoveTarget::TopLevel)
         .await
         .unwrap();
-    let notes = store.search(&("!orphan".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
-}
-
-pub(super) async fn search_notag(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
+    let note_x = store.get_note(&loc_x.current()).await.unwrap();
+    let note_y = store.get_note(&loc_y.current()).await.unwrap();
+    let note_z = store.get_note(&loc_z.current()).await.unwrap();
+    assert_eq!(
+        note_x.get_branches(),
+        HashSet::from([loc_z.get_id().clone()])
+    );
+    assert_eq!(note_z.get_parent().as_ref(), Some(loc_x.get_id()));
+    assert_eq!(note_z.get_prev(), None);
+    assert_eq!(note_y.get_parent(), None);
+    assert_eq!(note_y.get_next(), None);
+    // A note cannot become a branch of its own branch
+    assert!(matches!(
+        store
+            .move_note(loc_x.get_id(), &MoveTarget::Branch(loc_z.get_id().clone()))
+            .await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    // Until the branch is detached
     store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .move_note(loc_z.get_id(), &MoveTarget::TopLevel)
         .await
         .unwrap();
     store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
+        .move_note(loc_x.get_id(), &MoveTarget::Branch(loc_z.get_id().clone()))
+        .await
+        .unwrap();
+    let note_z = store.get_note(&loc_z.current()).await.unwrap();
+    assert_eq!(
+        note_z.get_branches(),
+        HashSet::from([loc_x.get_id().clone()])
+    );
+}
+
+pub(super) async fn insert_after(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_c = new_note_helper(&store, "C", Some(&loc_a)).await;
+    let loc_b = store
+        .insert_after(
+            loc_a.get_id(),
+            "".to_owned(),
+            PlainNote::new("B".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("world !notag".into())).await.unwrap();
-    assert_eq!(notes[0].get_title(), "goodbye world");
-    assert_eq!(notes.len(), 1);
-    let notes = store.search(&("world".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    assert_eq!(note_a.get_next().as_ref(), Some(loc_b.get_id()));
+    assert_eq!(note_b.get_prev().as_ref(), Some(loc_a.get_id()));
+    assert_eq!(note_b.get_next().as_ref(), Some(loc_c.get_id()));
+    assert_eq!(note_c.get_prev().as_ref(), Some(loc_b.get_id()));
+    assert_eq!(note_b.get_revision(), loc_b.get_revision().unwrap().clone());
+    // Inserting after the last note is the same as appending
+    let loc_d = store
+        .insert_after(
+            loc_c.get_id(),
+            "".to_owned(),
+            PlainNote::new("D".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_c.get_next().as_ref(), Some(loc_d.get_id()));
+    assert_eq!(note_d.get_prev().as_ref(), Some(loc_c.get_id()));
+    assert_eq!(note_d.get_next(), None);
+    assert!(matches!(
+        store
+            .insert_after(
+                &Uuid::new_v4().into(),
+                "".to_owned(),
+                PlainNote::new("E".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
 }
 
-pub(super) async fn issue_151(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("".into());
-    let loc = store
-        .new_note(
-            "note 1".to_owned(),
-            note_inner.clone(),
+pub(super) async fn insert_before(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_c = new_note_helper(&store, "C", Some(&loc_a)).await;
+    let loc_b = store
+        .insert_before(
+            loc_c.get_id(),
+            "".to_owned(),
+            PlainNote::new("B".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let loc_next = store
-        .append_note(
-            loc.get_id(),
-            "note 2".to_owned(),
-            note_inner.clone(),
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    assert_eq!(note_a.get_next().as_ref(), Some(loc_b.get_id()));
+    assert_eq!(note_b.get_prev().as_ref(), Some(loc_a.get_id()));
+    assert_eq!(note_b.get_next().as_ref(), Some(loc_c.get_id()));
+    assert_eq!(note_c.get_prev().as_ref(), Some(loc_b.get_id()));
+    // Before the head of a sequence
+    let loc_head = store
+        .insert_before(
+            loc_a.get_id(),
+            "".to_owned(),
+            PlainNote::new("Head".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let mut note_inner_ref = PlainNote::new("ref".into());
-    note_inner_ref.add_referent(loc.get_id().clone());
-    let loc2 = store
-        .new_note(
-            "note 3".to_owned(),
-            note_inner_ref.clone(),
+    let note_head = store.get_note(&loc_head.current()).await.unwrap();
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    assert_eq!(note_head.get_prev(), None);
+    assert_eq!(note_head.get_parent(), None);
+    assert_eq!(note_head.get_next().as_ref(), Some(loc_a.get_id()));
+    assert_eq!(note_a.get_prev().as_ref(), Some(loc_head.get_id()));
+    // Before a branch, which takes over the position in the parent
+    let loc_branch = store
+        .add_branch(
+            loc_c.get_id(),
+            "".to_owned(),
+            PlainNote::new("Branch".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let loc3 = store
-        .new_note(
-            "note 4".to_owned(),
-            note_inner_ref.clone(),
+    let loc_new_branch = store
+        .insert_before(
+            loc_branch.get_id(),
+            "".to_owned(),
+            PlainNote::new("New branch".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let n = store.get_note(&loc.current()).await.unwrap();
-    assert_eq!(&n.get_next().unwrap(), loc_next.get_id());
-    assert_eq!(n.get_references().len(), 2);
-    assert!(n.get_references().contains(loc2.get_id()));
-    assert!(n.get_references().contains(loc3.get_id()));
-}
-
-pub(super) async fn tags(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("".into());
-    let md1 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
-    let md2 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag1".to_owned(), "tag2".to_owned()])),
-        custom_metadata: None,
-    };
-    let md3 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag2".to_owned(), "tag3".to_owned()])),
-        custom_metadata: None,
-    };
-    let _loc1 = store
-        .new_note("note 1".to_owned(), note_inner.clone(), md1)
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_branch = store.get_note(&loc_branch.current()).await.unwrap();
+    let note_new_branch = store.get_note(&loc_new_branch.current()).await.unwrap();
+    assert_eq!(
+        note_c.get_branches(),
+        HashSet::from([loc_new_branch.get_id().clone()])
+    );
+    assert_eq!(note_new_branch.get_parent().as_ref(), Some(loc_c.get_id()));
+    assert_eq!(
+        note_new_branch.get_next().as_ref(),
+        Some(loc_branch.get_id())
+    );
+    assert_eq!(note_branch.get_parent(), None);
+    assert_eq!(
+        note_branch.get_prev().as_ref(),
+        Some(loc_new_branch.get_id())
+    );
+}
+
+pub(super) async fn split_sequence(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_b = new_note_helper(&store, "B", Some(&loc_a)).await;
+    let loc_c = new_note_helper(&store, "C", Some(&loc_b)).await;
+    let loc_d = new_note_helper(&store, "D", Some(&loc_c)).await;
+    assert!(matches!(
+        store
+            .split_sequence(loc_a.get_id(), SplitTail::TopLevel)
+            .await,
+        Err(NoteStoreError::SplitAtHead(_))
+    ));
+    // A -> B | C -> D
+    store
+        .split_sequence(loc_c.get_id(), SplitTail::TopLevel)
         .await
         .unwrap();
-    let _loc2 = store
-        .new_note("note 2".to_owned(), note_inner.clone(), md2)
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_b.get_next(), None);
+    assert_eq!(note_c.get_prev(), None);
+    assert_eq!(note_c.get_parent(), None);
+    assert_eq!(note_c.get_next().as_ref(), Some(loc_d.get_id()));
+    assert_eq!(note_d.get_prev().as_ref(), Some(loc_c.get_id()));
+    // A | B as a branch of A
+    store
+        .split_sequence(loc_b.get_id(), SplitTail::Branch)
         .await
         .unwrap();
-    let loc3 = store
-        .new_note("note 3".to_owned(), note_inner.clone(), md3)
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    assert_eq!(note_a.get_next(), None);
+    assert_eq!(
+        note_a.get_branches(),
+        HashSet::from([loc_b.get_id().clone()])
+    );
+    assert_eq!(note_b.get_prev(), None);
+    assert_eq!(note_b.get_parent().as_ref(), Some(loc_a.get_id()));
+}
+
+pub(super) async fn merge_sequences(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_b = new_note_helper(&store, "B", Some(&loc_a)).await;
+    let loc_c = new_note_helper(&store, "C", None).await;
+    let loc_d = store
+        .add_branch(
+            loc_c.get_id(),
+            "".to_owned(),
+            PlainNote::new("D".into()),
+            NoteMetadataEditable::unchanged(),
+        )
         .await
         .unwrap();
-    assert_eq!(store.tags().await.unwrap().len(), 3);
-    store.delete_note(&loc3).await.unwrap();
-    let tags = store.tags().await.unwrap();
-    assert_eq!(tags.len(), 2);
-    assert!(tags.contains(&"tag1".to_owned()));
-    assert!(tags.contains(&"tag2".to_owned()));
+    assert!(matches!(
+        store.merge_sequences(loc_a.get_id(), loc_c.get_id()).await,
+        Err(NoteStoreError::ExistingNext(_, _))
+    ));
+    assert!(matches!(
+        store.merge_sequences(loc_c.get_id(), loc_b.get_id()).await,
+        Err(NoteStoreError::ExistingPrev(_, _))
+    ));
+    assert!(matches!(
+        store.merge_sequences(loc_b.get_id(), loc_a.get_id()).await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    // The last note of a sequence cannot be followed by one of its ancestors
+    assert!(matches!(
+        store.merge_sequences(loc_d.get_id(), loc_c.get_id()).await,
+        Err(NoteStoreError::WouldCreateCycle(_, _))
+    ));
+    // A -> B -> C
+    store
+        .merge_sequences(loc_b.get_id(), loc_c.get_id())
+        .await
+        .unwrap();
+    let note_b = store.get_note(&loc_b.current()).await.unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    assert_eq!(note_b.get_next().as_ref(), Some(loc_c.get_id()));
+    assert_eq!(note_c.get_prev().as_ref(), Some(loc_b.get_id()));
+    // C -> D, with D no longer a branch of C
+    store
+        .merge_sequences(loc_c.get_id(), loc_d.get_id())
+        .await
+        .unwrap();
+    let note_c = store.get_note(&loc_c.current()).await.unwrap();
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert!(note_c.get_branches().is_empty());
+    assert_eq!(note_c.get_next().as_ref(), Some(loc_d.get_id()));
+    assert_eq!(note_d.get_parent(), None);
+    assert_eq!(note_d.get_prev().as_ref(), Some(loc_c.get_id()));
 }
 
-pub(super) async fn search_limit_override(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
+fn note_ids(notes: &[Box<dyn Note<PlainNote>>]) -> Vec<NoteID> {
+    notes.iter().map(|n| n.get_id()).collect()
+}
+
+async fn populate_tree(store: &impl NoteStore<PlainNote>) -> Vec<NoteID> {
+    // A -> B -> C, with D -> F and E as branches of B
+    let loc_a = new_note_helper(store, "A", None).await;
+    let loc_b = new_note_helper(store, "B", Some(&loc_a)).await;
+    let loc_c = new_note_helper(store, "C", Some(&loc_b)).await;
+    let mut ids = vec![loc_a, loc_b, loc_c];
+    for body in ["D", "E"] {
+        let loc = store
+            .add_branch(
+                ids[1].get_id(),
+                "".to_owned(),
+                PlainNote::new(body.into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        ids.push(loc);
+    }
+    let loc_f = new_note_helper(store, "F", Some(&ids[3])).await;
+    ids.push(loc_f);
+    ids.into_iter().map(|loc| loc.get_id().clone()).collect()
+}
+
+pub(super) async fn get_sequence(store: impl NoteStore<PlainNote>) {
+    let ids = populate_tree(&store).await;
+    let (a, b, c, d, e, f) = (&ids[0], &ids[1], &ids[2], &ids[3], &ids[4], &ids[5]);
+    for id in [a, b, c] {
+        let notes = store.get_sequence(id).await.unwrap();
+        assert_eq!(note_ids(&notes), vec![a.clone(), b.clone(), c.clone()]);
+    }
+    let notes = store.get_sequence(f).await.unwrap();
+    assert_eq!(note_ids(&notes), vec![d.clone(), f.clone()]);
+    assert_eq!(notes[0].get_parent().as_ref(), Some(b));
+    let notes = store.get_sequence(e).await.unwrap();
+    assert_eq!(note_ids(&notes), vec![e.clone()]);
+    assert!(matches!(
+        store.get_sequence(&Uuid::new_v4().into()).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+}
+
+pub(super) async fn get_subtree(store: impl NoteStore<PlainNote>) {
+    let ids = populate_tree(&store).await;
+    let notes = store.get_subtree(&ids[0], None).await.unwrap();
+    assert_eq!(note_ids(&notes), ids);
+    let notes = store.get_subtree(&ids[0], Some(2)).await.unwrap();
+    assert_eq!(note_ids(&notes), ids[..5]);
+    let notes = store.get_subtree(&ids[1], Some(1)).await.unwrap();
+    assert_eq!(
+        note_ids(&notes),
+        vec![
+            ids[1].clone(),
+            ids[2].clone(),
+            ids[3].clone(),
+            ids[4].clone()
+        ]
+    );
+    let notes = store.get_subtree(&ids[3], Some(0)).await.unwrap();
+    assert_eq!(note_ids(&notes), vec![ids[3].clone()]);
+    assert!(matches!(
+        store.get_subtree(&Uuid::new_v4().into(), None).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+}
+
+pub(super) async fn get_ancestors(store: impl NoteStore<PlainNote>) {
+    let ids = populate_tree(&store).await;
+    let ancestors = store.get_ancestors(&ids[5]).await.unwrap();
+    let chain: Vec<(Link, NoteID)> = ancestors
+        .into_iter()
+        .map(|a| (a.link, a.note.get_id()))
+        .collect();
+    assert_eq!(
+        chain,
+        vec![
+            (Link::Sequence, ids[3].clone()),
+            (Link::Branch, ids[1].clone()),
+            (Link::Sequence, ids[0].clone()),
+        ]
+    );
+    assert!(store.get_ancestors(&ids[0]).await.unwrap().is_empty());
+    assert!(matches!(
+        store.get_ancestors(&Uuid::new_v4().into()).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+}
+
+pub(super) async fn list_deleted(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_b = new_note_helper(&store, "B", None).await;
+    new_note_helper(&store, "C", None).await;
+    assert!(store.list_deleted().await.unwrap().is_empty());
+    store.delete_note(&loc_a.current()).await.unwrap();
+    store.delete_note(&loc_b.current()).await.unwrap();
+    let deleted = store.list_deleted().await.unwrap();
+    assert_eq!(deleted.len(), 2);
+    assert_eq!(&deleted[0].note.get_id(), loc_b.get_id());
+    assert_eq!(&deleted[1].note.get_id(), loc_a.get_id());
+    assert_eq!(deleted[1].note.get_note_inner(), PlainNote::new("A".into()));
+    assert!(deleted[0].deleted_at >= deleted[1].deleted_at);
+    assert!(deleted[1].deleted_at >= deleted[1].note.get_metadata().modified_at);
+
+    let loc_a_restored = restore_deleted(&store, loc_a.get_id()).await.unwrap();
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    assert_eq!(
+        note_a.get_revision(),
+        loc_a_restored.get_revision().unwrap().clone()
+    );
+    assert_eq!(note_a.get_note_inner(), PlainNote::new("A".into()));
+    let deleted = store.list_deleted().await.unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(&deleted[0].note.get_id(), loc_b.get_id());
+    assert!(matches!(
+        restore_deleted(&store, loc_a.get_id()).await,
+        Err(NoteStoreError::NotDeleted(_))
+    ));
+
+    // A note deleted again shows up with its new last revision
+    store.delete_note(&loc_a.current()).await.unwrap();
+    let deleted = store.list_deleted().await.unwrap();
+    assert_eq!(deleted.len(), 2);
+    assert_eq!(&deleted[0].note.get_id(), loc_a.get_id());
+    assert_eq!(
+        deleted[0].note.get_revision(),
+        loc_a_restored.get_revision().unwrap().clone()
+    );
+}
+
+pub(super) async fn purge_note(store: impl NoteStore<PlainNote>) {
+    let loc_a = new_note_helper(&store, "A", None).await;
+    let loc_b = new_note_helper(&store, "B", None).await;
+    assert!(matches!(
+        store.purge_note(loc_a.get_id()).await,
+        Err(NoteStoreError::NotDeleted(_))
+    ));
+    store.delete_note(&loc_a.current()).await.unwrap();
+    store.purge_note(loc_a.get_id()).await.unwrap();
+    assert!(store.get_note(&loc_a).await.is_err());
+    assert!(store.get_note(&loc_a.current()).await.is_err());
+    assert!(store.list_deleted().await.unwrap().is_empty());
+
+    // The history of other notes is not changed, so a note in the middle of a sequence cannot be
+    // purged until the revisions linked to it are
+    let loc_e = new_note_helper(&store, "E", Some(&loc_b)).await;
+    let loc_f = new_note_helper(&store, "F", Some(&loc_e)).await;
+    store.delete_note(&loc_e.current()).await.unwrap();
+    let linked = match store.purge_note(loc_e.get_id()).await {
+        Err(NoteStoreError::LinkedInHistory(id, linked)) if &id == loc_e.get_id() => linked,
+        r => panic!("{:?}", r.err()),
+    };
+    assert!(!linked.is_empty());
+    assert!(linked
+        .iter()
+        .all(|id| id == loc_b.get_id() || id == loc_f.get_id()));
+    for id in &linked {
+        for note in store
+            .get_revisions(&NoteLocator::Current(id.clone()))
+            .await
+            .unwrap()
+        {
+            if !note.is_current() {
+                store
+                    .purge_revision(&NoteLocator::Specific(id.clone(), note.get_revision()))
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+    store.purge_note(loc_e.get_id()).await.unwrap();
+    let note_f = store.get_note(&loc_f.current()).await.unwrap();
+    assert_eq!(note_f.get_prev().as_ref(), Some(loc_b.get_id()));
+
+    // A deleted note cannot be purged while other notes refer to it
+    let loc_c = new_note_helper(&store, "C", None).await;
+    store.delete_note(&loc_c.current()).await.unwrap();
+    let mut note_inner = PlainNote::new("D".into());
+    note_inner.add_referent(loc_c.get_id().clone());
+    let loc_d = store
+        .new_note("".to_owned(), note_inner, NoteMetadataEditable::unchanged())
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.purge_note(loc_c.get_id()).await,
+        Err(NoteStoreError::HasReferences(_))
+    ));
     store
-        .new_note(
-            "hello world".to_owned(),
-            note_inner.clone(),
+        .update_note(
+            &loc_d.current(),
+            None,
+            Some(PlainNote::new("D".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    // Nor while a revision that is not current refers to it
+    for purged in [
+        store.purge_note(loc_c.get_id()).await,
+        store.purge_revision(&loc_c).await,
+    ] {
+        match purged {
+            Err(NoteStoreError::LinkedInHistory(_, linked)) => {
+                assert_eq!(linked, vec![loc_d.get_id().clone()])
+            }
+            r => panic!("{:?}", r.err()),
+        }
+    }
+    store.purge_revision(&loc_d).await.unwrap();
+    store.purge_note(loc_c.get_id()).await.unwrap();
+}
+
+pub(super) async fn purge_revision(store: impl NoteStore<PlainNote>) {
+    let loc_1 = new_note_helper(&store, "Secret", None).await;
+    let loc_2 = store
+        .update_note(
+            &loc_1,
+            None,
+            Some(PlainNote::new("Redacted".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.purge_revision(&loc_2).await,
+        Err(NoteStoreError::PurgeCurrentRevision(_, _))
+    ));
+    assert!(matches!(
+        store.purge_revision(&loc_2.current()).await,
+        Err(NoteStoreError::PurgeCurrentRevision(_, _))
+    ));
+    store.purge_revision(&loc_1).await.unwrap();
+    assert!(matches!(
+        store.get_note(&loc_1).await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+    let revisions = store.get_revisions(&loc_2).await.unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(&revisions[0].get_revision(), loc_2.get_revision().unwrap());
+    // Purging the only revision of a deleted note purges the note
+    store.delete_note(&loc_2.current()).await.unwrap();
+    store.purge_revision(&loc_2).await.unwrap();
+    assert!(store.list_deleted().await.unwrap().is_empty());
+    assert!(store.get_note(&loc_2).await.is_err());
+    // Revisions that reverted to a purged revision no longer refer to it
+    let loc_3 = new_note_helper(&store, "Original", None).await;
+    store
+        .update_note(
+            &loc_3,
+            None,
+            Some(PlainNote::new("Changed".into())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    let loc_4 = store.revert_note(&loc_3).await.unwrap();
+    let reverted = store.get_note(&loc_4).await.unwrap();
+    assert_eq!(
+        reverted.get_metadata().reverted_from.as_ref(),
+        loc_3.get_revision()
+    );
+    store.purge_revision(&loc_3).await.unwrap();
+    let reverted = store.get_note(&loc_4).await.unwrap();
+    assert_eq!(reverted.get_metadata().reverted_from, None);
+}
+
+fn record_at(
+    id: &NoteID,
+    modified_at: DateTime<Utc>,
+    is_current: bool,
+    reverted_from: Option<&Revision>,
+) -> RevisionRecord {
+    let metadata = NoteMetadata {
+        created_at: Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+        modified_at,
+        reverted_from: reverted_from.cloned(),
+        ..Default::default()
+    };
+    RevisionRecord {
+        id: id.clone(),
+        revision: Uuid::new_v4().into(),
+        title: "".to_owned(),
+        note_inner: PlainNote::new(modified_at.to_rfc3339()).into(),
+        parent: None,
+        prev: None,
+        metadata,
+        is_current,
+        based_on: None,
+        is_draft: false,
+        labels: vec![],
+        deleted_at: None,
+        past_deletions: vec![],
+    }
+}
+
+pub(super) async fn compact(store: impl NoteStore<PlainNote>) {
+    let at = |m, d, h| Utc.with_ymd_and_hms(2024, m, d, h, 0, 0).unwrap();
+    let now = at(3, 20, 12);
+    let policy = RetentionPolicy::new(vec![
+        RetentionTier {
+            after_days: 30,
+            keep: Keep::Monthly,
+        },
+        RetentionTier {
+            after_days: 7,
+            keep: Keep::Daily,
+        },
+    ]);
+    let id: NoteID = Uuid::new_v4().into();
+    let mut records = vec![
+        record_at(&id, at(1, 5, 10), false, None),
+        record_at(&id, at(1, 20, 10), false, None),
+        record_at(&id, at(2, 25, 9), false, None),
+        record_at(&id, at(2, 25, 18), false, None),
+        record_at(&id, at(3, 18, 8), false, None),
+    ];
+    let reverted = records[2].revision.clone();
+    records.push(record_at(&id, at(3, 18, 9), true, Some(&reverted)));
+    let kept: Vec<Revision> = [1, 2, 3, 4, 5]
+        .iter()
+        .map(|&i| records[i].revision.clone())
+        .collect();
+    store.import_note(records).await.unwrap();
+
+    let deleted_id: NoteID = Uuid::new_v4().into();
+    let deleted_records = vec![
+        record_at(&deleted_id, at(1, 1, 0), false, None),
+        record_at(&deleted_id, at(1, 2, 0), false, None),
+        record_at(&deleted_id, at(1, 3, 0), false, None),
+    ];
+    let deleted_kept = deleted_records[2].revision.clone();
+    store.import_note(deleted_records).await.unwrap();
+
+    let report = retention::compact(&store, &policy, now).await.unwrap();
+    assert_eq!(
+        report,
+        CompactionReport {
+            notes: 2,
+            revisions_purged: 3
+        }
+    );
+    let revisions: Vec<Revision> = store
+        .get_revisions(&NoteLocator::Current(id.clone()))
+        .await
+        .unwrap()
+        .iter()
+        .map(|n| n.get_revision())
+        .collect();
+    // The revision reverted from survives although it is not the last of its day
+    assert_eq!(revisions, kept);
+    let revisions = store
+        .get_revisions(&NoteLocator::Current(deleted_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].get_revision(), deleted_kept);
+
+    // Compaction is idempotent
+    let report = retention::compact(&store, &policy, now).await.unwrap();
+    assert_eq!(report.revisions_purged, 0);
+}
+
+pub(super) async fn search_recent(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
     store
         .new_note(
-            "goodbye world".to_owned(),
+            "".to_owned(),
             note_inner.clone(),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
     let notes = store.search(&("".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
-    let notes = store.search(&("!limit=1".into())).await.unwrap();
+    assert_eq!(notes[0].get_note_inner(), note_inner);
     assert_eq!(notes.len(), 1);
 }
 
-pub(super) async fn search_tag_exclude(store: impl NoteStore<PlainNote>) {
+pub(super) async fn search_fulltext(store: impl NoteStore<PlainNote>) {
     let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
-    let loc1 = store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+    store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
         .await
         .unwrap();
-    let loc2 = store
+    store
         .new_note(
             "goodbye world".to_owned(),
             note_inner.clone(),
@@ -821,19 +1595,16 @@ pub(super) async fn search_tag_exclude(store: impl NoteStore<PlainNote>) {
         )
         .await
         .unwrap();
-    let notes = store.search(&("world".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
-    let notes = store.search(&("world -#tag1".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
-    assert_eq!(&notes[0].get_id(), loc2.get_id());
-    let notes = store.search(&("world #tag1".into())).await.unwrap();
+    let notes = store.search(&("hello".into())).await.unwrap();
+    assert_eq!(notes[0].get_title(), "hello world");
     assert_eq!(notes.len(), 1);
-    assert_eq!(&notes[0].get_id(), loc1.get_id());
+    let notes = store.search(&("foo".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
 }
 
-pub(super) async fn search_lexeme_exclude(store: impl NoteStore<PlainNote>) {
+pub(super) async fn search_nonexist(store: impl NoteStore<PlainNote>) {
     let note_inner = PlainNote::new("Foo".into());
-    let loc1 = store
+    store
         .new_note(
             "hello world".to_owned(),
             note_inner.clone(),
@@ -841,7 +1612,7 @@ pub(super) async fn search_lexeme_exclude(store: impl NoteStore<PlainNote>) {
         )
         .await
         .unwrap();
-    let loc2 = store
+    store
         .new_note(
             "goodbye world".to_owned(),
             note_inner.clone(),
@@ -849,40 +1620,1574 @@ pub(super) async fn search_lexeme_exclude(store: impl NoteStore<PlainNote>) {
         )
         .await
         .unwrap();
-    let notes = store.search(&("world".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
-    let notes = store.search(&("world -hello".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
-    assert_eq!(&notes[0].get_id(), loc2.get_id());
-    let notes = store.search(&("world -goodbye".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
-    assert_eq!(&notes[0].get_id(), loc1.get_id());
+    let notes = store.search(&("fizzbuzz".into())).await.unwrap();
+    assert_eq!(notes.len(), 0);
 }
 
-pub(super) async fn issue_158(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
+pub(super) async fn backlink(store: impl NoteStore<PlainNote>) {
+    let note_inner_1 = PlainNote::new("Hello world".into());
     let loc1 = store
         .new_note(
-            "hello world".to_owned(),
-            note_inner.clone(),
+            "".to_owned(),
+            note_inner_1.clone(),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    let mut note_inner_2 = PlainNote::new("Goodbye world".into());
+    note_inner_2.add_referent(loc1.get_id().clone());
     let loc2 = store
         .new_note(
             "goodbye world".to_owned(),
-            note_inner.clone(),
+            note_inner_2.clone(),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("world".into())).await.unwrap();
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    let references = note.get_references();
+    assert!(references.contains(loc2.get_id()));
+}
+
+pub(super) async fn search_tags(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    store
+        .new_note("goodbye world".to_owned(), note_inner.clone(), md)
+        .await
+        .unwrap();
+    let notes = store.search(&("hello #tag1".into())).await.unwrap();
+    assert_eq!(notes[0].get_title(), "hello world");
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("#tag1".into())).await.unwrap();
     assert_eq!(notes.len(), 2);
-    let notes = store.search(&("-hello".into())).await.unwrap();
+}
+
+pub(super) async fn search_orphan(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Head".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .append_note(
+            loc1.get_id(),
+            "".to_owned(),
+            PlainNote::new("Middle".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("!orphan".into())).await.unwrap();
     assert_eq!(notes.len(), 1);
-    assert_eq!(&notes[0].get_id(), loc2.get_id());
+}
+
+pub(super) async fn search_notag(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world !notag".into())).await.unwrap();
+    assert_eq!(notes[0].get_title(), "goodbye world");
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("world".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+}
+
+pub(super) async fn issue_151(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("".into());
+    let loc = store
+        .new_note(
+            "note 1".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_next = store
+        .append_note(
+            loc.get_id(),
+            "note 2".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut note_inner_ref = PlainNote::new("ref".into());
+    note_inner_ref.add_referent(loc.get_id().clone());
+    let loc2 = store
+        .new_note(
+            "note 3".to_owned(),
+            note_inner_ref.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc3 = store
+        .new_note(
+            "note 4".to_owned(),
+            note_inner_ref.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let n = store.get_note(&loc.current()).await.unwrap();
+    assert_eq!(&n.get_next().unwrap(), loc_next.get_id());
+    assert_eq!(n.get_references().len(), 2);
+    assert!(n.get_references().contains(loc2.get_id()));
+    assert!(n.get_references().contains(loc3.get_id()));
+}
+
+pub(super) async fn tags(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("".into());
+    let md1 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag1".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    let md2 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag1".to_owned(), "tag2".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    let md3 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag2".to_owned(), "tag3".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    let _loc1 = store
+        .new_note("note 1".to_owned(), note_inner.clone(), md1)
+        .await
+        .unwrap();
+    let _loc2 = store
+        .new_note("note 2".to_owned(), note_inner.clone(), md2)
+        .await
+        .unwrap();
+    let loc3 = store
+        .new_note("note 3".to_owned(), note_inner.clone(), md3)
+        .await
+        .unwrap();
+    assert_eq!(store.tags().await.unwrap().len(), 3);
+    store.delete_note(&loc3).await.unwrap();
+    let tags = store.tags().await.unwrap();
+    assert_eq!(tags.len(), 2);
+    assert!(tags.contains(&"tag1".to_owned()));
+    assert!(tags.contains(&"tag2".to_owned()));
+}
+
+pub(super) async fn search_limit_override(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = store.search(&("!limit=1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+}
+
+pub(super) async fn search_tag_exclude(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        custom_metadata: None,
+        author: None,
+        message: None,
+    };
+    let loc1 = store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = store.search(&("world -#tag1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc2.get_id());
+    let notes = store.search(&("world #tag1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc1.get_id());
+}
+
+pub(super) async fn search_lexeme_exclude(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let loc1 = store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = store.search(&("world -hello".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc2.get_id());
+    let notes = store.search(&("world -goodbye".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc1.get_id());
+}
+
+pub(super) async fn issue_158(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let loc1 = store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = store.search(&("-hello".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc2.get_id());
     let notes = store.search(&("-goodbye".into())).await.unwrap();
     assert_eq!(notes.len(), 1);
     assert_eq!(&notes[0].get_id(), loc1.get_id());
 }
+
+/// Check that two stores hold the same notes
+async fn assert_same_notes(
+    store: &impl NoteStore<PlainNote>,
+    store_restore: &impl NoteStore<PlainNote>,
+    locs: &[&NoteLocator],
+) {
+    for loc in locs {
+        let revisions = store.get_revisions(loc).await.unwrap();
+        let revisions_restore = store_restore.get_revisions(loc).await.unwrap();
+        assert_eq!(revisions.len(), revisions_restore.len());
+        for (r, r_restore) in revisions.iter().zip(revisions_restore.iter()) {
+            assert_eq!(r.get_revision(), r_restore.get_revision());
+            assert_eq!(r.get_title(), r_restore.get_title());
+            assert_eq!(r.get_note_inner(), r_restore.get_note_inner());
+            assert_eq!(r.get_metadata(), r_restore.get_metadata());
+            assert_eq!(r.is_current(), r_restore.is_current());
+        }
+        if store.get_current_revision(loc).await.unwrap().is_some() {
+            let note = store.get_note(&loc.current()).await.unwrap();
+            let note_restore = store_restore.get_note(&loc.current()).await.unwrap();
+            assert_eq!(
+                serde_json::to_value(NoteSerializable::all_fields(note)).unwrap(),
+                serde_json::to_value(NoteSerializable::all_fields(note_restore)).unwrap()
+            );
+        }
+    }
+}
+
+/// Populate a store with a branch, a sequence, an updated note and a deleted note
+async fn populate_for_copy(
+    store: &impl NoteStore<PlainNote>,
+) -> (NoteLocator, NoteLocator, NoteLocator, NoteLocator) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Parent".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .add_branch(
+            loc1.get_id(),
+            "".to_owned(),
+            PlainNote::new("Branch".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc3 = store
+        .append_note(
+            loc2.get_id(),
+            "".to_owned(),
+            PlainNote::new("Next".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .update_note(
+            &loc1.current(),
+            Some("Parent title".to_owned()),
+            Some(PlainNote::new("Parent1".into())),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+                custom_metadata: None,
+                author: None,
+                message: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc4 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Deleted".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store.delete_note(&loc4.current()).await.unwrap();
+    store.add_label(&loc1, "first").await.unwrap();
+
+    (loc1, loc2, loc3, loc4)
+}
+
+/// Assert that the labels and deletion times of notes are the same in both stores.
+async fn assert_same_labels_and_deletion(
+    store: &impl NoteStore<PlainNote>,
+    other: &impl NoteStore<PlainNote>,
+    locs: &[&NoteLocator],
+) {
+    for loc in locs {
+        assert_eq!(
+            store.list_labels(loc.get_id()).await.unwrap(),
+            other.list_labels(loc.get_id()).await.unwrap()
+        );
+    }
+    let deleted = |d: Vec<DeletedNote<PlainNote>>| -> Vec<(NoteID, DateTime<Utc>)> {
+        d.into_iter()
+            .map(|d| (d.note.get_id(), d.deleted_at))
+            .collect()
+    };
+    assert_eq!(
+        deleted(store.list_deleted().await.unwrap()),
+        deleted(other.list_deleted().await.unwrap())
+    );
+}
+
+pub(super) async fn backup_restore(
+    store: impl NoteStore<PlainNote>,
+    store_restore: impl NoteStore<PlainNote>,
+) {
+    let (loc1, loc2, loc3, loc4) = populate_for_copy(&store).await;
+    store
+        .put_session_draft("note.new.1", None, serde_json::json!({"title": "New"}))
+        .await
+        .unwrap();
+    store
+        .put_session_draft("note.edit.1", Some(&loc3), serde_json::json!({}))
+        .await
+        .unwrap();
+    let path = backup_dir();
+    store.backup(Box::new(path.clone())).await.unwrap();
+    store_restore.restore_into(&path).await.unwrap();
+    assert_same_notes(&store, &store_restore, &[&loc1, &loc2, &loc3, &loc4]).await;
+    assert!(is_deleted(&store_restore, &loc4).await.unwrap());
+    assert_same_labels_and_deletion(&store, &store_restore, &[&loc1, &loc2, &loc3, &loc4]).await;
+    assert_eq!(
+        store_restore.list_session_drafts("").await.unwrap(),
+        store.list_session_drafts("").await.unwrap()
+    );
+    assert!(matches!(
+        store_restore.restore_into(&path).await,
+        Err(NoteStoreError::StoreNotEmpty)
+    ));
+    // The restored store can be used as usual
+    let loc5 = store_restore
+        .append_note(
+            loc3.get_id(),
+            "".to_owned(),
+            PlainNote::new("Last".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    for loc in [&loc1, &loc2, &loc3, &loc4] {
+        assert_ne!(loc.get_id(), loc5.get_id());
+    }
+    assert_eq!(
+        store_restore
+            .get_note(&loc3.current())
+            .await
+            .unwrap()
+            .get_next()
+            .as_ref(),
+        Some(loc5.get_id())
+    );
+}
+
+pub(super) async fn export_import(
+    store: impl NoteStore<PlainNote> + Sync,
+    store_import: impl NoteStore<PlainNote>,
+) {
+    let (loc1, loc2, loc3, loc4) = populate_for_copy(&store).await;
+    let mut archive: Vec<u8> = vec![];
+    export(&store, &mut archive).await.unwrap();
+    let text = String::from_utf8(archive.clone()).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        r#"{"format":"notegraf","version":2}"#
+    );
+    let mut revision_count = 0;
+    for loc in [&loc1, &loc2, &loc3, &loc4] {
+        revision_count += store.get_revisions(loc).await.unwrap().len();
+    }
+    assert_eq!(lines.count(), revision_count);
+    // A note that fails to import leaves nothing behind
+    let mut bad_archive = archive.clone();
+    let mut record = RevisionRecord::from_note(store.get_note(&loc1).await.unwrap().as_ref());
+    record.id = Uuid::new_v4().into();
+    record.revision = Uuid::new_v4().into();
+    record.parent = Some(Uuid::new_v4().into());
+    record.is_current = true;
+    record.based_on = None;
+    record.labels = vec![];
+    write_record(&mut bad_archive, &record).unwrap();
+    assert!(matches!(
+        import(&store_import, bad_archive.as_slice()).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+    assert!(store_import.get_note_ids().await.unwrap().is_empty());
+    import(&store_import, archive.as_slice()).await.unwrap();
+    assert_same_notes(&store, &store_import, &[&loc1, &loc2, &loc3, &loc4]).await;
+    assert_same_labels_and_deletion(&store, &store_import, &[&loc1, &loc2, &loc3, &loc4]).await;
+    assert!(matches!(
+        import(&store_import, archive.as_slice()).await,
+        Err(NoteStoreError::NoteIDConflict(_))
+    ));
+    assert!(matches!(
+        import(&store_import, &br#"{"format":"notegraf","version":0}"#[..]).await,
+        Err(NoteStoreError::UnsupportedFormat(_))
+    ));
+    assert!(matches!(
+        import(&store_import, &br#"{"format":"notegraf","version":3}"#[..]).await,
+        Err(NoteStoreError::UnsupportedFormat(_))
+    ));
+    // Archives of version 1 don't have the fields added later
+    let id = Uuid::new_v4();
+    let revision = Uuid::new_v4();
+    let archive = format!(
+        "{}\n{}\n",
+        r#"{"format":"notegraf","version":1}"#,
+        serde_json::json!({
+            "id": id.to_string(),
+            "revision": revision.to_string(),
+            "title": "Old",
+            "note_inner": String::from(PlainNote::new("Old archive".into())),
+            "parent": null,
+            "prev": null,
+            "metadata": {
+                "schema_version": 0,
+                "created_at": "2022-01-02T03:04:05Z",
+                "modified_at": "2022-01-02T03:04:05Z",
+                "tags": [],
+                "custom_metadata": {}
+            },
+            "is_current": true
+        })
+    );
+    import(&store_import, archive.as_bytes()).await.unwrap();
+    let note = store_import
+        .get_note(&NoteLocator::Current(id.into()))
+        .await
+        .unwrap();
+    assert_eq!(note.get_revision(), revision.into());
+    assert!(!note.is_draft());
+    assert_eq!(note.get_metadata().reverted_from, None);
+}
+
+/// Capture a point in time strictly between two operations
+fn checkpoint() -> DateTime<Utc> {
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let t = Utc::now();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    t
+}
+
+pub(super) async fn note_at_time(store: impl NoteStore<PlainNote>) {
+    let t0 = checkpoint();
+    let loc_a = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("apple".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_a = loc_a.get_id().clone();
+    let t1 = checkpoint();
+    store
+        .update_note(
+            &loc_a.current(),
+            None,
+            Some(PlainNote::new("banana".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_b = store
+        .add_branch(
+            &id_a,
+            "".to_owned(),
+            PlainNote::new("branch".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_b = loc_b.get_id().clone();
+    let mut note_inner_ref = PlainNote::new("ref".into());
+    note_inner_ref.add_referent(id_a.clone());
+    let loc_c = store
+        .new_note(
+            "".to_owned(),
+            note_inner_ref,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_c = loc_c.get_id().clone();
+    let t2 = checkpoint();
+    store.delete_note(&loc_b.current()).await.unwrap();
+    store
+        .update_note(
+            &loc_c.current(),
+            None,
+            Some(PlainNote::new("ref".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .update_note(
+            &loc_a.current(),
+            None,
+            Some(PlainNote::new("cherry".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let t3 = checkpoint();
+
+    // Before the note was created
+    assert!(matches!(
+        store.get_note(&NoteLocator::AtTime(id_a.clone(), t0)).await,
+        Err(NoteStoreError::NoteNotExistAtTime(id, t)) if id == id_a && t == t0
+    ));
+    let note = store
+        .get_note(&NoteLocator::AtTime(id_a.clone(), t1))
+        .await
+        .unwrap();
+    assert_eq!(note.get_note_inner(), PlainNote::new("apple".into()));
+    assert!(!note.is_current());
+    assert!(note.get_branches().is_empty());
+    assert!(note.get_references().is_empty());
+
+    let view = AsOf::new(&store, t2);
+    let note = view.get_note(&id_a).await.unwrap();
+    assert_eq!(note.get_note_inner(), PlainNote::new("banana".into()));
+    assert_eq!(note.get_branches(), HashSet::from([id_b.clone()]));
+    assert_eq!(note.get_references(), HashSet::from([id_c.clone()]));
+    let note = view.get_note(&id_b).await.unwrap();
+    assert_eq!(note.get_parent(), Some(id_a.clone()));
+
+    let note = store
+        .get_note(&NoteLocator::AtTime(id_a.clone(), t3))
+        .await
+        .unwrap();
+    assert_eq!(note.get_note_inner(), PlainNote::new("cherry".into()));
+    assert!(note.is_current());
+    assert!(note.get_branches().is_empty());
+    assert!(note.get_references().is_empty());
+    // After the note was deleted
+    assert!(matches!(
+        store.get_note(&NoteLocator::AtTime(id_b.clone(), t3)).await,
+        Err(NoteStoreError::NoteNotExistAtTime(id, _)) if id == id_b
+    ));
+    assert!(matches!(
+        store
+            .get_note(&NoteLocator::AtTime(Uuid::new_v4().into(), t3))
+            .await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+
+    let ids = |notes: Vec<Box<dyn Note<PlainNote>>>| {
+        notes
+            .into_iter()
+            .map(|n| n.get_id())
+            .collect::<HashSet<NoteID>>()
+    };
+    let all = "".into();
+    let found = ids(store.search_at(&all, t0).await.unwrap());
+    assert!(!found.contains(&id_a));
+    let found = ids(store.search_at(&all, t1).await.unwrap());
+    assert!(found.contains(&id_a));
+    assert!(!found.contains(&id_b));
+    let found = ids(view.search(&all).await.unwrap());
+    assert!(found.contains(&id_a) && found.contains(&id_b) && found.contains(&id_c));
+    let found = ids(store.search_at(&all, t3).await.unwrap());
+    assert!(found.contains(&id_a) && !found.contains(&id_b) && found.contains(&id_c));
+    let found = ids(view.search(&"banana".into()).await.unwrap());
+    assert_eq!(found, HashSet::from([id_a.clone()]));
+    let found = ids(store.search(&"banana".into()).await.unwrap());
+    assert!(found.is_empty());
+
+    // Writing through an old point in time follows the rules for old revisions
+    assert!(matches!(
+        store
+            .update_note(
+                &NoteLocator::AtTime(id_a.clone(), t1),
+                None,
+                Some(PlainNote::new("durian".into())),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await,
+        Err(NoteStoreError::UpdateOldRevision(..))
+    ));
+    assert!(matches!(
+        store
+            .delete_note(&NoteLocator::AtTime(id_a.clone(), t1))
+            .await,
+        Err(NoteStoreError::DeleteOldRevision(..))
+    ));
+    store
+        .update_note(
+            &NoteLocator::AtTime(id_a.clone(), Utc::now()),
+            None,
+            Some(PlainNote::new("durian".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+}
+
+/// Whether a note existed at a point in time, checking that locating and searching agree
+async fn exists_at(store: &impl NoteStore<PlainNote>, id: &NoteID, t: DateTime<Utc>) -> bool {
+    let exists = match store.get_note(&NoteLocator::AtTime(id.clone(), t)).await {
+        Ok(_) => true,
+        Err(NoteStoreError::NoteNotExistAtTime(..)) => false,
+        Err(e) => panic!("{e}"),
+    };
+    let found = store.search_at(&"".into(), t).await.unwrap();
+    assert_eq!(found.iter().any(|n| &n.get_id() == id), exists);
+    exists
+}
+
+pub(super) async fn note_at_time_restored(
+    store: impl NoteStore<PlainNote> + Sync,
+    store_import: impl NoteStore<PlainNote>,
+) {
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("apple".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id = loc.get_id().clone();
+    let t1 = checkpoint();
+    store.delete_note(&loc.current()).await.unwrap();
+    let t2 = checkpoint();
+    restore_deleted(&store, &id).await.unwrap();
+    let t3 = checkpoint();
+    store.delete_note(&loc.current()).await.unwrap();
+    let t4 = checkpoint();
+
+    let deletions = store.list_past_deletions(&id).await.unwrap();
+    assert_eq!(deletions.len(), 1);
+    assert!(t1 < deletions[0].deleted_at && deletions[0].deleted_at < t2);
+    assert!(t2 < deletions[0].restored_at && deletions[0].restored_at < t3);
+    assert!(matches!(
+        store.list_past_deletions(&Uuid::new_v4().into()).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+
+    assert!(exists_at(&store, &id, t1).await);
+    // While the note was deleted, before it was restored
+    assert!(!exists_at(&store, &id, t2).await);
+    assert!(exists_at(&store, &id, t3).await);
+    assert!(!exists_at(&store, &id, t4).await);
+
+    // Past deletions are exported
+    let mut archive: Vec<u8> = vec![];
+    export(&store, &mut archive).await.unwrap();
+    import(&store_import, archive.as_slice()).await.unwrap();
+    assert_eq!(
+        store_import.list_past_deletions(&id).await.unwrap(),
+        deletions
+    );
+    assert!(!exists_at(&store_import, &id, t2).await);
+    assert!(exists_at(&store_import, &id, t3).await);
+}
+
+pub(super) async fn revision_author_message(store: impl NoteStore<PlainNote>) {
+    let signed = |author: &str, message: &str| NoteMetadataEditable {
+        tags: None,
+        custom_metadata: None,
+        author: Some(author.to_owned()),
+        message: Some(message.to_owned()),
+    };
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            signed("alice", "Create"),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            None,
+            Some(PlainNote::new("Bar".into())),
+            signed("bob", "Fix typo"),
+        )
+        .await
+        .unwrap();
+    // Author and message belong to a single revision
+    let loc3 = store
+        .update_note(
+            &loc2,
+            None,
+            Some(PlainNote::new("Baz".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let metadata = store.get_note(&loc1).await.unwrap().get_metadata();
+    assert_eq!(metadata.author.as_deref(), Some("alice"));
+    assert_eq!(metadata.message.as_deref(), Some("Create"));
+    let metadata = store.get_note(&loc2).await.unwrap().get_metadata();
+    assert_eq!(metadata.author.as_deref(), Some("bob"));
+    assert_eq!(metadata.message.as_deref(), Some("Fix typo"));
+    let metadata = store.get_note(&loc3).await.unwrap().get_metadata();
+    assert_eq!(metadata.author, None);
+    assert_eq!(metadata.message, None);
+
+    let log: Vec<(Option<String>, Option<String>)> = store
+        .get_revisions(&loc1.current())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| {
+            let metadata = n.get_metadata();
+            (metadata.author, metadata.message)
+        })
+        .collect();
+    assert_eq!(
+        log,
+        vec![
+            (Some("alice".to_owned()), Some("Create".to_owned())),
+            (Some("bob".to_owned()), Some("Fix typo".to_owned())),
+            (None, None),
+        ]
+    );
+}
+
+pub(super) async fn revision_labels(store: impl NoteStore<PlainNote>) {
+    let update = |loc: NoteLocator, text: &'static str| {
+        let store = &store;
+        async move {
+            store
+                .update_note(
+                    &loc,
+                    None,
+                    Some(PlainNote::new(text.into())),
+                    NoteMetadataEditable::unchanged(),
+                )
+                .await
+        }
+    };
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id = loc1.get_id().clone();
+    let rev1 = loc1.get_revision().unwrap().clone();
+    let loc2 = update(loc1.current(), "Bar").await.unwrap();
+    let rev2 = loc2.get_revision().unwrap().clone();
+    let label = |l: &str| NoteLocator::Label(id.clone(), l.to_owned());
+
+    store.add_label(&loc1, "draft").await.unwrap();
+    store.add_label(&loc1.current(), "v2").await.unwrap();
+    // Attaching a label to the same revision again does nothing
+    store.add_label(&loc1, "draft").await.unwrap();
+    assert!(matches!(
+        store.add_label(&loc2, "draft").await,
+        Err(NoteStoreError::LabelExists(_, l, r)) if l == "draft" && r == rev1
+    ));
+    assert_eq!(
+        store.list_labels(&id).await.unwrap(),
+        vec![
+            RevisionLabel {
+                label: "draft".to_owned(),
+                revision: rev1.clone()
+            },
+            RevisionLabel {
+                label: "v2".to_owned(),
+                revision: rev2.clone()
+            },
+        ]
+    );
+    let note = store.get_note(&label("draft")).await.unwrap();
+    assert_eq!(note.get_revision(), rev1);
+    assert_eq!(note.get_note_inner(), PlainNote::new("Foo".into()));
+    assert!(matches!(
+        store.get_note(&label("final")).await,
+        Err(NoteStoreError::LabelNotExist(_, l)) if l == "final"
+    ));
+    let unknown: NoteID = Uuid::new_v4().into();
+    assert!(matches!(
+        store.list_labels(&unknown).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+    assert!(matches!(
+        store
+            .get_note(&NoteLocator::Label(unknown.clone(), "draft".to_owned()))
+            .await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+
+    // Labels locate revisions for writing as well
+    assert!(matches!(
+        update(label("draft"), "Baz").await,
+        Err(NoteStoreError::UpdateOldRevision(..))
+    ));
+    update(label("v2"), "Baz").await.unwrap();
+    let loc4 = update(loc1.current(), "Qux").await.unwrap();
+
+    // Labelled revisions survive compaction
+    let policy = RetentionPolicy::new(vec![RetentionTier {
+        after_days: 0,
+        keep: Keep::Monthly,
+    }]);
+    retention::compact(&store, &policy, Utc::now() + chrono::Duration::days(1))
+        .await
+        .unwrap();
+    let revisions: Vec<Revision> = store
+        .get_revisions(&loc1)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.get_revision())
+        .collect();
+    assert_eq!(
+        revisions,
+        vec![rev1, rev2, loc4.get_revision().unwrap().clone()]
+    );
+
+    store.remove_label(&id, "draft").await.unwrap();
+    assert!(matches!(
+        store.remove_label(&id, "draft").await,
+        Err(NoteStoreError::LabelNotExist(..))
+    ));
+    assert!(matches!(
+        store.get_note(&label("draft")).await,
+        Err(NoteStoreError::LabelNotExist(..))
+    ));
+    // Purging a revision removes its labels
+    store.purge_revision(&label("v2")).await.unwrap();
+    assert!(store.list_labels(&id).await.unwrap().is_empty());
+}
+
+pub(super) async fn drafts(store: impl NoteStore<PlainNote>) {
+    let draft = |loc: NoteLocator, text: &'static str| {
+        let store = &store;
+        async move {
+            store
+                .create_draft(
+                    &loc,
+                    None,
+                    Some(PlainNote::new(text.into())),
+                    NoteMetadataEditable::unchanged(),
+                )
+                .await
+        }
+    };
+    let update = |loc: NoteLocator, text: &'static str| {
+        let store = &store;
+        async move {
+            store
+                .update_note(
+                    &loc,
+                    None,
+                    Some(PlainNote::new(text.into())),
+                    NoteMetadataEditable::unchanged(),
+                )
+                .await
+        }
+    };
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id = loc1.get_id().clone();
+    let rev1 = loc1.get_revision().unwrap().clone();
+    let loc2 = update(loc1.current(), "Bar").await.unwrap();
+    let rev2 = loc2.get_revision().unwrap().clone();
+    let note = store.get_note(&loc2).await.unwrap();
+    assert_eq!(note.get_based_on(), Some(rev1.clone()));
+    assert!(!note.is_draft());
+
+    // Drafts can start from an old revision, and leave the current revision alone
+    let draft1 = draft(loc1.at_revision(&rev1), "Draft").await.unwrap();
+    let note = store.get_note(&draft1).await.unwrap();
+    assert!(note.is_draft());
+    assert!(!note.is_current());
+    assert_eq!(note.get_based_on(), Some(rev1.clone()));
+    assert_eq!(
+        store.get_current_revision(&loc1).await.unwrap(),
+        Some(rev2.clone())
+    );
+    let now = Utc::now();
+    assert_eq!(
+        store
+            .get_note(&NoteLocator::AtTime(id.clone(), now))
+            .await
+            .unwrap()
+            .get_revision(),
+        rev2
+    );
+    // A draft is continued by starting a draft from it, as neither drafts nor old revisions
+    // can be updated
+    let draft2 = draft(draft1.clone(), "Draft 2").await.unwrap();
+    let note = store.get_note(&draft2).await.unwrap();
+    assert!(note.is_draft());
+    assert_eq!(note.get_based_on(), draft1.get_revision().cloned());
+    assert!(matches!(
+        update(draft1.clone(), "Baz").await,
+        Err(NoteStoreError::UpdateOldRevision(..))
+    ));
+    assert!(matches!(
+        update(loc1.at_revision(&rev1), "Baz").await,
+        Err(NoteStoreError::UpdateOldRevision(..))
+    ));
+    assert_eq!(
+        store.get_current_revision(&loc1).await.unwrap(),
+        Some(rev2.clone())
+    );
+    let drafts: Vec<Revision> = store
+        .list_drafts(&id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.get_revision())
+        .collect();
+    assert_eq!(drafts, vec![draft2.get_revision().unwrap().clone()]);
+
+    // Promoting a draft makes a new current revision based on the draft
+    assert!(matches!(
+        store.promote_draft(&loc2).await,
+        Err(NoteStoreError::NotADraft(_, r)) if r == rev2
+    ));
+    let loc3 = store.promote_draft(&draft2).await.unwrap();
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(Some(&note.get_revision()), loc3.get_revision());
+    assert_eq!(note.get_note_inner(), PlainNote::new("Draft 2".into()));
+    assert_eq!(note.get_based_on(), draft2.get_revision().cloned());
+    assert!(!note.is_draft());
+    assert!(store.list_drafts(&id).await.unwrap().is_empty());
+
+    // Restoring a deleted note skips its drafts
+    let draft3 = draft(loc3.current(), "Revived").await.unwrap();
+    store.delete_note(&loc3).await.unwrap();
+    let deleted = store.list_deleted().await.unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(Some(&deleted[0].note.get_revision()), loc3.get_revision());
+    restore_deleted(&store, &id).await.unwrap();
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(note.get_note_inner(), PlainNote::new("Draft 2".into()));
+    assert_eq!(note.get_based_on(), loc3.get_revision().cloned());
+
+    // Promoting a draft of a deleted note resurrects it
+    store.delete_note(&loc1.current()).await.unwrap();
+    store.promote_draft(&draft3).await.unwrap();
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(note.get_note_inner(), PlainNote::new("Revived".into()));
+
+    // Purging a revision keeps the history connected
+    store.purge_revision(&draft2).await.unwrap();
+    assert_eq!(
+        store.get_note(&loc3).await.unwrap().get_based_on(),
+        draft1.get_revision().cloned()
+    );
+    let unknown: NoteID = Uuid::new_v4().into();
+    assert!(matches!(
+        store.list_drafts(&unknown).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+}
+
+pub(super) async fn update_note_merging_concurrent(store: impl NoteStore<PlainNote>) {
+    let merge = |loc: NoteLocator, title: &'static str, text: &'static str| {
+        let store = &store;
+        async move {
+            update_note_merging(
+                store,
+                &loc,
+                Some(title.to_owned()),
+                Some(PlainNote::new(text.into())),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap()
+        }
+    };
+    let loc1 = store
+        .new_note(
+            "Meeting notes".to_owned(),
+            PlainNote::new("Foo\nBar\nBaz\n".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let rev1 = loc1.get_revision().unwrap().clone();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Meeting notes 2024".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+
+    // An edit based on an older revision is merged with the current revision
+    // The body of a PlainNote is a single line, so only one side can change it
+    let loc3 = match merge(
+        loc1.at_revision(&rev1),
+        "Weekly Meeting notes",
+        "Foo\nBar!\nBaz\n",
+    )
+    .await
+    {
+        MergeOutcome::Updated(loc) => loc,
+        MergeOutcome::Conflict(c) => panic!("Unexpected conflict {c:?}"),
+    };
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(Some(&note.get_revision()), loc3.get_revision());
+    assert_eq!(note.get_title(), "Weekly Meeting notes 2024");
+    assert_eq!(
+        note.get_note_inner(),
+        PlainNote::new("Foo\nBar!\nBaz\n".into())
+    );
+    assert_eq!(note.get_based_on(), loc2.get_revision().cloned());
+
+    // Conflicting edits are not saved
+    let outcome = merge(loc1.at_revision(&rev1), "Meeting notes", "Foo\nBar?\nBaz\n").await;
+    let conflict = match outcome {
+        MergeOutcome::Conflict(c) => c,
+        MergeOutcome::Updated(loc) => panic!("Unexpected update {loc:?}"),
+    };
+    assert_eq!(&conflict.id, loc1.get_id());
+    assert_eq!(conflict.base, rev1);
+    assert_eq!(Some(&conflict.theirs), loc3.get_revision());
+    assert_eq!(
+        conflict.note_inner,
+        MergeVersions {
+            base: PlainNote::new("Foo\nBar\nBaz\n".into()).into(),
+            ours: PlainNote::new("Foo\nBar?\nBaz\n".into()).into(),
+            theirs: PlainNote::new("Foo\nBar!\nBaz\n".into()).into(),
+        }
+    );
+    assert_eq!(
+        store.get_current_revision(&loc1).await.unwrap().as_ref(),
+        loc3.get_revision()
+    );
+
+    // An edit based on the current revision is saved as is
+    match merge(loc3, "Done", "Quux\n").await {
+        MergeOutcome::Updated(loc) => {
+            let note = store.get_note(&loc).await.unwrap();
+            assert!(note.is_current());
+            assert_eq!(note.get_note_inner(), PlainNote::new("Quux\n".into()));
+        }
+        MergeOutcome::Conflict(c) => panic!("Unexpected conflict {c:?}"),
+    }
+}
+
+pub(super) async fn change_feed(store: impl NoteStore<PlainNote>) {
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, NoteID, Option<Revision>)> {
+        changes
+            .iter()
+            .map(|c| {
+                let (id, revision) = c.loc.unpack();
+                (c.kind, id.clone(), revision.cloned())
+            })
+            .collect()
+    }
+    assert!(store.changes_since(0).await.unwrap().is_empty());
+    let subscription = store.subscribe().await.unwrap();
+
+    let loc1 = store
+        .new_note(
+            "Foo".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .add_branch(
+            loc1.get_id(),
+            "Bar".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc3 = store
+        .append_note(
+            loc1.get_id(),
+            "Baz".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc4 = store
+        .update_note(
+            &loc3,
+            Some("Qux".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    // Drafts are not changes to the notes
+    store
+        .create_draft(
+            &loc4,
+            Some("Quux".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store.delete_note(&loc2.current()).await.unwrap();
+    let expected = vec![
+        (
+            ChangeKind::Create,
+            loc1.get_id().clone(),
+            loc1.get_revision().cloned(),
+        ),
+        (
+            ChangeKind::Branch,
+            loc2.get_id().clone(),
+            loc2.get_revision().cloned(),
+        ),
+        (
+            ChangeKind::Append,
+            loc3.get_id().clone(),
+            loc3.get_revision().cloned(),
+        ),
+        (
+            ChangeKind::Update,
+            loc4.get_id().clone(),
+            loc4.get_revision().cloned(),
+        ),
+        (
+            ChangeKind::Delete,
+            loc2.get_id().clone(),
+            loc2.get_revision().cloned(),
+        ),
+    ];
+
+    // Depending on the store, Foo may get new revisions as the neighbour of the other notes,
+    // see change_feed_neighbours
+    let not_neighbour =
+        |c: &Change| !(c.kind == ChangeKind::Update && c.loc.get_id() == loc1.get_id());
+    let streamed: Vec<Change> = subscription
+        .filter(|c| futures::future::ready(not_neighbour(c)))
+        .take(expected.len())
+        .collect()
+        .await;
+    assert_eq!(summary(&streamed), expected);
+    assert!(streamed.windows(2).all(|w| w[0].cursor < w[1].cursor));
+    let changes: Vec<Change> = store
+        .changes_since(0)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(not_neighbour)
+        .collect();
+    assert_eq!(summary(&changes), expected);
+    let cursors: Vec<ChangeCursor> = changes.iter().map(|c| c.cursor).collect();
+    assert_eq!(
+        cursors,
+        streamed.iter().map(|c| c.cursor).collect::<Vec<_>>()
+    );
+    let later: Vec<Change> = store
+        .changes_since(cursors[1])
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(not_neighbour)
+        .collect();
+    assert_eq!(summary(&later), expected[2..]);
+    assert!(store.changes_since(cursors[4]).await.unwrap().is_empty());
+}
+
+pub(super) async fn change_feed_neighbours(store: impl NoteStore<PlainNote>) {
+    async fn current_revisions(
+        store: &impl NoteStore<PlainNote>,
+        ids: &[NoteID],
+    ) -> Vec<Option<Revision>> {
+        let mut revisions = Vec::new();
+        for id in ids {
+            let loc = NoteLocator::Current(id.clone());
+            revisions.push(store.get_current_revision(&loc).await.unwrap());
+        }
+        revisions
+    }
+    // Every note that got a new current revision has a change with that revision,
+    // whether it is the note being operated on or one of its neighbours
+    async fn assert_recorded(
+        store: &impl NoteStore<PlainNote>,
+        ids: &[NoteID],
+        before: &[Option<Revision>],
+        cursor: ChangeCursor,
+    ) -> ChangeCursor {
+        let changes = store.changes_since(cursor).await.unwrap();
+        let after = current_revisions(store, ids).await;
+        for (i, (id, revision)) in ids.iter().zip(&after).enumerate() {
+            let Some(revision) = revision else { continue };
+            if before.get(i).cloned().flatten().as_ref() == Some(revision) {
+                continue;
+            }
+            assert!(
+                changes
+                    .iter()
+                    .any(|c| c.loc.unpack() == (id, Some(revision))),
+                "no change for revision {revision:?} of {id:?}"
+            );
+        }
+        changes.last().map_or(cursor, |c| c.cursor)
+    }
+    fn content() -> PlainNote {
+        PlainNote::new("".into())
+    }
+
+    let a = store
+        .new_note("A".to_owned(), content(), NoteMetadataEditable::unchanged())
+        .await
+        .unwrap()
+        .get_id()
+        .clone();
+    let mut ids = vec![a.clone()];
+    let mut cursor = store.changes_since(0).await.unwrap()[0].cursor;
+
+    // A -> B
+    let before = current_revisions(&store, &ids).await;
+    let loc = store
+        .append_note(
+            &a,
+            "B".to_owned(),
+            content(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let b = loc.get_id().clone();
+    ids.push(b.clone());
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // C is a branch of A
+    let before = current_revisions(&store, &ids).await;
+    let loc = store
+        .add_branch(
+            &a,
+            "C".to_owned(),
+            content(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let c = loc.get_id().clone();
+    ids.push(c.clone());
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> D -> B
+    let before = current_revisions(&store, &ids).await;
+    let loc = store
+        .insert_after(
+            &a,
+            "D".to_owned(),
+            content(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let d = loc.get_id().clone();
+    ids.push(d.clone());
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> D -> E -> B
+    let before = current_revisions(&store, &ids).await;
+    let loc = store
+        .insert_before(
+            &b,
+            "E".to_owned(),
+            content(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let e = loc.get_id().clone();
+    ids.push(e.clone());
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> E -> B, D is a branch of C
+    let before = current_revisions(&store, &ids).await;
+    store
+        .move_note(&d, &MoveTarget::Branch(c.clone()))
+        .await
+        .unwrap();
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> E, B is a branch of E
+    let before = current_revisions(&store, &ids).await;
+    store.split_sequence(&b, SplitTail::Branch).await.unwrap();
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // D -> B
+    let before = current_revisions(&store, &ids).await;
+    store.merge_sequences(&d, &b).await.unwrap();
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> E -> B
+    let before = current_revisions(&store, &ids).await;
+    store
+        .move_note(&b, &MoveTarget::EndOf(a.clone()))
+        .await
+        .unwrap();
+    cursor = assert_recorded(&store, &ids, &before, cursor).await;
+
+    // A -> B
+    let before = current_revisions(&store, &ids).await;
+    store.delete_note(&NoteLocator::Current(e)).await.unwrap();
+    assert_recorded(&store, &ids, &before, cursor).await;
+    assert_eq!(
+        store
+            .get_note(&NoteLocator::Current(b))
+            .await
+            .unwrap()
+            .get_prev(),
+        Some(a)
+    );
+}
+
+pub(super) async fn prune_changes(store: impl NoteStore<PlainNote>) {
+    for title in ["Foo", "Bar"] {
+        store
+            .new_note(
+                title.to_owned(),
+                PlainNote::new("".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+    }
+    let changes = store.changes_since(0).await.unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(
+        store.prune_changes(&changes[1].changed_at).await.unwrap(),
+        1
+    );
+    let kept = store.changes_since(0).await.unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].cursor, changes[1].cursor);
+
+    let later = Utc::now() + chrono::Duration::days(1);
+    assert_eq!(store.prune_changes(&later).await.unwrap(), 1);
+    assert!(store.changes_since(0).await.unwrap().is_empty());
+    // Cursors keep increasing after all changes are pruned
+    let loc = store
+        .new_note(
+            "Baz".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let changes_after = store.changes_since(changes[1].cursor).await.unwrap();
+    assert_eq!(changes_after.len(), 1);
+    assert_eq!(changes_after[0].loc.get_revision(), loc.get_revision());
+}
+
+pub(super) async fn session_drafts(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id = loc1.get_id().clone();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            None,
+            Some(PlainNote::new("Bar".into())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let edit = format!("note.{id}.edit.1");
+    let append = format!("note.{id}.append.1");
+
+    let new = store
+        .put_session_draft("note.new.1", None, serde_json::json!({"title": "Baz"}))
+        .await
+        .unwrap();
+    assert_eq!(new.id, None);
+    assert_eq!(new.base, None);
+    let draft = store
+        .put_session_draft(&edit, Some(&loc1.current()), serde_json::json!({}))
+        .await
+        .unwrap();
+    assert_eq!(draft.id, Some(id.clone()));
+    assert_eq!(draft.base, None);
+    // Saving again replaces the draft
+    let draft = store
+        .put_session_draft(&edit, Some(&loc2), serde_json::json!({"note_inner": "Qux"}))
+        .await
+        .unwrap();
+    assert_eq!(draft.base.as_ref(), loc2.get_revision());
+    assert_eq!(store.get_session_draft(&edit).await.unwrap(), draft);
+    store
+        .put_session_draft(&append, Some(&loc1), serde_json::json!({}))
+        .await
+        .unwrap();
+    assert!(matches!(
+        store
+            .put_session_draft(
+                "note.unknown.edit.1",
+                Some(&NoteLocator::Current(Uuid::new_v4().into())),
+                serde_json::json!({})
+            )
+            .await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+
+    let sessions = |drafts: Vec<SessionDraft>| -> Vec<String> {
+        drafts.into_iter().map(|d| d.session).collect()
+    };
+    let mut all = vec![append.clone(), edit.clone(), "note.new.1".to_owned()];
+    all.sort();
+    assert_eq!(
+        sessions(store.list_session_drafts("note.").await.unwrap()),
+        all
+    );
+    assert_eq!(
+        sessions(
+            store
+                .list_session_drafts(&format!("note.{id}.edit."))
+                .await
+                .unwrap()
+        ),
+        vec![edit.clone()]
+    );
+    assert!(store.list_session_drafts("tag.").await.unwrap().is_empty());
+
+    // Session drafts are not revisions
+    assert_eq!(store.get_revisions(&loc1).await.unwrap().len(), 2);
+    assert!(store.list_drafts(&id).await.unwrap().is_empty());
+
+    store.delete_session_draft("note.new.1").await.unwrap();
+    assert!(matches!(
+        store.get_session_draft("note.new.1").await,
+        Err(NoteStoreError::SessionDraftNotExist(s)) if s == "note.new.1"
+    ));
+    assert!(matches!(
+        store.delete_session_draft("note.new.1").await,
+        Err(NoteStoreError::SessionDraftNotExist(_))
+    ));
+
+    // Drafts lose the base revision when it is purged, and the note when it is purged, but keep
+    // what was written
+    store.purge_revision(&loc1).await.unwrap();
+    let draft = store.get_session_draft(&append).await.unwrap();
+    assert_eq!(draft.id, Some(id.clone()));
+    assert_eq!(draft.base, None);
+    store.delete_note(&loc2.current()).await.unwrap();
+    let drafts = store.list_session_drafts("").await.unwrap();
+    assert!(!drafts.is_empty());
+    store.purge_note(&id).await.unwrap();
+    let purged = store.list_session_drafts("").await.unwrap();
+    assert_eq!(sessions(purged.clone()), sessions(drafts.clone()));
+    for (draft, before) in purged.iter().zip(&drafts) {
+        assert_eq!(draft.id, None);
+        assert_eq!(draft.base, None);
+        assert_eq!(draft.content, before.content);
+    }
+}
@@ -0,0 +1,57 @@
+//! Deleted notes.
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadataEditable;
+use crate::{Note, NoteID, NoteLocator, NoteStore, NoteType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A deleted note, as returned by [`NoteStore::list_deleted`].
+pub struct DeletedNote<T> {
+    /// The last revision of the note, i.e., the current revision when it was deleted.
+    pub note: Box<dyn Note<T>>,
+    /// When the note was deleted.
+    ///
+    /// If the deletion time was not recorded (e.g., the note was imported from an archive without
+    /// it), this is the time the last revision was modified.
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// A period during which a note was deleted, before it was restored, as returned by
+/// [`NoteStore::list_past_deletions`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeletionPeriod {
+    /// When the note was deleted, as in [`DeletedNote::deleted_at`].
+    pub deleted_at: DateTime<Utc>,
+    /// When the note was restored, i.e., the modification time of the revision that restored it.
+    pub restored_at: DateTime<Utc>,
+}
+
+/// Restore a deleted note by resurrecting its last revision other than drafts.
+///
+/// As with any resurrection, the note becomes a standalone note.
+/// If the note is not deleted, [`NoteStoreError::NotDeleted`] is returned.
+pub async fn restore_deleted<T, S>(store: &S, id: &NoteID) -> Result<NoteLocator, NoteStoreError>
+where
+    T: NoteType,
+    S: NoteStore<T> + ?Sized,
+{
+    let loc = NoteLocator::Current(id.clone());
+    if store.get_current_revision(&loc).await?.is_some() {
+        return Err(NoteStoreError::NotDeleted(id.clone()));
+    }
+    // Drafts were never current
+    let last = store
+        .get_revisions(&loc)
+        .await?
+        .into_iter()
+        .rfind(|n| !n.is_draft())
+        .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))?;
+    store
+        .update_note(
+            &NoteLocator::Specific(id.clone(), last.get_revision()),
+            None,
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+}
@@ -22,7 +22,9 @@ pub async fn populate_test_data(store: &BoxedNoteStore<MarkdownNote>) {
                 tags: Some(HashSet::from_iter(
                     ["tag1".to_owned(), "tag2".to_owned()].iter().cloned(),
                 )),
-                custom_metadata: None
+                custom_metadata: None,
+                author: None,
+                message: None,
             },
         )
         .await
@@ -0,0 +1,363 @@
+//! Operational transformation of plain text, for editing a note together in real time.
+//!
+//! A [`TextOperation`] turns a text of one length into a text of another length, by retaining,
+//! inserting and deleting characters.
+//! Two operations made concurrently on the same text can be transformed, so that applying
+//! either one and then the other transformed one gives the same text.
+//!
+//! Lengths are in Unicode scalar values (`char`s).
+//! Operations are serialized the same way as [ot.js](https://github.com/Operational-Transformation/ot.js),
+//! i.e., an array where a positive number retains, a negative number deletes, and a string
+//! inserts.
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum OperationError {
+    #[error("operation expects a text of length {0}, not {1}")]
+    LengthMismatch(usize, usize),
+    #[error("operations on texts of length {0} and {1} can't be transformed")]
+    NotConcurrent(usize, usize),
+    #[error("invalid component of an operation")]
+    InvalidComponent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Component {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawComponent {
+    Count(i64),
+    Insert(String),
+}
+
+/// An edit of a whole text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<RawComponent>", into = "Vec<RawComponent>")]
+pub struct TextOperation {
+    components: Vec<Component>,
+    base_len: usize,
+    target_len: usize,
+}
+
+impl TryFrom<Vec<RawComponent>> for TextOperation {
+    type Error = OperationError;
+
+    fn try_from(raw: Vec<RawComponent>) -> Result<Self, Self::Error> {
+        let mut op = TextOperation::new();
+        for c in raw {
+            match c {
+                RawComponent::Count(0) => return Err(OperationError::InvalidComponent),
+                RawComponent::Count(n) if n > 0 => {
+                    let n = usize::try_from(n).map_err(|_| OperationError::InvalidComponent)?;
+                    op.check_growth(n, n)?.retain(n)
+                }
+                RawComponent::Count(n) => {
+                    let n = usize::try_from(n.unsigned_abs())
+                        .map_err(|_| OperationError::InvalidComponent)?;
+                    op.check_growth(n, 0)?.delete(n)
+                }
+                RawComponent::Insert(s) if s.is_empty() => {
+                    return Err(OperationError::InvalidComponent)
+                }
+                RawComponent::Insert(s) => op.check_growth(0, s.chars().count())?.insert(&s),
+            };
+        }
+        Ok(op)
+    }
+}
+
+impl From<TextOperation> for Vec<RawComponent> {
+    fn from(op: TextOperation) -> Self {
+        op.components
+            .into_iter()
+            .map(|c| match c {
+                Component::Retain(n) => RawComponent::Count(n as i64),
+                Component::Insert(s) => RawComponent::Insert(s),
+                Component::Delete(n) => RawComponent::Count(-(n as i64)),
+            })
+            .collect()
+    }
+}
+
+impl TextOperation {
+    /// An operation that leaves an empty text as is.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The length of the texts that the operation can be applied to.
+    pub fn base_len(&self) -> usize {
+        self.base_len
+    }
+
+    /// The length of the texts that the operation results in.
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    /// Make sure that the lengths can grow by `base` and `target` characters, since the counts of
+    /// a deserialized operation come from the client.
+    fn check_growth(&mut self, base: usize, target: usize) -> Result<&mut Self, OperationError> {
+        match (
+            self.base_len.checked_add(base),
+            self.target_len.checked_add(target),
+        ) {
+            (Some(_), Some(_)) => Ok(self),
+            _ => Err(OperationError::InvalidComponent),
+        }
+    }
+
+    /// Whether the operation leaves the text as is.
+    pub fn is_noop(&self) -> bool {
+        self.components
+            .iter()
+            .all(|c| matches!(c, Component::Retain(_)))
+    }
+
+    /// Keep the next `n` characters.
+    pub fn retain(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.base_len += n;
+        self.target_len += n;
+        if let Some(Component::Retain(last)) = self.components.last_mut() {
+            *last += n;
+        } else {
+            self.components.push(Component::Retain(n));
+        }
+        self
+    }
+
+    /// Insert a string at the current position.
+    pub fn insert(&mut self, s: &str) -> &mut Self {
+        if s.is_empty() {
+            return self;
+        }
+        self.target_len += s.chars().count();
+        // Inserts always go before deletes at the same position, so that equal operations
+        // have the same components
+        let at = match self.components.last() {
+            Some(Component::Delete(_)) => self.components.len() - 1,
+            _ => self.components.len(),
+        };
+        if let Some(Component::Insert(prev)) = at.checked_sub(1).map(|i| &mut self.components[i]) {
+            prev.push_str(s);
+        } else {
+            self.components.insert(at, Component::Insert(s.to_owned()));
+        }
+        self
+    }
+
+    /// Delete the next `n` characters.
+    pub fn delete(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.base_len += n;
+        if let Some(Component::Delete(last)) = self.components.last_mut() {
+            *last += n;
+        } else {
+            self.components.push(Component::Delete(n));
+        }
+        self
+    }
+
+    /// An operation that turns `old` into `new`, replacing what is between their common prefix
+    /// and suffix.
+    pub fn from_diff(old: &str, new: &str) -> Self {
+        let old: Vec<char> = old.chars().collect();
+        let new: Vec<char> = new.chars().collect();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let inserted: String = new[prefix..new.len() - suffix].iter().collect();
+        let mut op = TextOperation::new();
+        op.retain(prefix)
+            .insert(&inserted)
+            .delete(old.len() - suffix - prefix)
+            .retain(suffix);
+        op
+    }
+
+    /// Apply the operation to a text.
+    pub fn apply(&self, text: &str) -> Result<String, OperationError> {
+        let len = text.chars().count();
+        if len != self.base_len {
+            return Err(OperationError::LengthMismatch(self.base_len, len));
+        }
+        let mut chars = text.chars();
+        let mut result = String::with_capacity(text.len());
+        for c in &self.components {
+            match c {
+                Component::Retain(n) => result.extend(chars.by_ref().take(*n)),
+                Component::Insert(s) => result.push_str(s),
+                Component::Delete(n) => {
+                    chars.by_ref().take(*n).for_each(drop);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Transform two operations made concurrently on the same text into `(a', b')`, so that
+    /// applying `a` then `b'` gives the same text as applying `b` then `a'`.
+    ///
+    /// When both insert at the same position, the insert of `a` goes first.
+    pub fn transform(a: &Self, b: &Self) -> Result<(Self, Self), OperationError> {
+        if a.base_len != b.base_len {
+            return Err(OperationError::NotConcurrent(a.base_len, b.base_len));
+        }
+        let (mut a_prime, mut b_prime) = (TextOperation::new(), TextOperation::new());
+        let mut a_iter = a.components.iter().cloned();
+        let mut b_iter = b.components.iter().cloned();
+        let (mut a_next, mut b_next) = (a_iter.next(), b_iter.next());
+        loop {
+            match (a_next.take(), b_next.take()) {
+                (None, None) => return Ok((a_prime, b_prime)),
+                (Some(Component::Insert(s)), b_c) => {
+                    a_prime.insert(&s);
+                    b_prime.retain(s.chars().count());
+                    (a_next, b_next) = (a_iter.next(), b_c);
+                }
+                (a_c, Some(Component::Insert(s))) => {
+                    a_prime.retain(s.chars().count());
+                    b_prime.insert(&s);
+                    (a_next, b_next) = (a_c, b_iter.next());
+                }
+                (Some(a_c), Some(b_c)) => {
+                    let (a_len, b_len) = (a_c.len(), b_c.len());
+                    let n = a_len.min(b_len);
+                    match (&a_c, &b_c) {
+                        (Component::Retain(_), Component::Retain(_)) => {
+                            a_prime.retain(n);
+                            b_prime.retain(n);
+                        }
+                        // Both deleted the same characters
+                        (Component::Delete(_), Component::Delete(_)) => {}
+                        (Component::Delete(_), Component::Retain(_)) => {
+                            a_prime.delete(n);
+                        }
+                        (Component::Retain(_), Component::Delete(_)) => {
+                            b_prime.delete(n);
+                        }
+                        _ => unreachable!("Inserts are handled above"),
+                    }
+                    a_next = a_c.shorten(n).or_else(|| a_iter.next());
+                    b_next = b_c.shorten(n).or_else(|| b_iter.next());
+                }
+                // The base lengths are equal, so both run out at the same time
+                _ => unreachable!("Operations of different lengths"),
+            }
+        }
+    }
+}
+
+impl Component {
+    fn len(&self) -> usize {
+        match self {
+            Component::Retain(n) | Component::Delete(n) => *n,
+            Component::Insert(s) => s.chars().count(),
+        }
+    }
+
+    /// The rest of a retain or delete after `n` characters, if any.
+    fn shorten(self, n: usize) -> Option<Self> {
+        match self {
+            Component::Retain(m) if m > n => Some(Component::Retain(m - n)),
+            Component::Delete(m) if m > n => Some(Component::Delete(m - n)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(json: &str) -> TextOperation {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn serde() {
+        let o = op(r#"[3, "ab", -2, 1]"#);
+        assert_eq!(o.base_len(), 6);
+        assert_eq!(o.target_len(), 6);
+        assert_eq!(serde_json::to_string(&o).unwrap(), r#"[3,"ab",-2,1]"#);
+        // Inserts go before deletes
+        assert_eq!(op(r#"[-2, "ab"]"#), op(r#"["ab", -2]"#));
+        assert!(serde_json::from_str::<TextOperation>("[0]").is_err());
+        // Lengths that overflow
+        let max = i64::MAX;
+        assert!(serde_json::from_str::<TextOperation>(&format!("[{max}, {max}, {max}]")).is_err());
+        assert!(
+            serde_json::from_str::<TextOperation>(&format!("[-{max}, -{max}, -{max}]")).is_err()
+        );
+    }
+
+    #[test]
+    fn apply() {
+        assert_eq!(
+            op(r#"[3, "ab", -2, 1]"#).apply("Foo barz").as_deref(),
+            Err(&OperationError::LengthMismatch(6, 8))
+        );
+        assert_eq!(
+            op(r#"[4, "ab", -3, 1]"#).apply("Foo barz").as_deref(),
+            Ok("Foo abz")
+        );
+        assert_eq!(op(r#"[1, "é", 1]"#).apply("añ").as_deref(), Ok("aéñ"));
+    }
+
+    #[test]
+    fn from_diff() {
+        let o = TextOperation::from_diff("Foo bar baz", "Foo qux baz");
+        assert_eq!(o, op(r#"[4, "qux", -3, 4]"#));
+        assert_eq!(o.apply("Foo bar baz").as_deref(), Ok("Foo qux baz"));
+        assert!(TextOperation::from_diff("Foo", "Foo").is_noop());
+        assert_eq!(
+            TextOperation::from_diff("aaa", "aaaa")
+                .apply("aaa")
+                .as_deref(),
+            Ok("aaaa")
+        );
+    }
+
+    #[test]
+    fn transform() {
+        let text = "Foo bar baz";
+        let cases = [
+            (r#"[4, "quick ", 7]"#, r#"[8, "lazy ", 3]"#),
+            (r#"[4, -4, 3]"#, r#"[6, -5]"#),
+            (r#"[4, "A", 7]"#, r#"[4, "B", 7]"#),
+            (r#"[-11]"#, r#"[11, "!"]"#),
+        ];
+        for (a, b) in cases {
+            let (a, b) = (op(a), op(b));
+            let (a_prime, b_prime) = TextOperation::transform(&a, &b).unwrap();
+            let ab = b_prime.apply(&a.apply(text).unwrap()).unwrap();
+            let ba = a_prime.apply(&b.apply(text).unwrap()).unwrap();
+            assert_eq!(ab, ba);
+        }
+        let (_, b_prime) =
+            TextOperation::transform(&op(r#"[4, "A", 7]"#), &op(r#"[4, "B", 7]"#)).unwrap();
+        assert_eq!(
+            b_prime.apply("Foo Abar baz").as_deref(),
+            Ok("Foo ABbar baz")
+        );
+        assert_eq!(
+            TextOperation::transform(&op("[1]"), &op("[2]")),
+            Err(OperationError::NotConcurrent(1, 2))
+        );
+    }
+}